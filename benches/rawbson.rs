@@ -1,6 +1,6 @@
 use bson::doc;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use rawbson::{Doc, DocBuf};
+use rawbson::{Doc, DocBuf, IndexedDoc};
 use std::convert::TryInto;
 use std::io::{Cursor, Read};
 
@@ -120,7 +120,8 @@ fn access_deep_from_type(c: &mut Criterion) {
 /// adjust the number of elements to fetch from the document.  We always
 /// fetch the last N elements, which are the least performant for a
 /// rawbson::DocumentBuf since we have to iterate through the document
-/// to find the relevant keys.
+/// to find the relevant keys.  The indexed variant builds a
+/// rawbson::IndexedDoc first, and then looks each key up in the index.
 ///
 /// This benchmark starts from a Vec<u8> of bytes in bson format, and
 /// a list of keys to fetch, and then times the following steps:
@@ -154,7 +155,23 @@ fn access_broad_from_bytes(c: &mut Criterion) {
                     reader.read_to_end(&mut bytes).unwrap();
                     let rawdoc = DocBuf::new(bytes).expect("invalid document");
                     for key in keys_to_get {
-                        rawdoc.get_str(&key).unwrap();
+                        rawdoc.get_str(key).unwrap();
+                    }
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("indexed", count),
+            &keys_to_get,
+            |b, keys_to_get| {
+                b.iter(|| {
+                    let mut reader = Cursor::new(inbytes);
+                    let mut bytes = Vec::new();
+                    reader.read_to_end(&mut bytes).unwrap();
+                    let rawdoc = DocBuf::new(bytes).expect("invalid document");
+                    let indexed = IndexedDoc::new(&rawdoc).expect("invalid document");
+                    for key in keys_to_get {
+                        indexed.get_str(key).unwrap();
                     }
                 });
             },
@@ -167,7 +184,7 @@ fn access_broad_from_bytes(c: &mut Criterion) {
                     let mut reader = Cursor::new(inbytes);
                     let doc = bson::Document::from_reader(&mut reader).unwrap();
                     for key in keys_to_get {
-                        doc.get_str(&key).unwrap();
+                        doc.get_str(key).unwrap();
                     }
                 });
             },
@@ -183,7 +200,9 @@ fn access_broad_from_bytes(c: &mut Criterion) {
 /// adjust the number of elements to fetch from the document.  We always
 /// fetch the last N elements, which are the least performant for a
 /// rawbson::DocumentBuf since we have to iterate through the document
-/// to find the relevant keys.
+/// to find the relevant keys.  The indexed variant builds a
+/// rawbson::IndexedDoc inside the timed loop, so the cost of indexing is
+/// included.
 ///
 /// This benchmark starts from an object of the appropriate type (bson::Bson,
 /// for parsed, rawbson::DocumentBuf for raw), and a list of keys to fetch,
@@ -213,7 +232,20 @@ fn access_broad_from_type(c: &mut Criterion) {
                 let rawdoc = DocBuf::new(inbytes.clone()).expect("invalid document");
                 b.iter(|| {
                     for key in keys_to_get {
-                        rawdoc.get_str(&key).unwrap();
+                        rawdoc.get_str(key).unwrap();
+                    }
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("indexed", count),
+            &keys_to_get,
+            |b, keys_to_get| {
+                let rawdoc = DocBuf::new(inbytes.clone()).expect("invalid document");
+                b.iter(|| {
+                    let indexed = IndexedDoc::new(&rawdoc).expect("invalid document");
+                    for key in keys_to_get {
+                        indexed.get_str(key).unwrap();
                     }
                 });
             },
@@ -227,7 +259,7 @@ fn access_broad_from_type(c: &mut Criterion) {
 
                 b.iter(|| {
                     for key in keys_to_get {
-                        doc.get_str(&key).unwrap();
+                        doc.get_str(key).unwrap();
                     }
                 });
             },
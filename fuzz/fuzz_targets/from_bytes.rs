@@ -0,0 +1,11 @@
+#![no_main]
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use rawbson::de::from_bytes;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = from_bytes::<bson::Document>(data);
+    let _ = from_bytes::<HashMap<&str, &str>>(data);
+    let _ = from_bytes::<HashMap<String, Vec<bson::Bson>>>(data);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use rawbson::{validate::walk, Doc};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(doc) = Doc::new(data) {
+        let walked = walk(doc);
+        if doc.validate().is_ok() {
+            assert!(walked.is_ok(), "validated document failed to iterate");
+        }
+    }
+});
@@ -0,0 +1,752 @@
+//! Incremental construction of raw BSON documents.
+//!
+//! A [`DocBufBuilder`] appends elements directly to a byte buffer, keeping
+//! the length prefix and NUL terminator of each (sub-)document correct as
+//! it goes, so no intermediate [`bson::Document`] is needed.
+//!
+//! ```
+//! use rawbson::DocBufBuilder;
+//!
+//! let mut builder = DocBufBuilder::new();
+//! builder
+//!     .append_str("name", "Herman Melville")
+//!     .append_i32("born", 1819)
+//!     .append_document("book", |book| {
+//!         book.append_str("title", "Moby-Dick")
+//!             .append_array("chapters", |chapters| {
+//!                 chapters.append_str("Loomings").append_str("The Carpet-Bag");
+//!             });
+//!     });
+//! let docbuf = builder.finish()?;
+//!
+//! assert_eq!(docbuf.get_i32("born")?, Some(1819));
+//! let book = docbuf.get_document("book")?.unwrap();
+//! assert_eq!(book.get_array("chapters")?.unwrap().get_str(1)?, Some("The Carpet-Bag"));
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::convert::TryFrom;
+
+use bson::{
+    oid,
+    spec::{BinarySubtype, ElementType},
+};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    elem::{Element, RawDecimal128},
+    Array, Doc, DocBuf, ErrorKind, RawError, RawResult,
+};
+
+/// Builds a [`DocBuf`] one element at a time.
+///
+/// Errors, such as a key containing a NUL byte, are recorded as they happen
+/// and reported by [`DocBufBuilder::finish`], so that calls can be chained.
+#[derive(Debug)]
+pub struct DocBufBuilder {
+    data: Vec<u8>,
+    error: Option<RawError>,
+}
+
+impl DocBufBuilder {
+    /// Create a builder for an empty document.
+    pub fn new() -> DocBufBuilder {
+        DocBufBuilder {
+            data: vec![0; 4],
+            error: None,
+        }
+    }
+
+    /// Write the type byte and key of a new element.
+    fn append_key(&mut self, element_type: ElementType, key: &str) {
+        self.data.push(element_type as u8);
+        self.append_cstring(key);
+    }
+
+    fn append_cstring(&mut self, value: &str) {
+        if let Some(position) = value.bytes().position(|b| b == 0) {
+            let offset = self.data.len() + position;
+            self.fail(RawError::new(ErrorKind::InteriorNul).at(offset));
+        }
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn append_lenencoded(&mut self, value: &str) {
+        match i32::try_from(value.len() + 1) {
+            Ok(length) => self.data.extend_from_slice(&length.to_le_bytes()),
+            Err(_) => self.fail(RawError::new(ErrorKind::BadLength)),
+        }
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn fail(&mut self, err: RawError) {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+    }
+
+    /// Build a nested document in place, at the end of this builder's
+    /// buffer.
+    ///
+    /// The closure only gets a [`DocBuilder`], which can append to the
+    /// buffer but not replace it, so `start` is still in bounds when the
+    /// length prefix is filled in.
+    fn nested(&mut self, f: impl FnOnce(&mut DocBuilder<'_>)) {
+        let start = self.data.len();
+        self.data.extend_from_slice(&[0; 4]);
+        f(&mut DocBuilder { doc: self });
+        self.close(start);
+    }
+
+    /// Write the trailing NUL and fill in the length prefix of the
+    /// document starting at `start`.
+    fn close(&mut self, start: usize) {
+        self.data.push(0);
+        match i32::try_from(self.data.len() - start) {
+            Ok(length) => self.data[start..start + 4].copy_from_slice(&length.to_le_bytes()),
+            Err(_) => self.fail(RawError::new(ErrorKind::BadLength)),
+        }
+    }
+
+    pub fn append_f64(&mut self, key: &str, value: f64) -> &mut Self {
+        self.append_key(ElementType::Double, key);
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn append_str(&mut self, key: &str, value: &str) -> &mut Self {
+        self.append_key(ElementType::String, key);
+        self.append_lenencoded(value);
+        self
+    }
+
+    /// Append an embedded document, built by the provided closure.
+    pub fn append_document(&mut self, key: &str, f: impl FnOnce(&mut DocBuilder<'_>)) -> &mut Self {
+        self.append_key(ElementType::EmbeddedDocument, key);
+        self.nested(f);
+        self
+    }
+
+    /// Append an array, built by the provided closure.
+    pub fn append_array(&mut self, key: &str, f: impl FnOnce(&mut ArrayBuilder<'_>)) -> &mut Self {
+        self.append_key(ElementType::Array, key);
+        self.nested(|doc| {
+            f(&mut ArrayBuilder {
+                doc: &mut *doc.doc,
+                index: 0,
+            })
+        });
+        self
+    }
+
+    /// Append a copy of an existing document, without decoding it.
+    pub fn append_raw_document(&mut self, key: &str, value: &Doc) -> &mut Self {
+        self.append_key(ElementType::EmbeddedDocument, key);
+        self.data.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    /// Append a copy of an existing array, without decoding it.
+    pub fn append_raw_array(&mut self, key: &str, value: &Array) -> &mut Self {
+        self.append_key(ElementType::Array, key);
+        self.data.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    /// Append a copy of an element taken from another document, without
+    /// decoding it.
+    pub fn append_element(&mut self, key: &str, value: Element<'_>) -> &mut Self {
+        self.append_key(value.element_type(), key);
+        self.data.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    pub fn append_binary(&mut self, key: &str, subtype: BinarySubtype, data: &[u8]) -> &mut Self {
+        self.append_key(ElementType::Binary, key);
+        let inner_length = if let BinarySubtype::BinaryOld = subtype {
+            data.len() + 4
+        } else {
+            data.len()
+        };
+        match i32::try_from(inner_length) {
+            Ok(length) => self.data.extend_from_slice(&length.to_le_bytes()),
+            Err(_) => self.fail(RawError::new(ErrorKind::BadLength)),
+        }
+        self.data.push(subtype.into());
+        if let BinarySubtype::BinaryOld = subtype {
+            self.data
+                .extend_from_slice(&(data.len() as i32).to_le_bytes());
+        }
+        self.data.extend_from_slice(data);
+        self
+    }
+
+    pub fn append_undefined(&mut self, key: &str) -> &mut Self {
+        self.append_key(ElementType::Undefined, key);
+        self
+    }
+
+    pub fn append_object_id(&mut self, key: &str, value: oid::ObjectId) -> &mut Self {
+        self.append_key(ElementType::ObjectId, key);
+        self.data.extend_from_slice(&value.bytes());
+        self
+    }
+
+    pub fn append_bool(&mut self, key: &str, value: bool) -> &mut Self {
+        self.append_key(ElementType::Boolean, key);
+        self.data.push(value as u8);
+        self
+    }
+
+    pub fn append_datetime(&mut self, key: &str, value: DateTime<Utc>) -> &mut Self {
+        self.append_key(ElementType::DateTime, key);
+        self.data
+            .extend_from_slice(&value.timestamp_millis().to_le_bytes());
+        self
+    }
+
+    pub fn append_null(&mut self, key: &str) -> &mut Self {
+        self.append_key(ElementType::Null, key);
+        self
+    }
+
+    pub fn append_regex(&mut self, key: &str, pattern: &str, options: &str) -> &mut Self {
+        self.append_key(ElementType::RegularExpression, key);
+        self.append_cstring(pattern);
+        self.append_cstring(options);
+        self
+    }
+
+    pub fn append_db_pointer(
+        &mut self,
+        key: &str,
+        namespace: &str,
+        id: oid::ObjectId,
+    ) -> &mut Self {
+        self.append_key(ElementType::DbPointer, key);
+        self.append_lenencoded(namespace);
+        self.data.extend_from_slice(&id.bytes());
+        self
+    }
+
+    pub fn append_javascript(&mut self, key: &str, code: &str) -> &mut Self {
+        self.append_key(ElementType::JavaScriptCode, key);
+        self.append_lenencoded(code);
+        self
+    }
+
+    pub fn append_symbol(&mut self, key: &str, symbol: &str) -> &mut Self {
+        self.append_key(ElementType::Symbol, key);
+        self.append_lenencoded(symbol);
+        self
+    }
+
+    pub fn append_javascript_with_scope(
+        &mut self,
+        key: &str,
+        code: &str,
+        scope: &Doc,
+    ) -> &mut Self {
+        self.append_key(ElementType::JavaScriptCodeWithScope, key);
+        match i32::try_from(4 + 4 + code.len() + 1 + scope.as_bytes().len()) {
+            Ok(length) => self.data.extend_from_slice(&length.to_le_bytes()),
+            Err(_) => self.fail(RawError::new(ErrorKind::BadLength)),
+        }
+        self.append_lenencoded(code);
+        self.data.extend_from_slice(scope.as_bytes());
+        self
+    }
+
+    pub fn append_i32(&mut self, key: &str, value: i32) -> &mut Self {
+        self.append_key(ElementType::Int32, key);
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn append_timestamp(&mut self, key: &str, time: u32, increment: u32) -> &mut Self {
+        self.append_key(ElementType::Timestamp, key);
+        self.data.extend_from_slice(&increment.to_le_bytes());
+        self.data.extend_from_slice(&time.to_le_bytes());
+        self
+    }
+
+    pub fn append_i64(&mut self, key: &str, value: i64) -> &mut Self {
+        self.append_key(ElementType::Int64, key);
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn append_decimal128(&mut self, key: &str, value: RawDecimal128) -> &mut Self {
+        self.append_key(ElementType::Decimal128, key);
+        self.data.extend_from_slice(&value.bytes());
+        self
+    }
+
+    pub fn append_max_key(&mut self, key: &str) -> &mut Self {
+        self.append_key(ElementType::MaxKey, key);
+        self
+    }
+
+    pub fn append_min_key(&mut self, key: &str) -> &mut Self {
+        self.append_key(ElementType::MinKey, key);
+        self
+    }
+
+    /// Complete the document and validate the whole tree, as
+    /// [`Doc::validate`] does, returning the first error encountered while
+    /// building, if any, or else any error found by validation, such as a
+    /// malformed document copied in with
+    /// [`append_raw_document`](DocBufBuilder::append_raw_document).
+    ///
+    /// ```
+    /// # use rawbson::DocBufBuilder;
+    /// let mut builder = DocBufBuilder::new();
+    /// builder.append_bool("nul\0key", true);
+    /// assert!(builder.finish().is_err());
+    /// ```
+    pub fn finish(mut self) -> RawResult<DocBuf> {
+        self.close(0);
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(DocBuf::new(self.data)?.into_validated()?.into_docbuf()),
+        }
+    }
+}
+
+impl Default for DocBufBuilder {
+    fn default() -> DocBufBuilder {
+        DocBufBuilder::new()
+    }
+}
+
+/// Builds an embedded document within a [`DocBufBuilder`].
+///
+/// It appends to the enclosing builder's buffer, and cannot be swapped for
+/// another builder from inside the closure:
+///
+/// ```compile_fail
+/// # use rawbson::DocBufBuilder;
+/// let mut builder = DocBufBuilder::new();
+/// builder.append_document("inner", |doc| {
+///     *doc = DocBufBuilder::new();
+/// });
+/// ```
+#[derive(Debug)]
+pub struct DocBuilder<'a> {
+    doc: &'a mut DocBufBuilder,
+}
+
+impl<'a> DocBuilder<'a> {
+    pub fn append_f64(&mut self, key: &str, value: f64) -> &mut Self {
+        self.doc.append_f64(key, value);
+        self
+    }
+
+    pub fn append_str(&mut self, key: &str, value: &str) -> &mut Self {
+        self.doc.append_str(key, value);
+        self
+    }
+
+    /// Append an embedded document, built by the provided closure.
+    pub fn append_document(&mut self, key: &str, f: impl FnOnce(&mut DocBuilder<'_>)) -> &mut Self {
+        self.doc.append_document(key, f);
+        self
+    }
+
+    /// Append an array, built by the provided closure.
+    pub fn append_array(&mut self, key: &str, f: impl FnOnce(&mut ArrayBuilder<'_>)) -> &mut Self {
+        self.doc.append_array(key, f);
+        self
+    }
+
+    /// Append a copy of an existing document, without decoding it.
+    pub fn append_raw_document(&mut self, key: &str, value: &Doc) -> &mut Self {
+        self.doc.append_raw_document(key, value);
+        self
+    }
+
+    /// Append a copy of an existing array, without decoding it.
+    pub fn append_raw_array(&mut self, key: &str, value: &Array) -> &mut Self {
+        self.doc.append_raw_array(key, value);
+        self
+    }
+
+    /// Append a copy of an element taken from another document, without
+    /// decoding it.
+    pub fn append_element(&mut self, key: &str, value: Element<'_>) -> &mut Self {
+        self.doc.append_element(key, value);
+        self
+    }
+
+    pub fn append_binary(&mut self, key: &str, subtype: BinarySubtype, data: &[u8]) -> &mut Self {
+        self.doc.append_binary(key, subtype, data);
+        self
+    }
+
+    pub fn append_undefined(&mut self, key: &str) -> &mut Self {
+        self.doc.append_undefined(key);
+        self
+    }
+
+    pub fn append_object_id(&mut self, key: &str, value: oid::ObjectId) -> &mut Self {
+        self.doc.append_object_id(key, value);
+        self
+    }
+
+    pub fn append_bool(&mut self, key: &str, value: bool) -> &mut Self {
+        self.doc.append_bool(key, value);
+        self
+    }
+
+    pub fn append_datetime(&mut self, key: &str, value: DateTime<Utc>) -> &mut Self {
+        self.doc.append_datetime(key, value);
+        self
+    }
+
+    pub fn append_null(&mut self, key: &str) -> &mut Self {
+        self.doc.append_null(key);
+        self
+    }
+
+    pub fn append_regex(&mut self, key: &str, pattern: &str, options: &str) -> &mut Self {
+        self.doc.append_regex(key, pattern, options);
+        self
+    }
+
+    pub fn append_db_pointer(
+        &mut self,
+        key: &str,
+        namespace: &str,
+        id: oid::ObjectId,
+    ) -> &mut Self {
+        self.doc.append_db_pointer(key, namespace, id);
+        self
+    }
+
+    pub fn append_javascript(&mut self, key: &str, code: &str) -> &mut Self {
+        self.doc.append_javascript(key, code);
+        self
+    }
+
+    pub fn append_symbol(&mut self, key: &str, symbol: &str) -> &mut Self {
+        self.doc.append_symbol(key, symbol);
+        self
+    }
+
+    pub fn append_javascript_with_scope(
+        &mut self,
+        key: &str,
+        code: &str,
+        scope: &Doc,
+    ) -> &mut Self {
+        self.doc.append_javascript_with_scope(key, code, scope);
+        self
+    }
+
+    pub fn append_i32(&mut self, key: &str, value: i32) -> &mut Self {
+        self.doc.append_i32(key, value);
+        self
+    }
+
+    pub fn append_timestamp(&mut self, key: &str, time: u32, increment: u32) -> &mut Self {
+        self.doc.append_timestamp(key, time, increment);
+        self
+    }
+
+    pub fn append_i64(&mut self, key: &str, value: i64) -> &mut Self {
+        self.doc.append_i64(key, value);
+        self
+    }
+
+    pub fn append_decimal128(&mut self, key: &str, value: RawDecimal128) -> &mut Self {
+        self.doc.append_decimal128(key, value);
+        self
+    }
+
+    pub fn append_max_key(&mut self, key: &str) -> &mut Self {
+        self.doc.append_max_key(key);
+        self
+    }
+
+    pub fn append_min_key(&mut self, key: &str) -> &mut Self {
+        self.doc.append_min_key(key);
+        self
+    }
+}
+
+/// Builds an array within a [`DocBufBuilder`].  Keys are generated from
+/// the index of each element.
+#[derive(Debug)]
+pub struct ArrayBuilder<'a> {
+    doc: &'a mut DocBufBuilder,
+    index: usize,
+}
+
+impl<'a> ArrayBuilder<'a> {
+    fn next_key(&mut self) -> String {
+        let key = self.index.to_string();
+        self.index += 1;
+        key
+    }
+
+    pub fn append_f64(&mut self, value: f64) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_f64(&key, value);
+        self
+    }
+
+    pub fn append_str(&mut self, value: &str) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_str(&key, value);
+        self
+    }
+
+    pub fn append_document(&mut self, f: impl FnOnce(&mut DocBuilder<'_>)) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_document(&key, f);
+        self
+    }
+
+    pub fn append_array(&mut self, f: impl FnOnce(&mut ArrayBuilder<'_>)) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_array(&key, f);
+        self
+    }
+
+    pub fn append_raw_document(&mut self, value: &Doc) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_raw_document(&key, value);
+        self
+    }
+
+    pub fn append_raw_array(&mut self, value: &Array) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_raw_array(&key, value);
+        self
+    }
+
+    pub fn append_element(&mut self, value: Element<'_>) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_element(&key, value);
+        self
+    }
+
+    pub fn append_binary(&mut self, subtype: BinarySubtype, data: &[u8]) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_binary(&key, subtype, data);
+        self
+    }
+
+    pub fn append_undefined(&mut self) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_undefined(&key);
+        self
+    }
+
+    pub fn append_object_id(&mut self, value: oid::ObjectId) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_object_id(&key, value);
+        self
+    }
+
+    pub fn append_bool(&mut self, value: bool) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_bool(&key, value);
+        self
+    }
+
+    pub fn append_datetime(&mut self, value: DateTime<Utc>) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_datetime(&key, value);
+        self
+    }
+
+    pub fn append_null(&mut self) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_null(&key);
+        self
+    }
+
+    pub fn append_regex(&mut self, pattern: &str, options: &str) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_regex(&key, pattern, options);
+        self
+    }
+
+    pub fn append_db_pointer(&mut self, namespace: &str, id: oid::ObjectId) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_db_pointer(&key, namespace, id);
+        self
+    }
+
+    pub fn append_javascript(&mut self, code: &str) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_javascript(&key, code);
+        self
+    }
+
+    pub fn append_symbol(&mut self, symbol: &str) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_symbol(&key, symbol);
+        self
+    }
+
+    pub fn append_javascript_with_scope(&mut self, code: &str, scope: &Doc) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_javascript_with_scope(&key, code, scope);
+        self
+    }
+
+    pub fn append_i32(&mut self, value: i32) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_i32(&key, value);
+        self
+    }
+
+    pub fn append_timestamp(&mut self, time: u32, increment: u32) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_timestamp(&key, time, increment);
+        self
+    }
+
+    pub fn append_i64(&mut self, value: i64) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_i64(&key, value);
+        self
+    }
+
+    pub fn append_decimal128(&mut self, value: RawDecimal128) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_decimal128(&key, value);
+        self
+    }
+
+    pub fn append_max_key(&mut self) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_max_key(&key);
+        self
+    }
+
+    pub fn append_min_key(&mut self) -> &mut Self {
+        let key = self.next_key();
+        self.doc.append_min_key(&key);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{
+        doc, oid::ObjectId, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope, Regex,
+        Timestamp,
+    };
+    use chrono::{TimeZone, Utc};
+
+    use super::DocBufBuilder;
+    use crate::{Doc, DocBuf, ErrorKind};
+
+    #[test]
+    fn matches_bson_encoding() {
+        let oid = ObjectId::with_bytes([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let datetime = Utc.timestamp_millis_opt(1_600_000_000_123).unwrap();
+        let scope = DocBuf::from_document(&doc! {"ok": true});
+
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_f64("f64", 2.5)
+            .append_str("string", "hello")
+            .append_document("document", |_| {})
+            .append_array("array", |arr| {
+                arr.append_str("binary").append_i32(2).append_document(|d| {
+                    d.append_null("null");
+                });
+            })
+            .append_binary("binary", BinarySubtype::Generic, &[1, 2, 3])
+            .append_binary("old", BinarySubtype::BinaryOld, &[4, 5])
+            .append_object_id("object_id", oid.clone())
+            .append_bool("boolean", true)
+            .append_datetime("datetime", datetime)
+            .append_null("null")
+            .append_regex("regex", r"end\s*$", "i")
+            .append_javascript("javascript", "console.log(console);")
+            .append_symbol("symbol", "artist-formerly-known-as")
+            .append_javascript_with_scope("js_with_scope", "console.log(msg);", &scope)
+            .append_i32("int32", 23)
+            .append_timestamp("timestamp", 3542578, 7)
+            .append_i64("int64", 46);
+        let docbuf = builder.finish().expect("valid document");
+
+        let expected = DocBuf::from_document(&doc! {
+            "f64": 2.5,
+            "string": "hello",
+            "document": {},
+            "array": ["binary", 2, { "null": Bson::Null }],
+            "binary": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
+            "old": Binary { subtype: BinarySubtype::BinaryOld, bytes: vec![4, 5] },
+            "object_id": oid,
+            "boolean": true,
+            "datetime": datetime,
+            "null": Bson::Null,
+            "regex": Regex { pattern: String::from(r"end\s*$"), options: String::from("i") },
+            "javascript": Bson::JavaScriptCode(String::from("console.log(console);")),
+            "symbol": Bson::Symbol(String::from("artist-formerly-known-as")),
+            "js_with_scope": JavaScriptCodeWithScope {
+                code: String::from("console.log(msg);"),
+                scope: doc! { "ok": true },
+            },
+            "int32": 23,
+            "timestamp": Timestamp { time: 3542578, increment: 7 },
+            "int64": 46i64,
+        });
+        assert_eq!(docbuf.as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn copies_raw_values() {
+        let source = DocBuf::from_document(&doc! {
+            "inner": { "a": 1 },
+            "list": [1, 2, 3],
+            "value": "copied",
+        });
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_raw_document("inner", source.get_document("inner").unwrap().unwrap())
+            .append_raw_array("list", source.get_array("list").unwrap().unwrap())
+            .append_element("value", source.get("value").unwrap().unwrap());
+        let docbuf = builder.finish().expect("valid document");
+        assert_eq!(docbuf.as_bytes(), source.as_bytes());
+    }
+
+    #[test]
+    fn empty_document() {
+        let docbuf = DocBufBuilder::new().finish().expect("valid document");
+        assert_eq!(docbuf.as_bytes(), b"\x05\0\0\0\0");
+    }
+
+    #[test]
+    fn nul_in_key_is_reported() {
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_i32("ok", 1)
+            .append_document("outer", |doc| {
+                doc.append_i32("bad\0key", 2);
+            })
+            .append_i32("after", 3);
+        let err = builder.finish().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InteriorNul);
+        assert_eq!(err.offset(), Some(27));
+    }
+
+    #[test]
+    fn malformed_raw_values_are_reported() {
+        // {"b": <boolean 0x02>}
+        let bad = Doc::new(b"\x09\0\0\0\x08b\0\x02\0").unwrap();
+        let mut builder = DocBufBuilder::new();
+        builder.append_i32("ok", 1).append_raw_document("inner", bad);
+        let err = builder.finish().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadBool(2));
+        assert_eq!(err.key_path(), "inner.b");
+    }
+}
@@ -0,0 +1,653 @@
+//! MongoDB's comparison order for values of any type.
+//!
+//! [`compare`] orders two [`Element`]s the way MongoDB sorts values, and
+//! [`Ordered`] wraps raw values so that they can be sorted, deduplicated,
+//! or used as `BTreeMap` keys.
+//!
+//! ```
+//! use std::cmp::Ordering;
+//! use bson::doc;
+//! use rawbson::{cmp::compare, DocBuf};
+//!
+//! let docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 1.5, "c": "1"});
+//! let (a, b, c) = (docbuf.get("a")?.unwrap(), docbuf.get("b")?.unwrap(), docbuf.get("c")?.unwrap());
+//! assert_eq!(compare(a, b), Ordering::Less);
+//! // Every number sorts before every string.
+//! assert_eq!(compare(b, c), Ordering::Less);
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::cmp::Ordering;
+use std::f64::consts::LOG2_10;
+
+use bson::spec::ElementType;
+
+use crate::{
+    elem::{Element, RawBson, RawDecimal128},
+    Array, Doc, DocBuf, ErrorKind, RawError, RawResult,
+};
+
+/// The position of a type in MongoDB's comparison order.  Types with the
+/// same rank, such as the four numeric types, compare by value.
+pub(crate) fn rank(element_type: ElementType) -> u8 {
+    match element_type {
+        ElementType::MinKey => 0,
+        ElementType::Undefined => 1,
+        ElementType::Null => 2,
+        ElementType::Double | ElementType::Int32 | ElementType::Int64 | ElementType::Decimal128 => {
+            3
+        }
+        ElementType::String | ElementType::Symbol => 4,
+        ElementType::EmbeddedDocument => 5,
+        ElementType::Array => 6,
+        ElementType::Binary => 7,
+        ElementType::ObjectId => 8,
+        ElementType::Boolean => 9,
+        ElementType::DateTime => 10,
+        ElementType::Timestamp => 11,
+        ElementType::RegularExpression => 12,
+        ElementType::DbPointer => 13,
+        ElementType::JavaScriptCode => 14,
+        ElementType::JavaScriptCodeWithScope => 15,
+        ElementType::MaxKey => 16,
+    }
+}
+
+/// Compare two values in MongoDB's order: first by the rank of their
+/// types, and then by value.
+///
+/// The types rank in this order, lowest first: MinKey, undefined, null,
+/// numbers, strings and symbols, documents, arrays, binary, ObjectId,
+/// booleans, datetimes, timestamps, regular expressions, DBPointers,
+/// JavaScript code, JavaScript code with scope, and MaxKey.
+///
+/// Numbers compare by value across Int32, Int64, Double and Decimal128,
+/// with NaN equal to itself and less than every other number.  Strings
+/// and symbols compare bytewise, and documents and arrays compare element
+/// by element: by the rank of each value's type, then its key, then its
+/// value.
+///
+/// A value that cannot be read, which can only come from a document that
+/// has not been [validated](Doc::validate), sorts after the readable
+/// values of the same rank, and among other such values by its type and
+/// bytes.  Placing it takes no allocation, only another read of the two
+/// values.
+pub fn compare(a: Element<'_>, b: Element<'_>) -> Ordering {
+    try_compare(a, b).unwrap_or_else(|_| fallback_key(a).cmp(&fallback_key(b)))
+}
+
+/// How to order values that cannot be compared by value.
+///
+/// A value is readable if it can be compared with itself, which reads every
+/// part of it that a comparison with any other value could.  Two readable
+/// values of the same rank therefore always compare by value, and putting
+/// the unreadable ones after them keeps the order total.
+fn fallback_key(elem: Element<'_>) -> (u8, bool, u8, &[u8]) {
+    let element_type = elem.element_type();
+    (
+        rank(element_type),
+        try_compare(elem, elem).is_err(),
+        element_type as u8,
+        elem.as_bytes(),
+    )
+}
+
+/// Like [`compare`], but failing on values that cannot be read.
+pub(crate) fn try_compare(a: Element<'_>, b: Element<'_>) -> RawResult<Ordering> {
+    let (a_type, b_type) = (a.element_type(), b.element_type());
+    match rank(a_type).cmp(&rank(b_type)) {
+        Ordering::Equal => {}
+        ordering => return Ok(ordering),
+    }
+    Ok(match a_type {
+        ElementType::MinKey | ElementType::MaxKey | ElementType::Null | ElementType::Undefined => {
+            Ordering::Equal
+        }
+        ElementType::Double | ElementType::Int32 | ElementType::Int64 | ElementType::Decimal128 => {
+            compare_numbers(Number::new(a)?, Number::new(b)?)
+        }
+        ElementType::String | ElementType::Symbol => string(a)?.cmp(string(b)?),
+        ElementType::EmbeddedDocument => compare_documents(a.as_document()?, b.as_document()?)?,
+        ElementType::Array => compare_documents(&a.as_array()?.doc, &b.as_array()?.doc)?,
+        ElementType::Binary => {
+            let (a, b) = (a.as_binary()?, b.as_binary()?);
+            a.as_bytes()
+                .len()
+                .cmp(&b.as_bytes().len())
+                .then(u8::from(a.subtype()).cmp(&u8::from(b.subtype())))
+                .then(a.as_bytes().cmp(b.as_bytes()))
+        }
+        ElementType::ObjectId => a.as_object_id()?.bytes().cmp(&b.as_object_id()?.bytes()),
+        ElementType::Boolean => a.as_bool()?.cmp(&b.as_bool()?),
+        ElementType::DateTime => a.datetime_millis()?.cmp(&b.datetime_millis()?),
+        ElementType::Timestamp => {
+            let (a, b) = (a.as_timestamp()?, b.as_timestamp()?);
+            (a.time(), a.increment()).cmp(&(b.time(), b.increment()))
+        }
+        ElementType::RegularExpression => {
+            let (a, b) = (a.as_regex()?, b.as_regex()?);
+            (a.pattern(), a.options()).cmp(&(b.pattern(), b.options()))
+        }
+        ElementType::DbPointer => {
+            let (a, b) = (a.as_db_pointer()?, b.as_db_pointer()?);
+            (a.namespace(), a.id().bytes()).cmp(&(b.namespace(), b.id().bytes()))
+        }
+        ElementType::JavaScriptCode => a.as_javascript()?.cmp(b.as_javascript()?),
+        ElementType::JavaScriptCodeWithScope => {
+            let (a_code, a_scope) = a.as_javascript_with_scope()?;
+            let (b_code, b_scope) = b.as_javascript_with_scope()?;
+            a_code
+                .cmp(b_code)
+                .then(compare_documents(a_scope, b_scope)?)
+        }
+    })
+}
+
+/// Types that [`Ordered`] can wrap: [`Element`], [`RawBson`], [`Doc`],
+/// [`DocBuf`], [`Array`], and references to them.
+///
+/// This cannot be implemented outside this crate.
+pub trait AsElement: private::Sealed {
+    /// Borrow the value as an element.  Documents become embedded
+    /// documents, and arrays arrays.
+    fn as_element(&self) -> Element<'_>;
+}
+
+impl AsElement for Element<'_> {
+    fn as_element(&self) -> Element<'_> {
+        *self
+    }
+}
+
+impl AsElement for RawBson {
+    fn as_element(&self) -> Element<'_> {
+        RawBson::as_element(self)
+    }
+}
+
+impl AsElement for Doc {
+    fn as_element(&self) -> Element<'_> {
+        Element::new(ElementType::EmbeddedDocument, self.as_bytes())
+    }
+}
+
+impl AsElement for DocBuf {
+    fn as_element(&self) -> Element<'_> {
+        Element::new(ElementType::EmbeddedDocument, self.as_bytes())
+    }
+}
+
+impl AsElement for Array {
+    fn as_element(&self) -> Element<'_> {
+        Element::new(ElementType::Array, self.as_bytes())
+    }
+}
+
+impl<T: AsElement + ?Sized> AsElement for &T {
+    fn as_element(&self) -> Element<'_> {
+        (**self).as_element()
+    }
+}
+
+mod private {
+    use crate::{elem::Element, elem::RawBson, Array, Doc, DocBuf};
+
+    pub trait Sealed {}
+
+    impl Sealed for Element<'_> {}
+    impl Sealed for RawBson {}
+    impl Sealed for Doc {}
+    impl Sealed for DocBuf {}
+    impl Sealed for Array {}
+    impl<T: Sealed + ?Sized> Sealed for &T {}
+}
+
+/// A raw value ordered by [`compare`], for sorting, deduplicating, or use
+/// as a `BTreeMap` key.
+///
+/// Values that MongoDB considers equal are equal here even if their bytes
+/// differ, such as `1` and `1.0`, or a string and a symbol with the same
+/// text.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use bson::{doc, spec::ElementType};
+/// use rawbson::{cmp::Ordered, DocBuf};
+///
+/// let docbuf = DocBuf::from_document(&doc! {"values": ["x", 3, 1.0, null, 1i64, {"a": 1}]});
+/// let mut counts = BTreeMap::new();
+/// for value in docbuf.get_array("values")?.unwrap() {
+///     *counts.entry(Ordered(value?)).or_insert(0) += 1;
+/// }
+/// let types: Vec<_> = counts.keys().map(|key| key.0.element_type()).collect();
+/// assert_eq!(
+///     types,
+///     [ElementType::Null, ElementType::Double, ElementType::Int32, ElementType::String, ElementType::EmbeddedDocument],
+/// );
+/// assert_eq!(counts.values().copied().collect::<Vec<_>>(), [1, 2, 1, 1, 1]);
+/// # Ok::<(), rawbson::RawError>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ordered<T>(pub T);
+
+impl<T: AsElement> PartialEq for Ordered<T> {
+    fn eq(&self, other: &Ordered<T>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: AsElement> Eq for Ordered<T> {}
+
+impl<T: AsElement> PartialOrd for Ordered<T> {
+    fn partial_cmp(&self, other: &Ordered<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: AsElement> Ord for Ordered<T> {
+    fn cmp(&self, other: &Ordered<T>) -> Ordering {
+        compare(self.0.as_element(), other.0.as_element())
+    }
+}
+
+/// Compare documents element by element, by the rank of each value's type,
+/// then its key, then its value.  A document that is a prefix of another
+/// sorts first.
+fn compare_documents(a: &Doc, b: &Doc) -> RawResult<Ordering> {
+    let mut a = a.into_iter();
+    let mut b = b.into_iter();
+    loop {
+        let (a_key, a_elem, b_key, b_elem) = match (a.next().transpose()?, b.next().transpose()?) {
+            (Some((a_key, a_elem)), Some((b_key, b_elem))) => (a_key, a_elem, b_key, b_elem),
+            (Some(_), None) => return Ok(Ordering::Greater),
+            (None, Some(_)) => return Ok(Ordering::Less),
+            (None, None) => return Ok(Ordering::Equal),
+        };
+        let ordering = rank(a_elem.element_type())
+            .cmp(&rank(b_elem.element_type()))
+            .then(a_key.cmp(b_key));
+        let ordering = match ordering {
+            Ordering::Equal => try_compare(a_elem, b_elem)?,
+            ordering => ordering,
+        };
+        if ordering != Ordering::Equal {
+            return Ok(ordering);
+        }
+    }
+}
+
+fn string(elem: Element<'_>) -> RawResult<&str> {
+    match elem.element_type() {
+        ElementType::Symbol => elem.as_symbol(),
+        _ => elem.as_str(),
+    }
+}
+
+/// A value of any of the numeric types.  Decimals are always finite:
+/// infinities and NaN are held as doubles.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Number {
+    Int(i64),
+    Double(f64),
+    Decimal(RawDecimal128),
+}
+
+impl Number {
+    /// Read a numeric element.  Other types produce an error of kind
+    /// [`ErrorKind::UnexpectedType`].
+    pub(crate) fn new(elem: Element<'_>) -> RawResult<Number> {
+        Ok(match elem.element_type() {
+            ElementType::Int32 => Number::Int(elem.as_i32()?.into()),
+            ElementType::Int64 => Number::Int(elem.as_i64()?),
+            ElementType::Double => Number::Double(elem.as_f64()?),
+            ElementType::Decimal128 => {
+                let value = elem.as_decimal128()?;
+                if value.is_finite() {
+                    Number::Decimal(value)
+                } else {
+                    Number::Double(value.to_f64())
+                }
+            }
+            element_type => {
+                return Err(RawError::new(ErrorKind::UnexpectedType).of_type(element_type))
+            }
+        })
+    }
+
+    pub(crate) fn to_f64(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Double(value) => value,
+            Number::Decimal(value) => value.to_f64(),
+        }
+    }
+}
+
+/// Compare numbers by value.  NaN is equal to itself and less than every
+/// other number.
+pub(crate) fn compare_numbers(a: Number, b: Number) -> Ordering {
+    let scaled = |value: RawDecimal128| value.to_i128_with_scale().unwrap();
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => a.cmp(&b),
+        (Number::Int(a), Number::Double(b)) => compare_int_double(a, b),
+        (Number::Double(a), Number::Int(b)) => compare_int_double(b, a).reverse(),
+        (Number::Int(a), Number::Decimal(b)) => compare_scaled((a.into(), 0), scaled(b)),
+        (Number::Decimal(a), Number::Int(b)) => compare_scaled(scaled(a), (b.into(), 0)),
+        (Number::Decimal(a), Number::Decimal(b)) => compare_scaled(scaled(a), scaled(b)),
+        (Number::Double(a), Number::Decimal(b)) => compare_double_decimal(a, scaled(b)),
+        (Number::Decimal(a), Number::Double(b)) => compare_double_decimal(b, scaled(a)).reverse(),
+        (Number::Double(a), Number::Double(b)) => compare_doubles(a, b),
+    }
+}
+
+fn compare_doubles(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).unwrap(),
+    }
+}
+
+/// Compare an integer and a double exactly, without rounding the integer.
+fn compare_int_double(a: i64, b: f64) -> Ordering {
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if b.is_nan() {
+        Ordering::Greater
+    } else if b >= LIMIT {
+        Ordering::Less
+    } else if b < -LIMIT {
+        Ordering::Greater
+    } else {
+        let whole = b.trunc();
+        // Every double in this range has an exact integer part.
+        a.cmp(&(whole as i64))
+            .then(0.0.partial_cmp(&(b - whole)).unwrap())
+    }
+}
+
+/// Compare a double and a decimal of the form `mantissa * 10^-scale`
+/// exactly, without rounding either.
+fn compare_double_decimal(a: f64, (b, b_scale): (i128, i32)) -> Ordering {
+    if a.is_nan() {
+        return Ordering::Less;
+    } else if a.is_infinite() {
+        return 0.0.partial_cmp(&a).unwrap().reverse();
+    }
+    // Split the double into `mantissa * 2^exponent`.
+    let bits = a.to_bits();
+    let (mantissa, exponent) = match ((bits >> 52) & 0x7ff) as i32 {
+        0 => (bits & ((1 << 52) - 1), -1074),
+        biased => (bits & ((1 << 52) - 1) | 1 << 52, biased - 1075),
+    };
+    let a_sign = if mantissa == 0 { 0 } else if a < 0.0 { -1 } else { 1 };
+    match a_sign.cmp(&b.signum()) {
+        Ordering::Equal if b == 0 => return Ordering::Equal,
+        Ordering::Equal => {}
+        ordering => return ordering,
+    }
+    let ordering = compare_magnitudes(mantissa, exponent, b.unsigned_abs(), -b_scale);
+    if a_sign < 0 {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// Compare `a * 2^a_exponent` with `b * 10^b_exponent`, both nonzero.
+fn compare_magnitudes(a: u64, a_exponent: i32, b: u128, b_exponent: i32) -> Ordering {
+    // Values whose bit lengths are far apart are ordered by them.
+    let a_bits = f64::from(64 - a.leading_zeros() as i32 + a_exponent);
+    let b_bits = f64::from(128 - b.leading_zeros() as i32) + f64::from(b_exponent) * LOG2_10;
+    if a_bits + 2.0 < b_bits {
+        return Ordering::Less;
+    } else if a_bits > b_bits + 2.0 {
+        return Ordering::Greater;
+    }
+    // Otherwise scale both to integers and compare them exactly, as
+    // `a * 2^(a_exponent - b_exponent)` against `b * 5^b_exponent`.
+    let mut a = Magnitude::new(a.into());
+    let mut b = Magnitude::new(b);
+    if a_exponent > b_exponent {
+        a.mul_pow(2, (a_exponent - b_exponent) as u32);
+    } else {
+        b.mul_pow(2, (b_exponent - a_exponent) as u32);
+    }
+    if b_exponent < 0 {
+        a.mul_pow(5, b_exponent.unsigned_abs());
+    } else {
+        b.mul_pow(5, b_exponent as u32);
+    }
+    a.cmp(&b)
+}
+
+/// An unsigned integer of up to 1536 bits, with its most significant limb
+/// first so that the derived ordering compares values.
+///
+/// Once [`compare_magnitudes`] has ruled out values of very different size,
+/// the exponents left are small enough for the scaled values to fit.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Magnitude([u32; 48]);
+
+impl Magnitude {
+    fn new(value: u128) -> Magnitude {
+        let mut limbs = [0; 48];
+        for (i, limb) in limbs.iter_mut().rev().take(4).enumerate() {
+            *limb = (value >> (32 * i)) as u32;
+        }
+        Magnitude(limbs)
+    }
+
+    /// Multiply by `base^exponent`.
+    fn mul_pow(&mut self, base: u32, mut exponent: u32) {
+        // Multiply by the largest power of `base` that fits a limb at a time.
+        let (mut chunk, mut chunk_exponent) = (base, 1);
+        while let Some(next) = chunk.checked_mul(base) {
+            chunk = next;
+            chunk_exponent += 1;
+        }
+        while exponent > 0 {
+            let step = exponent.min(chunk_exponent);
+            self.mul_small(base.pow(step));
+            exponent -= step;
+        }
+    }
+
+    fn mul_small(&mut self, factor: u32) {
+        let mut carry = 0;
+        for limb in self.0.iter_mut().rev() {
+            let product = u64::from(*limb) * u64::from(factor) + carry;
+            *limb = product as u32;
+            carry = product >> 32;
+        }
+        debug_assert_eq!(carry, 0, "magnitude overflow");
+    }
+}
+
+/// Compare two values of the form `mantissa * 10^-scale`.
+fn compare_scaled((a, a_scale): (i128, i32), (b, b_scale): (i128, i32)) -> Ordering {
+    match a.signum().cmp(&b.signum()) {
+        Ordering::Equal if a == 0 => return Ordering::Equal,
+        Ordering::Equal => {}
+        ordering => return ordering,
+    }
+    // Bring both mantissas to the larger scale.  If that overflows, the
+    // scaled value is larger in magnitude than any mantissa.
+    let shift =
+        |mantissa: i128, by: i32| (0..by).try_fold(mantissa, |value, _| value.checked_mul(10));
+    if a_scale <= b_scale {
+        match shift(a, b_scale - a_scale) {
+            Some(a) => a.cmp(&b),
+            None => a.signum().cmp(&0),
+        }
+    } else {
+        match shift(b, a_scale - b_scale) {
+            Some(b) => a.cmp(&b),
+            None => 0.cmp(&b.signum()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::collections::BTreeSet;
+    use std::convert::TryFrom;
+
+    use bson::{doc, spec::ElementType, Bson};
+
+    use super::{compare, try_compare, AsElement, Ordered};
+    use crate::{
+        elem::{Element, RawBson, RawBsonRef},
+        DocBuf,
+    };
+
+    fn value(value: impl Into<Bson>) -> RawBson {
+        let docbuf = DocBuf::from_document(&doc! {"v": value.into()});
+        docbuf.get("v").unwrap().unwrap().to_owned()
+    }
+
+    // bson::Decimal128 does not encode reliably, so build these directly.
+    fn decimal(value: &str) -> RawBson {
+        RawBson::try_from(RawBsonRef::Decimal128(value.parse().unwrap())).unwrap()
+    }
+
+    fn order(a: RawBson, b: RawBson) -> Ordering {
+        try_compare(a.as_element(), b.as_element()).unwrap()
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(order(value(1), value(1.0)), Ordering::Equal);
+        assert_eq!(order(value(1i64), value(1.5)), Ordering::Less);
+        assert_eq!(order(value(-1i64), value(-1.5)), Ordering::Greater);
+        assert_eq!(
+            order(value(i64::MAX), value(i64::MAX as f64)),
+            Ordering::Less
+        );
+        assert_eq!(
+            order(value(i64::MIN), value(i64::MIN as f64)),
+            Ordering::Equal
+        );
+        assert_eq!(order(value(f64::NAN), value(f64::NAN)), Ordering::Equal);
+        assert_eq!(order(value(f64::NAN), value(i64::MIN)), Ordering::Less);
+        assert_eq!(order(value(0.0), value(-0.0)), Ordering::Equal);
+
+        assert_eq!(order(decimal("1.00"), value(1)), Ordering::Equal);
+        assert_eq!(order(decimal("0.1E1"), decimal("1")), Ordering::Equal);
+        assert_eq!(
+            order(decimal("12345678901234567890.1"), value(i64::MAX)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            order(decimal("-1E-6000"), decimal("-1E+6000")),
+            Ordering::Greater
+        );
+        assert_eq!(order(decimal("-Infinity"), value(f64::MIN)), Ordering::Less);
+        assert_eq!(order(decimal("NaN"), value(f64::NAN)), Ordering::Equal);
+        assert_eq!(order(decimal("2.5"), value(2.25)), Ordering::Greater);
+
+        // Doubles and decimals compare exactly, keeping the order
+        // transitive with integers that doubles cannot represent.
+        let two_53 = 9_007_199_254_740_992i64;
+        assert_eq!(
+            order(decimal("9007199254740993"), value(two_53 as f64)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            order(value(two_53 + 1), decimal("9007199254740993")),
+            Ordering::Equal
+        );
+        assert_eq!(order(value(0.1), decimal("0.1")), Ordering::Greater);
+        assert_eq!(
+            order(value(0.1), decimal("0.1000000000000000055511151231257828")),
+            Ordering::Less
+        );
+        assert_eq!(order(value(5e-324), decimal("1E-6176")), Ordering::Greater);
+        assert_eq!(order(value(-f64::MAX), decimal("-1E+6144")), Ordering::Greater);
+        assert_eq!(order(value(-0.0), decimal("0E+10")), Ordering::Equal);
+    }
+
+    #[test]
+    fn types_and_containers() {
+        assert_eq!(
+            order(value(Bson::Null), value(Bson::MinKey)),
+            Ordering::Greater
+        );
+        assert_eq!(order(value(Bson::MaxKey), value(true)), Ordering::Greater);
+        assert_eq!(order(value(1_000_000), value("0")), Ordering::Less);
+        assert_eq!(
+            order(value("a"), value(Bson::Symbol("a".into()))),
+            Ordering::Equal
+        );
+        assert_eq!(order(value("ab"), value("b")), Ordering::Less);
+        assert_eq!(order(value(false), value(true)), Ordering::Less);
+
+        assert_eq!(
+            order(value(doc! {"a": 1}), value(doc! {"a": 1.0})),
+            Ordering::Equal
+        );
+        assert_eq!(
+            order(value(doc! {"a": 1}), value(doc! {"b": 0})),
+            Ordering::Less
+        );
+        // The type of a value outranks its key.
+        assert_eq!(
+            order(value(doc! {"b": 1}), value(doc! {"a": "x"})),
+            Ordering::Less
+        );
+        assert_eq!(
+            order(value(doc! {}), value(doc! {"a": Bson::MinKey})),
+            Ordering::Less
+        );
+        assert_eq!(order(value(vec![1, 2]), value(vec![1, 3])), Ordering::Less);
+        assert_eq!(order(value(vec![2]), value(vec![1, 3])), Ordering::Greater);
+        assert_eq!(
+            order(value(vec![1]), value(doc! {"0": 1})),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn malformed_and_ordered() {
+        // A string whose length prefix runs past its end.
+        let bad = Element::new(ElementType::String, b"\x09\0\0\0ab\0");
+        let worse = Element::new(ElementType::String, b"\x09\0\0\0zz\0");
+        let string = value("zzz");
+        assert!(try_compare(bad, string.as_element()).is_err());
+        assert_eq!(compare(bad, string.as_element()), Ordering::Greater);
+        assert_eq!(compare(string.as_element(), bad), Ordering::Less);
+        assert_eq!(compare(bad, worse), Ordering::Less);
+        assert_eq!(compare(bad, value(doc! {}).as_element()), Ordering::Less);
+        assert_eq!(compare(bad, bad), Ordering::Equal);
+
+        // An array with a wrong index key fails validation, but can still be
+        // read, so it sorts with the readable arrays.
+        let misindexed = Element::new(
+            ElementType::Array,
+            b"\x10\0\0\0\x12x\0\x01\0\0\0\0\0\0\0\0",
+        );
+        let unreadable = Element::new(ElementType::Array, b"\x0f\0\0\0\x020\0\x09\0\0\0ab\0\0");
+        assert!(try_compare(misindexed, misindexed).is_ok());
+        assert_eq!(compare(misindexed, unreadable), Ordering::Less);
+        assert_eq!(
+            compare(unreadable, value(vec![0]).as_element()),
+            Ordering::Greater
+        );
+
+        let docbuf = DocBuf::from_document(&doc! {"a": 2, "b": 1.0, "c": 1i64, "d": "x"});
+        // Inserting a value equal to one already in the set leaves it as it is.
+        let mut set = BTreeSet::new();
+        for result in &docbuf {
+            set.insert(Ordered(result.unwrap().1));
+        }
+        let values: Vec<_> = set.iter().map(|value| value.0.element_type()).collect();
+        assert_eq!(
+            values,
+            [ElementType::Double, ElementType::Int32, ElementType::String]
+        );
+        assert!(Ordered(value(1)) == Ordered(decimal("1.0")));
+        assert!(Ordered(docbuf.as_element()) < Ordered(value(vec![0]).as_element()));
+        assert!(Ordered(docbuf.clone()) > Ordered(DocBuf::from_document(&doc! {"a": 1})));
+    }
+}
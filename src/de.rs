@@ -2,38 +2,149 @@ use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visit
 use serde::forward_to_deserialize_any;
 use serde::Deserialize;
 
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
 use std::num::TryFromIntError;
 
-use crate::{elem::Element, ArrayIter, Doc, DocBuf, DocIter, RawError};
+use crate::{
+    elem::Element, error::Context, offset_in, ArrayIter, Doc, DocBuf, DocIter, RawError,
+};
 use bson::spec::ElementType;
 
 use object_id::RawObjectIdDeserializer;
 
 pub mod binary;
 pub mod datetime;
+pub mod db_pointer;
+pub mod decimal128;
 pub mod js;
+pub mod marker;
 pub mod object_id;
+pub mod raw;
 pub mod regex;
+pub mod symbol;
+pub mod timestamp;
 
+/// The kind of problem described by a deserialization [`Error`].
 #[derive(Debug)]
-pub enum Error {
-    Eof,
-    TrailingData(Vec<u8>),
-    EncodingError,
-    MalformedDocument,
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The input was not well-formed BSON.
+    Malformed(crate::ErrorKind),
+    /// A value had a BSON type that cannot be deserialized into the
+    /// requested type.
     UnexpectedType,
-    Unimplemented,
+    /// An integer value did not fit in the requested integer type.
     IntConversion(TryFromIntError),
-    Internal(String),
-    NotFound,
-    TmPErroR,
+    /// The requested conversion is not supported yet.
+    Unimplemented,
+    /// An error reported by a `Deserialize` implementation.
+    Custom(String),
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ErrorKind::Malformed(kind) => write!(f, "malformed bson: {}", kind),
+            ErrorKind::UnexpectedType => write!(f, "unexpected type"),
+            ErrorKind::IntConversion(err) => write!(f, "{}", err),
+            ErrorKind::Unimplemented => write!(f, "unimplemented"),
+            ErrorKind::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// Error returned when deserializing from raw BSON.
+///
+/// Like [`RawError`], it records the byte offset, dotted key path and
+/// element type of the value being deserialized when the error occurred.
+/// Offsets are relative to the start of the document passed to
+/// [`from_doc`] or [`from_bytes`].
+///
+/// ```
+/// use bson::doc;
+/// use rawbson::{de::{from_doc, ErrorKind}, DocBuf};
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Inner {
+///     n: i32,
+/// }
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Outer {
+///     inner: Inner,
+/// }
+///
+/// let docbuf = DocBuf::from_document(&doc! {"inner": {"n": "one"}});
+/// let err = from_doc::<Outer>(&docbuf).unwrap_err();
+/// assert!(matches!(err.kind(), ErrorKind::UnexpectedType));
+/// assert_eq!(err.key_path(), "inner.n");
+/// assert_eq!(err.offset(), Some(18));
+/// ```
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    context: Context,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            context: Context::default(),
+        }
+    }
+
+    /// Record the type of the element involved, unless one is already known.
+    fn of_type(mut self, element_type: ElementType) -> Error {
+        self.context.of_type(element_type);
+        self
+    }
+
+    /// Add context for an error found while deserializing the value of
+    /// element `key`, whose value starts `value_offset` bytes into the
+    /// enclosing document.
+    fn within(mut self, key: &str, value_offset: usize, element_type: ElementType) -> Error {
+        self.context.within(key, value_offset, element_type);
+        self
+    }
+
+    /// Make the offset of an error found inside the scope of a javascript
+    /// with scope value relative to the start of that value.
+    fn in_scope(mut self, element: &Element, scope: &Doc) -> Error {
+        if self.context.offset.is_some() {
+            self.context
+                .shifted(offset_in(element.as_bytes(), scope.as_bytes()));
+        }
+        self
+    }
+
+    /// The kind of problem that was found.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The byte offset where the problem was found, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.context.offset
+    }
+
+    /// The dotted path of keys leading to the element involved, or an empty
+    /// string if the error concerns the top-level document.
+    pub fn key_path(&self) -> String {
+        self.context.key_path()
+    }
+
+    /// The type of the element involved, if known.
+    pub fn element_type(&self) -> Option<ElementType> {
+        self.context.element_type
+    }
 }
 
 impl From<TryFromIntError> for Error {
     fn from(err: TryFromIntError) -> Error {
-        Error::IntConversion(err)
+        Error::new(ErrorKind::IntConversion(err))
     }
 }
 
@@ -41,22 +152,25 @@ impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}{}", self.kind, self.context)
     }
 }
 
 impl de::Error for Error {
     fn custom<T: std::fmt::Display>(err: T) -> Error {
-        Error::Internal(format!("{}", err))
+        Error::new(ErrorKind::Custom(format!("{}", err)))
     }
 }
 
 impl From<RawError> for Error {
-    fn from(val: RawError) -> Error {
-        match val {
-            RawError::Utf8EncodingError(_) => Error::EncodingError,
-            RawError::UnexpectedType => Error::UnexpectedType,
-            RawError::MalformedValue(_) => Error::MalformedDocument,
+    fn from(err: RawError) -> Error {
+        let kind = match err.kind() {
+            crate::ErrorKind::UnexpectedType => ErrorKind::UnexpectedType,
+            kind => ErrorKind::Malformed(kind.clone()),
+        };
+        Error {
+            kind,
+            context: err.context,
         }
     }
 }
@@ -78,6 +192,10 @@ impl<'de> BsonDeserializer<'de> {
     pub fn from_rawbson(bson: Element<'de>) -> Self {
         BsonDeserializer { bson }
     }
+
+    fn unexpected_type(&self) -> Error {
+        Error::new(ErrorKind::UnexpectedType).of_type(self.bson.element_type())
+    }
 }
 
 #[deprecated(since = "0.2.0", note = "use from_doc(&docbuf) instead")]
@@ -104,9 +222,9 @@ where
     from_doc(rawdoc)
 }
 
-pub fn from_bytes<'de, T: 'de>(data: &'de [u8]) -> Result<T, crate::de::Error>
+pub fn from_bytes<'de, T>(data: &'de [u8]) -> Result<T, crate::de::Error>
 where
-    T: Deserialize<'de>,
+    T: Deserialize<'de> + 'de,
 {
     let raw_document = Doc::new(data)?;
     from_doc(raw_document)
@@ -122,7 +240,9 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
             ElementType::EmbeddedDocument => self.deserialize_map(visitor),
             ElementType::Array => self.deserialize_seq(visitor),
             ElementType::Binary => self.deserialize_bytes(visitor),
-            ElementType::Undefined => self.deserialize_unit(visitor),
+            ElementType::Undefined => {
+                self.deserialize_struct(marker::UNDEFINED_NAME, marker::UNDEFINED_FIELDS, visitor)
+            }
             ElementType::ObjectId => {
                 self.deserialize_struct(object_id::NAME, object_id::FIELDS, visitor)
             }
@@ -131,21 +251,31 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
                 self.deserialize_struct(datetime::NAME, datetime::FIELDS, visitor)
             }
             ElementType::Null => self.deserialize_unit(visitor),
-            ElementType::DbPointer => Err(Error::Unimplemented), // deserialize (&str, ObjectId), or struct
+            ElementType::DbPointer => {
+                self.deserialize_struct(db_pointer::NAME, db_pointer::FIELDS, visitor)
+            }
             ElementType::RegularExpression => {
                 self.deserialize_struct(regex::NAME, regex::FIELDS, visitor)
             }
             ElementType::JavaScriptCode => self.deserialize_str(visitor),
-            ElementType::Symbol => self.deserialize_str(visitor),
+            ElementType::Symbol => self.deserialize_struct(symbol::NAME, symbol::FIELDS, visitor),
             ElementType::JavaScriptCodeWithScope => {
                 self.deserialize_struct(js::WITH_SCOPE_NAME, js::WITH_SCOPE_FIELDS, visitor)
             } // deserialize (&'str, Map) or struct
             ElementType::Int32 => self.deserialize_i32(visitor),
-            ElementType::Timestamp => self.deserialize_u64(visitor),
+            ElementType::Timestamp => {
+                self.deserialize_struct(timestamp::NAME, timestamp::FIELDS, visitor)
+            }
             ElementType::Int64 => self.deserialize_i64(visitor),
-            ElementType::MinKey => self.deserialize_unit(visitor),
-            ElementType::MaxKey => self.deserialize_unit(visitor),
-            ElementType::Decimal128 => self.deserialize_i128(visitor),
+            ElementType::MinKey => {
+                self.deserialize_struct(marker::MIN_KEY_NAME, marker::MIN_KEY_FIELDS, visitor)
+            }
+            ElementType::MaxKey => {
+                self.deserialize_struct(marker::MAX_KEY_NAME, marker::MAX_KEY_FIELDS, visitor)
+            }
+            ElementType::Decimal128 => {
+                self.deserialize_struct(decimal128::NAME, decimal128::FIELDS, visitor)
+            }
         }
     }
 
@@ -158,7 +288,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u8(val)
     }
@@ -167,7 +297,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_i8(val)
     }
@@ -177,7 +307,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u16(val)
     }
@@ -185,7 +315,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_i16(val)
     }
@@ -195,7 +325,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u32(val)
     }
@@ -204,7 +334,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_i32(val)
     }
@@ -220,7 +350,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
                 .as_utc_date_time()?
                 .timestamp_millis()
                 .try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u64(val)
     }
@@ -230,7 +360,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
             ElementType::Int32 => self.bson.as_i32()?.into(),
             ElementType::Int64 => self.bson.as_i64()?,
             ElementType::DateTime => self.bson.as_datetime()?.timestamp_millis(),
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_i64(val)
     }
@@ -239,7 +369,8 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int32 => self.bson.as_i32()?.into(),
             ElementType::Int64 => self.bson.as_i64()?.into(),
-            _ => return Err(Error::UnexpectedType),
+            ElementType::Decimal128 => self.bson.as_decimal128()?.try_into()?,
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_i128(val)
     }
@@ -250,7 +381,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
             ElementType::Timestamp => self.bson.as_timestamp()?.into(),
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u128(val)
     }
@@ -260,7 +391,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u8(val)
     }
@@ -270,7 +401,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u16(val)
     }
@@ -280,7 +411,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let val = match self.bson.element_type() {
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u32(val)
     }
@@ -291,17 +422,25 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
             ElementType::Timestamp => self.bson.as_timestamp()?.time() as u64, // TODO: Proper Timestamp handling
             ElementType::Int64 => self.bson.as_i64()?.try_into()?,
             ElementType::Int32 => self.bson.as_i32()?.try_into()?,
-            _ => return Err(Error::UnexpectedType),
+            _ => return Err(self.unexpected_type()),
         };
         visitor.visit_u64(val)
     }
 
     fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        visitor.visit_f64(self.bson.as_f64()?)
+        let val = match self.bson.element_type() {
+            ElementType::Decimal128 => f64::try_from(self.bson.as_decimal128()?)?,
+            _ => self.bson.as_f64()?,
+        };
+        visitor.visit_f64(val)
     }
 
     fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        visitor.visit_f64(self.bson.as_f64()?)
+        let val = match self.bson.element_type() {
+            ElementType::Decimal128 => f64::try_from(self.bson.as_decimal128()?)?,
+            _ => self.bson.as_f64()?,
+        };
+        visitor.visit_f64(val)
     }
 
     fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -309,13 +448,13 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         let mut chars = s.chars();
         let char = match chars.next() {
             Some(char) => char,
-            None => return Err(Error::UnexpectedType),
+            None => return Err(self.unexpected_type()),
         };
         if chars.next().is_none() {
             visitor.visit_char(char)
         } else {
             // Got multiple characters.
-            Err(Error::UnexpectedType)
+            Err(self.unexpected_type())
         }
     }
 
@@ -324,8 +463,8 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
             ElementType::String => visitor.visit_borrowed_str(self.bson.as_str()?),
             ElementType::JavaScriptCode => visitor.visit_borrowed_str(self.bson.as_javascript()?),
             ElementType::Symbol => visitor.visit_borrowed_str(self.bson.as_symbol()?),
-
-            _ => Err(Error::MalformedDocument),
+            ElementType::Decimal128 => visitor.visit_string(self.bson.as_decimal128()?.to_string()),
+            _ => Err(self.unexpected_type()),
         }
     }
 
@@ -335,69 +474,54 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
             ElementType::JavaScriptCode => visitor.visit_str(self.bson.as_javascript()?),
             ElementType::Symbol => visitor.visit_str(self.bson.as_symbol()?),
             ElementType::ObjectId => visitor.visit_str(&self.bson.as_object_id()?.to_hex()),
-            _ => Err(Error::Unimplemented),
+            ElementType::Decimal128 => visitor.visit_string(self.bson.as_decimal128()?.to_string()),
+            _ => Err(Error::new(ErrorKind::Unimplemented)),
         }
     }
 
     fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.bson.element_type() {
             ElementType::String => {
-                let raw_data = self.bson.as_bytes();
-                let len = i32::from_le_bytes(raw_data[0..4].try_into().expect("i32 needs 4 bytes"));
-                assert_eq!(raw_data.len(), len as usize + 4);
-                visitor.visit_borrowed_bytes(&raw_data[4..])
+                visitor.visit_borrowed_bytes(lenencoded_bytes(self.bson.as_bytes())?)
             }
             ElementType::Binary => {
-                let binary = self.bson.as_binary().expect("was not binary");
+                let binary = self.bson.as_binary()?;
                 let deserializer = binary::BinaryDeserializer::new(binary);
                 deserializer.deserialize_bytes(visitor)
             }
             ElementType::Symbol => {
-                let raw_data = self.bson.as_bytes();
-                let len = i32::from_le_bytes(raw_data[0..4].try_into().expect("i32 needs 4 bytes"));
-                assert_eq!(raw_data.len(), len as usize + 4);
-                visitor.visit_borrowed_bytes(&raw_data[4..])
+                visitor.visit_borrowed_bytes(lenencoded_bytes(self.bson.as_bytes())?)
             }
             ElementType::ObjectId => visitor.visit_borrowed_bytes(self.bson.as_bytes()),
-            _ => Err(Error::MalformedDocument),
+            _ => Err(self.unexpected_type()),
         }
     }
 
     fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.bson.element_type() {
-            ElementType::String => {
-                let raw_data = self.bson.as_bytes();
-                let len = i32::from_le_bytes(raw_data[0..4].try_into().expect("i32 needs 4 bytes"));
-                assert_eq!(raw_data.len(), len as usize + 4);
-                visitor.visit_bytes(&raw_data[4..])
-            }
+            ElementType::String => visitor.visit_bytes(lenencoded_bytes(self.bson.as_bytes())?),
             ElementType::Binary => {
                 let binary = self.bson.as_binary()?;
                 let deserializer = binary::BinaryDeserializer::new(binary);
                 deserializer.deserialize_byte_buf(visitor)
             }
-            ElementType::Symbol => {
-                let raw_data = self.bson.as_bytes();
-                let len = i32::from_le_bytes(raw_data[0..4].try_into().expect("i32 needs 4 bytes"));
-                assert_eq!(raw_data.len(), len as usize + 4);
-                visitor.visit_bytes(&raw_data[4..])
-            }
+            ElementType::Symbol => visitor.visit_bytes(lenencoded_bytes(self.bson.as_bytes())?),
             ElementType::ObjectId => visitor.visit_bytes(self.bson.as_bytes()),
-            _ => Err(Error::MalformedDocument),
+            _ => Err(self.unexpected_type()),
         }
     }
 
     fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.bson.element_type() {
-            ElementType::Null => visitor.visit_none(),
+            ElementType::Null | ElementType::Undefined => visitor.visit_none(),
             _ => visitor.visit_some(self),
         }
     }
 
     fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.bson.element_type() {
-            ElementType::Null => visitor.visit_unit(),
-            _ => Err(Error::MalformedDocument),
+            ElementType::Null | ElementType::Undefined => visitor.visit_unit(),
+            _ => Err(self.unexpected_type()),
         }
     }
 
@@ -411,10 +535,22 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
 
     fn deserialize_newtype_struct<V: Visitor<'de>>(
         self,
-        _name: &str,
+        name: &str,
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
-        visitor.visit_newtype_struct(self)
+        if name == raw::DOC_NAME {
+            match self.bson.element_type() {
+                ElementType::EmbeddedDocument => visitor.visit_borrowed_bytes(self.bson.as_bytes()),
+                _ => Err(self.unexpected_type()),
+            }
+        } else if name == raw::ARRAY_NAME {
+            match self.bson.element_type() {
+                ElementType::Array => visitor.visit_borrowed_bytes(self.bson.as_bytes()),
+                _ => Err(self.unexpected_type()),
+            }
+        } else {
+            visitor.visit_newtype_struct(self)
+        }
     }
 
     fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -425,7 +561,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
                 visitor.visit_seq(sequencer)
             }
             ElementType::ObjectId => self.deserialize_byte_buf(visitor),
-            _ => Err::<V::Value, Self::Error>(Error::Unimplemented),
+            _ => Err::<V::Value, Self::Error>(Error::new(ErrorKind::Unimplemented)),
         }
     }
 
@@ -440,10 +576,7 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
                 let mapper = RawObjectIdDeserializer::new(self.bson);
                 visitor.visit_map(mapper)
             }
-            et => {
-                println!("Map of {:?}", et);
-                Err(Error::TmPErroR)
-            }
+            _ => Err(self.unexpected_type()),
         }
     }
 
@@ -455,14 +588,16 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
         match self.bson.element_type() {
             ElementType::Array => self.deserialize_seq(visitor),
             ElementType::JavaScriptCodeWithScope => {
-                js::JavaScriptWithScopeDeserializer::new(self.bson.as_javascript_with_scope()?)
+                let (code, scope) = self.bson.as_javascript_with_scope()?;
+                js::JavaScriptWithScopeDeserializer::new((code, scope))
                     .deserialize_tuple(len, visitor)
+                    .map_err(|err| err.in_scope(&self.bson, scope))
             }
             ElementType::RegularExpression => {
                 regex::RegexDeserializer::new(self.bson.as_regex()?).deserialize_tuple(len, visitor)
             }
 
-            _ => Err(Error::TmPErroR),
+            _ => Err(self.unexpected_type()),
         }
     }
 
@@ -498,17 +633,52 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
                 .map(datetime::DateTimeDeserializer::new)
                 .and_then(|de| de.deserialize_struct(name, fields, visitor))
         } else if name == js::WITH_SCOPE_NAME {
-            self.bson
-                .as_javascript_with_scope()
-                .map_err(Error::from)
-                .map(js::JavaScriptWithScopeDeserializer::new)
-                .and_then(|de| de.deserialize_struct(name, fields, visitor))
+            let (code, scope) = self.bson.as_javascript_with_scope()?;
+            js::JavaScriptWithScopeDeserializer::new((code, scope))
+                .deserialize_struct(name, fields, visitor)
+                .map_err(|err| err.in_scope(&self.bson, scope))
         } else if name == regex::NAME {
             self.bson
                 .as_regex()
                 .map_err(Error::from)
                 .map(regex::RegexDeserializer::new)
                 .and_then(|de| de.deserialize_struct(name, fields, visitor))
+        } else if name == db_pointer::NAME {
+            db_pointer::DbPointerDeserializer::new(self.bson)?
+                .deserialize_struct(name, fields, visitor)
+        } else if name == marker::UNDEFINED_NAME
+            || name == marker::MIN_KEY_NAME
+            || name == marker::MAX_KEY_NAME
+        {
+            match self.bson.element_type() {
+                ElementType::Undefined | ElementType::MinKey | ElementType::MaxKey => {
+                    marker::MarkerDeserializer::new(self.bson.element_type())
+                        .deserialize_struct(name, fields, visitor)
+                }
+                _ => Err(self.unexpected_type()),
+            }
+        } else if name == timestamp::NAME {
+            match self.bson.element_type() {
+                ElementType::Timestamp => {
+                    timestamp::TimestampDeserializer::new(self.bson.as_timestamp()?)
+                        .deserialize_struct(name, fields, visitor)
+                }
+                _ => Err(self.unexpected_type()),
+            }
+        } else if name == symbol::NAME {
+            match self.bson.element_type() {
+                ElementType::Symbol => symbol::SymbolDeserializer::new(self.bson.as_symbol()?)
+                    .deserialize_struct(name, fields, visitor),
+                _ => Err(self.unexpected_type()),
+            }
+        } else if name == decimal128::NAME {
+            match self.bson.element_type() {
+                ElementType::Decimal128 => {
+                    decimal128::Decimal128Deserializer::new(self.bson.as_decimal128()?)
+                        .deserialize_struct(name, fields, visitor)
+                }
+                _ => self.deserialize_any(visitor),
+            }
         } else {
             self.deserialize_map(visitor)
         }
@@ -517,10 +687,34 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
     fn deserialize_enum<V: Visitor<'de>>(
         self,
         _name: &str,
-        _fields: &[&str],
-        _visitor: V,
+        _variants: &[&str],
+        visitor: V,
     ) -> Result<V::Value, Self::Error> {
-        Err(Error::Unimplemented)
+        match self.bson.element_type() {
+            ElementType::String => visitor.visit_enum(BsonEnumAccess {
+                variant: self.bson.as_str()?,
+                value: None,
+            }),
+            ElementType::EmbeddedDocument => {
+                let doc = self.bson.as_document()?;
+                let mut iter = doc.into_iter();
+                let (variant, value) = match iter.next() {
+                    Some(Ok(entry)) => entry,
+                    Some(Err(err)) => return Err(err.into()),
+                    None => return Err(self.unexpected_type()),
+                };
+                match iter.next() {
+                    None => {}
+                    Some(Err(err)) => return Err(err.into()),
+                    Some(Ok(_)) => return Err(self.unexpected_type()),
+                }
+                visitor.visit_enum(BsonEnumAccess {
+                    variant,
+                    value: Some((doc, value)),
+                })
+            }
+            _ => Err(self.unexpected_type()),
+        }
     }
 
     fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -532,6 +726,16 @@ impl<'a, 'de: 'a> Deserializer<'de> for &'a mut BsonDeserializer<'de> {
     }
 }
 
+/// Return the bytes of a length-prefixed string value, after the prefix.
+fn lenencoded_bytes(raw_data: &[u8]) -> Result<&[u8], Error> {
+    match raw_data.get(..4) {
+        Some(len) if crate::i32_from_slice(len) as i64 + 4 == raw_data.len() as i64 => {
+            Ok(&raw_data[4..])
+        }
+        _ => Err(RawError::new(crate::ErrorKind::BadLength).at(0).into()),
+    }
+}
+
 struct BsonArraySequencer<'de> {
     arr_iter: ArrayIter<'de>,
 }
@@ -549,10 +753,17 @@ impl<'de> SeqAccess<'de> for BsonArraySequencer<'de> {
     where
         E: DeserializeSeed<'de>,
     {
+        let index = self.arr_iter.index;
         match self.arr_iter.next() {
             Some(Ok(bson)) => {
                 let mut deserializer = BsonDeserializer::from_rawbson(bson);
-                seed.deserialize(&mut deserializer).map(Some)
+                seed.deserialize(&mut deserializer)
+                    .map(Some)
+                    .map_err(|err| {
+                        let offset =
+                            offset_in(self.arr_iter.dociter.doc.as_bytes(), bson.as_bytes());
+                        err.within(&index.to_string(), offset, bson.element_type())
+                    })
             }
             Some(Err(err)) => Err(err.into()),
             None => Ok(None),
@@ -562,7 +773,7 @@ impl<'de> SeqAccess<'de> for BsonArraySequencer<'de> {
 
 struct BsonDocumentMap<'de> {
     doc_iter: DocIter<'de>,
-    next: Option<Element<'de>>,
+    next: Option<(&'de str, Element<'de>)>,
 }
 
 impl<'de> BsonDocumentMap<'de> {
@@ -583,7 +794,7 @@ impl<'de> MapAccess<'de> for BsonDocumentMap<'de> {
     {
         match self.doc_iter.next() {
             Some(Ok((key, value))) => {
-                self.next = Some(value);
+                self.next = Some((key, value));
                 let deserializer = StrDeserializer::new(key);
                 Ok(Some(seed.deserialize(deserializer)?))
             }
@@ -596,9 +807,83 @@ impl<'de> MapAccess<'de> for BsonDocumentMap<'de> {
     where
         V: DeserializeSeed<'de>,
     {
-        let bson = self.next.take().ok_or(Error::Eof)?;
+        let (key, bson) = self
+            .next
+            .take()
+            .ok_or_else(|| Error::new(ErrorKind::Custom("value requested before key".into())))?;
         let mut deserializer = BsonDeserializer::from_rawbson(bson);
-        seed.deserialize(&mut deserializer)
+        seed.deserialize(&mut deserializer).map_err(|err| {
+            let offset = offset_in(self.doc_iter.doc.as_bytes(), bson.as_bytes());
+            err.within(key, offset, bson.element_type())
+        })
+    }
+}
+
+/// Reads an externally tagged enum: either a string naming a unit variant,
+/// or a document whose only key names the variant and whose value holds its
+/// contents.
+struct BsonEnumAccess<'de> {
+    variant: &'de str,
+    value: Option<(&'de Doc, Element<'de>)>,
+}
+
+impl<'de> BsonEnumAccess<'de> {
+    /// Deserialize the variant's contents with `f`, reporting errors under
+    /// the variant's key.
+    fn with_value<T>(
+        self,
+        f: impl FnOnce(&mut BsonDeserializer<'de>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let (doc, bson) = self
+            .value
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedType).of_type(ElementType::String))?;
+        f(&mut BsonDeserializer::from_rawbson(bson)).map_err(|err| {
+            let offset = offset_in(doc.as_bytes(), bson.as_bytes());
+            err.within(self.variant, offset, bson.element_type())
+        })
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for BsonEnumAccess<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, Self), Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(StrDeserializer::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for BsonEnumAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            None => Ok(()),
+            Some(_) => self.with_value(|de| de.deserialize_unit(de::IgnoredAny).map(|_| ())),
+        }
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value, Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        self.with_value(|de| seed.deserialize(de))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.with_value(|de| de.deserialize_tuple(len, visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.with_value(|de| de.deserialize_map(visitor))
     }
 }
 
@@ -872,7 +1157,7 @@ mod tests {
         assert_eq!(p.last_name, "Teach");
         assert_eq!(p.id.to_hex(), "abcdefabcdefabcdefabcdef");
         assert_eq!(p.number, &[8, 6, 7, 5, 3, 0, 9]);
-        assert_eq!(p.has_cookies, false);
+        assert!(!p.has_cookies);
         assert_eq!(
             p.gid,
             uuid::Uuid::new(b"12345678901234567890123456789012".to_vec())
@@ -916,6 +1201,20 @@ mod tests {
         from_bytes::<Person>(&docbytes).expect_err("Should have failed to decode gid field");
     }
 
+    #[test]
+    fn errors_report_key_paths() {
+        let docbuf = DocBuf::from_document(&doc! {"list": [1, "two", 3]});
+        let err = from_doc::<HashMap<String, Vec<i32>>>(&docbuf).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::UnexpectedType));
+        assert_eq!(err.key_path(), "list.1");
+        assert_eq!(err.offset(), Some(24));
+        assert_eq!(err.element_type(), Some(bson::spec::ElementType::String));
+
+        let err = from_doc::<Person>(&docbuf).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::Custom(_)));
+        assert_eq!(err.key_path(), "");
+    }
+
     #[test]
     fn deserialize_map() {
         let mut docbytes = Vec::new();
@@ -952,7 +1251,7 @@ mod tests {
             from_bytes(&docbytes).expect("could not decode into HashMap<String, Vec<Bson>");
         assert_eq!(map.len(), 1);
         let arr = map.get("array").expect("key not found");
-        assert_eq!(arr.get(0).expect("no index 0"), &Bson::Int32(1));
+        assert_eq!(arr.first().expect("no index 0"), &Bson::Int32(1));
         assert_eq!(arr.get(1).expect("no index 1"), &Bson::Int64(2));
         assert_eq!(arr.get(2).expect("no index 2"), &Bson::Int32(3));
         assert_eq!(arr.get(3).expect("no index 3"), &Bson::String("abc".into()));
@@ -1114,6 +1413,7 @@ mod tests {
 
         // From mongodb::operation::WriteResponseBody
         #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
         struct WriteResponseBody<T = Option<()>> {
             #[serde(flatten)]
             body: T,
@@ -1139,7 +1439,338 @@ mod tests {
             32, 115, 116, 114, 105, 110, 103, 0, 0, 0, 0,
         ];
         let doc = Doc::new(&bytes)?;
-        let wrb: WriteResponseBody = from_doc(doc)?;
+        let _wrb: WriteResponseBody = from_doc(doc)?;
         Ok(())
     }
+
+    #[derive(Debug, Deserialize)]
+    struct Envelope<'a> {
+        kind: &'a str,
+        payload: &'a Doc,
+        items: &'a crate::Array,
+        owned: DocBuf,
+    }
+
+    #[test]
+    fn deserialize_raw_fields() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "kind": "event",
+            "payload": {"a": 1, "b": {"c": "deep"}},
+            "items": [1, "two"],
+            "owned": {"x": true},
+        });
+        let envelope: Envelope = from_doc(&docbuf).unwrap();
+        assert_eq!(envelope.kind, "event");
+        assert_eq!(envelope.payload.get_i32("a"), Ok(Some(1)));
+        assert_eq!(envelope.items.get_str(1), Ok(Some("two")));
+        assert_eq!(envelope.owned.get_bool("x"), Ok(Some(true)));
+
+        // the nested bytes are borrowed from the input, not copied
+        let payload = docbuf.get_document("payload").unwrap().unwrap();
+        assert_eq!(
+            envelope.payload.as_bytes().as_ptr(),
+            payload.as_bytes().as_ptr()
+        );
+        assert_eq!(envelope.payload.as_bytes(), payload.as_bytes());
+
+        let whole: &Doc = from_doc(&docbuf).unwrap();
+        assert_eq!(whole.as_bytes(), docbuf.as_bytes());
+    }
+
+    #[test]
+    fn deserialize_raw_fields_checks_types() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "kind": "event",
+            "payload": [1, 2],
+            "items": [],
+            "owned": {},
+        });
+        let err = from_doc::<Envelope>(&docbuf).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::UnexpectedType));
+        assert_eq!(err.key_path(), "payload");
+    }
+
+    #[test]
+    fn deserialize_docbuf_from_other_formats() {
+        use std::convert::TryInto;
+
+        let docbuf: DocBuf = serde_json::from_str(r#"{"a": 1, "b": ["c"]}"#).unwrap();
+        let document: bson::Document = docbuf.try_into().unwrap();
+        assert_eq!(document, doc! {"a": 1, "b": ["c"]});
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, serde::Serialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Point(i32, i32),
+        Rect { width: i32, height: i32 },
+    }
+
+    #[test]
+    fn deserialize_external_enums() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "shapes": [
+                "Empty",
+                {"Circle": 1.5},
+                {"Point": [3, 4]},
+                {"Rect": {"width": 5, "height": 6}},
+            ],
+        });
+        let shapes: HashMap<String, Vec<Shape>> = from_doc(&docbuf).unwrap();
+        let expected = vec![
+            Shape::Empty,
+            Shape::Circle(1.5),
+            Shape::Point(3, 4),
+            Shape::Rect {
+                width: 5,
+                height: 6,
+            },
+        ];
+        assert_eq!(shapes["shapes"], expected);
+
+        let mut roundtrip = HashMap::new();
+        roundtrip.insert("shapes", &expected);
+        let serialized = crate::ser::to_docbuf(&roundtrip).unwrap();
+        assert_eq!(serialized.as_bytes(), docbuf.as_bytes());
+    }
+
+    #[test]
+    fn deserialize_enum_errors() {
+        #[derive(Debug, Deserialize)]
+        struct Holder {
+            #[allow(dead_code)]
+            shape: Shape,
+        }
+
+        let two_keys = DocBuf::from_document(&doc! {"shape": {"Circle": 1.0, "Empty": null}});
+        let err = from_doc::<Holder>(&two_keys).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::UnexpectedType));
+        assert_eq!(err.key_path(), "shape");
+
+        let bad_value = DocBuf::from_document(&doc! {"shape": {"Rect": {"width": "wide"}}});
+        let err = from_doc::<Holder>(&bad_value).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::UnexpectedType));
+        assert_eq!(err.key_path(), "shape.Rect.width");
+
+        let unknown = DocBuf::from_document(&doc! {"shape": "Hexagon"});
+        let err = from_doc::<Holder>(&unknown).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::Custom(_)));
+        assert_eq!(err.key_path(), "shape");
+    }
+
+    #[test]
+    fn deserialize_tagged_enums() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "type")]
+        enum Internal<'a> {
+            Ping,
+            Message { text: &'a str, count: i64 },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Unit,
+            Number(i32),
+            Pair(String, bool),
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(untagged)]
+        enum Untagged {
+            Number(i32),
+            Text(String),
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct All<'a> {
+            #[serde(borrow)]
+            internal: Vec<Internal<'a>>,
+            adjacent: Vec<Adjacent>,
+            untagged: Vec<Untagged>,
+        }
+
+        let docbuf = DocBuf::from_document(&doc! {
+            "internal": [
+                {"type": "Ping"},
+                {"type": "Message", "text": "hi", "count": 2i64},
+            ],
+            "adjacent": [
+                {"t": "Unit"},
+                {"t": "Number", "c": 7},
+                {"t": "Pair", "c": ["x", true]},
+            ],
+            "untagged": [1, "one"],
+        });
+        let all: All = from_doc(&docbuf).unwrap();
+        assert_eq!(
+            all,
+            All {
+                internal: vec![
+                    Internal::Ping,
+                    Internal::Message {
+                        text: "hi",
+                        count: 2
+                    }
+                ],
+                adjacent: vec![
+                    Adjacent::Unit,
+                    Adjacent::Number(7),
+                    Adjacent::Pair(String::from("x"), true)
+                ],
+                untagged: vec![Untagged::Number(1), Untagged::Text(String::from("one"))],
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_decimal128() {
+        use crate::{elem::RawDecimal128, DocBufBuilder};
+
+        #[derive(Debug, Deserialize, serde::Serialize)]
+        struct Prices {
+            price: RawDecimal128,
+            text: RawDecimal128,
+            count: i128,
+            ratio: f64,
+            scale: f32,
+            label: String,
+        }
+
+        let price: RawDecimal128 = "12.50".parse().unwrap();
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_decimal128("price", price)
+            .append_str("text", "-1E+3")
+            .append_decimal128("count", RawDecimal128::from(3i64))
+            .append_decimal128("ratio", "0.25".parse().unwrap())
+            .append_decimal128("scale", "-1.5".parse().unwrap())
+            .append_decimal128("label", "1.0E-8".parse().unwrap());
+        let docbuf = builder.finish().unwrap();
+
+        let prices: Prices = from_doc(&docbuf).unwrap();
+        assert_eq!(prices.price, price);
+        assert_eq!(prices.text.to_string(), "-1E+3");
+        assert_eq!(prices.count, 3);
+        assert_eq!(prices.ratio, 0.25);
+        assert_eq!(prices.scale, -1.5);
+        assert_eq!(prices.label, "1.0E-8");
+
+        let document: bson::Document = from_doc(&docbuf).unwrap();
+        assert_eq!(document.get("price"), Some(&Bson::Decimal128(price.into())));
+
+        let json = serde_json::to_value(&docbuf).unwrap();
+        assert_eq!(
+            json["price"],
+            serde_json::json!({"$numberDecimal": "12.50"})
+        );
+        let from_json: RawDecimal128 = serde_json::from_value(json["price"].clone()).unwrap();
+        assert_eq!(from_json, price);
+
+        let mut prices = prices;
+        prices.text = RawDecimal128::from(7u8);
+        let serialized = crate::ser::to_docbuf(&prices).unwrap();
+        assert_eq!(
+            serialized.get("price").unwrap().unwrap().as_decimal128(),
+            Ok(price)
+        );
+        assert_eq!(
+            serialized.get("text").unwrap().unwrap().as_decimal128(),
+            Ok(RawDecimal128::from(7u8))
+        );
+
+        let mut builder = DocBufBuilder::new();
+        builder.append_decimal128("ratio", "0.1000000000000000000001".parse().unwrap());
+        let err = from_doc::<HashMap<String, f64>>(&builder.finish().unwrap()).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            super::ErrorKind::Malformed(crate::ErrorKind::OutOfRange)
+        ));
+        assert_eq!(err.key_path(), "ratio");
+    }
+
+    #[test]
+    fn deserialize_legacy_types() {
+        use crate::{elem::RawDbPointer, DocBufBuilder};
+
+        #[derive(Debug, Deserialize, serde::Serialize)]
+        struct Archived<'a> {
+            #[serde(borrow)]
+            pointer: RawDbPointer<'a>,
+            undefined: Option<i32>,
+            nothing: (),
+            min: Bson,
+            max: Bson,
+        }
+
+        let id = ObjectId::with_string("abcdefabcdefabcdefabcdef").unwrap();
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_db_pointer("pointer", "db.coll", id.clone())
+            .append_undefined("undefined")
+            .append_undefined("nothing")
+            .append_min_key("min")
+            .append_max_key("max");
+        let docbuf = builder.finish().unwrap();
+
+        let archived: Archived = from_doc(&docbuf).unwrap();
+        assert_eq!(archived.pointer, RawDbPointer::new("db.coll", id.clone()));
+        assert_eq!(archived.undefined, None);
+        assert_eq!(archived.min, Bson::MinKey);
+        assert_eq!(archived.max, Bson::MaxKey);
+
+        let document: bson::Document = from_doc(&docbuf).unwrap();
+        assert_eq!(
+            document,
+            bson::Document::from_reader(&mut docbuf.as_bytes()).unwrap()
+        );
+        assert_eq!(document.get("undefined"), Some(&Bson::Undefined));
+        assert!(document.get("pointer").unwrap().as_db_pointer().is_some());
+
+        let json = serde_json::to_value(&docbuf).unwrap();
+        assert_eq!(
+            json["pointer"],
+            serde_json::json!({"$dbPointer": {"$ref": "db.coll", "$id": {"$oid": id.to_hex()}}})
+        );
+        let json = json["pointer"].to_string();
+        let from_json: RawDbPointer = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, archived.pointer);
+
+        let serialized = crate::ser::to_docbuf(&archived).unwrap();
+        let pointer = serialized.get("pointer").unwrap().unwrap();
+        assert_eq!(
+            pointer.as_bytes(),
+            docbuf.get("pointer").unwrap().unwrap().as_bytes()
+        );
+
+        let docbuf = crate::DocBuf::from_document(&doc! {"pointer": 1});
+        let err = from_doc::<Archived>(&docbuf).unwrap_err();
+        assert!(matches!(err.kind(), super::ErrorKind::UnexpectedType));
+        assert_eq!(err.key_path(), "pointer");
+    }
+
+    #[test]
+    fn deserialize_timestamp_and_symbol() {
+        #[derive(Debug, Deserialize)]
+        struct Oplog {
+            ts: Bson,
+            symbol: String,
+        }
+
+        let docbuf = crate::DocBuf::from_document(&doc! {
+            "ts": bson::Timestamp { time: 7, increment: 3 },
+            "symbol": Bson::Symbol(String::from("sym")),
+        });
+        let oplog: Oplog = from_doc(&docbuf).unwrap();
+        assert_eq!(oplog.ts, Bson::Timestamp(bson::Timestamp { time: 7, increment: 3 }));
+        assert_eq!(oplog.symbol, "sym");
+
+        let document: bson::Document = from_doc(&docbuf).unwrap();
+        assert_eq!(
+            document,
+            bson::Document::from_reader(&mut docbuf.as_bytes()).unwrap()
+        );
+        assert_eq!(crate::ser::to_docbuf(&docbuf).unwrap().as_bytes(), docbuf.as_bytes());
+    }
 }
@@ -1,7 +1,7 @@
 use serde::de::{DeserializeSeed, Deserializer, MapAccess, Visitor};
 use serde::forward_to_deserialize_any;
 
-use super::Error;
+use super::{Error, ErrorKind};
 use crate::elem::RawBsonBinary;
 use bson::spec::BinarySubtype;
 
@@ -60,7 +60,7 @@ impl<'de> Deserializer<'de> for BinaryDeserializer<'de> {
         if name == NAME {
             visitor.visit_map(self)
         } else {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         }
     }
 
@@ -100,7 +100,7 @@ impl<'de> MapAccess<'de> for BinaryDeserializer<'de> {
                 self.visiting = Visiting::Done;
                 Ok(None)
             }
-            _ => Err(Error::MalformedDocument),
+            _ => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
         }
     }
 
@@ -113,7 +113,7 @@ impl<'de> MapAccess<'de> for BinaryDeserializer<'de> {
                 seed.deserialize(BinarySubtypeDeserializer::new(self.binary.subtype()))
             }
             Visiting::Data => seed.deserialize(BinaryDataDeserializer::new(self.binary)),
-            _ => Err(Error::MalformedDocument),
+            _ => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
         }
     }
 }
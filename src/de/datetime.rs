@@ -3,7 +3,7 @@ use std::convert::TryInto;
 use serde::de::{DeserializeSeed, Deserializer, MapAccess, Visitor};
 use serde::forward_to_deserialize_any;
 
-use super::Error;
+use super::{Error, ErrorKind};
 
 pub static NAME: &str = "$__bson_DateTime";
 pub static FIELD: &str = "$date";
@@ -87,7 +87,7 @@ impl<'de> Deserializer<'de> for DateTimeDeserializer {
         if name == NAME {
             visitor.visit_map(self)
         } else {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         }
     }
 
@@ -122,7 +122,7 @@ impl<'de> MapAccess<'de> for DateTimeDeserializer {
                 self.visited = true;
                 seed.deserialize(DateTimeFieldDeserializer::new(self.data))
             }
-            true => Err(Error::MalformedDocument),
+            true => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
         }
     }
 }
@@ -131,7 +131,7 @@ struct DateTimeFieldDeserializer {
     data: i64,
 }
 
-impl<'de> DateTimeFieldDeserializer {
+impl DateTimeFieldDeserializer {
     fn new(data: i64) -> DateTimeFieldDeserializer {
         DateTimeFieldDeserializer { data }
     }
@@ -0,0 +1,221 @@
+// DBPointer handling
+
+use std::fmt;
+
+use serde::de::{Deserialize, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use bson::{oid::ObjectId, spec::ElementType};
+
+use super::object_id::RawObjectIdDeserializer;
+use super::{Error, ErrorKind, StrDeserializer};
+use crate::elem::{Element, RawDbPointer};
+
+pub static NAME: &str = "$__bson_DbPointer";
+pub static FIELD: &str = "$dbPointer";
+pub static FIELDS: &[&str] = &[FIELD];
+pub static REF_FIELD: &str = "$ref";
+pub static ID_FIELD: &str = "$id";
+static BODY_FIELDS: &[&str] = &[REF_FIELD, ID_FIELD];
+
+/// Deserializes a DBPointer value as a struct with a single `$dbPointer`
+/// field, which holds a map of the namespace (`$ref`) and the object id
+/// (`$id`), matching its extended JSON form.
+pub struct DbPointerDeserializer<'de> {
+    namespace: &'de str,
+    id: Element<'de>,
+    visited: bool,
+}
+
+impl<'de> DbPointerDeserializer<'de> {
+    /// Create a deserializer for the DBPointer element `bson`.
+    pub fn new(bson: Element<'de>) -> Result<DbPointerDeserializer<'de>, Error> {
+        let namespace = bson.as_db_pointer()?.namespace();
+        let data = bson.as_bytes();
+        Ok(DbPointerDeserializer {
+            namespace,
+            id: Element::new(ElementType::ObjectId, &data[data.len() - 12..]),
+            visited: false,
+        })
+    }
+}
+
+impl<'de> Deserializer<'de> for DbPointerDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_struct(NAME, FIELDS, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &str,
+        _fields: &[&str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == NAME {
+            visitor.visit_map(self)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedType))
+        }
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string bytes byte_buf
+        option unit newtype_struct tuple
+        ignored_any seq unit_struct tuple_struct enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for DbPointerDeserializer<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => seed.deserialize(FIELD.into_deserializer()).map(Some),
+            true => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => {
+                self.visited = true;
+                seed.deserialize(DbPointerBodyDeserializer {
+                    namespace: self.namespace,
+                    id: self.id,
+                    field: 0,
+                })
+            }
+            true => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
+
+/// The `{"$ref": ..., "$id": ...}` map inside the `$dbPointer` field.
+struct DbPointerBodyDeserializer<'de> {
+    namespace: &'de str,
+    id: Element<'de>,
+    field: usize,
+}
+
+impl<'de> Deserializer<'de> for DbPointerBodyDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string seq
+        bytes byte_buf map struct option unit newtype_struct
+        ignored_any unit_struct tuple_struct tuple enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for DbPointerBodyDeserializer<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.field {
+            0 => seed.deserialize(REF_FIELD.into_deserializer()).map(Some),
+            1 => seed.deserialize(ID_FIELD.into_deserializer()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.field += 1;
+        match self.field {
+            1 => seed.deserialize(StrDeserializer::new(self.namespace)),
+            2 => seed.deserialize(RawObjectIdDeserializer::new(self.id)),
+            _ => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
+
+struct RawDbPointerVisitor;
+
+impl<'de> Visitor<'de> for RawDbPointerVisitor {
+    type Value = RawDbPointer<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a dbpointer value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawDbPointer<'de>, A::Error> {
+        match map.next_key::<String>()? {
+            Some(key) if key == FIELD => {}
+            _ => return Err(serde::de::Error::missing_field(FIELD)),
+        }
+        let Body(pointer) = map.next_value()?;
+        Ok(pointer)
+    }
+}
+
+struct BodyVisitor;
+
+impl<'de> Visitor<'de> for BodyVisitor {
+    type Value = RawDbPointer<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map with $ref and $id fields")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawDbPointer<'de>, A::Error> {
+        let mut namespace = None;
+        let mut id = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == REF_FIELD {
+                namespace = Some(map.next_value::<&'de str>()?);
+            } else if key == ID_FIELD {
+                id = Some(map.next_value::<ObjectId>()?);
+            } else {
+                return Err(serde::de::Error::unknown_field(&key, BODY_FIELDS));
+            }
+        }
+        let namespace = namespace.ok_or_else(|| serde::de::Error::missing_field(REF_FIELD))?;
+        let id = id.ok_or_else(|| serde::de::Error::missing_field(ID_FIELD))?;
+        Ok(RawDbPointer::new(namespace, id))
+    }
+}
+
+/// The value of the `$dbPointer` field.
+struct Body<'de>(RawDbPointer<'de>);
+
+impl<'de> Deserialize<'de> for Body<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(BodyVisitor).map(Body)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawDbPointer<'a> {
+    /// Accepts a DBPointer element, or its extended JSON form
+    /// `{"$dbPointer": {"$ref": "...", "$id": {"$oid": "..."}}}` with a
+    /// borrowed namespace.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct(NAME, FIELDS, RawDbPointerVisitor)
+    }
+}
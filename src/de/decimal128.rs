@@ -0,0 +1,228 @@
+// Decimal128 handling
+
+use std::convert::TryInto;
+use std::fmt;
+
+use serde::de::{Deserialize, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{Error, ErrorKind};
+use crate::elem::RawDecimal128;
+
+pub static NAME: &str = "$__bson_Decimal128";
+pub static FIELD: &str = "$numberDecimal";
+pub static FIELDS: &[&str] = &[FIELD];
+
+struct Decimal128KeyDeserializer;
+
+impl<'de> Deserializer<'de> for Decimal128KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_str(FIELD)
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string seq
+        bytes byte_buf map struct option unit newtype_struct
+        ignored_any unit_struct tuple_struct tuple enum identifier
+    );
+}
+
+/// Deserializes a decimal128 value as a struct with a single field, holding
+/// either the formatted string or, when bytes are requested, the sixteen
+/// byte encoding.
+pub struct Decimal128Deserializer {
+    data: RawDecimal128,
+    visited: bool,
+}
+
+impl Decimal128Deserializer {
+    pub fn new(data: RawDecimal128) -> Decimal128Deserializer {
+        Decimal128Deserializer {
+            data,
+            visited: false,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for Decimal128Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_struct(NAME, FIELDS, visitor)
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.data.to_string())
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.data.to_string())
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &str,
+        _fields: &[&str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == NAME {
+            visitor.visit_map(self)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedType))
+        }
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char bytes byte_buf
+        option unit newtype_struct tuple
+        ignored_any seq unit_struct tuple_struct enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for Decimal128Deserializer {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => seed.deserialize(Decimal128KeyDeserializer).map(Some),
+            true => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => {
+                self.visited = true;
+                seed.deserialize(Decimal128FieldDeserializer::new(self.data))
+            }
+            true => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
+
+struct Decimal128FieldDeserializer {
+    data: RawDecimal128,
+}
+
+impl Decimal128FieldDeserializer {
+    fn new(data: RawDecimal128) -> Decimal128FieldDeserializer {
+        Decimal128FieldDeserializer { data }
+    }
+}
+
+impl<'de> Deserializer<'de> for Decimal128FieldDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.data.to_string())
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bytes(&self.data.bytes())
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bytes(&self.data.bytes())
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char seq
+        str string map struct option unit newtype_struct
+        ignored_any unit_struct tuple_struct tuple enum identifier
+    );
+}
+
+struct RawDecimal128Visitor;
+
+impl<'de> Visitor<'de> for RawDecimal128Visitor {
+    type Value = RawDecimal128;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a decimal128 value")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<RawDecimal128, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<RawDecimal128, E> {
+        let bytes = value
+            .try_into()
+            .map_err(|_| E::invalid_length(value.len(), &"sixteen bytes"))?;
+        Ok(RawDecimal128::from_bytes(bytes))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<RawDecimal128, E> {
+        Ok(value.into())
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<RawDecimal128, E> {
+        Ok(value.into())
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<RawDecimal128, E> {
+        Ok(value.into())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawDecimal128, A::Error> {
+        match map.next_key::<String>()? {
+            Some(key) if key == FIELD => {}
+            _ => return Err(serde::de::Error::missing_field(FIELD)),
+        }
+        let FieldValue(value) = map.next_value()?;
+        Ok(value)
+    }
+}
+
+/// The value of the `$numberDecimal` field, as a string or as bytes.
+struct FieldValue(RawDecimal128);
+
+impl<'de> Deserialize<'de> for FieldValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_any(RawDecimal128Visitor)
+            .map(FieldValue)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawDecimal128 {
+    /// Accepts a decimal128 element, a string in decimal128 syntax, an
+    /// integer or float, or an extended JSON `{"$numberDecimal": "..."}`
+    /// document.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RawDecimal128Visitor)
+    }
+}
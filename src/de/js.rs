@@ -1,7 +1,7 @@
 use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::forward_to_deserialize_any;
 
-use super::Error;
+use super::{Error, ErrorKind};
 use crate::{de::BsonDeserializer, Doc};
 
 pub static NAME: &str = "$__bson_JavaScript";
@@ -92,7 +92,7 @@ impl<'de> Deserializer<'de> for JavaScriptWithScopeDeserializer<'de> {
         V: Visitor<'de>,
     {
         if ct != 2 {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         } else {
             visitor.visit_seq(self)
         }
@@ -111,7 +111,7 @@ impl<'de> Deserializer<'de> for JavaScriptWithScopeDeserializer<'de> {
         if name == NAME {
             visitor.visit_map(self)
         } else {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         }
     }
 
@@ -143,7 +143,7 @@ impl<'de> SeqAccess<'de> for JavaScriptWithScopeDeserializer<'de> {
             }
             ScopedVisiting::Scope => {
                 self.visiting = ScopedVisiting::Done;
-                seed.deserialize(&mut BsonDeserializer::from_doc(&self.scope))
+                seed.deserialize(&mut BsonDeserializer::from_doc(self.scope))
                     .map(Some)
             }
             ScopedVisiting::Done => Ok(None),
@@ -182,7 +182,7 @@ impl<'de> MapAccess<'de> for JavaScriptWithScopeDeserializer<'de> {
                 self.visiting = ScopedVisiting::Done;
                 seed.deserialize(&mut BsonDeserializer::from_doc(self.scope))
             }
-            ScopedVisiting::Done => Err(Error::MalformedDocument),
+            ScopedVisiting::Done => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
         }
     }
 }
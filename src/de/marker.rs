@@ -0,0 +1,111 @@
+// Undefined, MinKey and MaxKey handling
+
+use serde::de::{DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{Error, ErrorKind};
+use bson::spec::ElementType;
+
+pub static UNDEFINED_NAME: &str = "$__bson_Undefined";
+pub static UNDEFINED_FIELD: &str = "$undefined";
+pub static UNDEFINED_FIELDS: &[&str] = &[UNDEFINED_FIELD];
+pub static MIN_KEY_NAME: &str = "$__bson_MinKey";
+pub static MIN_KEY_FIELD: &str = "$minKey";
+pub static MIN_KEY_FIELDS: &[&str] = &[MIN_KEY_FIELD];
+pub static MAX_KEY_NAME: &str = "$__bson_MaxKey";
+pub static MAX_KEY_FIELD: &str = "$maxKey";
+pub static MAX_KEY_FIELDS: &[&str] = &[MAX_KEY_FIELD];
+
+/// Deserializes one of the valueless BSON types as a struct with a single
+/// field, matching its extended JSON form: `{"$undefined": true}`,
+/// `{"$minKey": 1}` or `{"$maxKey": 1}`.
+pub struct MarkerDeserializer {
+    element_type: ElementType,
+    visited: bool,
+}
+
+impl MarkerDeserializer {
+    /// Create a deserializer for an Undefined, MinKey or MaxKey value.
+    pub fn new(element_type: ElementType) -> MarkerDeserializer {
+        MarkerDeserializer {
+            element_type,
+            visited: false,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.element_type {
+            ElementType::Undefined => UNDEFINED_NAME,
+            ElementType::MinKey => MIN_KEY_NAME,
+            _ => MAX_KEY_NAME,
+        }
+    }
+
+    fn field(&self) -> &'static str {
+        match self.element_type {
+            ElementType::Undefined => UNDEFINED_FIELD,
+            ElementType::MinKey => MIN_KEY_FIELD,
+            _ => MAX_KEY_FIELD,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for MarkerDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &str,
+        _fields: &[&str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == self.name() {
+            visitor.visit_map(self)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedType))
+        }
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string bytes byte_buf
+        map option unit newtype_struct tuple
+        ignored_any seq unit_struct tuple_struct enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for MarkerDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => seed.deserialize(self.field().into_deserializer()).map(Some),
+            true => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => {
+                self.visited = true;
+                match self.element_type {
+                    ElementType::Undefined => seed.deserialize(true.into_deserializer()),
+                    _ => seed.deserialize(1i32.into_deserializer()),
+                }
+            }
+            true => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
@@ -3,7 +3,7 @@
 use serde::de::{DeserializeSeed, Deserializer, MapAccess, Visitor};
 use serde::forward_to_deserialize_any;
 
-use super::Error;
+use super::{Error, ErrorKind};
 use crate::elem::Element;
 use bson::spec::ElementType;
 
@@ -31,14 +31,14 @@ impl<'de> Deserializer<'de> for RawObjectIdDeserializer<'de> {
     fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.bson.element_type() {
             ElementType::ObjectId => self.deserialize_struct(NAME, FIELDS, visitor),
-            _ => Err(Error::MalformedDocument),
+            _ => Err(Error::new(ErrorKind::UnexpectedType)),
         }
     }
 
     fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.bson.element_type() {
             ElementType::ObjectId => visitor.visit_bytes(self.bson.as_bytes()),
-            _ => Err(Error::MalformedDocument),
+            _ => Err(Error::new(ErrorKind::UnexpectedType)),
         }
     }
 
@@ -55,7 +55,7 @@ impl<'de> Deserializer<'de> for RawObjectIdDeserializer<'de> {
         if name == NAME && fields == FIELDS {
             visitor.visit_map(self)
         } else {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         }
     }
 
@@ -134,7 +134,7 @@ impl<'de> Deserializer<'de> for ObjectIdValueDeserializer<'de> {
                 let hex = self.0.as_object_id()?.to_hex();
                 visitor.visit_string(hex)
             }
-            _ => Err(Error::MalformedDocument),
+            _ => Err(Error::new(ErrorKind::UnexpectedType)),
         }
     }
 
@@ -0,0 +1,82 @@
+// Borrowed raw document handling
+
+use std::fmt;
+
+use serde::de::{Deserialize, Deserializer, Visitor};
+
+use crate::{Array, Doc, DocBuf};
+
+/// Newtype name under which [`Doc`] and [`DocBuf`] ask for the raw bytes of
+/// an embedded document.
+pub static DOC_NAME: &str = "$__rawbson_Doc";
+
+/// Newtype name under which [`Array`] asks for the raw bytes of an array.
+pub static ARRAY_NAME: &str = "$__rawbson_Array";
+
+struct DocVisitor;
+
+impl<'de> Visitor<'de> for DocVisitor {
+    type Value = &'de Doc;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a borrowed bson document")
+    }
+
+    fn visit_borrowed_bytes<E: serde::de::Error>(self, bytes: &'de [u8]) -> Result<&'de Doc, E> {
+        Doc::new(bytes).map_err(E::custom)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a Doc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(DOC_NAME, DocVisitor)
+    }
+}
+
+struct ArrayVisitor;
+
+impl<'de> Visitor<'de> for ArrayVisitor {
+    type Value = &'de Array;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a borrowed bson array")
+    }
+
+    fn visit_borrowed_bytes<E: serde::de::Error>(self, bytes: &'de [u8]) -> Result<&'de Array, E> {
+        Doc::new(bytes).map(Array::from_doc).map_err(E::custom)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a Array {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(ARRAY_NAME, ArrayVisitor)
+    }
+}
+
+struct DocBufVisitor;
+
+impl<'de> Visitor<'de> for DocBufVisitor {
+    type Value = DocBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a bson document")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<DocBuf, E> {
+        DocBuf::new(bytes.to_vec()).map_err(E::custom)
+    }
+
+    /// Formats other than raw BSON go through [`bson::Document`].
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<DocBuf, D::Error> {
+        bson::Document::deserialize(deserializer).map(|doc| DocBuf::from_document(&doc))
+    }
+}
+
+impl<'de> Deserialize<'de> for DocBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(DOC_NAME, DocBufVisitor)
+    }
+}
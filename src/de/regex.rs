@@ -1,7 +1,7 @@
 use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::forward_to_deserialize_any;
 
-use super::Error;
+use super::{Error, ErrorKind};
 use crate::elem::RawBsonRegex;
 
 pub static NAME: &str = "$__bson_Regex";
@@ -74,7 +74,7 @@ impl<'de> Deserializer<'de> for RegexDeserializer<'de> {
         if ct == 2 {
             visitor.visit_seq(self)
         } else {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         }
     }
 
@@ -91,7 +91,7 @@ impl<'de> Deserializer<'de> for RegexDeserializer<'de> {
         if name == NAME {
             visitor.visit_map(self)
         } else {
-            Err(Error::MalformedDocument)
+            Err(Error::new(ErrorKind::UnexpectedType))
         }
     }
 
@@ -162,7 +162,7 @@ impl<'de> MapAccess<'de> for RegexDeserializer<'de> {
                 self.visiting = Visiting::Done;
                 seed.deserialize(RegexFieldDeserializer::new(self.data.options()))
             }
-            Visiting::Done => Err(Error::MalformedDocument),
+            Visiting::Done => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
         }
     }
 }
@@ -0,0 +1,83 @@
+// Symbol handling
+
+use serde::de::{DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{Error, ErrorKind, StrDeserializer};
+
+pub static NAME: &str = "$__bson_Symbol";
+pub static FIELD: &str = "$symbol";
+pub static FIELDS: &[&str] = &[FIELD];
+
+/// Deserializes a (deprecated) Symbol value as a struct with a single
+/// `$symbol` field holding the string, matching its extended JSON form.
+pub struct SymbolDeserializer<'de> {
+    symbol: &'de str,
+    visited: bool,
+}
+
+impl<'de> SymbolDeserializer<'de> {
+    pub fn new(symbol: &'de str) -> SymbolDeserializer<'de> {
+        SymbolDeserializer {
+            symbol,
+            visited: false,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for SymbolDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &str,
+        _fields: &[&str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == NAME {
+            visitor.visit_map(self)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedType))
+        }
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string bytes byte_buf
+        map option unit newtype_struct tuple
+        ignored_any seq unit_struct tuple_struct enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for SymbolDeserializer<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => seed.deserialize(FIELD.into_deserializer()).map(Some),
+            true => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => {
+                self.visited = true;
+                seed.deserialize(StrDeserializer::new(self.symbol))
+            }
+            true => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// Timestamp handling
+
+use serde::de::{DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{Error, ErrorKind};
+use crate::elem::RawBsonTimestamp;
+
+pub static NAME: &str = "$__bson_Timestamp";
+pub static FIELD: &str = "$timestamp";
+pub static FIELDS: &[&str] = &[FIELD];
+pub static TIME_FIELD: &str = "t";
+pub static INCREMENT_FIELD: &str = "i";
+
+/// Deserializes a Timestamp value as a struct with a single `$timestamp`
+/// field, which holds a map of the time (`t`) and the increment (`i`),
+/// matching its extended JSON form.
+pub struct TimestampDeserializer {
+    time: u32,
+    increment: u32,
+    visited: bool,
+}
+
+impl TimestampDeserializer {
+    pub fn new(timestamp: RawBsonTimestamp<'_>) -> TimestampDeserializer {
+        TimestampDeserializer {
+            time: timestamp.time(),
+            increment: timestamp.increment(),
+            visited: false,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for TimestampDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &str,
+        _fields: &[&str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == NAME {
+            visitor.visit_map(self)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedType))
+        }
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string bytes byte_buf
+        map option unit newtype_struct tuple
+        ignored_any seq unit_struct tuple_struct enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for TimestampDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => seed.deserialize(FIELD.into_deserializer()).map(Some),
+            true => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.visited {
+            false => {
+                self.visited = true;
+                seed.deserialize(TimestampBodyDeserializer {
+                    time: self.time,
+                    increment: self.increment,
+                    field: 0,
+                })
+            }
+            true => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
+
+/// The `{"t": ..., "i": ...}` map inside the `$timestamp` field.
+struct TimestampBodyDeserializer {
+    time: u32,
+    increment: u32,
+    field: usize,
+}
+
+impl<'de> Deserializer<'de> for TimestampBodyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any!(
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string seq
+        bytes byte_buf map struct option unit newtype_struct
+        ignored_any unit_struct tuple_struct tuple enum identifier
+    );
+}
+
+impl<'de> MapAccess<'de> for TimestampBodyDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.field {
+            0 => seed.deserialize(TIME_FIELD.into_deserializer()).map(Some),
+            1 => seed.deserialize(INCREMENT_FIELD.into_deserializer()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.field += 1;
+        match self.field {
+            1 => seed.deserialize(self.time.into_deserializer()),
+            2 => seed.deserialize(self.increment.into_deserializer()),
+            _ => Err(Error::new(ErrorKind::Custom("no more fields".into()))),
+        }
+    }
+}
@@ -1,17 +1,18 @@
-use std::{
-    convert::{TryFrom, TryInto},
-    time::Duration,
-};
+use std::convert::{TryFrom, TryInto};
 
 use bson::oid;
 pub use bson::spec::{BinarySubtype, ElementType};
 use chrono::{DateTime, TimeZone, Utc};
 
 use crate::{
-    d128_from_slice, i32_from_slice, i64_from_slice, read_lenencoded, read_nullterminated,
-    u32_from_slice, Array, Doc, RawError, RawResult,
+    i32_from_slice, i64_from_slice, read_lenencoded, read_nullterminated, u32_from_slice, Array,
+    Doc, ErrorKind, RawError, RawResult,
 };
 
+mod decimal128;
+
+pub use decimal128::RawDecimal128;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Element<'a> {
     element_type: ElementType,
@@ -29,67 +30,108 @@ impl<'a> Element<'a> {
         self.element_type
     }
 
+    /// Create an error of the given kind concerning this element.
+    fn error(self, kind: ErrorKind) -> RawError {
+        RawError::new(kind).of_type(self.element_type)
+    }
+
     pub fn as_bytes(self) -> &'a [u8] {
         self.data
     }
 
+    /// Copy this element into an owned [`RawBson`] value.
+    pub fn to_owned(self) -> RawBson {
+        RawBson {
+            element_type: self.element_type,
+            data: self.data.to_vec(),
+        }
+    }
+
+    /// Decode this element into a [`RawBsonRef`], which can be matched on
+    /// by type.
+    pub fn as_raw_bson_ref(self) -> RawResult<RawBsonRef<'a>> {
+        Ok(match self.element_type {
+            ElementType::Double => RawBsonRef::Double(self.as_f64()?),
+            ElementType::String => RawBsonRef::String(self.as_str()?),
+            ElementType::EmbeddedDocument => RawBsonRef::Document(self.as_document()?),
+            ElementType::Array => RawBsonRef::Array(self.as_array()?),
+            ElementType::Binary => RawBsonRef::Binary(self.as_binary()?),
+            ElementType::Undefined => RawBsonRef::Undefined,
+            ElementType::ObjectId => RawBsonRef::ObjectId(self.as_object_id()?),
+            ElementType::Boolean => RawBsonRef::Boolean(self.as_bool()?),
+            ElementType::DateTime => RawBsonRef::DateTime(self.as_datetime()?),
+            ElementType::Null => RawBsonRef::Null,
+            ElementType::RegularExpression => RawBsonRef::RegularExpression(self.as_regex()?),
+            ElementType::DbPointer => RawBsonRef::DbPointer(self.as_db_pointer()?),
+            ElementType::JavaScriptCode => RawBsonRef::JavaScriptCode(self.as_javascript()?),
+            ElementType::Symbol => RawBsonRef::Symbol(self.as_symbol()?),
+            ElementType::JavaScriptCodeWithScope => {
+                let (code, scope) = self.as_javascript_with_scope()?;
+                RawBsonRef::JavaScriptCodeWithScope { code, scope }
+            }
+            ElementType::Int32 => RawBsonRef::Int32(self.as_i32()?),
+            ElementType::Timestamp => RawBsonRef::Timestamp(self.as_timestamp()?),
+            ElementType::Int64 => RawBsonRef::Int64(self.as_i64()?),
+            ElementType::Decimal128 => RawBsonRef::Decimal128(self.as_decimal128()?),
+            ElementType::MaxKey => RawBsonRef::MaxKey,
+            ElementType::MinKey => RawBsonRef::MinKey,
+        })
+    }
+
     pub fn as_f64(self) -> RawResult<f64> {
         if let ElementType::Double = self.element_type {
             Ok(f64::from_bits(u64::from_le_bytes(
                 self.data
                     .try_into()
-                    .map_err(|_| RawError::MalformedValue("f64 should be 8 bytes long".into()))?,
+                    .map_err(|_| self.error(ErrorKind::BadLength).at(0))?,
             )))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_str(self) -> RawResult<&'a str> {
         if let ElementType::String = self.element_type {
-            read_lenencoded(self.data)
+            read_lenencoded(self.data).map_err(|err| err.of_type(self.element_type))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_document(self) -> RawResult<&'a Doc> {
         if let ElementType::EmbeddedDocument = self.element_type {
-            Doc::new(self.data)
+            Doc::new(self.data).map_err(|err| err.of_type(self.element_type))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_array(self) -> RawResult<&'a Array> {
         if let ElementType::Array = self.element_type {
-            Array::new(self.data)
+            Array::new(self.data).map_err(|err| err.of_type(self.element_type))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_binary(self) -> RawResult<RawBsonBinary<'a>> {
         if let ElementType::Binary = self.element_type {
+            if self.data.len() < 5 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
+            }
             let length = i32_from_slice(&self.data[0..4]);
             let subtype = BinarySubtype::from(self.data[4]); // TODO: This mishandles reserved values
-            if self.data.len() as i32 != length + 5 {
-                return Err(RawError::MalformedValue(
-                    "binary bson has wrong declared length".into(),
-                ));
+            if self.data.len() as i64 != length as i64 + 5 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
             }
             let data = match subtype {
                 BinarySubtype::BinaryOld => {
                     if length < 4 {
-                        return Err(RawError::MalformedValue(
-                            "old binary subtype has no inner declared length".into(),
-                        ));
+                        return Err(self.error(ErrorKind::BadLength).at(0));
                     }
                     let oldlength = i32_from_slice(&self.data[5..9]);
-                    if oldlength + 4 != length {
-                        return Err(RawError::MalformedValue(
-                            "old binary subtype has wrong inner declared length".into(),
-                        ));
+                    if i64::from(oldlength) + 4 != i64::from(length) {
+                        return Err(self.error(ErrorKind::BadLength).at(5));
                     }
                     &self.data[9..]
                 }
@@ -97,60 +139,62 @@ impl<'a> Element<'a> {
             };
             Ok(RawBsonBinary::new(subtype, data))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_object_id(self) -> RawResult<oid::ObjectId> {
         if let ElementType::ObjectId = self.element_type {
-            Ok(oid::ObjectId::with_bytes(self.data.try_into().map_err(
-                |_| RawError::MalformedValue("object id should be 12 bytes long".into()),
-            )?))
+            Ok(oid::ObjectId::with_bytes(
+                self.data
+                    .try_into()
+                    .map_err(|_| self.error(ErrorKind::BadLength).at(0))?,
+            ))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_bool(self) -> RawResult<bool> {
         if let ElementType::Boolean = self.element_type {
             if self.data.len() != 1 {
-                Err(RawError::MalformedValue("boolean has length != 1".into()))
+                Err(self.error(ErrorKind::BadLength).at(0))
             } else {
                 match self.data[0] {
                     0 => Ok(false),
                     1 => Ok(true),
-                    _ => Err(RawError::MalformedValue(
-                        "boolean value was not 0 or 1".into(),
-                    )),
+                    value => Err(self.error(ErrorKind::BadBool(value)).at(0)),
                 }
             }
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_datetime(self) -> RawResult<DateTime<Utc>> {
         if let ElementType::DateTime = self.element_type {
+            if self.data.len() != 8 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
+            }
             let millis = i64_from_slice(self.data);
-            if millis >= 0 {
-                let duration = Duration::from_millis(millis as u64);
-                Ok(Utc.timestamp(
-                    duration.as_secs().try_into().unwrap(),
-                    duration.subsec_nanos(),
-                ))
-            } else {
-                let duration = Duration::from_millis((-millis).try_into().unwrap());
-                let mut secs: i64 = duration.as_secs().try_into().unwrap();
-                secs *= -1;
-                let mut nanos = duration.subsec_nanos();
-                if nanos > 0 {
-                    secs -= 1;
-                    nanos = 1_000_000_000 - nanos;
-                }
-                Ok(Utc.timestamp(secs, nanos))
+            Utc.timestamp_millis_opt(millis)
+                .single()
+                .ok_or_else(|| self.error(ErrorKind::OutOfRange).at(0))
+        } else {
+            Err(self.error(ErrorKind::UnexpectedType))
+        }
+    }
+
+    /// Read the milliseconds of a datetime, including those outside the
+    /// range of [`chrono::DateTime`].
+    pub(crate) fn datetime_millis(self) -> RawResult<i64> {
+        if let ElementType::DateTime = self.element_type {
+            if self.data.len() != 8 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
             }
+            Ok(i64_from_slice(self.data))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
@@ -158,81 +202,132 @@ impl<'a> Element<'a> {
         if let ElementType::Null = self.element_type {
             Ok(())
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
+        }
+    }
+
+    /// Returns `Ok(())` if this is a (deprecated) undefined value.
+    pub fn as_undefined(self) -> RawResult<()> {
+        if let ElementType::Undefined = self.element_type {
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::UnexpectedType))
+        }
+    }
+
+    /// Returns `Ok(())` if this is the MinKey value.
+    pub fn as_min_key(self) -> RawResult<()> {
+        if let ElementType::MinKey = self.element_type {
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::UnexpectedType))
+        }
+    }
+
+    /// Returns `Ok(())` if this is the MaxKey value.
+    pub fn as_max_key(self) -> RawResult<()> {
+        if let ElementType::MaxKey = self.element_type {
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_regex(self) -> RawResult<RawBsonRegex<'a>> {
         if let ElementType::RegularExpression = self.element_type {
-            RawBsonRegex::new(self.data)
+            RawBsonRegex::new(self.data).map_err(|err| err.of_type(self.element_type))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
+        }
+    }
+
+    /// Decode a (deprecated) DBPointer value into its namespace and object
+    /// id.
+    pub fn as_db_pointer(self) -> RawResult<RawDbPointer<'a>> {
+        if let ElementType::DbPointer = self.element_type {
+            RawDbPointer::from_slice(self.data).map_err(|err| err.of_type(self.element_type))
+        } else {
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_javascript(self) -> RawResult<&'a str> {
         if let ElementType::JavaScriptCode = self.element_type {
-            read_lenencoded(self.data)
+            read_lenencoded(self.data).map_err(|err| err.of_type(self.element_type))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_symbol(self) -> RawResult<&'a str> {
         if let ElementType::Symbol = self.element_type {
-            read_lenencoded(self.data)
+            read_lenencoded(self.data).map_err(|err| err.of_type(self.element_type))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_javascript_with_scope(self) -> RawResult<(&'a str, &'a Doc)> {
         if let ElementType::JavaScriptCodeWithScope = self.element_type {
-            let length = i32_from_slice(&self.data[..4]);
-            assert_eq!(self.data.len() as i32, length);
+            if self.data.len() < 4
+                || self.data.len() as i64 != i32_from_slice(&self.data[..4]) as i64
+            {
+                return Err(self.error(ErrorKind::BadLength).at(0));
+            }
 
-            let js = read_lenencoded(&self.data[4..])?;
-            let doc = Doc::new(&self.data[9 + js.len()..])?;
+            let js = read_lenencoded(&self.data[4..])
+                .map_err(|err| err.shifted(4).of_type(self.element_type))?;
+            let doc = Doc::new(&self.data[9 + js.len()..])
+                .map_err(|err| err.shifted(9 + js.len()).of_type(self.element_type))?;
 
             Ok((js, doc))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_i32(self) -> RawResult<i32> {
         if let ElementType::Int32 = self.element_type {
-            assert_eq!(self.data.len(), 4);
+            if self.data.len() != 4 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
+            }
             Ok(i32_from_slice(self.data))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_timestamp(self) -> RawResult<RawBsonTimestamp<'a>> {
         if let ElementType::Timestamp = self.element_type {
-            assert_eq!(self.data.len(), 8);
+            if self.data.len() != 8 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
+            }
             Ok(RawBsonTimestamp { data: self.data })
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
     pub fn as_i64(self) -> RawResult<i64> {
         if let ElementType::Int64 = self.element_type {
-            assert_eq!(self.data.len(), 8);
+            if self.data.len() != 8 {
+                return Err(self.error(ErrorKind::BadLength).at(0));
+            }
             Ok(i64_from_slice(self.data))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 
-    pub fn as_decimal128(self) -> RawResult<bson::Decimal128> {
+    pub fn as_decimal128(self) -> RawResult<RawDecimal128> {
         if let ElementType::Decimal128 = self.element_type {
-            assert_eq!(self.data.len(), 16);
-            Ok(d128_from_slice(self.data))
+            Ok(RawDecimal128::from_bytes(
+                self.data
+                    .try_into()
+                    .map_err(|_| self.error(ErrorKind::BadLength).at(0))?,
+            ))
         } else {
-            Err(RawError::UnexpectedType)
+            Err(self.error(ErrorKind::UnexpectedType))
         }
     }
 }
@@ -285,8 +380,8 @@ impl<'a> TryFrom<Element<'a>> for bson::Bson {
                 })
             }
             ElementType::Int64 => bson::Bson::Int64(rawbson.as_i64()?),
-            ElementType::Undefined => bson::Bson::Null,
-            ElementType::DbPointer => panic!("Uh oh. Maybe this should be a TryFrom"),
+            ElementType::Undefined => bson::Bson::Undefined,
+            ElementType::DbPointer => rawbson.as_db_pointer()?.into(),
             ElementType::Symbol => bson::Bson::Symbol(String::from(rawbson.as_symbol()?)),
             ElementType::JavaScriptCodeWithScope => {
                 let (js, scope) = rawbson.as_javascript_with_scope()?;
@@ -295,13 +390,221 @@ impl<'a> TryFrom<Element<'a>> for bson::Bson {
                     scope: scope.try_into()?,
                 })
             }
-            ElementType::Decimal128 => bson::Bson::Decimal128(rawbson.as_decimal128()?),
-            ElementType::MaxKey => unimplemented!(),
-            ElementType::MinKey => unimplemented!(),
+            ElementType::Decimal128 => bson::Bson::Decimal128(rawbson.as_decimal128()?.into()),
+            ElementType::MaxKey => bson::Bson::MaxKey,
+            ElementType::MinKey => bson::Bson::MinKey,
+        })
+    }
+}
+
+impl<'a> From<Element<'a>> for RawBson {
+    fn from(element: Element<'a>) -> RawBson {
+        element.to_owned()
+    }
+}
+
+impl<'a> TryFrom<Element<'a>> for RawBsonRef<'a> {
+    type Error = RawError;
+
+    fn try_from(element: Element<'a>) -> RawResult<RawBsonRef<'a>> {
+        element.as_raw_bson_ref()
+    }
+}
+
+/// An owned BSON value, holding the element type and the raw bytes of the
+/// value.
+///
+/// A `RawBson` can be created from an [`Element`] borrowed from a document,
+/// or encoded from a [`RawBsonRef`], and outlives the buffer it came from.
+///
+/// ```
+/// # use bson::doc;
+/// # use std::convert::TryFrom;
+/// # use rawbson::{DocBuf, elem::{RawBson, RawBsonRef}};
+/// let value: RawBson = {
+///     let doc = DocBuf::from_document(&doc! {"answer": 42});
+///     doc.get("answer")?.unwrap().to_owned()
+/// };
+/// assert!(matches!(value.as_raw_bson_ref()?, RawBsonRef::Int32(42)));
+///
+/// let value = RawBson::try_from(RawBsonRef::String("hello"))?;
+/// assert_eq!(value.as_element().as_str()?, "hello");
+/// # Ok::<(), rawbson::RawError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawBson {
+    element_type: ElementType,
+    data: Vec<u8>,
+}
+
+impl RawBson {
+    pub fn element_type(&self) -> ElementType {
+        self.element_type
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Borrow this value as an [`Element`], giving access to the `as_*`
+    /// accessors.
+    pub fn as_element(&self) -> Element<'_> {
+        Element::new(self.element_type, &self.data)
+    }
+
+    pub fn as_raw_bson_ref(&self) -> RawResult<RawBsonRef<'_>> {
+        self.as_element().as_raw_bson_ref()
+    }
+}
+
+impl<'a> TryFrom<RawBsonRef<'a>> for RawBson {
+    type Error = RawError;
+
+    /// Encode a value.  This fails if a regular expression contains a NUL
+    /// byte, or if a string or binary value is too long for BSON.
+    fn try_from(value: RawBsonRef<'a>) -> RawResult<RawBson> {
+        let mut data = Vec::new();
+        value.write_value(&mut data)?;
+        Ok(RawBson {
+            element_type: value.element_type(),
+            data,
         })
     }
 }
 
+/// A decoded BSON value borrowed from a document, with one variant per
+/// [`ElementType`].
+#[derive(Clone, Debug)]
+pub enum RawBsonRef<'a> {
+    Double(f64),
+    String(&'a str),
+    Document(&'a Doc),
+    Array(&'a Array),
+    Binary(RawBsonBinary<'a>),
+    Undefined,
+    ObjectId(oid::ObjectId),
+    Boolean(bool),
+    DateTime(DateTime<Utc>),
+    Null,
+    RegularExpression(RawBsonRegex<'a>),
+    /// A (deprecated) DBPointer value.
+    DbPointer(RawDbPointer<'a>),
+    JavaScriptCode(&'a str),
+    Symbol(&'a str),
+    JavaScriptCodeWithScope {
+        code: &'a str,
+        scope: &'a Doc,
+    },
+    Int32(i32),
+    Timestamp(RawBsonTimestamp<'a>),
+    Int64(i64),
+    Decimal128(RawDecimal128),
+    MaxKey,
+    MinKey,
+}
+
+impl<'a> RawBsonRef<'a> {
+    pub fn element_type(&self) -> ElementType {
+        match self {
+            RawBsonRef::Double(_) => ElementType::Double,
+            RawBsonRef::String(_) => ElementType::String,
+            RawBsonRef::Document(_) => ElementType::EmbeddedDocument,
+            RawBsonRef::Array(_) => ElementType::Array,
+            RawBsonRef::Binary(_) => ElementType::Binary,
+            RawBsonRef::Undefined => ElementType::Undefined,
+            RawBsonRef::ObjectId(_) => ElementType::ObjectId,
+            RawBsonRef::Boolean(_) => ElementType::Boolean,
+            RawBsonRef::DateTime(_) => ElementType::DateTime,
+            RawBsonRef::Null => ElementType::Null,
+            RawBsonRef::RegularExpression(_) => ElementType::RegularExpression,
+            RawBsonRef::DbPointer(_) => ElementType::DbPointer,
+            RawBsonRef::JavaScriptCode(_) => ElementType::JavaScriptCode,
+            RawBsonRef::Symbol(_) => ElementType::Symbol,
+            RawBsonRef::JavaScriptCodeWithScope { .. } => ElementType::JavaScriptCodeWithScope,
+            RawBsonRef::Int32(_) => ElementType::Int32,
+            RawBsonRef::Timestamp(_) => ElementType::Timestamp,
+            RawBsonRef::Int64(_) => ElementType::Int64,
+            RawBsonRef::Decimal128(_) => ElementType::Decimal128,
+            RawBsonRef::MaxKey => ElementType::MaxKey,
+            RawBsonRef::MinKey => ElementType::MinKey,
+        }
+    }
+
+    /// Append the BSON encoding of this value (without type byte or key) to
+    /// `buf`.
+    ///
+    /// Fails with `InteriorNul` if a regular expression contains a NUL
+    /// byte, or with `BadLength` if a value is too long for its length
+    /// prefix.  `buf` may be partly written when an error is returned.
+    pub(crate) fn write_value(&self, buf: &mut Vec<u8>) -> RawResult<()> {
+        fn write_length(buf: &mut Vec<u8>, length: usize) -> RawResult<()> {
+            let length =
+                i32::try_from(length).map_err(|_| RawError::new(ErrorKind::BadLength))?;
+            buf.extend_from_slice(&length.to_le_bytes());
+            Ok(())
+        }
+
+        fn write_lenencoded(buf: &mut Vec<u8>, value: &str) -> RawResult<()> {
+            write_length(buf, value.len() + 1)?;
+            buf.extend_from_slice(value.as_bytes());
+            buf.push(0);
+            Ok(())
+        }
+
+        fn write_cstring(buf: &mut Vec<u8>, value: &str) -> RawResult<()> {
+            if let Some(position) = value.bytes().position(|b| b == 0) {
+                return Err(RawError::new(ErrorKind::InteriorNul).at(buf.len() + position));
+            }
+            buf.extend_from_slice(value.as_bytes());
+            buf.push(0);
+            Ok(())
+        }
+
+        match self {
+            RawBsonRef::Double(value) => buf.extend_from_slice(&value.to_le_bytes()),
+            RawBsonRef::String(value)
+            | RawBsonRef::JavaScriptCode(value)
+            | RawBsonRef::Symbol(value) => write_lenencoded(buf, value)?,
+            RawBsonRef::Document(doc) => buf.extend_from_slice(doc.as_bytes()),
+            RawBsonRef::Array(arr) => buf.extend_from_slice(arr.as_bytes()),
+            RawBsonRef::Binary(binary) => {
+                let old = matches!(binary.subtype, BinarySubtype::BinaryOld);
+                let inner_length = binary.data.len();
+                write_length(buf, if old { inner_length + 4 } else { inner_length })?;
+                buf.push(binary.subtype.into());
+                if old {
+                    write_length(buf, inner_length)?;
+                }
+                buf.extend_from_slice(binary.data);
+            }
+            RawBsonRef::Undefined | RawBsonRef::Null | RawBsonRef::MaxKey | RawBsonRef::MinKey => {}
+            RawBsonRef::ObjectId(oid) => buf.extend_from_slice(&oid.bytes()),
+            RawBsonRef::Boolean(value) => buf.push(*value as u8),
+            RawBsonRef::DateTime(value) => {
+                buf.extend_from_slice(&value.timestamp_millis().to_le_bytes())
+            }
+            RawBsonRef::RegularExpression(regex) => {
+                write_cstring(buf, regex.pattern)?;
+                write_cstring(buf, regex.options)?;
+            }
+            RawBsonRef::DbPointer(pointer) => {
+                write_lenencoded(buf, pointer.namespace)?;
+                buf.extend_from_slice(&pointer.id.bytes());
+            }
+            RawBsonRef::JavaScriptCodeWithScope { code, scope } => {
+                write_length(buf, 4 + 4 + code.len() + 1 + scope.as_bytes().len())?;
+                write_lenencoded(buf, code)?;
+                buf.extend_from_slice(scope.as_bytes());
+            }
+            RawBsonRef::Int32(value) => buf.extend_from_slice(&value.to_le_bytes()),
+            RawBsonRef::Timestamp(ts) => buf.extend_from_slice(ts.data),
+            RawBsonRef::Int64(value) => buf.extend_from_slice(&value.to_le_bytes()),
+            RawBsonRef::Decimal128(value) => buf.extend_from_slice(&value.bytes()),
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct RawBsonBinary<'a> {
     pub(super) subtype: BinarySubtype,
@@ -333,16 +636,15 @@ pub struct RawBsonRegex<'a> {
 impl<'a> RawBsonRegex<'a> {
     pub fn new(data: &'a [u8]) -> RawResult<RawBsonRegex<'a>> {
         let pattern = read_nullterminated(data)?;
-        let opts = read_nullterminated(&data[pattern.len() + 1..])?;
+        let opts = read_nullterminated(&data[pattern.len() + 1..])
+            .map_err(|err| err.shifted(pattern.len() + 1))?;
         if pattern.len() + opts.len() == data.len() - 2 {
             Ok(RawBsonRegex {
                 pattern,
                 options: opts,
             })
         } else {
-            Err(RawError::MalformedValue(
-                "expected two null-terminated strings".into(),
-            ))
+            Err(RawError::new(ErrorKind::BadLength).at(0))
         }
     }
 
@@ -355,6 +657,61 @@ impl<'a> RawBsonRegex<'a> {
     }
 }
 
+/// A (deprecated) DBPointer value, referring to the document with the given
+/// object id in the namespace `namespace`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawDbPointer<'a> {
+    pub(super) namespace: &'a str,
+    pub(super) id: oid::ObjectId,
+}
+
+impl<'a> RawDbPointer<'a> {
+    pub fn new(namespace: &'a str, id: oid::ObjectId) -> RawDbPointer<'a> {
+        RawDbPointer { namespace, id }
+    }
+
+    fn from_slice(data: &'a [u8]) -> RawResult<RawDbPointer<'a>> {
+        let namespace = read_lenencoded(data)?;
+        let id_offset = 4 + namespace.len() + 1;
+        let id = data[id_offset..]
+            .try_into()
+            .map_err(|_| RawError::new(ErrorKind::BadLength).at(0))?;
+        Ok(RawDbPointer {
+            namespace,
+            id: oid::ObjectId::with_bytes(id),
+        })
+    }
+
+    /// Return the namespace, in `database.collection` form.
+    pub fn namespace(&self) -> &'a str {
+        self.namespace
+    }
+
+    /// Return the object id of the referenced document.
+    pub fn id(&self) -> &oid::ObjectId {
+        &self.id
+    }
+}
+
+impl<'a> From<RawDbPointer<'a>> for bson::Bson {
+    /// The bson crate offers no constructor for its `DbPointer` type, so the
+    /// value is decoded from a one-element document.
+    fn from(pointer: RawDbPointer<'a>) -> bson::Bson {
+        let mut data = vec![ElementType::DbPointer as u8, 0];
+        RawBsonRef::DbPointer(pointer)
+            .write_value(&mut data)
+            .expect("dbpointer namespace fits in a BSON string");
+        let length = 4 + data.len() as i32 + 1;
+        let mut bytes = length.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&data);
+        bytes.push(0);
+        bson::Document::from_reader(&mut bytes.as_slice())
+            .ok()
+            .and_then(|mut doc| doc.remove(""))
+            .expect("dbpointer encoding is valid")
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct RawBsonTimestamp<'a> {
     data: &'a [u8],
@@ -373,3 +730,143 @@ impl<'a> RawBsonTimestamp<'a> {
         u32_from_slice(&self.data[0..4])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope, Regex, Timestamp};
+    use chrono::{TimeZone, Utc};
+
+    use std::convert::TryFrom;
+
+    use super::{Element, ElementType, RawBson, RawBsonRef, RawBsonRegex, RawDbPointer};
+    use crate::{DocBuf, DocBufBuilder, ErrorKind};
+
+    #[test]
+    fn owned_values_roundtrip() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "f64": 2.5,
+            "string": "hello",
+            "document": { "a": 1 },
+            "array": [1, "two"],
+            "binary": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
+            "old": Binary { subtype: BinarySubtype::BinaryOld, bytes: vec![4, 5] },
+            "object_id": bson::oid::ObjectId::with_bytes([1; 12]),
+            "boolean": true,
+            "datetime": Utc.timestamp_millis_opt(1_600_000_000_123).unwrap(),
+            "null": Bson::Null,
+            "regex": Regex { pattern: String::from("^a"), options: String::from("i") },
+            "javascript": Bson::JavaScriptCode(String::from("1 + 1")),
+            "symbol": Bson::Symbol(String::from("sym")),
+            "js_with_scope": JavaScriptCodeWithScope {
+                code: String::from("x"),
+                scope: doc! { "x": 1 },
+            },
+            "int32": 23,
+            "timestamp": Timestamp { time: 3542578, increment: 7 },
+            "int64": 46i64,
+        });
+        let owned: Vec<RawBson> = docbuf
+            .iter()
+            .map(|item| item.map(|(_, elem)| elem.to_owned()))
+            .collect::<Result<_, _>>()
+            .expect("valid document");
+        drop(docbuf);
+
+        for value in &owned {
+            let reencoded =
+                RawBson::try_from(value.as_raw_bson_ref().expect("valid value")).expect("encodes");
+            assert_eq!(&reencoded, value);
+        }
+    }
+
+    #[test]
+    fn match_on_values() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "string": "hello",
+            "inner": { "int": 5 },
+        });
+        for item in docbuf.iter() {
+            let (key, elem) = item.expect("valid element");
+            match elem.as_raw_bson_ref().expect("valid value") {
+                RawBsonRef::String(s) => assert_eq!((key, s), ("string", "hello")),
+                RawBsonRef::Document(doc) => {
+                    assert_eq!(key, "inner");
+                    assert_eq!(doc.get_i32("int").unwrap(), Some(5));
+                }
+                other => panic!("unexpected value {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn unencodable_values() {
+        let regex = RawBsonRegex {
+            pattern: "a\0b",
+            options: "",
+        };
+        let err = RawBson::try_from(RawBsonRef::RegularExpression(regex)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InteriorNul);
+        assert_eq!(err.offset(), Some(1));
+
+        let mut docbuf = DocBuf::new(b"\x05\0\0\0\0".to_vec()).unwrap();
+        assert!(docbuf
+            .append("regex", RawBsonRef::RegularExpression(regex))
+            .is_err());
+        assert_eq!(docbuf.as_bytes(), b"\x05\0\0\0\0");
+    }
+
+    #[test]
+    fn legacy_values() {
+        let id = bson::oid::ObjectId::with_bytes([7; 12]);
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_db_pointer("pointer", "db.coll", id.clone())
+            .append_undefined("undefined")
+            .append_min_key("min")
+            .append_max_key("max");
+        let docbuf = builder.finish().unwrap();
+
+        let pointer = docbuf.get("pointer").unwrap().unwrap();
+        let value = pointer.as_db_pointer().unwrap();
+        assert_eq!(value.namespace(), "db.coll");
+        assert_eq!(value.id(), &id);
+        let undefined = docbuf.get("undefined").unwrap().unwrap();
+        assert_eq!(undefined.as_undefined(), Ok(()));
+        assert_eq!(
+            undefined.as_null().unwrap_err().kind(),
+            &ErrorKind::UnexpectedType
+        );
+        assert_eq!(docbuf.get("min").unwrap().unwrap().as_min_key(), Ok(()));
+        assert_eq!(docbuf.get("max").unwrap().unwrap().as_max_key(), Ok(()));
+        assert!(docbuf.get("max").unwrap().unwrap().as_min_key().is_err());
+
+        let document = bson::Document::try_from(&*docbuf).unwrap();
+        assert_eq!(
+            document,
+            bson::Document::from_reader(&mut docbuf.as_bytes()).unwrap()
+        );
+        assert_eq!(
+            DocBuf::from_document(&document).as_bytes(),
+            docbuf.as_bytes()
+        );
+        assert_eq!(document.get("undefined"), Some(&Bson::Undefined));
+        assert_eq!(document.get("min"), Some(&Bson::MinKey));
+        assert_eq!(document.get("max"), Some(&Bson::MaxKey));
+
+        for item in docbuf.iter() {
+            let (_, elem) = item.unwrap();
+            let owned = elem.to_owned();
+            assert_eq!(RawBson::try_from(owned.as_raw_bson_ref().unwrap()).unwrap(), owned);
+        }
+        assert!(matches!(
+            pointer.as_raw_bson_ref(),
+            Ok(RawBsonRef::DbPointer(p)) if p == RawDbPointer::new("db.coll", id)
+        ));
+
+        let truncated = &pointer.as_bytes()[..pointer.as_bytes().len() - 1];
+        let err = Element::new(ElementType::DbPointer, truncated)
+            .as_db_pointer()
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadLength);
+    }
+}
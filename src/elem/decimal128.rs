@@ -0,0 +1,919 @@
+//! IEEE 754-2008 decimal128 values in the binary integer decimal (BID)
+//! encoding used by BSON.
+
+// `u128::is_multiple_of` needs Rust 1.87, and the crate declares no such
+// minimum.
+#![allow(clippy::manual_is_multiple_of)]
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    str::FromStr,
+};
+
+use crate::{ErrorKind, RawError, RawResult};
+
+/// The largest coefficient a decimal128 can hold, 10^34 - 1.
+const MAX_COEFFICIENT: u128 = 10u128.pow(34) - 1;
+const MAX_DIGITS: usize = 34;
+const EXPONENT_BIAS: i32 = 6176;
+const EXPONENT_MIN: i32 = -6176;
+const EXPONENT_MAX: i32 = 6111;
+
+const SIGN_BIT: u128 = 1 << 127;
+const INFINITY_BITS: u128 = 0x7800 << 112;
+const NAN_BITS: u128 = 0x7c00 << 112;
+const SIGNALING_NAN_BITS: u128 = 0x7e00 << 112;
+
+/// A BSON decimal128 value.
+///
+/// The value is stored as the sixteen bytes found in a BSON document and
+/// decoded on demand, so reading one never loses information.  It can be
+/// parsed from and formatted as a string, following the BSON decimal128
+/// specification, and converted to and from Rust integers and floats.
+///
+/// Equality compares the encoded bytes, so `1.0` and `1.00`, which have
+/// different exponents, are not equal.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use rawbson::elem::RawDecimal128;
+///
+/// let price: RawDecimal128 = "12.50".parse()?;
+/// assert_eq!(price.to_string(), "12.50");
+/// assert_eq!(price.to_i128_with_scale(), Some((1250, 2)));
+/// assert_eq!(f64::try_from(price)?, 12.5);
+/// assert!(i128::try_from(price).is_err());
+///
+/// let count = RawDecimal128::from(42i64);
+/// assert_eq!(i128::try_from(count)?, 42);
+/// # Ok::<(), rawbson::RawError>(())
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawDecimal128 {
+    bytes: [u8; 16],
+}
+
+/// A decoded decimal128 value.
+enum Decoded {
+    Finite { coefficient: u128, exponent: i32 },
+    Infinite,
+    NaN,
+}
+
+impl RawDecimal128 {
+    /// Create a value from its BSON encoding: sixteen little-endian bytes.
+    pub fn from_bytes(bytes: [u8; 16]) -> RawDecimal128 {
+        RawDecimal128 { bytes }
+    }
+
+    /// The BSON encoding of this value.
+    pub fn bytes(self) -> [u8; 16] {
+        self.bytes
+    }
+
+    fn from_bits(bits: u128) -> RawDecimal128 {
+        RawDecimal128::from_bytes(bits.to_le_bytes())
+    }
+
+    fn bits(self) -> u128 {
+        u128::from_le_bytes(self.bytes)
+    }
+
+    /// Encode a finite value.  The coefficient and exponent must already be
+    /// in range.
+    fn from_parts(negative: bool, coefficient: u128, exponent: i32) -> RawDecimal128 {
+        debug_assert!(coefficient <= MAX_COEFFICIENT);
+        debug_assert!((EXPONENT_MIN..=EXPONENT_MAX).contains(&exponent));
+        let sign = if negative { SIGN_BIT } else { 0 };
+        let exponent = ((exponent + EXPONENT_BIAS) as u128) << 113;
+        RawDecimal128::from_bits(sign | exponent | coefficient)
+    }
+
+    /// Bring `coefficient * 10^exponent` into range without changing its
+    /// value, or fail if that is impossible.
+    fn from_unclamped(negative: bool, mut coefficient: u128, exponent: i64) -> RawResult<Self> {
+        if coefficient == 0 {
+            let exponent = exponent.max(EXPONENT_MIN.into()).min(EXPONENT_MAX.into());
+            return Ok(RawDecimal128::from_parts(negative, 0, exponent as i32));
+        }
+        let mut exponent = exponent;
+        while coefficient > MAX_COEFFICIENT || exponent < EXPONENT_MIN.into() {
+            if coefficient % 10 != 0 {
+                return Err(RawError::new(ErrorKind::OutOfRange));
+            }
+            coefficient /= 10;
+            exponent += 1;
+        }
+        while exponent > EXPONENT_MAX.into() {
+            if coefficient > MAX_COEFFICIENT / 10 {
+                return Err(RawError::new(ErrorKind::OutOfRange));
+            }
+            coefficient *= 10;
+            exponent -= 1;
+        }
+        Ok(RawDecimal128::from_parts(
+            negative,
+            coefficient,
+            exponent as i32,
+        ))
+    }
+
+    fn decode(self) -> Decoded {
+        let bits = self.bits();
+        if bits & INFINITY_BITS == INFINITY_BITS {
+            if bits & NAN_BITS == NAN_BITS {
+                Decoded::NaN
+            } else {
+                Decoded::Infinite
+            }
+        } else if (bits >> 125) & 0b11 == 0b11 {
+            // The implied coefficient of this form always exceeds the
+            // maximum, which makes the value a non-canonical zero.
+            let exponent = ((bits >> 111) & 0x3fff) as i32 - EXPONENT_BIAS;
+            Decoded::Finite {
+                coefficient: 0,
+                exponent,
+            }
+        } else {
+            let exponent = ((bits >> 113) & 0x3fff) as i32 - EXPONENT_BIAS;
+            let coefficient = bits & ((1 << 113) - 1);
+            Decoded::Finite {
+                coefficient: if coefficient > MAX_COEFFICIENT {
+                    0
+                } else {
+                    coefficient
+                },
+                exponent,
+            }
+        }
+    }
+
+    /// Returns true if this value is NaN.
+    pub fn is_nan(self) -> bool {
+        matches!(self.decode(), Decoded::NaN)
+    }
+
+    /// Returns true if this value is positive or negative infinity.
+    pub fn is_infinite(self) -> bool {
+        matches!(self.decode(), Decoded::Infinite)
+    }
+
+    /// Returns true if this value is neither infinite nor NaN.
+    pub fn is_finite(self) -> bool {
+        matches!(self.decode(), Decoded::Finite { .. })
+    }
+
+    /// Returns true if the sign bit is set, including for `-0` and negative
+    /// NaNs.
+    pub fn is_sign_negative(self) -> bool {
+        self.bits() & SIGN_BIT != 0
+    }
+
+    /// Create a value equal to `mantissa * 10^-scale`, as used by
+    /// fixed-point decimal types such as `rust_decimal::Decimal`.
+    ///
+    /// Returns an error if the value cannot be represented exactly.
+    pub fn from_i128_with_scale(mantissa: i128, scale: i32) -> RawResult<RawDecimal128> {
+        RawDecimal128::from_unclamped(mantissa < 0, mantissa.unsigned_abs(), -i64::from(scale))
+    }
+
+    /// Split a finite value into a mantissa and scale, so that the value is
+    /// `mantissa * 10^-scale`.  Returns `None` for infinities and NaN.
+    ///
+    /// The sign of a negative zero is lost.
+    pub fn to_i128_with_scale(self) -> Option<(i128, i32)> {
+        match self.decode() {
+            Decoded::Finite {
+                coefficient,
+                exponent,
+            } => {
+                // The coefficient is at most 10^34 - 1, so this cannot fail.
+                let mantissa = coefficient as i128;
+                let mantissa = if self.is_sign_negative() {
+                    -mantissa
+                } else {
+                    mantissa
+                };
+                Some((mantissa, -exponent))
+            }
+            _ => None,
+        }
+    }
+
+    /// Convert to the nearest `f64`.  Use `f64::try_from` to check that
+    /// no precision is lost.
+    pub fn to_f64(self) -> f64 {
+        // The formatted value is always valid float syntax.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The sign, coefficient and exponent with trailing zeros removed, for
+    /// comparing values rather than encodings.
+    fn normalized(self) -> Option<(bool, u128, i32)> {
+        match self.decode() {
+            Decoded::Finite {
+                mut coefficient,
+                mut exponent,
+            } => {
+                if coefficient == 0 {
+                    exponent = 0;
+                }
+                while coefficient != 0 && coefficient % 10 == 0 {
+                    coefficient /= 10;
+                    exponent += 1;
+                }
+                Some((self.is_sign_negative(), coefficient, exponent))
+            }
+            _ => None,
+        }
+    }
+
+    /// Add two values, rounding the result to 34 significant digits with
+    /// ties to even, as IEEE 754 decimal arithmetic does.
+    pub(crate) fn add_rounded(self, other: RawDecimal128) -> RawDecimal128 {
+        let (a, b) = match (self.decode(), other.decode()) {
+            (Decoded::NaN, _) | (_, Decoded::NaN) => return RawDecimal128::from_bits(NAN_BITS),
+            (Decoded::Infinite, Decoded::Infinite)
+                if self.is_sign_negative() != other.is_sign_negative() =>
+            {
+                return RawDecimal128::from_bits(NAN_BITS)
+            }
+            (Decoded::Infinite, _) => return self,
+            (_, Decoded::Infinite) => return other,
+            (
+                Decoded::Finite {
+                    coefficient: a,
+                    exponent: a_exponent,
+                },
+                Decoded::Finite {
+                    coefficient: b,
+                    exponent: b_exponent,
+                },
+            ) => (
+                (self.is_sign_negative(), a, a_exponent),
+                (other.is_sign_negative(), b, b_exponent),
+            ),
+        };
+        // Make `a` the operand with the larger exponent, and give it as
+        // many digits as it can hold.
+        let ((a_negative, mut a, mut a_exponent), (b_negative, b, b_exponent)) =
+            if a.2 >= b.2 { (a, b) } else { (b, a) };
+        if a == 0 {
+            a_exponent = b_exponent;
+        }
+        while a <= MAX_COEFFICIENT / 10 && a_exponent > b_exponent {
+            a *= 10;
+            a_exponent -= 1;
+        }
+        let shift = (a_exponent - b_exponent) as u32;
+        let (mut sum, exponent, b, sticky) = if shift <= MAX_DIGITS as u32 {
+            (Wide::shifted(a, shift), b_exponent, b, false)
+        } else {
+            // `b` is too small to reach the digits kept in the result, so
+            // only whether it has any digits below `a`'s matters.
+            let cut = shift - MAX_DIGITS as u32;
+            let (b, rest) = match 10u128.checked_pow(cut) {
+                Some(divisor) => (b / divisor, b % divisor),
+                None => (0, b),
+            };
+            (Wide::shifted(a, MAX_DIGITS as u32), a_exponent - MAX_DIGITS as i32, b, rest != 0)
+        };
+        let mut negative = a_negative;
+        if a_negative == b_negative {
+            sum.add(b);
+        } else if sum.at_least(b) {
+            sum.sub(b);
+            if sticky {
+                // The exact result is just below `sum`, rather than above.
+                sum.sub(1);
+            }
+        } else {
+            // `a` was too small to be shifted, so `b` is not truncated.
+            sum = Wide::shifted(b - sum.low, 0);
+            negative = b_negative;
+        }
+        if sum.is_zero() && !sticky {
+            // An exact zero is negative only if both operands were.
+            negative = a_negative && b_negative;
+        }
+        RawDecimal128::rounded(negative, sum, exponent.into(), sticky)
+    }
+
+    /// Multiply two values, rounding the result to 34 significant digits
+    /// with ties to even, as IEEE 754 decimal arithmetic does.
+    pub(crate) fn mul_rounded(self, other: RawDecimal128) -> RawDecimal128 {
+        let negative = self.is_sign_negative() != other.is_sign_negative();
+        let sign = if negative { SIGN_BIT } else { 0 };
+        match (self.decode(), other.decode()) {
+            (Decoded::NaN, _) | (_, Decoded::NaN) => RawDecimal128::from_bits(NAN_BITS),
+            (Decoded::Infinite, Decoded::Finite { coefficient: 0, .. })
+            | (Decoded::Finite { coefficient: 0, .. }, Decoded::Infinite) => {
+                RawDecimal128::from_bits(NAN_BITS)
+            }
+            (Decoded::Infinite, _) | (_, Decoded::Infinite) => {
+                RawDecimal128::from_bits(sign | INFINITY_BITS)
+            }
+            (
+                Decoded::Finite {
+                    coefficient: a,
+                    exponent: a_exponent,
+                },
+                Decoded::Finite {
+                    coefficient: b,
+                    exponent: b_exponent,
+                },
+            ) => RawDecimal128::rounded(
+                negative,
+                Wide::product(a, b),
+                i64::from(a_exponent) + i64::from(b_exponent),
+                false,
+            ),
+        }
+    }
+
+    /// Round `value * 10^exponent` to a decimal128, where `sticky` records
+    /// that the exact value has further nonzero digits below `value`.
+    /// Values too large for the range become infinite.
+    fn rounded(negative: bool, value: Wide, exponent: i64, mut sticky: bool) -> RawDecimal128 {
+        // Drop enough digits to fit the coefficient, and any that would
+        // fall below the smallest exponent.
+        let digits = if value.high == 0 {
+            0
+        } else {
+            MAX_DIGITS as i64 + i64::from(value.high.ilog10()) + 1
+        };
+        let drop = (digits - MAX_DIGITS as i64)
+            .max(i64::from(EXPONENT_MIN) - exponent)
+            .clamp(0, 2 * MAX_DIGITS as i64 + 1) as u32;
+        let (mut coefficient, round) = if drop == 0 {
+            (value.low, 0)
+        } else if drop <= MAX_DIGITS as u32 {
+            let divisor = 10u128.pow(drop);
+            let rest = value.low % divisor;
+            sticky |= rest % (divisor / 10) != 0;
+            (
+                value.high * 10u128.pow(MAX_DIGITS as u32 - drop) + value.low / divisor,
+                rest / (divisor / 10),
+            )
+        } else if drop <= 2 * MAX_DIGITS as u32 {
+            let divisor = 10u128.pow(drop - MAX_DIGITS as u32);
+            let rest = value.high % divisor;
+            sticky |= value.low != 0 || rest % (divisor / 10) != 0;
+            (value.high / divisor, rest / (divisor / 10))
+        } else {
+            sticky |= !value.is_zero();
+            (0, 0)
+        };
+        if round > 5 || (round == 5 && (sticky || coefficient % 2 == 1)) {
+            coefficient += 1;
+        }
+        let mut exponent = exponent + i64::from(drop);
+        if coefficient > MAX_COEFFICIENT {
+            coefficient /= 10;
+            exponent += 1;
+        }
+        RawDecimal128::from_unclamped(negative, coefficient, exponent).unwrap_or_else(|_| {
+            let sign = if negative { SIGN_BIT } else { 0 };
+            RawDecimal128::from_bits(sign | INFINITY_BITS)
+        })
+    }
+}
+
+/// An unsigned integer of up to 68 digits, `high * 10^34 + low`, for the
+/// exact results of decimal128 arithmetic before rounding.
+struct Wide {
+    high: u128,
+    low: u128,
+}
+
+/// 10^34, the base of the digits of a [`Wide`].
+const WIDE_BASE: u128 = MAX_COEFFICIENT + 1;
+
+impl Wide {
+    /// `value * 10^shift`, for a coefficient and a shift of at most 34.
+    fn shifted(value: u128, shift: u32) -> Wide {
+        let split = 10u128.pow(MAX_DIGITS as u32 - shift);
+        Wide {
+            high: value / split,
+            low: value % split * 10u128.pow(shift),
+        }
+    }
+
+    /// The exact product of two coefficients.
+    fn product(a: u128, b: u128) -> Wide {
+        // Split each coefficient into 17-digit halves, so that the partial
+        // products fit in a u128.
+        const HALF: u128 = 10u128.pow(17);
+        let (a_high, a_low) = (a / HALF, a % HALF);
+        let (b_high, b_low) = (b / HALF, b % HALF);
+        let middle = a_high * b_low + a_low * b_high;
+        let low = a_low * b_low + middle % HALF * HALF;
+        Wide {
+            high: a_high * b_high + middle / HALF + low / WIDE_BASE,
+            low: low % WIDE_BASE,
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.high == 0 && self.low == 0
+    }
+
+    fn at_least(&self, value: u128) -> bool {
+        self.high > 0 || self.low >= value
+    }
+
+    /// Add a coefficient.
+    fn add(&mut self, value: u128) {
+        self.low += value;
+        if self.low >= WIDE_BASE {
+            self.low -= WIDE_BASE;
+            self.high += 1;
+        }
+    }
+
+    /// Subtract a value no larger than `self`.
+    fn sub(&mut self, value: u128) {
+        if self.low >= value {
+            self.low -= value;
+        } else {
+            self.low = self.low + WIDE_BASE - value;
+            self.high -= 1;
+        }
+    }
+}
+
+impl fmt::Display for RawDecimal128 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (coefficient, exponent) = match self.decode() {
+            Decoded::NaN => return f.write_str("NaN"),
+            Decoded::Infinite if self.is_sign_negative() => return f.write_str("-Infinity"),
+            Decoded::Infinite => return f.write_str("Infinity"),
+            Decoded::Finite {
+                coefficient,
+                exponent,
+            } => (coefficient, exponent),
+        };
+        if self.is_sign_negative() {
+            f.write_str("-")?;
+        }
+        let digits = coefficient.to_string();
+        let adjusted = exponent + digits.len() as i32 - 1;
+        if exponent > 0 || adjusted < -6 {
+            f.write_str(&digits[..1])?;
+            if digits.len() > 1 {
+                write!(f, ".{}", &digits[1..])?;
+            }
+            write!(f, "E{:+}", adjusted)
+        } else if exponent == 0 {
+            f.write_str(&digits)
+        } else {
+            let point = digits.len() as i32 + exponent;
+            if point > 0 {
+                let (whole, fraction) = digits.split_at(point as usize);
+                write!(f, "{}.{}", whole, fraction)
+            } else {
+                let width = digits.len() + point.unsigned_abs() as usize;
+                write!(f, "0.{:0>width$}", digits, width = width)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for RawDecimal128 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RawDecimal128({})", self)
+    }
+}
+
+impl FromStr for RawDecimal128 {
+    type Err = RawError;
+
+    /// Parse a decimal string such as `"-1.25"`, `"6.02E+23"`, `"Infinity"`
+    /// or `"NaN"`.
+    ///
+    /// Values with more than 34 significant digits are only accepted when
+    /// the extra digits are zeros, since the specification forbids inexact
+    /// rounding.
+    fn from_str(s: &str) -> RawResult<RawDecimal128> {
+        let invalid = || RawError::new(ErrorKind::BadDecimal);
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let sign = if negative { SIGN_BIT } else { 0 };
+        if unsigned.eq_ignore_ascii_case("infinity") || unsigned.eq_ignore_ascii_case("inf") {
+            return Ok(RawDecimal128::from_bits(sign | INFINITY_BITS));
+        } else if unsigned.eq_ignore_ascii_case("nan") {
+            return Ok(RawDecimal128::from_bits(sign | NAN_BITS));
+        } else if unsigned.eq_ignore_ascii_case("snan") {
+            return Ok(RawDecimal128::from_bits(sign | SIGNALING_NAN_BITS));
+        }
+
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(split) => (&unsigned[..split], parse_exponent(&unsigned[split + 1..])?),
+            None => (unsigned, 0),
+        };
+
+        let mut coefficient: u128 = 0;
+        let mut digits = 0;
+        let mut exponent = exponent;
+        let mut seen_digit = false;
+        let mut seen_point = false;
+        for b in mantissa.bytes() {
+            match b {
+                b'.' if !seen_point => seen_point = true,
+                b'0'..=b'9' => {
+                    seen_digit = true;
+                    let digit = u128::from(b - b'0');
+                    if digits < MAX_DIGITS {
+                        if digit != 0 || digits > 0 {
+                            coefficient = coefficient * 10 + digit;
+                            digits += 1;
+                        }
+                        if seen_point {
+                            exponent -= 1;
+                        }
+                    } else if digit != 0 {
+                        return Err(RawError::new(ErrorKind::OutOfRange));
+                    } else if !seen_point {
+                        exponent += 1;
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if !seen_digit {
+            return Err(invalid());
+        }
+        RawDecimal128::from_unclamped(negative, coefficient, exponent)
+    }
+}
+
+/// Parse the exponent of a decimal string, saturating values too large to
+/// matter.
+fn parse_exponent(s: &str) -> RawResult<i64> {
+    let (negative, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(RawError::new(ErrorKind::BadDecimal));
+    }
+    let magnitude = digits
+        .bytes()
+        .fold(0i64, |acc, b| {
+            acc.saturating_mul(10).saturating_add(i64::from(b - b'0'))
+        })
+        .min(i64::from(i32::MAX));
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+macro_rules! from_int {
+    ($($int:ty),*) => {
+        $(
+            impl From<$int> for RawDecimal128 {
+                fn from(value: $int) -> RawDecimal128 {
+                    let value = i128::from(value);
+                    RawDecimal128::from_parts(value < 0, value.unsigned_abs(), 0)
+                }
+            }
+        )*
+    };
+}
+
+from_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl TryFrom<i128> for RawDecimal128 {
+    type Error = RawError;
+
+    /// Fails if the value has more than 34 significant digits.
+    fn try_from(value: i128) -> RawResult<RawDecimal128> {
+        RawDecimal128::from_i128_with_scale(value, 0)
+    }
+}
+
+impl TryFrom<RawDecimal128> for i128 {
+    type Error = RawError;
+
+    /// Fails for infinities, NaN, values with a fractional part, and values
+    /// outside the range of `i128`.
+    fn try_from(value: RawDecimal128) -> RawResult<i128> {
+        let out_of_range = || RawError::new(ErrorKind::OutOfRange);
+        let (mantissa, scale) = value.to_i128_with_scale().ok_or_else(out_of_range)?;
+        let mut result = mantissa;
+        if scale < 0 {
+            for _ in scale..0 {
+                if result == 0 {
+                    break;
+                }
+                result = result.checked_mul(10).ok_or_else(out_of_range)?;
+            }
+        } else {
+            for _ in 0..scale {
+                if result == 0 {
+                    break;
+                }
+                if result % 10 != 0 {
+                    return Err(out_of_range());
+                }
+                result /= 10;
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl TryFrom<RawDecimal128> for i64 {
+    type Error = RawError;
+
+    /// Fails for infinities, NaN, values with a fractional part, and values
+    /// outside the range of `i64`.
+    fn try_from(value: RawDecimal128) -> RawResult<i64> {
+        i128::try_from(value)?
+            .try_into()
+            .map_err(|_| RawError::new(ErrorKind::OutOfRange))
+    }
+}
+
+impl From<f64> for RawDecimal128 {
+    /// Convert to the shortest decimal that rounds to the same `f64`, as
+    /// printed by `{:e}`.
+    fn from(value: f64) -> RawDecimal128 {
+        // Float formatting never produces more than 17 significant digits,
+        // or an exponent beyond the decimal128 range.
+        format!("{:e}", value)
+            .parse()
+            .expect("formatted floats are valid decimals")
+    }
+}
+
+impl TryFrom<RawDecimal128> for f64 {
+    type Error = RawError;
+
+    /// Fails unless converting the result back with
+    /// `RawDecimal128::from` gives the same value, ignoring trailing zeros.
+    fn try_from(value: RawDecimal128) -> RawResult<f64> {
+        let float = value.to_f64();
+        let exact = match value.decode() {
+            Decoded::NaN => true,
+            Decoded::Infinite => true,
+            Decoded::Finite { .. } => RawDecimal128::from(float).normalized() == value.normalized(),
+        };
+        if exact {
+            Ok(float)
+        } else {
+            Err(RawError::new(ErrorKind::OutOfRange))
+        }
+    }
+}
+
+impl From<RawDecimal128> for bson::Decimal128 {
+    fn from(value: RawDecimal128) -> bson::Decimal128 {
+        value
+            .to_string()
+            .parse()
+            .expect("formatted decimals are valid d128 strings")
+    }
+}
+
+impl TryFrom<&bson::Decimal128> for RawDecimal128 {
+    type Error = RawError;
+
+    fn try_from(value: &bson::Decimal128) -> RawResult<RawDecimal128> {
+        value.to_string().parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::RawDecimal128;
+    use crate::ErrorKind;
+
+    fn from_hex(high: u64, low: u64) -> RawDecimal128 {
+        RawDecimal128::from_bits(u128::from(high) << 64 | u128::from(low))
+    }
+
+    #[test]
+    fn decodes_specification_vectors() {
+        let cases = [
+            (0x3040000000000000, 0x0000000000000001, "1"),
+            (0xb040000000000000, 0x0000000000000001, "-1"),
+            (0x3040000000000000, 0x0000000000000000, "0"),
+            (0xb040000000000000, 0x0000000000000000, "-0"),
+            (0x303e000000000000, 0x0000000000000001, "0.1"),
+            (0x3034000000000000, 0x00000000000004d2, "0.001234"),
+            (0x302c000000000000, 0x00000000000004d2, "1.234E-7"),
+            (0x3040000000000000, 0x0000000000003039, "12345"),
+            (0x3042000000000000, 0x0000000000000001, "1E+1"),
+            (0x0000000000000000, 0x0000000000000001, "1E-6176"),
+            (0x5ffe000000000000, 0x0000000000000000, "0E+6111"),
+            (
+                0x5fffed09bead87c0,
+                0x378d8e63ffffffff,
+                "9.999999999999999999999999999999999E+6144",
+            ),
+            (0x7800000000000000, 0x0000000000000000, "Infinity"),
+            (0xf800000000000000, 0x0000000000000000, "-Infinity"),
+            (0x7c00000000000000, 0x0000000000000000, "NaN"),
+            (0xfc00000000000000, 0x0000000000000000, "NaN"),
+            (0x7e00000000000000, 0x0000000000000000, "NaN"),
+            // non-canonical encodings are zero
+            (0x6c10000000000000, 0x0000000000000000, "0"),
+            (0x3041ed09bead87c0, 0x378d8e6400000000, "0"),
+        ];
+        for &(high, low, expected) in &cases {
+            assert_eq!(from_hex(high, low).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn parses_strings() {
+        let cases = [
+            ("1", (0x3040000000000000, 0x0000000000000001)),
+            ("-0.0", (0xb03e000000000000, 0x0000000000000000)),
+            ("+12.50", (0x303c000000000000, 0x00000000000004e2)),
+            (".5", (0x303e000000000000, 0x0000000000000005)),
+            ("1e1", (0x3042000000000000, 0x0000000000000001)),
+            ("1.234E-7", (0x302c000000000000, 0x00000000000004d2)),
+            ("inf", (0x7800000000000000, 0x0000000000000000)),
+            ("-Infinity", (0xf800000000000000, 0x0000000000000000)),
+            ("nan", (0x7c00000000000000, 0x0000000000000000)),
+            // zeros are clamped to the exponent range
+            ("0E+6112", (0x5ffe000000000000, 0x0000000000000000)),
+            ("0E-6177", (0x0000000000000000, 0x0000000000000000)),
+            // exact clamping of non-zero values
+            ("1E+6112", (0x5ffe000000000000, 0x000000000000000a)),
+            ("1000E-6179", (0x0000000000000000, 0x0000000000000001)),
+            // trailing zeros beyond 34 digits are dropped
+            (
+                "10000000000000000000000000000000000",
+                (0x3042314dc6448d93, 0x38c15b0a00000000),
+            ),
+        ];
+        for &(input, (high, low)) in &cases {
+            assert_eq!(
+                input.parse::<RawDecimal128>(),
+                Ok(from_hex(high, low)),
+                "{}",
+                input
+            );
+        }
+
+        for &input in &[
+            "", "-", ".", "1.2.3", "1e", "1e+", "E5", "1x", "Infinit", "1,000",
+        ] {
+            let err = input.parse::<RawDecimal128>().unwrap_err();
+            assert_eq!(err.kind(), &ErrorKind::BadDecimal, "{}", input);
+        }
+        for &input in &[
+            "10000000000000000000000000000000001",
+            "1E+6145",
+            "1E-6177",
+            "1.1E-6176",
+        ] {
+            let err = input.parse::<RawDecimal128>().unwrap_err();
+            assert_eq!(err.kind(), &ErrorKind::OutOfRange, "{}", input);
+        }
+    }
+
+    #[test]
+    fn formats_and_reparses() {
+        for &input in &[
+            "0",
+            "-0",
+            "1",
+            "0.0001",
+            "0.000001",
+            "1E-7",
+            "123.456",
+            "1.00",
+            "-5E+3",
+            "0E-6176",
+            "Infinity",
+            "NaN",
+            "1.000000000000000000000000000000000E+6144",
+        ] {
+            let value: RawDecimal128 = input.parse().unwrap();
+            assert_eq!(value.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn integer_conversions() {
+        assert_eq!(i128::try_from(RawDecimal128::from(-7i32)), Ok(-7));
+        assert_eq!(
+            i64::try_from(RawDecimal128::from(u64::MAX))
+                .unwrap_err()
+                .kind(),
+            &ErrorKind::OutOfRange
+        );
+        assert_eq!(
+            i128::try_from("1.50E+2".parse::<RawDecimal128>().unwrap()),
+            Ok(150)
+        );
+        assert_eq!(
+            i128::try_from("2.000".parse::<RawDecimal128>().unwrap()),
+            Ok(2)
+        );
+        assert!(i128::try_from("2.5".parse::<RawDecimal128>().unwrap()).is_err());
+        assert!(i128::try_from("1E+39".parse::<RawDecimal128>().unwrap()).is_err());
+        assert!(i128::try_from("NaN".parse::<RawDecimal128>().unwrap()).is_err());
+
+        assert!(RawDecimal128::try_from(i128::MAX).is_err());
+        let big = RawDecimal128::try_from(10i128.pow(37)).unwrap();
+        assert_eq!(big.to_string(), "1.000000000000000000000000000000000E+37");
+        assert_eq!(i128::try_from(big), Ok(10i128.pow(37)));
+
+        let value = RawDecimal128::from_i128_with_scale(-1234, 3).unwrap();
+        assert_eq!(value.to_string(), "-1.234");
+        assert_eq!(value.to_i128_with_scale(), Some((-1234, 3)));
+    }
+
+    #[test]
+    fn float_conversions() {
+        let tenth = RawDecimal128::from(0.1);
+        assert_eq!(tenth.to_string(), "0.1");
+        assert_eq!(f64::try_from(tenth), Ok(0.1));
+        assert_eq!(RawDecimal128::from(-2.5e-10).to_string(), "-2.5E-10");
+        assert!(RawDecimal128::from(f64::NAN).is_nan());
+        assert!(RawDecimal128::from(f64::INFINITY).is_infinite());
+        assert!(RawDecimal128::from(-0.0).is_sign_negative());
+
+        let precise: RawDecimal128 = "0.1000000000000000000001".parse().unwrap();
+        assert_eq!(precise.to_f64(), 0.1);
+        assert_eq!(
+            f64::try_from(precise).unwrap_err().kind(),
+            &ErrorKind::OutOfRange
+        );
+        assert_eq!(
+            f64::try_from("1.500".parse::<RawDecimal128>().unwrap()),
+            Ok(1.5)
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let add = |a: &str, b: &str| {
+            let a: RawDecimal128 = a.parse().unwrap();
+            a.add_rounded(b.parse().unwrap()).to_string()
+        };
+        let mul = |a: &str, b: &str| {
+            let a: RawDecimal128 = a.parse().unwrap();
+            a.mul_rounded(b.parse().unwrap()).to_string()
+        };
+        assert_eq!(add("1.10", "0.2"), "1.30");
+        // Results are rounded to 34 digits, however far apart the
+        // exponents are.
+        assert_eq!(add("1E+40", "1"), "1.000000000000000000000000000000000E+40");
+        assert_eq!(add("1E+40", "-1"), "1.000000000000000000000000000000000E+40");
+        assert_eq!(add("1E+34", "-0.6"), "9999999999999999999999999999999999");
+        assert_eq!(
+            add("1E+100", "1E-100"),
+            "1.000000000000000000000000000000000E+100"
+        );
+        assert_eq!(
+            add("9999999999999999999999999999999999", "1"),
+            "1.000000000000000000000000000000000E+34"
+        );
+        // Ties round to even.
+        assert_eq!(add("1E+34", "-0.5"), "1.000000000000000000000000000000000E+34");
+        assert_eq!(
+            add("1000000000000000000000000000000001E+1", "5"),
+            "1.000000000000000000000000000000002E+34"
+        );
+        assert_eq!(add("1", "-1"), "0");
+        assert_eq!(add("-0", "-0"), "-0");
+        assert_eq!(add("0E+10", "1E-5"), "0.00001");
+        assert_eq!(
+            add("9.999999999999999999999999999999999E+6144", "1E+6111"),
+            "Infinity"
+        );
+        assert!(RawDecimal128::from(f64::INFINITY)
+            .add_rounded(RawDecimal128::from(f64::NEG_INFINITY))
+            .is_nan());
+
+        assert_eq!(mul("1.5", "-2"), "-3.0");
+        assert_eq!(
+            mul("1111111111111111111111111111111111", "1111111111111111111111111111111111"),
+            "1.234567901234567901234567901234568E+66"
+        );
+        assert_eq!(mul("1E-6176", "0.1"), "0E-6176");
+        assert_eq!(mul("1E-6176", "0.6"), "1E-6176");
+        assert_eq!(mul("1E+6144", "-10"), "-Infinity");
+        assert!(RawDecimal128::from(0u8)
+            .mul_rounded(RawDecimal128::from(f64::INFINITY))
+            .is_nan());
+    }
+
+    #[test]
+    fn bson_conversions() {
+        let value: RawDecimal128 = "-12.345E+20".parse().unwrap();
+        let decimal = bson::Decimal128::from(value);
+        assert_eq!(RawDecimal128::try_from(&decimal), Ok(value));
+    }
+}
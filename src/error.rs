@@ -0,0 +1,285 @@
+use std::fmt;
+
+use bson::{document::ValueAccessError, spec::ElementType};
+
+/// The kind of problem described by a [`RawError`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Found a Bson value with the specified key, but not with the expected type
+    UnexpectedType,
+
+    /// A length prefix, or the value it describes, extends past the end of
+    /// the enclosing buffer
+    TruncatedLength,
+
+    /// A declared length is negative, too small, or disagrees with the data
+    BadLength,
+
+    /// A document, string or key is missing its NUL terminator
+    MissingNul,
+
+    /// A key or other NUL-terminated string contains an interior NUL byte
+    InteriorNul,
+
+    /// Found a value where a utf-8 string was expected, but it was not valid
+    /// utf-8
+    BadUtf8,
+
+    /// An element has an unknown type tag
+    InvalidTag(u8),
+
+    /// A boolean value was neither 0 nor 1
+    BadBool(u8),
+
+    /// An array key was not the expected index
+    BadArrayIndex,
+
+    /// A binary value uses a reserved subtype
+    ReservedSubtype(u8),
+
+    /// A value cannot be represented in the requested type
+    OutOfRange,
+
+    /// A string could not be parsed as a decimal128 value
+    BadDecimal,
+
+    /// A declared document length exceeds the configured maximum size
+    TooLarge,
+
+    /// A wire protocol message sets flag bits that are required to be
+    /// understood, but are not
+    BadFlags(u32),
+
+    /// An `OP_MSG` does not have exactly one body section
+    BadSections,
+
+    /// A query or update document uses an operator that is not supported
+    UnknownOperator(String),
+
+    /// The value given to a query or update operator has the wrong type or
+    /// is out of range for it
+    BadOperand(String),
+
+    /// A regular expression in a query could not be compiled
+    BadRegex(String),
+
+    /// A projection mixes inclusion and exclusion, or names a field both
+    /// on its own and through a longer path
+    BadProjection,
+
+    /// An update changes the same field twice, or both a field and a path
+    /// through it
+    ConflictingUpdate,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ErrorKind::*;
+        match self {
+            UnexpectedType => write!(f, "unexpected type"),
+            TruncatedLength => write!(f, "value truncated"),
+            BadLength => write!(f, "bad declared length"),
+            MissingNul => write!(f, "missing NUL terminator"),
+            InteriorNul => write!(f, "interior NUL byte"),
+            BadUtf8 => write!(f, "utf-8 encoding error"),
+            InvalidTag(tag) => write!(f, "invalid element type tag {:#04x}", tag),
+            BadBool(value) => write!(f, "boolean value {:#04x} was not 0 or 1", value),
+            BadArrayIndex => write!(f, "wrong array index found"),
+            ReservedSubtype(subtype) => write!(f, "reserved binary subtype {:#04x}", subtype),
+            OutOfRange => write!(f, "value out of range"),
+            BadDecimal => write!(f, "invalid decimal128 string"),
+            TooLarge => write!(f, "document exceeds the maximum size"),
+            BadFlags(flags) => write!(f, "unknown required flag bits {:#010x}", flags),
+            BadSections => write!(f, "message does not have exactly one body section"),
+            UnknownOperator(operator) => write!(f, "unknown operator {}", operator),
+            BadOperand(operator) => write!(f, "invalid operand for {}", operator),
+            BadRegex(message) => write!(f, "invalid regular expression: {}", message),
+            BadProjection => write!(f, "conflicting projection"),
+            ConflictingUpdate => write!(f, "conflicting update paths"),
+        }
+    }
+}
+
+/// Error returned when reading or building raw BSON.
+///
+/// Besides its [`ErrorKind`], a `RawError` records where the problem was
+/// found, when that is known: the byte offset, the dotted key path (such as
+/// `a.b.3.c`) and the type of the element involved.
+///
+/// Offsets and key paths are relative to the document the failing operation
+/// started from.  Operations that walk a whole document, such as iteration,
+/// [`Doc::validate`](crate::Doc::validate) or deserialization, report
+/// offsets into the root buffer; an error from a nested document reached
+/// with [`Doc::get_document`](crate::Doc::get_document) is relative to that
+/// nested document.
+///
+/// ```
+/// # use rawbson::{Doc, ErrorKind};
+/// use bson::spec::ElementType;
+/// // {"a": {"b": <boolean 0x02>}}
+/// let bytes = b"\x11\0\0\0\x03a\0\x09\0\0\0\x08b\0\x02\0\0";
+/// let err = Doc::new(&bytes[..])?.validate().unwrap_err();
+/// assert_eq!(err.kind(), &ErrorKind::BadBool(2));
+/// assert_eq!(err.offset(), Some(14));
+/// assert_eq!(err.key_path(), "a.b");
+/// assert_eq!(err.element_type(), Some(ElementType::Boolean));
+/// # Ok::<(), rawbson::RawError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawError {
+    kind: ErrorKind,
+    pub(crate) context: Context,
+}
+
+/// Where an error was found: the offset, key path and element type shared
+/// by [`RawError`] and [`de::Error`](crate::de::Error).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Context {
+    pub(crate) offset: Option<usize>,
+    pub(crate) key_path: Vec<String>,
+    pub(crate) element_type: Option<ElementType>,
+}
+
+impl Context {
+    pub(crate) fn of_type(&mut self, element_type: ElementType) {
+        self.element_type.get_or_insert(element_type);
+    }
+
+    pub(crate) fn in_key(&mut self, key: &str) {
+        self.key_path.insert(0, key.into());
+    }
+
+    pub(crate) fn shifted(&mut self, base: usize) {
+        self.offset = Some(base + self.offset.unwrap_or(0));
+    }
+
+    pub(crate) fn within(&mut self, key: &str, value_offset: usize, element_type: ElementType) {
+        if self.key_path.is_empty() {
+            self.of_type(element_type);
+        }
+        self.shifted(value_offset);
+        self.in_key(key);
+    }
+
+    pub(crate) fn key_path(&self) -> String {
+        self.key_path.join(".")
+    }
+}
+
+/// Writes the location, if any is known, to follow the error kind.
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        if !self.key_path.is_empty() {
+            write!(f, " in key {:?}", self.key_path())?;
+        }
+        if let Some(element_type) = self.element_type {
+            write!(f, " ({:?})", element_type)?;
+        }
+        Ok(())
+    }
+}
+
+impl RawError {
+    pub(crate) fn new(kind: ErrorKind) -> RawError {
+        RawError {
+            kind,
+            context: Context::default(),
+        }
+    }
+
+    /// Record the offset of the error, relative to the buffer being read.
+    pub(crate) fn at(mut self, offset: usize) -> RawError {
+        self.context.offset = Some(offset);
+        self
+    }
+
+    /// Record the type of the element involved, unless one is already known.
+    pub(crate) fn of_type(mut self, element_type: ElementType) -> RawError {
+        self.context.of_type(element_type);
+        self
+    }
+
+    /// Record the key of the element involved, without adjusting the offset.
+    pub(crate) fn in_key(mut self, key: &str) -> RawError {
+        self.context.in_key(key);
+        self
+    }
+
+    /// Move the offset of the error forward by `base` bytes, for an error
+    /// found in a sub-slice starting at `base`.
+    pub(crate) fn shifted(mut self, base: usize) -> RawError {
+        self.context.shifted(base);
+        self
+    }
+
+    /// Add context for an error found while reading the value of element
+    /// `key`, whose value starts `value_offset` bytes into the enclosing
+    /// document.
+    pub(crate) fn within(
+        mut self,
+        key: &str,
+        value_offset: usize,
+        element_type: ElementType,
+    ) -> RawError {
+        self.context.within(key, value_offset, element_type);
+        self
+    }
+
+    /// The kind of problem that was found.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The byte offset where the problem was found, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.context.offset
+    }
+
+    /// The dotted path of keys leading to the element involved, or an empty
+    /// string if the error concerns the document itself.
+    pub fn key_path(&self) -> String {
+        self.context.key_path()
+    }
+
+    /// The type of the element involved, if known.
+    pub fn element_type(&self) -> Option<ElementType> {
+        self.context.element_type
+    }
+}
+
+impl From<ErrorKind> for RawError {
+    fn from(kind: ErrorKind) -> RawError {
+        RawError::new(kind)
+    }
+}
+
+/// Lets APIs that accept `impl TryInto<RawBson>` take infallible
+/// conversions, such as from an [`Element`](crate::elem::Element), too.
+impl From<std::convert::Infallible> for RawError {
+    fn from(never: std::convert::Infallible) -> RawError {
+        match never {}
+    }
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.kind, self.context)
+    }
+}
+
+impl std::error::Error for RawError {}
+
+pub type RawResult<T> = Result<T, RawError>;
+
+impl From<ValueAccessError> for RawError {
+    fn from(src: ValueAccessError) -> RawError {
+        match src {
+            ValueAccessError::NotPresent => unreachable!("This should be converted to an Option"),
+            _ => RawError::new(ErrorKind::UnexpectedType),
+        }
+    }
+}
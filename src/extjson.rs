@@ -0,0 +1,506 @@
+//! MongoDB Extended JSON (v2) input and output.
+//!
+//! [`Doc::to_extjson`] and [`Doc::write_extjson`] walk a raw document
+//! directly, without converting it to a [`bson::Document`] first, and write
+//! it in either of the two formats defined by the [Extended JSON
+//! specification](https://github.com/mongodb/specifications/blob/master/source/extended-json.rst):
+//!
+//! * [`Mode::Canonical`] preserves the type of every value, wrapping numbers
+//!   as `{"$numberInt": "1"}`, `{"$numberLong": "1"}` and so on.
+//! * [`Mode::Relaxed`] writes numbers as plain JSON numbers where that loses
+//!   nothing but the exact type, and dates between the years 1970 and 9999
+//!   as ISO-8601 strings.
+//!
+//! The output is compact, with no whitespace between tokens.
+//!
+//! ```
+//! use bson::doc;
+//! use rawbson::{extjson::Mode, DocBuf};
+//!
+//! let docbuf = DocBuf::from_document(&doc! {"n": 1, "x": 2.5, "big": 1i64 << 40});
+//! assert_eq!(
+//!     docbuf.to_extjson(Mode::Canonical)?,
+//!     r#"{"n":{"$numberInt":"1"},"x":{"$numberDouble":"2.5"},"big":{"$numberLong":"1099511627776"}}"#,
+//! );
+//! assert_eq!(docbuf.to_extjson(Mode::Relaxed)?, r#"{"n":1,"x":2.5,"big":1099511627776}"#);
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+//!
+//! [`DocBuf::from_extjson`](crate::DocBuf::from_extjson) goes the other way,
+//! writing BSON bytes as it parses either format.
+
+mod parse;
+
+use std::io::{self, Write};
+
+use bson::spec::ElementType;
+use chrono::{Datelike, TimeZone, Utc};
+
+use crate::{elem::Element, ArrayIter, Doc, DocIter, RawError, RawResult};
+
+pub use parse::ParseError;
+pub(crate) use parse::parse_document;
+
+/// The flavor of Extended JSON to write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Preserve the exact BSON type of every value.
+    Canonical,
+    /// Prefer plain JSON numbers and readable dates where possible.
+    Relaxed,
+}
+
+/// A failure while writing extended JSON: either the document is malformed,
+/// or the writer failed.
+pub(crate) enum WriteError {
+    Raw(RawError),
+    Io(io::Error),
+}
+
+impl From<RawError> for WriteError {
+    fn from(err: RawError) -> WriteError {
+        WriteError::Raw(err)
+    }
+}
+
+impl From<io::Error> for WriteError {
+    fn from(err: io::Error) -> WriteError {
+        WriteError::Io(err)
+    }
+}
+
+enum Frame<'a> {
+    Doc(DocIter<'a>),
+    Array(ArrayIter<'a>),
+}
+
+impl<'a> Frame<'a> {
+    fn next(&mut self) -> Option<RawResult<(Option<&'a str>, Element<'a>)>> {
+        match self {
+            Frame::Doc(iter) => iter
+                .next()
+                .map(|result| result.map(|(key, elem)| (Some(key), elem))),
+            Frame::Array(iter) => iter.next().map(|result| result.map(|elem| (None, elem))),
+        }
+    }
+}
+
+/// Write `doc` to `writer` as extended JSON.
+///
+/// Nested documents and arrays are tracked on an explicit stack, so deeply
+/// nested input cannot overflow the call stack.
+pub(crate) fn write_document<W: Write + ?Sized>(
+    doc: &Doc,
+    mode: Mode,
+    writer: &mut W,
+) -> Result<(), WriteError> {
+    // Each frame is paired with the text that closes it, and whether an
+    // element has been written into it yet.
+    let mut stack = vec![(Frame::Doc(doc.into_iter()), "}", true)];
+    writer.write_all(b"{")?;
+    while let Some((frame, close, first)) = stack.last_mut() {
+        let (key, elem) = match frame.next() {
+            Some(result) => result?,
+            None => {
+                writer.write_all(close.as_bytes())?;
+                stack.pop();
+                continue;
+            }
+        };
+        if !std::mem::replace(first, false) {
+            writer.write_all(b",")?;
+        }
+        if let Some(key) = key {
+            write_string(writer, key)?;
+            writer.write_all(b":")?;
+        }
+        match elem.element_type() {
+            ElementType::EmbeddedDocument => {
+                writer.write_all(b"{")?;
+                stack.push((Frame::Doc(elem.as_document()?.into_iter()), "}", true));
+            }
+            ElementType::Array => {
+                writer.write_all(b"[")?;
+                stack.push((Frame::Array(elem.as_array()?.into_iter()), "]", true));
+            }
+            ElementType::JavaScriptCodeWithScope => {
+                let (code, scope) = elem.as_javascript_with_scope()?;
+                writer.write_all(br#"{"$code":"#)?;
+                write_string(writer, code)?;
+                writer.write_all(br#","$scope":{"#)?;
+                stack.push((Frame::Doc(scope.into_iter()), "}}", true));
+            }
+            _ => write_value(writer, elem, mode)?,
+        }
+    }
+    Ok(())
+}
+
+/// Write a value that is not a document, an array, or javascript with scope.
+fn write_value<W: Write + ?Sized>(
+    writer: &mut W,
+    elem: Element<'_>,
+    mode: Mode,
+) -> Result<(), WriteError> {
+    match elem.element_type() {
+        ElementType::Double => {
+            let value = elem.as_f64()?;
+            let formatted = format_double(value);
+            if mode == Mode::Relaxed && value.is_finite() {
+                writer.write_all(formatted.as_bytes())?;
+            } else {
+                writer.write_all(br#"{"$numberDouble":""#)?;
+                writer.write_all(formatted.as_bytes())?;
+                writer.write_all(br#""}"#)?;
+            }
+        }
+        ElementType::String => write_string(writer, elem.as_str()?)?,
+        ElementType::Binary => {
+            let binary = elem.as_binary()?;
+            write!(
+                writer,
+                r#"{{"$binary":{{"base64":"{}","subType":"{:02x}"}}}}"#,
+                base64::encode(binary.as_bytes()),
+                u8::from(binary.subtype()),
+            )?;
+        }
+        ElementType::Undefined => writer.write_all(br#"{"$undefined":true}"#)?,
+        ElementType::ObjectId => {
+            write!(writer, r#"{{"$oid":"{}"}}"#, elem.as_object_id()?.to_hex())?
+        }
+        ElementType::Boolean => match elem.as_bool()? {
+            true => writer.write_all(b"true")?,
+            false => writer.write_all(b"false")?,
+        },
+        ElementType::DateTime => {
+            let millis = elem.datetime_millis()?;
+            let relaxed = match Utc.timestamp_millis_opt(millis).single() {
+                Some(date) if mode == Mode::Relaxed && (1970..=9999).contains(&date.year()) => {
+                    Some(date)
+                }
+                _ => None,
+            };
+            match relaxed {
+                Some(date) if millis % 1000 == 0 => write!(
+                    writer,
+                    r#"{{"$date":"{}"}}"#,
+                    date.format("%Y-%m-%dT%H:%M:%SZ")
+                )?,
+                Some(date) => write!(
+                    writer,
+                    r#"{{"$date":"{}"}}"#,
+                    date.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                )?,
+                None => write!(writer, r#"{{"$date":{{"$numberLong":"{}"}}}}"#, millis)?,
+            }
+        }
+        ElementType::Null => writer.write_all(b"null")?,
+        ElementType::RegularExpression => {
+            let regex = elem.as_regex()?;
+            let mut options: Vec<char> = regex.options().chars().collect();
+            options.sort_unstable();
+            let options: String = options.into_iter().collect();
+            writer.write_all(br#"{"$regularExpression":{"pattern":"#)?;
+            write_string(writer, regex.pattern())?;
+            writer.write_all(br#","options":"#)?;
+            write_string(writer, &options)?;
+            writer.write_all(b"}}")?;
+        }
+        ElementType::DbPointer => {
+            let pointer = elem.as_db_pointer()?;
+            writer.write_all(br#"{"$dbPointer":{"$ref":"#)?;
+            write_string(writer, pointer.namespace())?;
+            write!(
+                writer,
+                r#","$id":{{"$oid":"{}"}}}}}}"#,
+                pointer.id().to_hex()
+            )?;
+        }
+        ElementType::JavaScriptCode => {
+            writer.write_all(br#"{"$code":"#)?;
+            write_string(writer, elem.as_javascript()?)?;
+            writer.write_all(b"}")?;
+        }
+        ElementType::Symbol => {
+            writer.write_all(br#"{"$symbol":"#)?;
+            write_string(writer, elem.as_symbol()?)?;
+            writer.write_all(b"}")?;
+        }
+        ElementType::Int32 => match mode {
+            Mode::Canonical => write!(writer, r#"{{"$numberInt":"{}"}}"#, elem.as_i32()?)?,
+            Mode::Relaxed => write!(writer, "{}", elem.as_i32()?)?,
+        },
+        ElementType::Timestamp => {
+            let ts = elem.as_timestamp()?;
+            write!(
+                writer,
+                r#"{{"$timestamp":{{"t":{},"i":{}}}}}"#,
+                ts.time(),
+                ts.increment()
+            )?;
+        }
+        ElementType::Int64 => match mode {
+            Mode::Canonical => write!(writer, r#"{{"$numberLong":"{}"}}"#, elem.as_i64()?)?,
+            Mode::Relaxed => write!(writer, "{}", elem.as_i64()?)?,
+        },
+        ElementType::Decimal128 => write!(
+            writer,
+            r#"{{"$numberDecimal":"{}"}}"#,
+            elem.as_decimal128()?
+        )?,
+        ElementType::MinKey => writer.write_all(br#"{"$minKey":1}"#)?,
+        ElementType::MaxKey => writer.write_all(br#"{"$maxKey":1}"#)?,
+        ElementType::EmbeddedDocument
+        | ElementType::Array
+        | ElementType::JavaScriptCodeWithScope => unreachable!("written by write_document"),
+    }
+    Ok(())
+}
+
+/// Format a double the way the extended JSON corpus expects: the shortest
+/// representation that round-trips, with an exponent only for very large or
+/// very small magnitudes.
+fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return String::from("NaN");
+    } else if value.is_infinite() {
+        return String::from(if value > 0.0 { "Infinity" } else { "-Infinity" });
+    }
+    let formatted = format!("{:?}", value);
+    match formatted.split_once('e') {
+        Some((mantissa, exponent)) if exponent.starts_with('-') => {
+            format!("{}E{}", mantissa, exponent)
+        }
+        Some((mantissa, exponent)) => format!("{}E+{}", mantissa, exponent),
+        None => formatted,
+    }
+}
+
+/// Write `value` as a quoted JSON string.
+fn write_string<W: Write + ?Sized>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    let bytes = value.as_bytes();
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        let escape: &[u8] = match byte {
+            b'"' => b"\\\"",
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            0x08 => b"\\b",
+            0x0c => b"\\f",
+            0..=0x1f => b"",
+            _ => continue,
+        };
+        writer.write_all(&bytes[start..i])?;
+        if escape.is_empty() {
+            write!(writer, "\\u{:04x}", byte)?;
+        } else {
+            writer.write_all(escape)?;
+        }
+        start = i + 1;
+    }
+    writer.write_all(&bytes[start..])?;
+    writer.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope};
+    use chrono::TimeZone;
+
+    use super::Mode;
+    use crate::{elem::RawDecimal128, Doc, DocBuf, DocBufBuilder, ErrorKind};
+
+    fn both(docbuf: &DocBuf) -> (String, String) {
+        (
+            docbuf.to_extjson(Mode::Canonical).unwrap(),
+            docbuf.to_extjson(Mode::Relaxed).unwrap(),
+        )
+    }
+
+    #[test]
+    fn numbers() {
+        let cases: &[(f64, &str, &str)] = &[
+            (1.0, r#"{"$numberDouble":"1.0"}"#, "1.0"),
+            (-0.0, r#"{"$numberDouble":"-0.0"}"#, "-0.0"),
+            (
+                1.0001220703125,
+                r#"{"$numberDouble":"1.0001220703125"}"#,
+                "1.0001220703125",
+            ),
+            (
+                -1.2345678921232e18,
+                r#"{"$numberDouble":"-1.2345678921232E+18"}"#,
+                "-1.2345678921232E+18",
+            ),
+            (
+                f64::MAX,
+                r#"{"$numberDouble":"1.7976931348623157E+308"}"#,
+                "1.7976931348623157E+308",
+            ),
+            (5e-324, r#"{"$numberDouble":"5E-324"}"#, "5E-324"),
+            (
+                f64::NAN,
+                r#"{"$numberDouble":"NaN"}"#,
+                r#"{"$numberDouble":"NaN"}"#,
+            ),
+            (
+                f64::NEG_INFINITY,
+                r#"{"$numberDouble":"-Infinity"}"#,
+                r#"{"$numberDouble":"-Infinity"}"#,
+            ),
+        ];
+        for &(value, canonical, relaxed) in cases {
+            let docbuf = DocBuf::from_document(&doc! {"d": value});
+            assert_eq!(
+                both(&docbuf),
+                (
+                    format!(r#"{{"d":{}}}"#, canonical),
+                    format!(r#"{{"d":{}}}"#, relaxed)
+                ),
+                "{}",
+                value
+            );
+        }
+
+        let docbuf = DocBuf::from_document(&doc! {"i": -2147483648, "l": i64::MAX});
+        assert_eq!(
+            both(&docbuf),
+            (
+                String::from(
+                    r#"{"i":{"$numberInt":"-2147483648"},"l":{"$numberLong":"9223372036854775807"}}"#
+                ),
+                String::from(r#"{"i":-2147483648,"l":9223372036854775807}"#),
+            )
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let cases: &[(i64, &str)] = &[
+            (0, r#""1970-01-01T00:00:00Z""#),
+            (1356351330501, r#""2012-12-24T12:15:30.501Z""#),
+            (-284643869501, r#"{"$numberLong":"-284643869501"}"#),
+            (253402300800000, r#"{"$numberLong":"253402300800000"}"#),
+            (i64::MAX, r#"{"$numberLong":"9223372036854775807"}"#),
+        ];
+        for &(millis, relaxed) in cases {
+            let mut bytes = vec![16, 0, 0, 0, 0x09, b'a', 0];
+            bytes.extend_from_slice(&millis.to_le_bytes());
+            bytes.push(0);
+            let docbuf = DocBuf::new(bytes).unwrap();
+            assert_eq!(
+                both(&docbuf),
+                (
+                    format!(r#"{{"a":{{"$date":{{"$numberLong":"{}"}}}}}}"#, millis),
+                    format!(r#"{{"a":{{"$date":{}}}}}"#, relaxed),
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn other_types() {
+        let id = ObjectId::with_string("57e193d7a9cc81b4027498b5").unwrap();
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_str("s", "tab\t\"quote\" \\ \u{1} é")
+            .append_binary("b", BinarySubtype::UserDefined(0x80), b"\xff\xff")
+            .append_binary("old", BinarySubtype::BinaryOld, b"\xff\xff")
+            .append_object_id("o", id.clone())
+            .append_bool("t", true)
+            .append_null("n")
+            .append_regex("r", "a\\d+", "mi")
+            .append_db_pointer("p", "db.c", id)
+            .append_javascript("c", "f()")
+            .append_symbol("sym", "x")
+            .append_timestamp("ts", 123456789, 42)
+            .append_decimal128("dec", "-1.0E+3".parse::<RawDecimal128>().unwrap())
+            .append_undefined("u")
+            .append_min_key("min")
+            .append_max_key("max");
+        let docbuf = builder.finish().unwrap();
+        let expected = concat!(
+            r#"{"s":"tab\t\"quote\" \\ \u0001 é","#,
+            r#""b":{"$binary":{"base64":"//8=","subType":"80"}},"#,
+            r#""old":{"$binary":{"base64":"//8=","subType":"02"}},"#,
+            r#""o":{"$oid":"57e193d7a9cc81b4027498b5"},"t":true,"n":null,"#,
+            r#""r":{"$regularExpression":{"pattern":"a\\d+","options":"im"}},"#,
+            r#""p":{"$dbPointer":{"$ref":"db.c","$id":{"$oid":"57e193d7a9cc81b4027498b5"}}},"#,
+            r#""c":{"$code":"f()"},"sym":{"$symbol":"x"},"#,
+            r#""ts":{"$timestamp":{"t":123456789,"i":42}},"#,
+            r#""dec":{"$numberDecimal":"-1.0E+3"},"#,
+            r#""u":{"$undefined":true},"min":{"$minKey":1},"max":{"$maxKey":1}}"#,
+        );
+        assert_eq!(both(&docbuf), (expected.into(), expected.into()));
+    }
+
+    #[test]
+    fn matches_bson_crate() {
+        let document = doc! {
+            "i": 1,
+            "l": 2i64,
+            "s": "hello",
+            "doc": {"list": [1, {"x": "y"}, []], "empty": {}},
+            "date": Bson::DateTime(chrono::Utc.timestamp_millis_opt(1_600_000_000_123).unwrap()),
+            "bin": Binary { subtype: BinarySubtype::Uuid, bytes: b"0123456789abcdef".to_vec() },
+            "scoped": JavaScriptCodeWithScope {
+                code: String::from("g(x)"),
+                scope: doc! { "x": 1, "inner": {"y": [2]} },
+            },
+            "max": Bson::MaxKey,
+        };
+        let docbuf = DocBuf::from_document(&document);
+        for (mode, expected) in [
+            (
+                Mode::Canonical,
+                Bson::Document(document.clone()).into_canonical_extjson(),
+            ),
+            (
+                Mode::Relaxed,
+                Bson::Document(document).into_relaxed_extjson(),
+            ),
+        ] {
+            let json: serde_json::Value =
+                serde_json::from_str(&docbuf.to_extjson(mode).unwrap()).unwrap();
+            assert_eq!(json, expected);
+        }
+    }
+
+    #[test]
+    fn malformed_documents() {
+        // The string claims to be 255 bytes long.
+        let doc = Doc::new(b"\x13\x00\x00\x00\x02hi\x00\xff\x00\x00\x00y'all\x00\x00").unwrap();
+        let err = doc.to_extjson(Mode::Canonical).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::TruncatedLength);
+
+        let mut out = Vec::new();
+        let err = doc.write_extjson(Mode::Relaxed, &mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(out, b"{");
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // {"a": {"a": ... {} ... }}, built directly to keep the test fast.
+        let depth = 100_000;
+        let mut bytes = Vec::new();
+        for level in 0..depth {
+            let length = 5 + 8 * (depth - level) as i32;
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(b"\x03a\x00");
+        }
+        bytes.extend_from_slice(&[5, 0, 0, 0, 0]);
+        bytes.resize(bytes.len() + depth, 0);
+        let doc = Doc::new(&bytes).unwrap();
+
+        let json = doc.to_extjson(Mode::Canonical).unwrap();
+        assert_eq!(json.len(), 6 * depth + 2);
+        assert!(json.starts_with(r#"{"a":{"a":{"#));
+        assert!(json.ends_with("}}}"));
+    }
+}
@@ -0,0 +1,1137 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+
+use bson::{oid, spec::ElementType};
+
+use crate::elem::RawDecimal128;
+
+/// Objects and arrays nested deeper than this are rejected, so that
+/// malicious input cannot overflow the stack of the recursive parser.
+const MAX_DEPTH: usize = 256;
+
+/// An error from [`DocBuf::from_extjson`](crate::DocBuf::from_extjson),
+/// with the line and column where it was detected.
+///
+/// Lines and columns start at 1, and columns count characters rather than
+/// bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    message: String,
+    line: usize,
+    column: usize,
+}
+
+impl ParseError {
+    /// A description of the problem, without the position.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+enum Number {
+    Int(i64),
+    Double(f64),
+}
+
+/// Parse an extended JSON object into the bytes of a BSON document.
+pub(crate) fn parse_document(text: &str) -> ParseResult<Vec<u8>> {
+    let mut parser = Parser {
+        text,
+        input: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+        out: Vec::with_capacity(text.len()),
+    };
+    if parser.peek() != Some(b'{') {
+        return Err(parser.error("expected `{`"));
+    }
+    parser.document()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(parser.out)
+}
+
+/// A recursive descent parser that writes BSON into `out` as it goes.
+struct Parser<'a> {
+    text: &'a str,
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+    out: Vec<u8>,
+}
+
+impl<'a> Parser<'a> {
+    fn error_at(&self, pos: usize, message: impl Into<String>) -> ParseError {
+        let before = &self.input[..pos];
+        let line_start = before
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |i| i + 1);
+        ParseError {
+            message: message.into(),
+            line: before.iter().filter(|&&byte| byte == b'\n').count() + 1,
+            // Count characters by skipping utf-8 continuation bytes.
+            column: before[line_start..]
+                .iter()
+                .filter(|&&byte| byte & 0xc0 != 0x80)
+                .count()
+                + 1,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.error_at(self.pos, message)
+    }
+
+    /// Skip whitespace and return the next byte without consuming it.
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+        self.input.get(self.pos).copied()
+    }
+
+    /// Consume `byte` if it is next.
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> ParseResult<()> {
+        match self.peek() {
+            Some(next) if next == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.error(format!("expected `{}`", byte as char))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn enter(&mut self) -> ParseResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str) -> ParseResult<()> {
+        if self.input[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(())
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn string(&mut self) -> ParseResult<Cow<'a, str>> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut owned: Option<String> = None;
+        let mut run_start = self.pos;
+        loop {
+            match self.input.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    let run = &self.text[run_start..self.pos];
+                    self.pos += 1;
+                    return Ok(match owned {
+                        None => Cow::Borrowed(run),
+                        Some(mut value) => {
+                            value.push_str(run);
+                            Cow::Owned(value)
+                        }
+                    });
+                }
+                Some(b'\\') => {
+                    let value = owned.get_or_insert_with(String::new);
+                    value.push_str(&self.text[run_start..self.pos]);
+                    self.pos += 1;
+                    value.push(self.escape()?);
+                    run_start = self.pos;
+                }
+                Some(&byte) if byte < 0x20 => return Err(self.error("control character in string")),
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Decode the escape sequence following a backslash.
+    fn escape(&mut self) -> ParseResult<char> {
+        let byte = match self.input.get(self.pos) {
+            Some(&byte) => byte,
+            None => return Err(self.error("unterminated string")),
+        };
+        self.pos += 1;
+        Ok(match byte {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let start = self.pos - 2;
+                let high = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    if !self.input[self.pos..].starts_with(b"\\u") {
+                        return Err(self.error_at(start, "unpaired surrogate in string"));
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error_at(start, "unpaired surrogate in string"));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+                std::char::from_u32(code)
+                    .ok_or_else(|| self.error_at(start, "unpaired surrogate in string"))?
+            }
+            _ => return Err(self.error_at(self.pos - 2, "invalid escape")),
+        })
+    }
+
+    fn hex4(&mut self) -> ParseResult<u32> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn number(&mut self) -> ParseResult<Number> {
+        self.peek();
+        let start = self.pos;
+        let digits = |input: &[u8], mut pos: usize| {
+            let from = pos;
+            while input.get(pos).is_some_and(u8::is_ascii_digit) {
+                pos += 1;
+            }
+            (pos, pos > from)
+        };
+        let mut pos = start;
+        if self.input.get(pos) == Some(&b'-') {
+            pos += 1;
+        }
+        let (end, found) = digits(self.input, pos);
+        let leading_zero = self.input.get(pos) == Some(&b'0') && end > pos + 1;
+        if !found || leading_zero {
+            return Err(self.error("invalid number"));
+        }
+        pos = end;
+        let mut integer = true;
+        if self.input.get(pos) == Some(&b'.') {
+            let (end, found) = digits(self.input, pos + 1);
+            if !found {
+                return Err(self.error_at(start, "invalid number"));
+            }
+            integer = false;
+            pos = end;
+        }
+        if let Some(b'e' | b'E') = self.input.get(pos) {
+            pos += 1;
+            if let Some(b'+' | b'-') = self.input.get(pos) {
+                pos += 1;
+            }
+            let (end, found) = digits(self.input, pos);
+            if !found {
+                return Err(self.error_at(start, "invalid number"));
+            }
+            integer = false;
+            pos = end;
+        }
+        self.pos = pos;
+        let text = &self.text[start..pos];
+        match text.parse() {
+            Ok(value) if integer => Ok(Number::Int(value)),
+            _ => text
+                .parse()
+                .map(Number::Double)
+                .map_err(|_| self.error_at(start, "invalid number")),
+        }
+    }
+
+    /// Parse an object key and the colon after it.
+    fn key(&mut self) -> ParseResult<Cow<'a, str>> {
+        self.peek();
+        let start = self.pos;
+        let key = self.string()?;
+        if key.contains('\0') {
+            return Err(self.error_at(start, "key contains a nul byte"));
+        }
+        self.expect(b':')?;
+        Ok(key)
+    }
+
+    fn patch_length(&mut self, start: usize) -> ParseResult<()> {
+        let length =
+            i32::try_from(self.out.len() - start).map_err(|_| self.error("document too large"))?;
+        self.out[start..start + 4].copy_from_slice(&length.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_lenencoded(&mut self, value: &str) -> ParseResult<()> {
+        let length = i32::try_from(value.len() + 1).map_err(|_| self.error("string too long"))?;
+        self.out.extend_from_slice(&length.to_le_bytes());
+        self.out.extend_from_slice(value.as_bytes());
+        self.out.push(0);
+        Ok(())
+    }
+
+    fn write_cstring(&mut self, value: &str, pos: usize) -> ParseResult<()> {
+        if value.contains('\0') {
+            return Err(self.error_at(pos, "string contains a nul byte"));
+        }
+        self.out.extend_from_slice(value.as_bytes());
+        self.out.push(0);
+        Ok(())
+    }
+
+    /// Parse an object as a document, whatever its keys.
+    fn document(&mut self) -> ParseResult<()> {
+        self.enter()?;
+        self.expect(b'{')?;
+        let start = self.out.len();
+        self.out.extend_from_slice(&[0; 4]);
+        if self.eat(b'}') {
+            self.out.push(0);
+            self.patch_length(start)?;
+        } else {
+            let key = self.key()?;
+            self.element(&key)?;
+            self.finish_document(start)?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Parse the remaining members of a document begun at `start`, which
+    /// already holds at least one element.
+    fn finish_document(&mut self, start: usize) -> ParseResult<()> {
+        while self.eat(b',') {
+            let key = self.key()?;
+            self.element(&key)?;
+        }
+        self.expect(b'}')?;
+        self.out.push(0);
+        self.patch_length(start)
+    }
+
+    fn array(&mut self) -> ParseResult<()> {
+        self.enter()?;
+        self.expect(b'[')?;
+        let start = self.out.len();
+        self.out.extend_from_slice(&[0; 4]);
+        if !self.eat(b']') {
+            let mut index = 0usize;
+            loop {
+                self.element(&index.to_string())?;
+                index += 1;
+                if !self.eat(b',') {
+                    break;
+                }
+            }
+            self.expect(b']')?;
+        }
+        self.out.push(0);
+        self.patch_length(start)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Parse a value and write it as an element named `key`.
+    fn element(&mut self, key: &str) -> ParseResult<()> {
+        let type_pos = self.out.len();
+        self.out.push(0);
+        self.out.extend_from_slice(key.as_bytes());
+        self.out.push(0);
+        let element_type = self.value()?;
+        self.out[type_pos] = element_type as u8;
+        Ok(())
+    }
+
+    fn value(&mut self) -> ParseResult<ElementType> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => {
+                self.array()?;
+                Ok(ElementType::Array)
+            }
+            Some(b'"') => {
+                let value = self.string()?;
+                self.write_lenencoded(&value)?;
+                Ok(ElementType::String)
+            }
+            Some(b't') => {
+                self.literal("true")?;
+                self.out.push(1);
+                Ok(ElementType::Boolean)
+            }
+            Some(b'f') => {
+                self.literal("false")?;
+                self.out.push(0);
+                Ok(ElementType::Boolean)
+            }
+            Some(b'n') => {
+                self.literal("null")?;
+                Ok(ElementType::Null)
+            }
+            Some(b'-' | b'0'..=b'9') => match self.number()? {
+                Number::Int(value) => match i32::try_from(value) {
+                    Ok(value) => {
+                        self.out.extend_from_slice(&value.to_le_bytes());
+                        Ok(ElementType::Int32)
+                    }
+                    Err(_) => {
+                        self.out.extend_from_slice(&value.to_le_bytes());
+                        Ok(ElementType::Int64)
+                    }
+                },
+                Number::Double(value) => {
+                    self.out.extend_from_slice(&value.to_le_bytes());
+                    Ok(ElementType::Double)
+                }
+            },
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// Parse an object in value position, which is either a type wrapper
+    /// such as `{"$oid": "..."}`, or an embedded document.
+    fn object(&mut self) -> ParseResult<ElementType> {
+        self.enter()?;
+        self.expect(b'{')?;
+        let element_type = if self.eat(b'}') {
+            self.out.extend_from_slice(&[5, 0, 0, 0, 0]);
+            ElementType::EmbeddedDocument
+        } else {
+            let key = self.key()?;
+            match key.as_ref() {
+                "$oid" => self.object_id()?,
+                "$symbol" => {
+                    let value = self.string()?;
+                    self.write_lenencoded(&value)?;
+                    self.end_wrapper(&key)?;
+                    ElementType::Symbol
+                }
+                "$numberInt" | "$numberLong" | "$numberDouble" | "$numberDecimal" => {
+                    self.numeric(&key)?
+                }
+                "$binary" => self.binary()?,
+                "$uuid" => self.uuid()?,
+                "$code" | "$scope" => self.code(&key)?,
+                "$timestamp" => self.timestamp()?,
+                "$regularExpression" => self.regex()?,
+                "$dbPointer" => self.db_pointer()?,
+                "$date" => self.date()?,
+                "$minKey" | "$maxKey" => {
+                    self.peek();
+                    let pos = self.pos;
+                    match self.number() {
+                        Ok(Number::Int(1)) => {}
+                        _ => return Err(self.error_at(pos, format!("invalid {} value", key))),
+                    }
+                    self.end_wrapper(&key)?;
+                    if key == "$minKey" {
+                        ElementType::MinKey
+                    } else {
+                        ElementType::MaxKey
+                    }
+                }
+                "$undefined" => {
+                    self.peek();
+                    let pos = self.pos;
+                    self.literal("true")
+                        .map_err(|_| self.error_at(pos, "invalid $undefined value"))?;
+                    self.end_wrapper(&key)?;
+                    ElementType::Undefined
+                }
+                "$regex" | "$options" | "$type" => self.legacy_or_document(key)?,
+                _ => {
+                    let start = self.out.len();
+                    self.out.extend_from_slice(&[0; 4]);
+                    self.element(&key)?;
+                    self.finish_document(start)?;
+                    ElementType::EmbeddedDocument
+                }
+            }
+        };
+        self.depth -= 1;
+        Ok(element_type)
+    }
+
+    fn end_wrapper(&mut self, name: &str) -> ParseResult<()> {
+        if self.eat(b'}') {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `}}` to close {}", name)))
+        }
+    }
+
+    /// Parse an object with exactly the keys in `names`, in any order,
+    /// calling `field` with the index of each key to parse its value.
+    fn fields(
+        &mut self,
+        wrapper: &str,
+        names: &[&str],
+        mut field: impl FnMut(&mut Self, usize) -> ParseResult<()>,
+    ) -> ParseResult<()> {
+        self.expect(b'{')?;
+        let mut seen = vec![false; names.len()];
+        loop {
+            self.peek();
+            let key_pos = self.pos;
+            let key = self.key()?;
+            match names.iter().position(|name| *name == key) {
+                Some(index) if !seen[index] => {
+                    seen[index] = true;
+                    field(self, index)?;
+                }
+                _ => return Err(self.error_at(key_pos, format!("unexpected key in {}", wrapper))),
+            }
+            if !self.eat(b',') {
+                break;
+            }
+        }
+        self.expect(b'}')?;
+        if let Some(index) = seen.iter().position(|seen| !seen) {
+            return Err(self.error(format!("missing {} in {}", names[index], wrapper)));
+        }
+        Ok(())
+    }
+
+    fn object_id(&mut self) -> ParseResult<ElementType> {
+        self.peek();
+        let pos = self.pos;
+        let hex = self.string()?;
+        let id = oid::ObjectId::with_string(&hex)
+            .map_err(|_| self.error_at(pos, "invalid $oid value"))?;
+        self.out.extend_from_slice(&id.bytes());
+        self.end_wrapper("$oid")?;
+        Ok(ElementType::ObjectId)
+    }
+
+    fn numeric(&mut self, key: &str) -> ParseResult<ElementType> {
+        self.peek();
+        let pos = self.pos;
+        let value = self.string()?;
+        let invalid = || self.error_at(pos, format!("invalid {} value", key));
+        let element_type = match key {
+            "$numberInt" => {
+                let value: i32 = value.parse().map_err(|_| invalid())?;
+                self.out.extend_from_slice(&value.to_le_bytes());
+                ElementType::Int32
+            }
+            "$numberLong" => {
+                let value: i64 = value.parse().map_err(|_| invalid())?;
+                self.out.extend_from_slice(&value.to_le_bytes());
+                ElementType::Int64
+            }
+            "$numberDouble" => {
+                let value = parse_double(&value).ok_or_else(invalid)?;
+                self.out.extend_from_slice(&value.to_le_bytes());
+                ElementType::Double
+            }
+            _ => {
+                let value: RawDecimal128 = value.parse().map_err(|_| invalid())?;
+                self.out.extend_from_slice(&value.bytes());
+                ElementType::Decimal128
+            }
+        };
+        self.end_wrapper(key)?;
+        Ok(element_type)
+    }
+
+    fn binary(&mut self) -> ParseResult<ElementType> {
+        let (data, subtype) = if self.peek() == Some(b'{') {
+            let mut data = None;
+            let mut subtype = None;
+            self.fields("$binary", &["base64", "subType"], |parser, index| {
+                match index {
+                    0 => data = Some(parser.base64()?),
+                    _ => subtype = Some(parser.subtype()?),
+                }
+                Ok(())
+            })?;
+            self.end_wrapper("$binary")?;
+            (data.unwrap_or_default(), subtype.unwrap_or_default())
+        } else {
+            // The legacy form, {"$binary": "...", "$type": "..."}.
+            let data = self.base64()?;
+            self.expect(b',')?;
+            self.peek();
+            let key_pos = self.pos;
+            if self.key()? != "$type" {
+                return Err(self.error_at(key_pos, "expected $type after $binary"));
+            }
+            let subtype = self.subtype()?;
+            self.end_wrapper("$binary")?;
+            (data, subtype)
+        };
+        self.write_binary(&data, subtype)
+    }
+
+    fn base64(&mut self) -> ParseResult<Vec<u8>> {
+        self.peek();
+        let pos = self.pos;
+        let value = self.string()?;
+        base64::decode(value.as_bytes()).map_err(|_| self.error_at(pos, "invalid base64 data"))
+    }
+
+    fn subtype(&mut self) -> ParseResult<u8> {
+        self.peek();
+        let pos = self.pos;
+        let value = self.string()?;
+        match value.len() {
+            1 | 2 => u8::from_str_radix(&value, 16).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| self.error_at(pos, "invalid binary subtype"))
+    }
+
+    fn write_binary(&mut self, data: &[u8], subtype: u8) -> ParseResult<ElementType> {
+        let length = i32::try_from(data.len()).map_err(|_| self.error("binary too long"))?;
+        if subtype == 2 {
+            self.out.extend_from_slice(&(length + 4).to_le_bytes());
+            self.out.push(subtype);
+            self.out.extend_from_slice(&length.to_le_bytes());
+        } else {
+            self.out.extend_from_slice(&length.to_le_bytes());
+            self.out.push(subtype);
+        }
+        self.out.extend_from_slice(data);
+        Ok(ElementType::Binary)
+    }
+
+    fn uuid(&mut self) -> ParseResult<ElementType> {
+        self.peek();
+        let pos = self.pos;
+        let value = self.string()?;
+        let hex: String = value.chars().filter(|&c| c != '-').collect();
+        let dashes: Vec<usize> = value.match_indices('-').map(|(i, _)| i).collect();
+        if hex.len() != 32 || !hex.is_ascii() || dashes != [8, 13, 18, 23] {
+            return Err(self.error_at(pos, "invalid $uuid value"));
+        }
+        let bytes = (0..16)
+            .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| self.error_at(pos, "invalid $uuid value"))?;
+        self.end_wrapper("$uuid")?;
+        self.write_binary(&bytes, 4)
+    }
+
+    /// Parse a `$code` wrapper, with or without `$scope`, whose keys may
+    /// come in either order.  `first` is the key that was already read.
+    fn code(&mut self, first: &str) -> ParseResult<ElementType> {
+        let start = self.out.len();
+        let mut scope = None;
+        if first == "$scope" {
+            scope = Some(self.scope()?);
+            if !self.eat(b',') {
+                return Err(self.error("missing $code in $code"));
+            }
+            self.partner_key("$scope", "$code")?;
+        }
+        let code = self.string()?;
+        self.write_lenencoded(&code)?;
+        if scope.is_none() && self.eat(b',') {
+            self.partner_key("$code", "$scope")?;
+            scope = Some(self.scope()?);
+        }
+        self.end_wrapper("$code")?;
+        match scope {
+            None => Ok(ElementType::JavaScriptCode),
+            Some(scope) => {
+                self.out.splice(start..start, [0; 4].iter().copied());
+                self.out.extend_from_slice(&scope);
+                self.patch_length(start)?;
+                Ok(ElementType::JavaScriptCodeWithScope)
+            }
+        }
+    }
+
+    /// Parse a `$scope` document, returning its bytes instead of leaving
+    /// them in the output.
+    fn scope(&mut self) -> ParseResult<Vec<u8>> {
+        if self.peek() != Some(b'{') {
+            return Err(self.error("invalid $scope value"));
+        }
+        let at = self.out.len();
+        self.document()?;
+        Ok(self.out.split_off(at))
+    }
+
+    /// Read the key that must follow `first` in a two-key wrapper.
+    fn partner_key(&mut self, first: &str, partner: &str) -> ParseResult<()> {
+        self.peek();
+        let key_pos = self.pos;
+        if self.key()? != partner {
+            return Err(self.error_at(key_pos, format!("expected {} after {}", partner, first)));
+        }
+        Ok(())
+    }
+
+    fn u32_field(&mut self, name: &str) -> ParseResult<u32> {
+        self.peek();
+        let pos = self.pos;
+        match self.number() {
+            Ok(Number::Int(value)) => u32::try_from(value).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| self.error_at(pos, format!("invalid {} value", name)))
+    }
+
+    fn timestamp(&mut self) -> ParseResult<ElementType> {
+        let mut time = 0;
+        let mut increment = 0;
+        self.fields("$timestamp", &["t", "i"], |parser, index| {
+            match index {
+                0 => time = parser.u32_field("$timestamp")?,
+                _ => increment = parser.u32_field("$timestamp")?,
+            }
+            Ok(())
+        })?;
+        self.end_wrapper("$timestamp")?;
+        self.out.extend_from_slice(&increment.to_le_bytes());
+        self.out.extend_from_slice(&time.to_le_bytes());
+        Ok(ElementType::Timestamp)
+    }
+
+    fn regex(&mut self) -> ParseResult<ElementType> {
+        let mut pattern = (Cow::Borrowed(""), 0);
+        let mut options = (Cow::Borrowed(""), 0);
+        self.fields(
+            "$regularExpression",
+            &["pattern", "options"],
+            |parser, index| {
+                parser.peek();
+                let value = (parser.string()?, parser.pos);
+                match index {
+                    0 => pattern = value,
+                    _ => options = value,
+                }
+                Ok(())
+            },
+        )?;
+        self.end_wrapper("$regularExpression")?;
+        self.write_cstring(&pattern.0, pattern.1)?;
+        self.write_cstring(&options.0, options.1)?;
+        Ok(ElementType::RegularExpression)
+    }
+
+    fn db_pointer(&mut self) -> ParseResult<ElementType> {
+        let mut namespace = Cow::Borrowed("");
+        let mut id = None;
+        self.fields("$dbPointer", &["$ref", "$id"], |parser, index| {
+            match index {
+                0 => namespace = parser.string()?,
+                _ => {
+                    parser.peek();
+                    let pos = parser.pos;
+                    parser.expect(b'{')?;
+                    if parser.key()? != "$oid" {
+                        return Err(parser.error_at(pos, "invalid $id value"));
+                    }
+                    parser.peek();
+                    let hex_pos = parser.pos;
+                    let hex = parser.string()?;
+                    id = Some(
+                        oid::ObjectId::with_string(&hex)
+                            .map_err(|_| parser.error_at(hex_pos, "invalid $oid value"))?,
+                    );
+                    parser.end_wrapper("$oid")?;
+                }
+            }
+            Ok(())
+        })?;
+        self.end_wrapper("$dbPointer")?;
+        self.write_lenencoded(&namespace)?;
+        if let Some(id) = id {
+            self.out.extend_from_slice(&id.bytes());
+        }
+        Ok(ElementType::DbPointer)
+    }
+
+    fn date(&mut self) -> ParseResult<ElementType> {
+        let pos = self.pos;
+        let millis = match self.peek() {
+            Some(b'{') => {
+                self.expect(b'{')?;
+                if self.key()? != "$numberLong" {
+                    return Err(self.error_at(pos, "invalid $date value"));
+                }
+                self.peek();
+                let value_pos = self.pos;
+                let value = self.string()?;
+                self.end_wrapper("$numberLong")?;
+                value
+                    .parse()
+                    .map_err(|_| self.error_at(value_pos, "invalid $numberLong value"))?
+            }
+            Some(b'"') => {
+                let value = self.string()?;
+                chrono::DateTime::parse_from_rfc3339(&value)
+                    .map_err(|_| self.error_at(pos, "invalid $date value"))?
+                    .timestamp_millis()
+            }
+            _ => match self.number() {
+                Ok(Number::Int(millis)) => millis,
+                _ => return Err(self.error_at(pos, "invalid $date value")),
+            },
+        };
+        self.end_wrapper("$date")?;
+        self.out.extend_from_slice(&i64::to_le_bytes(millis));
+        Ok(ElementType::DateTime)
+    }
+
+    /// Parse an object whose first key is `$regex`, `$options` or `$type`.
+    /// Together with a string-valued partner key (`$options`, `$regex` or
+    /// `$binary` respectively) these make up the legacy forms of regular
+    /// expressions and binary data.  Otherwise, as in the query
+    /// `{"$regex": "^a"}`, the object is an ordinary document.
+    fn legacy_or_document(&mut self, key: Cow<'a, str>) -> ParseResult<ElementType> {
+        let start = self.out.len();
+        self.out.extend_from_slice(&[0; 4]);
+        let first_pos = self.pos;
+        self.element(&key)?;
+        let partner = match key.as_ref() {
+            "$regex" => "$options",
+            "$options" => "$regex",
+            _ => "$binary",
+        };
+        if self.out[start + 4] == ElementType::String as u8 {
+            let resume = self.pos;
+            if self.eat(b',') && self.key()? == partner {
+                // The first value was just written as a string element.
+                let value_start = start + 4 + 1 + key.len() + 1 + 4;
+                let first = String::from_utf8(self.out[value_start..self.out.len() - 1].to_vec())
+                    .expect("parsed strings are utf-8");
+                self.out.truncate(start);
+                self.peek();
+                let second_pos = self.pos;
+                let element_type = match partner {
+                    "$binary" => {
+                        let data = self.base64()?;
+                        self.end_wrapper("$binary")?;
+                        let subtype = match first.len() {
+                            1 | 2 => u8::from_str_radix(&first, 16).ok(),
+                            _ => None,
+                        }
+                        .ok_or_else(|| self.error_at(first_pos, "invalid binary subtype"))?;
+                        self.write_binary(&data, subtype)?
+                    }
+                    _ => {
+                        let second = self.string()?;
+                        self.end_wrapper(partner)?;
+                        let (pattern, options) = match partner {
+                            "$options" => ((first, first_pos), (second, second_pos)),
+                            _ => ((second.into_owned(), second_pos), (first.into(), first_pos)),
+                        };
+                        self.write_cstring(&pattern.0, pattern.1)?;
+                        self.write_cstring(&options.0, options.1)?;
+                        ElementType::RegularExpression
+                    }
+                };
+                return Ok(element_type);
+            }
+            self.pos = resume;
+        }
+        self.finish_document(start)?;
+        Ok(ElementType::EmbeddedDocument)
+    }
+}
+
+/// Parse the string form of a `$numberDouble`, which is a JSON number or one
+/// of `Infinity`, `-Infinity` and `NaN`.
+fn parse_double(value: &str) -> Option<f64> {
+    match value {
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        _ if value
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || b"+-.eE".contains(&byte)) =>
+        {
+            value.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, Bson, Document, Regex};
+    use chrono::TimeZone;
+
+    use crate::{elem::RawDecimal128, extjson::Mode, DocBuf, DocBufBuilder};
+
+    fn parse(text: &str) -> DocBuf {
+        DocBuf::from_extjson(text).unwrap()
+    }
+
+    fn document(docbuf: &DocBuf) -> Document {
+        Document::try_from(docbuf.as_ref()).unwrap()
+    }
+
+    fn error(text: &str) -> (String, usize, usize) {
+        let err = DocBuf::from_extjson(text).unwrap_err();
+        (err.message().to_owned(), err.line(), err.column())
+    }
+
+    #[test]
+    fn round_trip() {
+        let id = ObjectId::with_string("57e193d7a9cc81b4027498b5").unwrap();
+        let mut scope = DocBufBuilder::new();
+        scope.append_i32("x", 1);
+        let scope = scope.finish().unwrap();
+        let mut builder = DocBufBuilder::new();
+        builder
+            .append_f64("d", 1.5)
+            .append_f64("whole", -3.0)
+            .append_f64("nan", f64::NAN)
+            .append_f64("inf", f64::INFINITY)
+            .append_str("s", "tab\t\"quote\" \\ \u{1} é 🦀")
+            .append_document("doc", |doc| {
+                doc.append_i32("x", 1);
+            })
+            .append_binary("b", BinarySubtype::UserDefined(0x80), b"\xff\xff")
+            .append_binary("old", BinarySubtype::BinaryOld, b"\xff\xff")
+            .append_object_id("o", id.clone())
+            .append_bool("t", true)
+            .append_datetime(
+                "date",
+                chrono::Utc.timestamp_millis_opt(1356351330501).unwrap(),
+            )
+            .append_datetime("early", chrono::Utc.timestamp_millis_opt(-1).unwrap())
+            .append_null("n")
+            .append_regex("r", "a\\d+", "im")
+            .append_db_pointer("p", "db.c", id)
+            .append_javascript("c", "f()")
+            .append_symbol("sym", "x")
+            .append_javascript_with_scope("cws", "g(x)", &scope)
+            .append_i32("i", -7)
+            .append_timestamp("ts", 123456789, 42)
+            .append_i64("l", 1 << 40)
+            .append_i64("small", 3)
+            .append_decimal128("dec", "-1.0E+3".parse::<RawDecimal128>().unwrap())
+            .append_undefined("u")
+            .append_min_key("min")
+            .append_max_key("max");
+        let docbuf = builder.finish().unwrap();
+
+        let canonical = docbuf.to_extjson(Mode::Canonical).unwrap();
+        assert_eq!(parse(&canonical).as_bytes(), docbuf.as_bytes());
+
+        // Relaxed output loses the distinction between small int64s and
+        // int32s, and between whole doubles and integers in general.
+        let relaxed = parse(&docbuf.to_extjson(Mode::Relaxed).unwrap());
+        assert_eq!(relaxed.get_f64("d").unwrap(), Some(1.5));
+        assert_eq!(relaxed.get_f64("whole").unwrap(), Some(-3.0));
+        assert_eq!(relaxed.get_i32("small").unwrap(), Some(3));
+        assert_eq!(relaxed.get_i64("l").unwrap(), Some(1 << 40));
+        assert_eq!(
+            relaxed.to_extjson(Mode::Canonical).unwrap(),
+            canonical.replace(r#"{"$numberLong":"3"}"#, r#"{"$numberInt":"3"}"#)
+        );
+    }
+
+    #[test]
+    fn arrays_and_whitespace() {
+        let docbuf = parse("{ \"a\" : [ 1 , [ ] , { } , \"x\" , null ] ,\n\t\"e\": {} }\r\n");
+        let expected = doc! {"a": [1, [], {}, "x", Bson::Null], "e": {}};
+        assert_eq!(document(&docbuf), expected);
+        assert_eq!(parse("{}").as_bytes(), &[5, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn legacy_forms() {
+        let docbuf = parse(concat!(
+            r#"{"d1": {"$date": 1356351330501}, "d2": {"$date": "2012-12-24T12:15:30.501+01:00"},"#,
+            r#""b": {"$binary": "//8=", "$type": "80"}, "b2": {"$type": "0", "$binary": "AQ=="},"#,
+            r#""r": {"$regex": "^a", "$options": "i"}, "r2": {"$options": "", "$regex": "b"},"#,
+            r#""u": {"$uuid": "73ffd264-44b3-4c69-90e8-e7d1dfc035d4"},"#,
+            r#""bin": {"$binary": {"subType": "02", "base64": "//8="}}}"#,
+        ));
+        let expected = doc! {
+            "d1": Bson::DateTime(chrono::Utc.timestamp_millis_opt(1356351330501).unwrap()),
+            "d2": Bson::DateTime(chrono::Utc.timestamp_millis_opt(1356347730501).unwrap()),
+            "b": Binary { subtype: BinarySubtype::UserDefined(0x80), bytes: vec![0xff, 0xff] },
+            "b2": Binary { subtype: BinarySubtype::Generic, bytes: vec![1] },
+            "r": Regex { pattern: "^a".into(), options: "i".into() },
+            "r2": Regex { pattern: "b".into(), options: "".into() },
+            "u": Binary {
+                subtype: BinarySubtype::Uuid,
+                bytes: vec![
+                    0x73, 0xff, 0xd2, 0x64, 0x44, 0xb3, 0x4c, 0x69,
+                    0x90, 0xe8, 0xe7, 0xd1, 0xdf, 0xc0, 0x35, 0xd4,
+                ],
+            },
+            "bin": Binary { subtype: BinarySubtype::BinaryOld, bytes: vec![0xff, 0xff] },
+        };
+        assert_eq!(document(&docbuf), expected);
+    }
+
+    #[test]
+    fn code_keys_in_either_order() {
+        let docbuf = parse(concat!(
+            r#"{"a": {"$code": "f(x)", "$scope": {"x": 1}},"#,
+            r#""b": {"$scope": {"x": 1}, "$code": "f(x)"}}"#,
+        ));
+        let code = Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+            code: "f(x)".into(),
+            scope: doc! {"x": 1},
+        });
+        assert_eq!(document(&docbuf), doc! {"a": code.clone(), "b": code});
+        assert_eq!(
+            error(r#"{"a": {"$scope": {}}}"#),
+            ("missing $code in $code".to_owned(), 1, 20)
+        );
+        assert_eq!(
+            error(r#"{"a": {"$scope": {}, "$scope": {}}}"#),
+            ("expected $code after $scope".to_owned(), 1, 22)
+        );
+    }
+
+    #[test]
+    fn query_operators_are_documents() {
+        let docbuf = parse(concat!(
+            r#"{"a": {"$regex": "^a"}, "b": {"$regex": {"$regularExpression": {"#,
+            r#""pattern": "x", "options": ""}}, "$options": "i"},"#,
+            r#""c": {"$type": "string", "$exists": true}, "d": {"$gt": 1, "$lt": 5}}"#,
+        ));
+        let expected = doc! {
+            "a": {"$regex": "^a"},
+            "b": {"$regex": Regex { pattern: "x".into(), options: "".into() }, "$options": "i"},
+            "c": {"$type": "string", "$exists": true},
+            "d": {"$gt": 1, "$lt": 5},
+        };
+        assert_eq!(document(&docbuf), expected);
+    }
+
+    #[test]
+    fn numbers() {
+        let docbuf =
+            parse(r#"{"a": 0, "b": -2147483649, "c": 1e2, "d": -0.5, "e": 9223372036854775808}"#);
+        assert_eq!(
+            document(&docbuf),
+            doc! {"a": 0, "b": -2147483649i64, "c": 100.0, "d": -0.5, "e": 9223372036854775808.0}
+        );
+        assert_eq!(error(r#"{"a": 01}"#), ("invalid number".to_owned(), 1, 7));
+        assert_eq!(
+            error(r#"{"a": {"$numberDouble": "nan"}}"#),
+            ("invalid $numberDouble value".to_owned(), 1, 25)
+        );
+        assert_eq!(
+            error(r#"{"a": {"$numberInt": "2147483648"}}"#),
+            ("invalid $numberInt value".to_owned(), 1, 22)
+        );
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        assert_eq!(error("[]"), ("expected `{`".to_owned(), 1, 1));
+        assert_eq!(
+            error("{\"a\": 1} x"),
+            ("trailing characters".to_owned(), 1, 10)
+        );
+        assert_eq!(
+            error("{\n  \"é\": tru\n}"),
+            ("expected a value".to_owned(), 2, 8)
+        );
+        assert_eq!(
+            error("{\"a\": \"x"),
+            ("unterminated string".to_owned(), 1, 9)
+        );
+        assert_eq!(
+            error("{\"a\\u0000\": 1}"),
+            ("key contains a nul byte".to_owned(), 1, 2)
+        );
+        assert_eq!(
+            error(r#"{"a": "\ud800"}"#),
+            ("unpaired surrogate in string".to_owned(), 1, 8)
+        );
+        assert_eq!(
+            error(r#"{"a": "\u+041"}"#),
+            ("invalid unicode escape".to_owned(), 1, 10)
+        );
+        assert_eq!(
+            error(r#"{"a": {"$oid": "xyz"}}"#),
+            ("invalid $oid value".to_owned(), 1, 16)
+        );
+        assert_eq!(
+            error(r#"{"a": {"\u0024oid": "57e193d7a9cc81b4027498b5", "b": 1}}"#),
+            ("expected `}` to close $oid".to_owned(), 1, 47)
+        );
+        assert_eq!(
+            error(r#"{"u": {"$uuid": "aé12345-1234-1234-1234-123456789012"}}"#),
+            ("invalid $uuid value".to_owned(), 1, 17)
+        );
+        assert_eq!(
+            error(r#"{"a": {"$timestamp": {"t": 1}}}"#),
+            ("missing i in $timestamp".to_owned(), 1, 30)
+        );
+        assert_eq!(
+            error(r#"{"a": {"$numberInt": "1", "b": 2}}"#),
+            ("expected `}` to close $numberInt".to_owned(), 1, 25)
+        );
+
+        let err = DocBuf::from_extjson("{\n\"a\": [1,\n 2,]}").unwrap_err();
+        assert_eq!(err.to_string(), "expected a value at line 3 column 4");
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| {
+            format!(
+                "{}{}",
+                "{\"a\":".repeat(depth),
+                "{}".to_owned() + &"}".repeat(depth)
+            )
+        };
+        assert!(DocBuf::from_extjson(&nested(200)).is_ok());
+        let (message, _, _) = error(&nested(300));
+        assert_eq!(message, "nesting too deep");
+    }
+}
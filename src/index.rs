@@ -0,0 +1,237 @@
+//! Constant-time key lookup in wide documents.
+//!
+//! [`Doc::get`] scans the document from the start on every call, so reading
+//! many fields from a wide document is quadratic in its size.  An
+//! [`IndexedDoc`] makes a single pass over the document, recording where
+//! each element is, and then answers every lookup with a hash table probe.
+//!
+//! ```
+//! use bson::doc;
+//! use rawbson::{DocBuf, IndexedDoc};
+//!
+//! let docbuf = DocBuf::from_document(&doc! {
+//!     "name": "Herman Melville",
+//!     "born": 1819,
+//!     "books": ["Typee", "Moby-Dick"],
+//! });
+//! let indexed = IndexedDoc::new(&docbuf)?;
+//! assert_eq!(indexed.get_i32("born")?, Some(1819));
+//! assert_eq!(indexed.get_str("name")?, Some("Herman Melville"));
+//! assert_eq!(indexed.get_array("books")?.unwrap().get_str(1)?, Some("Moby-Dick"));
+//! assert!(indexed.get("died")?.is_none());
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::collections::{hash_map::Entry, HashMap};
+
+use chrono::{DateTime, Utc};
+
+use bson::oid;
+
+use crate::{elem, offset_in, Array, Doc, OptResult, RawResult};
+
+/// A [`Doc`] together with an index from keys to elements.
+///
+/// Building the index reads every element header, so malformed documents
+/// are reported by [`IndexedDoc::new`].  Values are still only decoded when
+/// they are accessed.  If a key appears more than once, the first
+/// occurrence wins, as with [`Doc::get`].
+#[derive(Clone, Debug)]
+pub struct IndexedDoc<'a> {
+    doc: &'a Doc,
+    index: HashMap<&'a str, elem::Element<'a>>,
+}
+
+impl<'a> IndexedDoc<'a> {
+    /// Index every key of `doc`.
+    ///
+    /// Returns an error if the document is malformed.
+    pub fn new<D: AsRef<Doc> + ?Sized>(doc: &'a D) -> RawResult<IndexedDoc<'a>> {
+        let doc = doc.as_ref();
+        let mut index = HashMap::new();
+        for result in doc {
+            let (key, elem) = result?;
+            if let Entry::Vacant(entry) = index.entry(key) {
+                entry.insert(elem);
+            }
+        }
+        Ok(IndexedDoc { doc, index })
+    }
+
+    /// The underlying document.
+    pub fn as_doc(&self) -> &'a Doc {
+        self.doc
+    }
+
+    /// The number of distinct keys in the document.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if the document has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns true if the document contains `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Get an element from the document.  Returns `Ok(None)` if the key is
+    /// not found.
+    ///
+    /// This never fails, since every element header was read when the index
+    /// was built, but it returns a `Result` to match [`Doc::get`].
+    pub fn get(&self, key: &str) -> OptResult<elem::Element<'a>> {
+        Ok(self.index.get(key).copied())
+    }
+
+    fn get_with<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(elem::Element<'a>) -> RawResult<T>,
+    ) -> OptResult<T> {
+        self.index
+            .get(key)
+            .map(|elem| {
+                f(*elem).map_err(|err| {
+                    let offset = offset_in(self.doc.as_bytes(), elem.as_bytes());
+                    err.within(key, offset, elem.element_type())
+                })
+            })
+            .transpose()
+    }
+
+    pub fn get_f64(&self, key: &str) -> OptResult<f64> {
+        self.get_with(key, elem::Element::as_f64)
+    }
+
+    pub fn get_str(&self, key: &str) -> OptResult<&'a str> {
+        self.get_with(key, elem::Element::as_str)
+    }
+
+    pub fn get_document(&self, key: &str) -> OptResult<&'a Doc> {
+        self.get_with(key, elem::Element::as_document)
+    }
+
+    pub fn get_array(&self, key: &str) -> OptResult<&'a Array> {
+        self.get_with(key, elem::Element::as_array)
+    }
+
+    pub fn get_binary(&self, key: &str) -> OptResult<elem::RawBsonBinary<'a>> {
+        self.get_with(key, elem::Element::as_binary)
+    }
+
+    pub fn get_object_id(&self, key: &str) -> OptResult<oid::ObjectId> {
+        self.get_with(key, elem::Element::as_object_id)
+    }
+
+    pub fn get_bool(&self, key: &str) -> OptResult<bool> {
+        self.get_with(key, elem::Element::as_bool)
+    }
+
+    pub fn get_datetime(&self, key: &str) -> OptResult<DateTime<Utc>> {
+        self.get_with(key, elem::Element::as_datetime)
+    }
+
+    pub fn get_null(&self, key: &str) -> OptResult<()> {
+        self.get_with(key, elem::Element::as_null)
+    }
+
+    pub fn get_regex(&self, key: &str) -> OptResult<elem::RawBsonRegex<'a>> {
+        self.get_with(key, elem::Element::as_regex)
+    }
+
+    pub fn get_javascript(&self, key: &str) -> OptResult<&'a str> {
+        self.get_with(key, elem::Element::as_javascript)
+    }
+
+    pub fn get_symbol(&self, key: &str) -> OptResult<&'a str> {
+        self.get_with(key, elem::Element::as_symbol)
+    }
+
+    pub fn get_javascript_with_scope(&self, key: &str) -> OptResult<(&'a str, &'a Doc)> {
+        self.get_with(key, elem::Element::as_javascript_with_scope)
+    }
+
+    pub fn get_i32(&self, key: &str) -> OptResult<i32> {
+        self.get_with(key, elem::Element::as_i32)
+    }
+
+    pub fn get_timestamp(&self, key: &str) -> OptResult<elem::RawBsonTimestamp<'a>> {
+        self.get_with(key, elem::Element::as_timestamp)
+    }
+
+    pub fn get_i64(&self, key: &str) -> OptResult<i64> {
+        self.get_with(key, elem::Element::as_i64)
+    }
+}
+
+impl AsRef<Doc> for IndexedDoc<'_> {
+    fn as_ref(&self) -> &Doc {
+        self.doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::IndexedDoc;
+    use crate::{Doc, DocBuf, ErrorKind};
+
+    #[test]
+    fn matches_doc_lookups() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "f64": 2.5,
+            "str": "hello",
+            "doc": {"inner": true},
+            "null": null,
+            "i64": 5i64,
+        });
+        let indexed = IndexedDoc::new(&docbuf).unwrap();
+        assert_eq!(indexed.len(), 5);
+        assert!(indexed.contains_key("doc"));
+        for (key, _) in docbuf.iter().map(Result::unwrap) {
+            let expected = docbuf.get(key).unwrap().unwrap();
+            let found = indexed.get(key).unwrap().unwrap();
+            assert_eq!(found.as_bytes(), expected.as_bytes());
+        }
+        assert_eq!(indexed.get_f64("f64"), Ok(Some(2.5)));
+        assert_eq!(indexed.get_str("str"), Ok(Some("hello")));
+        assert_eq!(
+            indexed
+                .get_document("doc")
+                .unwrap()
+                .unwrap()
+                .get_bool("inner"),
+            Ok(Some(true))
+        );
+        assert_eq!(indexed.get_null("null"), Ok(Some(())));
+        assert_eq!(indexed.get_i64("i64"), Ok(Some(5)));
+        assert_eq!(indexed.get_i64("missing"), Ok(None));
+
+        let err = indexed.get_i32("str").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        assert_eq!(err.key_path(), "str");
+        assert_eq!(err, docbuf.get_i32("str").unwrap_err());
+    }
+
+    #[test]
+    fn first_duplicate_wins() {
+        let bytes = b"\x13\0\0\0\x10a\0\x01\0\0\0\x10a\0\x02\0\0\0\0";
+        let doc = Doc::new(bytes).unwrap();
+        let indexed = IndexedDoc::new(doc).unwrap();
+        assert_eq!(indexed.len(), 1);
+        assert_eq!(indexed.get_i32("a"), doc.get_i32("a"));
+        assert_eq!(indexed.get_i32("a"), Ok(Some(1)));
+    }
+
+    #[test]
+    fn malformed_documents_are_rejected() {
+        let bad_tag = Doc::new(b"\x08\0\0\0\x42a\0\0").unwrap();
+        let err = IndexedDoc::new(bad_tag).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidTag(0x42));
+    }
+}
@@ -49,6 +49,9 @@ assert_eq!(
     value,
     Some("world"),
 );
+
+// or, equivalently
+assert_eq!(raw.get_path_str("goodbye.cruel")?, Some("world"));
 # Ok::<(), rawbson::RawError>(())
 ```
 
@@ -95,13 +98,37 @@ assert_eq!(value.as_str()?, "MIT");
 # Ok::<(), rawbson::RawError>(())
 ```
 
+### Building documents
+
+Documents can be constructed incrementally with a [`DocBufBuilder`], which
+writes elements straight into the underlying buffer.
+
+```rust
+use rawbson::DocBufBuilder;
+
+let mut builder = DocBufBuilder::new();
+builder
+    .append_str("crate", "rawbson")
+    .append_array("keywords", |arr| {
+        arr.append_str("bson").append_str("zero-copy");
+    });
+let doc = builder.finish()?;
+assert_eq!(doc.get_str("crate")?, Some("rawbson"));
+# Ok::<(), rawbson::RawError>(())
+```
+
 ### serde support
 
 There is also serde deserialization support.
+Struct fields of type `&Doc` and `&Array` borrow nested documents and
+arrays straight from the input, so a few fields can be typed while an
+opaque payload stays raw.
 
-Serde serialization support is not yet provided.  For now, use
-[`bson::to_document`] instead, and then serialize it out using
-[`bson::Document::to_writer`] or [`DocBuf::from_document`].
+Serde serialization is provided by the [`ser`] module, which writes BSON
+bytes directly into a [`DocBuf`] without building a [`bson::Document`].
+[`Doc`], [`DocBuf`], [`Array`] and [`elem::Element`] implement
+`Serialize` themselves, so raw documents can be transcoded to other serde
+formats, such as JSON, without parsing them into a [`bson::Document`] first.
 
 ```rust
 use serde::Deserialize;
@@ -142,70 +169,47 @@ assert!(user.birthdate.is_none());
 use std::{
     borrow::Borrow,
     convert::{TryFrom, TryInto},
+    io,
     ops::Deref,
 };
 
 use chrono::{DateTime, Utc};
 
-use bson::{decimal128::Decimal128, document::ValueAccessError, oid, spec::ElementType, Bson};
+use bson::{oid, spec::ElementType, Bson};
 
+pub mod builder;
+pub mod cmp;
 pub mod de;
 pub mod elem;
+mod error;
+pub mod extjson;
+pub mod index;
+mod mutate;
+pub mod path;
+pub mod projection;
+pub mod query;
+pub mod reader;
+pub mod ser;
+pub mod update;
+pub mod validate;
+pub mod wire;
+
+pub use builder::{ArrayBuilder, DocBufBuilder, DocBuilder};
+pub use error::{ErrorKind, RawError, RawResult};
+pub use index::IndexedDoc;
+pub use path::KeyPath;
+pub use projection::Projection;
+pub use query::Matcher;
+pub use reader::{DocReader, DocSeq};
+#[cfg(feature = "async")]
+pub use reader::{AsyncDocReader, DocCodec};
+pub use validate::{ValidDoc, ValidDocBuf};
 
 #[cfg(test)]
 mod props;
 
-/// Error to indicate that either a value was empty or it contained an unexpected
-/// type, for use with the direct getters.
-#[derive(Debug, PartialEq)]
-pub enum RawError {
-    /// Found a Bson value with the specified key, but not with the expected type
-    UnexpectedType,
-
-    /// The found value was not well-formed
-    MalformedValue(String),
-
-    /// Found a value where a utf-8 string was expected, but it was not valid
-    /// utf-8.  The error value contains the malformed data as a string.
-    Utf8EncodingError(Vec<u8>),
-}
-
-impl std::fmt::Display for RawError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        use RawError::*;
-        match self {
-            UnexpectedType => write!(f, "unexpected type"),
-            MalformedValue(s) => write!(f, "malformed value: {:?}", s),
-            Utf8EncodingError(_) => write!(f, "utf-8 encoding error"),
-        }
-    }
-}
-
-impl std::error::Error for RawError {}
-
-pub type RawResult<T> = Result<T, RawError>;
 type OptResult<T> = RawResult<Option<T>>;
 
-impl<'a> From<RawError> for ValueAccessError {
-    fn from(src: RawError) -> ValueAccessError {
-        match src {
-            RawError::UnexpectedType => ValueAccessError::UnexpectedType,
-            RawError::MalformedValue(_) => ValueAccessError::UnexpectedType,
-            RawError::Utf8EncodingError(_) => ValueAccessError::UnexpectedType,
-        }
-    }
-}
-
-impl<'a> From<ValueAccessError> for RawError {
-    fn from(src: ValueAccessError) -> RawError {
-        match src {
-            ValueAccessError::NotPresent => unreachable!("This should be converted to an Option"),
-            ValueAccessError::UnexpectedType => RawError::UnexpectedType,
-            _ => RawError::UnexpectedType,
-        }
-    }
-}
-
 /// A BSON document, stored as raw binary data on the heap.  This can be created from
 /// a `Vec<u8>` or a [`bson::Document`].
 ///
@@ -256,21 +260,29 @@ impl DocBuf {
     /// # Ok::<(), RawError>(())
     /// ```
     pub fn new(data: Vec<u8>) -> RawResult<DocBuf> {
-        if data.len() < 5 {
-            return Err(RawError::MalformedValue("document too short".into()));
-        }
-        let length = i32_from_slice(&data[..4]);
-        if data.len() as i32 != length {
-            return Err(RawError::MalformedValue("document length incorrect".into()));
-        }
-        if data[data.len() - 1] != 0 {
-            return Err(RawError::MalformedValue(
-                "document not null-terminated".into(),
-            ));
-        }
+        check_envelope(&data)?;
         Ok(unsafe { DocBuf::new_unchecked(data) })
     }
 
+    /// Create a new `DocBuf` from the provided `Vec`, validating the entire
+    /// document tree up front.  See [`Doc::validate`].
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError, ValidDocBuf};
+    /// let docbuf: ValidDocBuf = DocBuf::new_validated(b"\x05\0\0\0\0".to_vec())?;
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn new_validated(data: Vec<u8>) -> RawResult<ValidDocBuf> {
+        DocBuf::new(data)?.into_validated()
+    }
+
+    /// Validate the entire document tree, converting self into a
+    /// [`ValidDocBuf`].  See [`Doc::validate`].
+    pub fn into_validated(self) -> RawResult<ValidDocBuf> {
+        validate::validate_document(&self.data)?;
+        Ok(ValidDocBuf::new_unchecked(self))
+    }
+
     /// Create a DocBuf from a [bson::Document].
     ///
     /// ```
@@ -290,6 +302,29 @@ impl DocBuf {
         unsafe { DocBuf::new_unchecked(data) }
     }
 
+    /// Parse a DocBuf from MongoDB Extended JSON.
+    ///
+    /// Both the canonical and relaxed formats are accepted, along with the
+    /// legacy forms of `$date`, `$binary` and `$regex`.  The BSON is written
+    /// as the text is parsed, without building a [bson::Document] first.
+    ///
+    /// ```
+    /// # use rawbson::DocBuf;
+    /// let docbuf = DocBuf::from_extjson(r#"{"n": {"$numberLong": "7"}, "s": "hi"}"#)?;
+    /// assert_eq!(docbuf.get_i64("n")?, Some(7));
+    /// assert_eq!(docbuf.get_str("s")?, Some("hi"));
+    ///
+    /// let err = DocBuf::from_extjson("{\n  \"n\": nope\n}").unwrap_err();
+    /// assert_eq!((err.line(), err.column()), (2, 8));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_extjson(text: &str) -> Result<DocBuf, extjson::ParseError> {
+        let data = extjson::parse_document(text)?;
+        // SAFETY: The parser writes a length prefix and trailing nul for
+        // every document it emits.
+        Ok(unsafe { DocBuf::new_unchecked(data) })
+    }
+
     /// Create a DocBuf from an owned Vec<u8> without performing any checks on the provided data.
     ///
     /// ```
@@ -381,7 +416,7 @@ impl<'a> IntoIterator for &'a DocBuf {
 
     fn into_iter(self) -> DocIter<'a> {
         DocIter {
-            doc: &self,
+            doc: self,
             offset: 4,
         }
     }
@@ -396,7 +431,7 @@ impl AsRef<Doc> for DocBuf {
 
 impl Borrow<Doc> for DocBuf {
     fn borrow(&self) -> &Doc {
-        &*self
+        self
     }
 }
 
@@ -450,18 +485,7 @@ pub struct Doc {
 impl Doc {
     pub fn new<D: AsRef<[u8]> + ?Sized>(data: &D) -> RawResult<&Doc> {
         let data = data.as_ref();
-        if data.len() < 5 {
-            return Err(RawError::MalformedValue("document too short".into()));
-        }
-        let length = i32_from_slice(&data[..4]);
-        if data.len() as i32 != length {
-            return Err(RawError::MalformedValue("document length incorrect".into()));
-        }
-        if data[data.len() - 1] != 0 {
-            return Err(RawError::MalformedValue(
-                "document not null-terminated".into(),
-            ));
-        }
+        check_envelope(data)?;
         Ok(unsafe { Doc::new_unchecked(data) })
     }
 
@@ -483,6 +507,75 @@ impl Doc {
         }
     }
 
+    /// Walk the entire document tree, checking every length, terminator,
+    /// UTF-8 string, boolean byte, binary subtype, array index and nested
+    /// document.
+    ///
+    /// Returns a [`ValidDoc`] marker on success, whose iterator and `get`
+    /// method cannot fail.
+    ///
+    /// ```
+    /// # use rawbson::{Doc, RawError};
+    /// let doc = Doc::new(b"\x13\x00\x00\x00\x02hi\x00\x06\x00\x00\x00y'all\x00\x00")?;
+    /// let valid = doc.validate()?;
+    /// assert_eq!(valid.get("hi").unwrap().as_str()?, "y'all");
+    ///
+    /// // The string claims to be 255 bytes long.
+    /// let doc = Doc::new(b"\x13\x00\x00\x00\x02hi\x00\xff\x00\x00\x00y'all\x00\x00")?;
+    /// assert!(doc.validate().is_err());
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn validate(&self) -> RawResult<&ValidDoc> {
+        validate::validate_document(&self.data)?;
+        // SAFETY: The document was just validated.
+        Ok(unsafe { ValidDoc::new_unchecked(self) })
+    }
+
+    /// Format this document as MongoDB Extended JSON.
+    ///
+    /// See the [`extjson`] module for details of the two modes.  Returns an
+    /// error if the document is malformed.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError};
+    /// use bson::{doc, oid::ObjectId};
+    /// use rawbson::extjson::Mode;
+    /// let docbuf = DocBuf::from_document(&doc! {
+    ///     "_id": ObjectId::with_string("5f5ea9d2000000000000beef").unwrap(),
+    ///     "tags": ["a", "b"],
+    /// });
+    /// assert_eq!(
+    ///     docbuf.to_extjson(Mode::Relaxed)?,
+    ///     r#"{"_id":{"$oid":"5f5ea9d2000000000000beef"},"tags":["a","b"]}"#,
+    /// );
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn to_extjson(&self, mode: extjson::Mode) -> RawResult<String> {
+        let mut out = Vec::new();
+        match extjson::write_document(self, mode, &mut out) {
+            Ok(()) => Ok(String::from_utf8(out).expect("extended JSON is valid utf-8")),
+            Err(extjson::WriteError::Raw(err)) => Err(err),
+            Err(extjson::WriteError::Io(_)) => unreachable!("writing to a Vec cannot fail"),
+        }
+    }
+
+    /// Stream this document to `writer` as MongoDB Extended JSON, walking
+    /// the raw bytes without building an intermediate value.
+    ///
+    /// If the document is malformed, the [`RawError`] is returned wrapped in
+    /// an [`io::Error`] of kind [`InvalidData`](io::ErrorKind::InvalidData).
+    /// Output written before the malformed element is not retracted.
+    pub fn write_extjson<W: io::Write + ?Sized>(
+        &self,
+        mode: extjson::Mode,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        extjson::write_document(self, mode, writer).map_err(|err| match err {
+            extjson::WriteError::Raw(err) => io::Error::new(io::ErrorKind::InvalidData, err),
+            extjson::WriteError::Io(err) => err,
+        })
+    }
+
     /// Create a new DocBuf with an owned copy of the data in self.
     ///
     /// ```
@@ -531,7 +624,17 @@ impl Doc {
         key: &str,
         f: impl FnOnce(elem::Element<'a>) -> RawResult<T>,
     ) -> OptResult<T> {
-        self.get(key)?.map(f).transpose()
+        self.get(key)?
+            .map(|elem| {
+                f(elem).map_err(|err| {
+                    err.within(
+                        key,
+                        offset_in(&self.data, elem.as_bytes()),
+                        elem.element_type(),
+                    )
+                })
+            })
+            .transpose()
     }
 
     /// Get an element from the document, and convert it to f64.
@@ -540,14 +643,14 @@ impl Doc {
     /// is not an f64.  Returns `Ok(None)` if the key is not found in the document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, elem::Element, RawError};
+    /// # use rawbson::{DocBuf, elem::Element, ErrorKind, RawError};
     /// use bson::doc;
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "bool": true,
     ///     "f64": 2.5,
     /// });
     /// assert_eq!(docbuf.get_f64("f64"), Ok(Some(2.5)));
-    /// assert_eq!(docbuf.get_f64("bool"), Err(RawError::UnexpectedType));
+    /// assert_eq!(docbuf.get_f64("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert_eq!(docbuf.get_f64("unknown"), Ok(None));
     /// # Ok::<(), RawError>(())
     /// ```
@@ -565,14 +668,14 @@ impl Doc {
     /// document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, elem::Element, RawError};
+    /// # use rawbson::{DocBuf, elem::Element, ErrorKind, RawError};
     /// use bson::doc;
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "string": "hello",
     ///     "bool": true,
     /// });
     /// assert_eq!(docbuf.get_str("string"), Ok(Some("hello")));
-    /// assert_eq!(docbuf.get_str("bool"), Err(RawError::UnexpectedType));
+    /// assert_eq!(docbuf.get_str("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert_eq!(docbuf.get_str("unknown"), Ok(None));
     /// # Ok::<(), RawError>(())
     /// ```
@@ -590,14 +693,14 @@ impl Doc {
     /// document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, elem::Element, RawError};
+    /// # use rawbson::{DocBuf, elem::Element, ErrorKind, RawError};
     /// use bson::doc;
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "doc": { "key": "value"},
     ///     "bool": true,
     /// });
     /// assert_eq!(docbuf.get_document("doc")?.expect("finding key doc").get_str("key"), Ok(Some("value")));
-    /// assert_eq!(docbuf.get_document("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_document("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_document("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -642,14 +745,14 @@ impl Doc {
     /// document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, elem, RawError};
+    /// # use rawbson::{DocBuf, elem, ErrorKind, RawError};
     /// use bson::{doc, Binary, spec::BinarySubtype};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "binary": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
     ///     "bool": true,
     /// });
     /// assert_eq!(docbuf.get_binary("binary")?.map(elem::RawBsonBinary::as_bytes), Some(&[1, 2, 3][..]));
-    /// assert_eq!(docbuf.get_binary("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_binary("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_binary("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -664,14 +767,14 @@ impl Doc {
     /// document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, RawError};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError};
     /// use bson::{doc, oid::ObjectId};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "_id": ObjectId::new(),
     ///     "bool": true,
     /// });
     /// let _: ObjectId = docbuf.get_object_id("_id")?.unwrap();
-    /// assert_eq!(docbuf.get_object_id("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_object_id("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_object_id("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -686,14 +789,14 @@ impl Doc {
     /// document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, RawError};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError};
     /// use bson::{doc, oid::ObjectId};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "_id": ObjectId::new(),
     ///     "bool": true,
     /// });
     /// assert!(docbuf.get_bool("bool")?.unwrap());
-    /// assert_eq!(docbuf.get_bool("_id").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_bool("_id").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_object_id("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -708,7 +811,7 @@ impl Doc {
     /// document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, RawError};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError};
     /// use bson::doc;
     /// use chrono::{Utc, Datelike, TimeZone};
     /// let docbuf = DocBuf::from_document(&doc! {
@@ -716,7 +819,7 @@ impl Doc {
     ///     "bool": true,
     /// });
     /// assert_eq!(docbuf.get_datetime("created_at")?.unwrap().year(), 2020);
-    /// assert_eq!(docbuf.get_datetime("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_datetime("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_datetime("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -734,14 +837,14 @@ impl Doc {
     /// exists for consistency with other element types, and as a way to assert
     /// type of the element.
     /// ```
-    /// # use rawbson::{DocBuf, RawError};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError};
     /// use bson::doc;
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "null": null,
     ///     "bool": true,
     /// });
     /// docbuf.get_null("null")?.unwrap();
-    /// assert_eq!(docbuf.get_null("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_null("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_null("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -757,7 +860,7 @@ impl Doc {
     /// is not a regex.  Returns `Ok(None)` if the key is not found in the
     /// document.
     /// ```
-    /// # use rawbson::{DocBuf, RawError, elem};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError, elem};
     /// use bson::{doc, Regex};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "regex": Regex {
@@ -768,7 +871,7 @@ impl Doc {
     /// });
     /// assert_eq!(docbuf.get_regex("regex")?.unwrap().pattern(), r"end\s*$");
     /// assert_eq!(docbuf.get_regex("regex")?.unwrap().options(), "i");
-    /// assert_eq!(docbuf.get_regex("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_regex("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_regex("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -786,14 +889,14 @@ impl Doc {
     /// is not a javascript code object.  Returns `Ok(None)` if the key is not found
     /// in the document.
     /// ```
-    /// # use rawbson::{DocBuf, RawError, elem};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError, elem};
     /// use bson::{doc, Bson};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "js": Bson::JavaScriptCode(String::from("console.log(\"hi y'all\");")),
     ///     "bool": true,
     /// });
     /// assert_eq!(docbuf.get_javascript("js")?, Some("console.log(\"hi y'all\");"));
-    /// assert_eq!(docbuf.get_javascript("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_javascript("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_javascript("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -811,14 +914,14 @@ impl Doc {
     /// is not a symbol object.  Returns `Ok(None)` if the key is not found
     /// in the document.
     /// ```
-    /// # use rawbson::{DocBuf, RawError, elem};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError, elem};
     /// use bson::{doc, Bson};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "symbol": Bson::Symbol(String::from("internal")),
     ///     "bool": true,
     /// });
     /// assert_eq!(docbuf.get_symbol("symbol")?, Some("internal"));
-    /// assert_eq!(docbuf.get_symbol("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_symbol("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_symbol("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -837,7 +940,7 @@ impl Doc {
     /// is not a javascript code with scope object.  Returns `Ok(None)` if the key is not found
     /// in the document.
     /// ```
-    /// # use rawbson::{DocBuf, RawError, elem};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError, elem};
     /// use bson::{doc, JavaScriptCodeWithScope};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "js": JavaScriptCodeWithScope {
@@ -849,7 +952,7 @@ impl Doc {
     /// let (js, scope) = docbuf.get_javascript_with_scope("js")?.unwrap();
     /// assert_eq!(js, "console.log(\"i:\", i);");
     /// assert_eq!(scope.get_i32("i")?.unwrap(), 42);
-    /// assert_eq!(docbuf.get_javascript_with_scope("bool").unwrap_err(), RawError::UnexpectedType);
+    /// assert_eq!(docbuf.get_javascript_with_scope("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert!(docbuf.get_javascript_with_scope("unknown")?.is_none());
     /// # Ok::<(), RawError>(())
     /// ```
@@ -863,14 +966,14 @@ impl Doc {
     /// is not an i32.  Returns `Ok(None)` if the key is not found in the document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, RawError};
+    /// # use rawbson::{DocBuf, ErrorKind, RawError};
     /// use bson::doc;
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "bool": true,
     ///     "i32": 1_000_000,
     /// });
     /// assert_eq!(docbuf.get_i32("i32"), Ok(Some(1_000_000)));
-    /// assert_eq!(docbuf.get_i32("bool"), Err(RawError::UnexpectedType));
+    /// assert_eq!(docbuf.get_i32("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert_eq!(docbuf.get_i32("unknown"), Ok(None));
     /// # Ok::<(), RawError>(())
     /// ```
@@ -884,7 +987,7 @@ impl Doc {
     /// is not an i32.  Returns `Ok(None)` if the key is not found in the document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, elem, RawError};
+    /// # use rawbson::{DocBuf, elem, ErrorKind, RawError};
     /// use bson::{doc, Timestamp};
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "bool": true,
@@ -894,7 +997,7 @@ impl Doc {
     ///
     /// assert_eq!(timestamp.time(), 649876543);
     /// assert_eq!(timestamp.increment(), 9);
-    /// assert_eq!(docbuf.get_timestamp("bool"), Err(RawError::UnexpectedType));
+    /// assert_eq!(docbuf.get_timestamp("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert_eq!(docbuf.get_timestamp("unknown"), Ok(None));
     /// # Ok::<(), RawError>(())
     /// ```
@@ -908,14 +1011,14 @@ impl Doc {
     /// is not an i64.  Returns `Ok(None)` if the key is not found in the document.
     ///
     /// ```
-    /// # use rawbson::{DocBuf, elem::Element, RawError};
+    /// # use rawbson::{DocBuf, elem::Element, ErrorKind, RawError};
     /// use bson::doc;
     /// let docbuf = DocBuf::from_document(&doc! {
     ///     "bool": true,
     ///     "i64": 9223372036854775807_i64,
     /// });
     /// assert_eq!(docbuf.get_i64("i64"), Ok(Some(9223372036854775807)));
-    /// assert_eq!(docbuf.get_i64("bool"), Err(RawError::UnexpectedType));
+    /// assert_eq!(docbuf.get_i64("bool").unwrap_err().kind(), &ErrorKind::UnexpectedType);
     /// assert_eq!(docbuf.get_i64("unknown"), Ok(None));
     /// # Ok::<(), RawError>(())
     /// ```
@@ -923,6 +1026,185 @@ impl Doc {
         self.get_with(key, elem::Element::as_i64)
     }
 
+    /// Get the element at a dotted path such as `"a.b.0.c"`, descending
+    /// through nested documents and arrays.
+    ///
+    /// Numeric segments index into arrays.  `path` may be a `&str`, or a
+    /// [`KeyPath`] parsed ahead of time for repeated lookups.  See the
+    /// [`path`] module for details.
+    ///
+    /// Returns an error if a document along the path is malformed.  Returns
+    /// `Ok(None)` if any segment is not found, or if the path runs through a
+    /// value that is neither a document nor an array.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError};
+    /// use bson::doc;
+    /// let docbuf = DocBuf::from_document(&doc! {
+    ///     "a": {"b": [{"c": 1}, {"c": 2}]},
+    /// });
+    /// let element = docbuf.get_path("a.b.1.c")?.expect("finding a.b.1.c");
+    /// assert_eq!(element.as_i32(), Ok(2));
+    /// assert!(docbuf.get_path("a.b.2.c")?.is_none());
+    /// assert!(docbuf.get_path("a.b.1.c.d")?.is_none());
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn get_path<'a, P>(&'a self, path: &P) -> OptResult<elem::Element<'a>>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, Ok)
+    }
+
+    /// Get the element at a dotted path, and convert it to an f64.
+    pub fn get_path_f64<P>(&self, path: &P) -> OptResult<f64>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_f64)
+    }
+
+    /// Get the element at a dotted path, and convert it to a &str.
+    ///
+    /// Returns an error if the document is malformed or if the value found is
+    /// not a string.  Errors report the offset and key path of the value
+    /// relative to `self`.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, ErrorKind, RawError};
+    /// use bson::doc;
+    /// let docbuf = DocBuf::from_document(&doc! {
+    ///     "book": {"title": "Moby-Dick", "chapters": [1, 2]},
+    /// });
+    /// assert_eq!(docbuf.get_path_str("book.title"), Ok(Some("Moby-Dick")));
+    /// let err = docbuf.get_path_str("book.chapters.1").unwrap_err();
+    /// assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+    /// assert_eq!(err.key_path(), "book.chapters.1");
+    /// assert_eq!(docbuf.get_path_str("book.author"), Ok(None));
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn get_path_str<'a, P>(&'a self, path: &P) -> OptResult<&'a str>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_str)
+    }
+
+    /// Get the element at a dotted path, and convert it to a [Doc].
+    pub fn get_path_document<'a, P>(&'a self, path: &P) -> OptResult<&'a Doc>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_document)
+    }
+
+    /// Get the element at a dotted path, and convert it to an [Array].
+    pub fn get_path_array<'a, P>(&'a self, path: &P) -> OptResult<&'a Array>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_array)
+    }
+
+    /// Get the element at a dotted path, and convert it to an [elem::RawBsonBinary].
+    pub fn get_path_binary<'a, P>(&'a self, path: &P) -> OptResult<elem::RawBsonBinary<'a>>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_binary)
+    }
+
+    /// Get the element at a dotted path, and convert it to a [bson::oid::ObjectId].
+    pub fn get_path_object_id<P>(&self, path: &P) -> OptResult<oid::ObjectId>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_object_id)
+    }
+
+    /// Get the element at a dotted path, and convert it to a [bool].
+    pub fn get_path_bool<P>(&self, path: &P) -> OptResult<bool>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_bool)
+    }
+
+    /// Get the element at a dotted path, and convert it to a [chrono::DateTime].
+    pub fn get_path_datetime<P>(&self, path: &P) -> OptResult<DateTime<Utc>>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_datetime)
+    }
+
+    /// Get the element at a dotted path, and return `()` if it is null.
+    pub fn get_path_null<P>(&self, path: &P) -> OptResult<()>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_null)
+    }
+
+    /// Get the element at a dotted path, and convert it to an [elem::RawBsonRegex].
+    pub fn get_path_regex<'a, P>(&'a self, path: &P) -> OptResult<elem::RawBsonRegex<'a>>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_regex)
+    }
+
+    /// Get the element at a dotted path, and convert it to javascript code.
+    pub fn get_path_javascript<'a, P>(&'a self, path: &P) -> OptResult<&'a str>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_javascript)
+    }
+
+    /// Get the element at a dotted path, and convert it to a symbol.
+    pub fn get_path_symbol<'a, P>(&'a self, path: &P) -> OptResult<&'a str>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_symbol)
+    }
+
+    /// Get the element at a dotted path, and convert it to javascript code and its scope.
+    pub fn get_path_javascript_with_scope<'a, P>(
+        &'a self,
+        path: &P,
+    ) -> OptResult<(&'a str, &'a Doc)>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_javascript_with_scope)
+    }
+
+    /// Get the element at a dotted path, and convert it to an i32.
+    pub fn get_path_i32<P>(&self, path: &P) -> OptResult<i32>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_i32)
+    }
+
+    /// Get the element at a dotted path, and convert it to an [elem::RawBsonTimestamp].
+    pub fn get_path_timestamp<'a, P>(&'a self, path: &P) -> OptResult<elem::RawBsonTimestamp<'a>>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_timestamp)
+    }
+
+    /// Get the element at a dotted path, and convert it to an i64.
+    pub fn get_path_i64<P>(&self, path: &P) -> OptResult<i64>
+    where
+        P: path::AsKeyPath + ?Sized,
+    {
+        path::get_path(self, path, elem::Element::as_i64)
+    }
+
     /// Return a reference to the contained data as a `&[u8]`
     ///
     /// ```
@@ -957,7 +1239,13 @@ impl TryFrom<&Doc> for bson::Document {
     fn try_from(rawdoc: &Doc) -> RawResult<bson::Document> {
         rawdoc
             .into_iter()
-            .map(|res| res.and_then(|(k, v)| Ok((k.to_owned(), v.try_into()?))))
+            .map(|res| {
+                let (k, v) = res?;
+                let bson = Bson::try_from(v).map_err(|err| {
+                    err.within(k, offset_in(&rawdoc.data, v.as_bytes()), v.element_type())
+                })?;
+                Ok((k.to_owned(), bson))
+            })
             .collect()
     }
 }
@@ -979,116 +1267,69 @@ pub struct DocIter<'a> {
     offset: usize,
 }
 
-impl<'a> Iterator for DocIter<'a> {
-    type Item = RawResult<(&'a str, elem::Element<'a>)>;
-
-    fn next(&mut self) -> Option<RawResult<(&'a str, elem::Element<'a>)>> {
-        if self.offset == self.doc.data.len() - 1 {
-            if self.doc.data[self.offset] == 0 {
-                // end of document marker
-                return None;
-            } else {
-                return Some(Err(RawError::MalformedValue(
-                    "document not null terminated".into(),
-                )));
-            }
+impl<'a> DocIter<'a> {
+    /// Read a non-negative i32 length prefix at `offset`.
+    fn read_length(&self, offset: usize) -> RawResult<usize> {
+        let bytes = self
+            .doc
+            .data
+            .get(offset..offset + 4)
+            .ok_or_else(|| RawError::new(ErrorKind::TruncatedLength).at(offset))?;
+        usize::try_from(i32_from_slice(bytes))
+            .map_err(|_| RawError::new(ErrorKind::BadLength).at(offset))
+    }
+
+    /// Read the size of a length-prefixed value at `offset`, and check
+    /// that it ends with a NUL byte.  `prefix` is the number of bytes not
+    /// counted by the length itself.
+    fn read_terminated_size(
+        &self,
+        offset: usize,
+        prefix: usize,
+        minimum: usize,
+    ) -> RawResult<usize> {
+        let size = prefix + self.read_length(offset)?;
+        if size < minimum + prefix {
+            return Err(RawError::new(ErrorKind::BadLength).at(offset));
         }
-        let key = match read_nullterminated(&self.doc.data[self.offset + 1..]) {
-            Ok(key) => key,
-            Err(err) => return Some(Err(err)),
-        };
-        let valueoffset = self.offset + 1 + key.len() + 1; // type specifier + key + \0
-        let element_type = match ElementType::from(self.doc.data[self.offset]) {
-            Some(et) => et,
-            None => {
-                return Some(Err(RawError::MalformedValue(format!(
-                    "invalid tag: {}",
-                    self.doc.data[self.offset]
-                ))))
-            }
-        };
-        let element_size = match element_type {
+        match self.doc.data.get(offset + size - 1) {
+            Some(0) => Ok(size),
+            Some(_) => Err(RawError::new(ErrorKind::MissingNul).at(offset + size - 1)),
+            None => Err(RawError::new(ErrorKind::TruncatedLength).at(offset)),
+        }
+    }
+
+    /// Return the size of the value of type `element_type` at `valueoffset`.
+    fn element_size(&self, element_type: ElementType, valueoffset: usize) -> RawResult<usize> {
+        let data = &self.doc.data;
+        Ok(match element_type {
             ElementType::Double => 8,
-            ElementType::String => {
-                let size =
-                    4 + i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize;
-                if self.doc.data[valueoffset + size - 1] != 0 {
-                    return Some(Err(RawError::MalformedValue(
-                        "string not null terminated".into(),
-                    )));
-                }
-                size
-            }
-            ElementType::EmbeddedDocument => {
-                let size = i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize;
-                if self.doc.data[valueoffset + size - 1] != 0 {
-                    return Some(Err(RawError::MalformedValue(
-                        "document not null terminated".into(),
-                    )));
-                }
-                size
-            }
-            ElementType::Array => {
-                let size = i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize;
-                if self.doc.data[valueoffset + size - 1] != 0 {
-                    return Some(Err(RawError::MalformedValue(
-                        "array not null terminated".into(),
-                    )));
-                }
-                size
-            }
-            ElementType::Binary => {
-                5 + i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize
-            }
+            ElementType::String => self.read_terminated_size(valueoffset, 4, 1)?,
+            ElementType::EmbeddedDocument => self.read_terminated_size(valueoffset, 0, 5)?,
+            ElementType::Array => self.read_terminated_size(valueoffset, 0, 5)?,
+            ElementType::Binary => 5 + self.read_length(valueoffset)?,
             ElementType::Undefined => 0,
             ElementType::ObjectId => 12,
             ElementType::Boolean => 1,
             ElementType::DateTime => 8,
             ElementType::Null => 0,
             ElementType::RegularExpression => {
-                let regex = match read_nullterminated(&self.doc.data[valueoffset..]) {
-                    Ok(regex) => regex,
-                    Err(err) => return Some(Err(err)),
-                };
-                let options =
-                    match read_nullterminated(&self.doc.data[valueoffset + regex.len() + 1..]) {
-                        Ok(options) => options,
-                        Err(err) => return Some(Err(err)),
-                    };
+                let regex = read_nullterminated(data.get(valueoffset..).unwrap_or_default())
+                    .map_err(|err| err.shifted(valueoffset))?;
+                let optionsoffset = valueoffset + regex.len() + 1;
+                let options = read_nullterminated(&data[optionsoffset..])
+                    .map_err(|err| err.shifted(optionsoffset))?;
                 regex.len() + options.len() + 2
             }
             ElementType::DbPointer => {
-                let string_size =
-                    4 + i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize;
+                let string_size = self.read_terminated_size(valueoffset, 4, 1)?;
                 let id_size = 12;
-                if self.doc.data[valueoffset + string_size - 1] != 0 {
-                    return Some(Err(RawError::MalformedValue(
-                        "DBPointer string not null-terminated".into(),
-                    )));
-                }
                 string_size + id_size
             }
-            ElementType::JavaScriptCode => {
-                let size =
-                    4 + i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize;
-                if self.doc.data[valueoffset + size - 1] != 0 {
-                    return Some(Err(RawError::MalformedValue(
-                        "javascript code not null-terminated".into(),
-                    )));
-                }
-                size
-            }
-            ElementType::Symbol => {
-                4 + i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize
-            }
+            ElementType::JavaScriptCode => self.read_terminated_size(valueoffset, 4, 1)?,
+            ElementType::Symbol => self.read_terminated_size(valueoffset, 4, 1)?,
             ElementType::JavaScriptCodeWithScope => {
-                let size = i32_from_slice(&self.doc.data[valueoffset..valueoffset + 4]) as usize;
-                if self.doc.data[valueoffset + size - 1] != 0 {
-                    return Some(Err(RawError::MalformedValue(
-                        "javascript with scope not null-terminated".into(),
-                    )));
-                }
-                size
+                self.read_terminated_size(valueoffset, 0, 14)?
             }
             ElementType::Int32 => 4,
             ElementType::Timestamp => 8,
@@ -1096,18 +1337,69 @@ impl<'a> Iterator for DocIter<'a> {
             ElementType::Decimal128 => 16,
             ElementType::MaxKey => 0,
             ElementType::MinKey => 0,
-        };
+        })
+    }
+
+    fn next_element(&mut self) -> RawResult<(&'a str, elem::Element<'a>)> {
+        let data: &'a [u8] = &self.doc.data;
+        let key = read_nullterminated(&data[self.offset + 1..])
+            .map_err(|err| err.shifted(self.offset + 1))?;
+        let valueoffset = self.offset + 1 + key.len() + 1; // type specifier + key + \0
+        let tag = data[self.offset];
+        let element_type = ElementType::from(tag).ok_or_else(|| {
+            RawError::new(ErrorKind::InvalidTag(tag))
+                .at(self.offset)
+                .in_key(key)
+        })?;
+        let element_size = self
+            .element_size(element_type, valueoffset)
+            .map_err(|err| err.in_key(key).of_type(element_type))?;
         let nextoffset = valueoffset + element_size;
+        // The last byte of the document is reserved for its terminator.
+        if nextoffset > data.len() - 1 {
+            return Err(RawError::new(ErrorKind::TruncatedLength)
+                .at(valueoffset)
+                .in_key(key)
+                .of_type(element_type));
+        }
         self.offset = nextoffset;
-        Some(Ok((
+        Ok((
             key,
-            elem::Element::new(element_type, &self.doc.data[valueoffset..nextoffset]),
-        )))
+            elem::Element::new(element_type, &data[valueoffset..nextoffset]),
+        ))
+    }
+}
+
+impl<'a> Iterator for DocIter<'a> {
+    type Item = RawResult<(&'a str, elem::Element<'a>)>;
+
+    fn next(&mut self) -> Option<RawResult<(&'a str, elem::Element<'a>)>> {
+        if self.offset >= self.doc.data.len() - 1 {
+            if self.offset == self.doc.data.len() - 1 && self.doc.data[self.offset] == 0 {
+                // end of document marker
+                self.offset += 1;
+                return None;
+            } else if self.offset >= self.doc.data.len() {
+                // iteration finished, or stopped after an error
+                return None;
+            } else {
+                let offset = self.offset;
+                self.offset = self.doc.data.len();
+                return Some(Err(RawError::new(ErrorKind::MissingNul).at(offset)));
+            }
+        }
+        let result = self.next_element();
+        if result.is_err() {
+            // Don't try to resume parsing from an unknown position.
+            self.offset = self.doc.data.len();
+        }
+        Some(result)
     }
 }
 
 pub type ArrayRef<'a> = &'a Array;
 
+#[derive(Debug)]
 pub struct Array {
     doc: Doc,
 }
@@ -1182,7 +1474,14 @@ impl Array {
         index: usize,
         f: impl FnOnce(elem::Element<'a>) -> RawResult<T>,
     ) -> OptResult<T> {
-        self.get(index)?.map(f).transpose()
+        self.get(index)?
+            .map(|elem| {
+                f(elem).map_err(|err| {
+                    let offset = offset_in(self.as_bytes(), elem.as_bytes());
+                    err.within(&index.to_string(), offset, elem.element_type())
+                })
+            })
+            .transpose()
     }
 
     pub fn get_f64(&self, index: usize) -> OptResult<f64> {
@@ -1263,9 +1562,13 @@ impl TryFrom<&Array> for Vec<Bson> {
 
     fn try_from(arr: &Array) -> RawResult<Vec<Bson>> {
         arr.into_iter()
-            .map(|result| {
+            .enumerate()
+            .map(|(index, result)| {
                 let rawbson = result?;
-                Bson::try_from(rawbson)
+                Bson::try_from(rawbson).map_err(|err| {
+                    let offset = offset_in(arr.as_bytes(), rawbson.as_bytes());
+                    err.within(&index.to_string(), offset, rawbson.element_type())
+                })
             })
             .collect()
     }
@@ -1298,14 +1601,14 @@ impl<'a> Iterator for ArrayIter<'a> {
                 Err(err) => return Err(err),
             };
 
-            let index: usize = key
-                .parse()
-                .map_err(|_| RawError::MalformedValue("non-integer array index found".into()))?;
-
-            if index == self.index {
+            if key.parse() == Ok(self.index) {
                 Ok(bson)
             } else {
-                Err(RawError::MalformedValue("wrong array index found".into()))
+                let offset = offset_in(&self.dociter.doc.data, bson.as_bytes());
+                Err(RawError::new(ErrorKind::BadArrayIndex)
+                    .at(offset)
+                    .in_key(key)
+                    .of_type(bson.element_type()))
             }
         });
         self.index += 1;
@@ -1342,36 +1645,55 @@ fn u32_from_slice(val: &[u8]) -> u32 {
     u32::from_le_bytes(val.try_into().expect("u32 is four bytes"))
 }
 
-fn d128_from_slice(val: &[u8]) -> Decimal128 {
-    // TODO: Handle Big Endian platforms
-    let d =
-        unsafe { decimal::d128::from_raw_bytes(val.try_into().expect("d128 is sixteen bytes")) };
-    Decimal128::from(d)
+/// Check the length prefix and trailing NUL of a document.
+fn check_envelope(data: &[u8]) -> RawResult<()> {
+    if data.len() < 5 {
+        return Err(RawError::new(ErrorKind::TruncatedLength).at(0));
+    }
+    let length = i32_from_slice(&data[..4]);
+    if data.len() as i64 != length as i64 {
+        return Err(RawError::new(ErrorKind::BadLength).at(0));
+    }
+    if data[data.len() - 1] != 0 {
+        return Err(RawError::new(ErrorKind::MissingNul).at(data.len() - 1));
+    }
+    Ok(())
+}
+
+/// Return the offset of `inner` within `outer`, which must contain it.
+fn offset_in(outer: &[u8], inner: &[u8]) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
 }
 
 fn read_nullterminated(buf: &[u8]) -> RawResult<&str> {
-    let mut splits = buf.splitn(2, |x| *x == 0);
-    let value = splits
-        .next()
-        .ok_or_else(|| RawError::MalformedValue("no value".into()))?;
-    if splits.next().is_some() {
-        Ok(try_to_str(value)?)
-    } else {
-        Err(RawError::MalformedValue("expected null terminator".into()))
+    match buf.iter().position(|x| *x == 0) {
+        Some(end) => try_to_str(&buf[..end]),
+        None => Err(RawError::new(ErrorKind::MissingNul).at(buf.len())),
     }
 }
 
 fn read_lenencoded(buf: &[u8]) -> RawResult<&str> {
-    let length = i32_from_slice(&buf[..4]);
-    assert!(buf.len() as i32 >= length + 4);
-    try_to_str(&buf[4..4 + length as usize - 1])
+    let length = buf
+        .get(..4)
+        .map(i32_from_slice)
+        .ok_or_else(|| RawError::new(ErrorKind::TruncatedLength).at(0))?;
+    let end = match usize::try_from(length) {
+        Ok(length) if length >= 1 => 4 + length,
+        _ => return Err(RawError::new(ErrorKind::BadLength).at(0)),
+    };
+    if end > buf.len() {
+        return Err(RawError::new(ErrorKind::TruncatedLength).at(0));
+    }
+    if buf[end - 1] != 0 {
+        return Err(RawError::new(ErrorKind::MissingNul).at(end - 1));
+    }
+    try_to_str(&buf[4..end - 1]).map_err(|err| err.shifted(4))
 }
 
+/// Decode a utf-8 string.  On failure, the error offset points at the first
+/// invalid byte.
 fn try_to_str(data: &[u8]) -> RawResult<&str> {
-    match std::str::from_utf8(data) {
-        Ok(s) => Ok(s),
-        Err(_) => Err(RawError::Utf8EncodingError(data.into())),
-    }
+    std::str::from_utf8(data).map_err(|err| RawError::new(ErrorKind::BadUtf8).at(err.valid_up_to()))
 }
 
 pub type DocRef<'a> = &'a Doc;
@@ -1379,7 +1701,9 @@ pub type DocRef<'a> = &'a Doc;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bson::{doc, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope, Regex, Timestamp};
+    use bson::{
+        doc, spec::BinarySubtype, Binary, Bson, Document, JavaScriptCodeWithScope, Regex, Timestamp,
+    };
     use chrono::TimeZone;
 
     fn to_bytes(doc: &bson::Document) -> Vec<u8> {
@@ -1575,14 +1899,14 @@ mod tests {
             .as_bool()
             .expect("result was not boolean");
 
-        assert_eq!(boolean, true);
+        assert!(boolean);
     }
 
     #[test]
     fn datetime() {
         let rawdoc = DocBuf::from_document(&doc! {
             "boolean": true,
-            "datetime": Utc.ymd(2000,10,31).and_hms(12, 30, 45),
+            "datetime": Utc.with_ymd_and_hms(2000, 10, 31, 12, 30, 45).unwrap(),
         });
         let datetime = rawdoc
             .get("datetime")
@@ -1668,7 +1992,7 @@ mod tests {
             .expect("invalid element");
         assert_eq!(scope_key, "ok");
         let scope_value = scope_value_bson.as_bool().expect("not a boolean");
-        assert_eq!(scope_value, true);
+        assert!(scope_value);
     }
 
     #[test]
@@ -1804,15 +2128,133 @@ mod tests {
             Bson::Boolean(false)
         );
     }
+
+    #[test]
+    fn malformed_lengths_are_errors() {
+        let cases: &[&[u8]] = &[
+            // string claims to be 255 bytes long
+            b"\x13\x00\x00\x00\x02hi\x00\xff\x00\x00\x00y'all\x00\x00",
+            // string with a negative length
+            b"\x13\x00\x00\x00\x02hi\x00\xff\xff\xff\xffy'all\x00\x00",
+            // string with a zero length
+            b"\x13\x00\x00\x00\x02hi\x00\x00\x00\x00\x00y'all\x00\x00",
+            // embedded document longer than its parent
+            b"\x0d\x00\x00\x00\x03d\x00\x40\x00\x00\x00\x00\x00",
+            // length prefix cut off by the end of the document
+            b"\x09\x00\x00\x00\x02s\x00\x01\x00",
+            // i64 value cut off by the end of the document
+            b"\x0b\x00\x00\x00\x12i\x00\x01\x02\x03\x00",
+            // key runs into the document terminator
+            b"\x07\x00\x00\x00\x0aa\x00",
+            // binary claims more data than is present
+            b"\x0e\x00\x00\x00\x05b\x00\x30\x00\x00\x00\x00\x01\x00",
+            // javascript with scope too short to hold its parts
+            b"\x0e\x00\x00\x00\x0fj\x00\x06\x00\x00\x00\x00\x00\x00",
+        ];
+        for bytes in cases {
+            let doc = Doc::new(bytes).expect("valid envelope");
+            let mut iter = doc.into_iter();
+            match iter.next() {
+                Some(Err(err)) => {
+                    assert_ne!(err.kind(), &ErrorKind::UnexpectedType);
+                    assert!(err.offset().is_some(), "{} has no offset", err);
+                }
+                other => panic!("{:?} should be malformed, got {:?}", bytes, other),
+            }
+            assert!(iter.next().is_none(), "iteration stops after an error");
+            assert!(doc.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn errors_report_offsets_and_key_paths() {
+        // {"a": {"b": <boolean 0x02>}}
+        let bytes = b"\x11\0\0\0\x03a\0\x09\0\0\0\x08b\0\x02\0\0";
+        let doc = Doc::new(bytes).unwrap();
+
+        // Accessors report positions relative to the document they are called on.
+        let err = doc
+            .get_document("a")
+            .unwrap()
+            .unwrap()
+            .get_bool("b")
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadBool(2));
+        assert_eq!(err.offset(), Some(7));
+        assert_eq!(err.key_path(), "b");
+        assert_eq!(err.element_type(), Some(ElementType::Boolean));
+
+        // Whole-document conversions report positions in the root document.
+        let err = Document::try_from(doc).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadBool(2));
+        assert_eq!(err.offset(), Some(14));
+        assert_eq!(err.key_path(), "a.b");
+        assert_eq!(err.element_type(), Some(ElementType::Boolean));
+        assert_eq!(
+            err.to_string(),
+            "boolean value 0x02 was not 0 or 1 at offset 14 in key \"a.b\" (Boolean)"
+        );
+
+        let err = de::from_doc::<Document>(doc).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            de::ErrorKind::Malformed(ErrorKind::BadBool(2))
+        ));
+        assert_eq!(err.offset(), Some(14));
+        assert_eq!(err.key_path(), "a.b");
+        assert_eq!(err.element_type(), Some(ElementType::Boolean));
+
+        // Iteration errors carry the key of the element being read.
+        let bad_tag = Doc::new(b"\x08\0\0\0\x42a\0\0").unwrap();
+        let err = bad_tag.into_iter().next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidTag(0x42));
+        assert_eq!(err.offset(), Some(4));
+        assert_eq!(err.key_path(), "a");
+
+        let err = Doc::new(b"\x06\0\0\0\0\x01").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::MissingNul);
+        assert_eq!(err.offset(), Some(5));
+        assert_eq!(err.key_path(), "");
+    }
+
+    #[test]
+    fn malformed_element_values_are_errors() {
+        // The declared lengths are consistent, but the inner values are
+        // malformed.
+        let mut bytes = to_bytes(&doc! {
+            "js": JavaScriptCodeWithScope { code: String::from("x"), scope: doc! {} },
+        });
+        // claim the code string extends into the scope document
+        bytes[12] = 0x20;
+        let doc = Doc::new(&bytes).unwrap();
+        let (_, elem) = doc.into_iter().next().unwrap().unwrap();
+        let err = elem.as_javascript_with_scope().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::TruncatedLength);
+        assert_eq!(
+            err.element_type(),
+            Some(ElementType::JavaScriptCodeWithScope)
+        );
+
+        // An old binary value whose inner length is i32::MAX.
+        let bytes = b"\x15\0\0\0\x05b\0\x08\0\0\0\x02\xff\xff\xff\x7f\x01\x02\x03\x04\0";
+        let err = Doc::new(bytes).unwrap().get_binary("b").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadLength);
+        assert_eq!(err.element_type(), Some(ElementType::Binary));
+    }
 }
 
 #[cfg(test)]
 mod proptests {
     use proptest::prelude::*;
+    use std::cmp::Ordering;
     use std::convert::TryInto;
 
-    use super::DocBuf;
-    use crate::props::arbitrary_bson;
+    use super::{Doc, DocBuf};
+    use crate::{
+        cmp::compare,
+        props::{arbitrary_bson, arbitrary_number},
+        validate::walk,
+    };
     use bson::doc;
 
     fn to_bytes(doc: &bson::Document) -> Vec<u8> {
@@ -1840,5 +2282,51 @@ mod proptests {
             let roundtrip = roundtrip.unwrap();
             prop_assert_eq!(doc, roundtrip);
         }
+
+        #[test]
+        fn mutations_never_panic(
+            bson in arbitrary_bson(),
+            index in any::<prop::sample::Index>(),
+            value: u8,
+        ) {
+            let mut raw = to_bytes(&doc!{"bson": bson});
+            let i = index.index(raw.len());
+            raw[i] = value;
+            if let Ok(doc) = Doc::new(&raw) {
+                let _ = walk(doc);
+                let _ = crate::de::from_doc::<bson::Document>(doc);
+            }
+        }
+
+        #[test]
+        fn validate_no_crashes(s: Vec<u8>) {
+            let _ = DocBuf::new_validated(s);
+        }
+
+        #[test]
+        fn encoded_bson_validates(bson in arbitrary_bson()) {
+            let raw = to_bytes(&doc!{"bson": bson});
+            prop_assert!(DocBuf::new_validated(raw).is_ok());
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(4096))]
+
+        #[test]
+        fn number_order_is_transitive(
+            a in arbitrary_number(),
+            b in arbitrary_number(),
+            c in arbitrary_number(),
+        ) {
+            let (a, b, c) = (a.as_element(), b.as_element(), c.as_element());
+            prop_assert_eq!(compare(a, b), compare(b, a).reverse());
+            if compare(a, b) != Ordering::Greater && compare(b, c) != Ordering::Greater {
+                prop_assert_ne!(compare(a, c), Ordering::Greater);
+            }
+            if compare(a, b) == Ordering::Equal && compare(b, c) == Ordering::Equal {
+                prop_assert_eq!(compare(a, c), Ordering::Equal);
+            }
+        }
     }
 }
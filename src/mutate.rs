@@ -0,0 +1,697 @@
+// In-place editing of a DocBuf.  Every edit splices the byte buffer and then
+// patches the length prefix of each document or array enclosing the change.
+
+use std::convert::{TryFrom, TryInto};
+use std::ops::Range;
+
+use bson::spec::ElementType;
+
+use crate::{
+    elem::{Element, RawBson},
+    i32_from_slice, offset_in,
+    path::{AsKeyPath, Segment},
+    Doc, DocBuf, ErrorKind, RawError, RawResult,
+};
+
+/// Where a path leads within a document.
+enum Target<'p> {
+    /// The path names an existing element.
+    Found {
+        in_array: bool,
+        // The whole element, from its type byte to the end of its value.
+        element: Range<usize>,
+        value_start: usize,
+        element_type: ElementType,
+    },
+    /// The path leaves the existing tree at `rest[0]`, which is missing from
+    /// a container holding `len` elements.
+    Missing {
+        in_array: bool,
+        len: usize,
+        rest: Vec<&'p str>,
+    },
+}
+
+/// Follow `segment` and then `segments` down from `container`, pushing the
+/// offset in `root` of every container passed through onto `ancestors`.
+///
+/// A path through a value that is neither a document nor an array produces
+/// an error of kind [`ErrorKind::UnexpectedType`].
+fn locate<'p>(
+    root: &[u8],
+    container: &Doc,
+    in_array: bool,
+    segment: Segment<'p>,
+    segments: &mut impl Iterator<Item = Segment<'p>>,
+    ancestors: &mut Vec<usize>,
+) -> RawResult<Target<'p>> {
+    ancestors.push(offset_in(root, container.as_bytes()));
+    let mut len = 0;
+    let mut found = None;
+    for result in container {
+        let (key, elem) = result?;
+        let matches = if in_array {
+            segment.index == Some(len)
+        } else {
+            key == segment.key
+        };
+        if matches {
+            found = Some((key, elem));
+            break;
+        }
+        len += 1;
+    }
+    let (key, elem) = match found {
+        Some(found) => found,
+        None => {
+            let mut rest = vec![segment.key];
+            rest.extend(segments.map(|segment| segment.key));
+            return Ok(Target::Missing {
+                in_array,
+                len,
+                rest,
+            });
+        }
+    };
+
+    let next = match segments.next() {
+        Some(next) => next,
+        None => {
+            let value_start = offset_in(root, elem.as_bytes());
+            return Ok(Target::Found {
+                in_array,
+                element: value_start - key.len() - 2..value_start + elem.as_bytes().len(),
+                value_start,
+                element_type: elem.element_type(),
+            });
+        }
+    };
+    let target = match elem.element_type() {
+        ElementType::EmbeddedDocument => elem
+            .as_document()
+            .and_then(|doc| locate(root, doc, false, next, segments, ancestors)),
+        ElementType::Array => elem
+            .as_array()
+            .and_then(|arr| locate(root, &arr.doc, true, next, segments, ancestors)),
+        _ => Err(RawError::new(ErrorKind::UnexpectedType).at(0)),
+    };
+    target.map_err(|err| {
+        err.within(
+            key,
+            offset_in(container.as_bytes(), elem.as_bytes()),
+            elem.element_type(),
+        )
+    })
+}
+
+/// Encode an element with the given key and value.
+fn encode_element(element_type: ElementType, key: &str, value: &[u8]) -> RawResult<Vec<u8>> {
+    if let Some(position) = key.bytes().position(|b| b == 0) {
+        return Err(RawError::new(ErrorKind::InteriorNul).at(1 + position));
+    }
+    let mut bytes = Vec::with_capacity(key.len() + 2 + value.len());
+    bytes.push(element_type as u8);
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(value);
+    Ok(bytes)
+}
+
+/// Wrap a length prefix and trailing nul around the elements in `body`.
+fn encode_document(body: &[u8]) -> RawResult<Vec<u8>> {
+    let length =
+        i32::try_from(body.len() + 5).map_err(|_| RawError::new(ErrorKind::BadLength).at(0))?;
+    let mut bytes = Vec::with_capacity(body.len() + 5);
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(body);
+    bytes.push(0);
+    Ok(bytes)
+}
+
+impl DocBuf {
+    /// Append an element to the end of the document.
+    ///
+    /// No check is made for an existing element with the same key; use
+    /// [`DocBuf::set`] to replace one.  Fails with
+    /// [`ErrorKind::InteriorNul`] if `key` (or a regular expression value)
+    /// contains a NUL byte, or
+    /// [`ErrorKind::BadLength`] if the document would grow past the largest
+    /// length BSON can describe.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError, elem::RawBsonRef};
+    /// let mut docbuf = DocBuf::new(b"\x05\0\0\0\0".to_vec())?;
+    /// docbuf.append("a", RawBsonRef::Int32(1))?;
+    /// docbuf.append("b", RawBsonRef::String("two"))?;
+    /// assert_eq!(docbuf.get_str("b")?, Some("two"));
+    /// assert_eq!(docbuf.as_bytes().len(), 23);
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn append<V>(&mut self, key: &str, value: V) -> RawResult<()>
+    where
+        V: TryInto<RawBson>,
+        RawError: From<V::Error>,
+    {
+        let value = value.try_into()?;
+        let end = self.data.len() - 1;
+        self.insert_element(&[0], end, key, &value)
+    }
+
+    /// Insert an element at position `index`, shifting the elements after
+    /// it along.
+    ///
+    /// Fails with [`ErrorKind::OutOfRange`] if `index` is greater than the
+    /// number of elements, and otherwise as [`DocBuf::append`] does.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError, elem::RawBsonRef};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "c": 3});
+    /// docbuf.insert(1, "b", RawBsonRef::Int32(2))?;
+    /// assert_eq!(Document::try_from(&*docbuf)?, doc! {"a": 1, "b": 2, "c": 3});
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn insert<V>(&mut self, index: usize, key: &str, value: V) -> RawResult<()>
+    where
+        V: TryInto<RawBson>,
+        RawError: From<V::Error>,
+    {
+        let value = value.try_into()?;
+        let mut position = None;
+        let mut len = 0;
+        for result in self.iter() {
+            let (elem_key, elem) = result?;
+            if len == index {
+                position = Some(offset_in(&self.data, elem.as_bytes()) - elem_key.len() - 2);
+                break;
+            }
+            len += 1;
+        }
+        let position = match position {
+            Some(position) => position,
+            None if index == len => self.data.len() - 1,
+            None => return Err(RawError::new(ErrorKind::OutOfRange)),
+        };
+        self.insert_element(&[0], position, key, &value)
+    }
+
+    /// Set the value of `key`, returning the value it replaces.
+    ///
+    /// An existing element keeps its position in the document; otherwise
+    /// the element is appended.  Only the first element with the key is
+    /// replaced.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError, elem::RawBsonRef};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 2});
+    /// let old = docbuf.set("a", RawBsonRef::String("one"))?.unwrap();
+    /// assert_eq!(old.as_element().as_i32()?, 1);
+    /// assert!(docbuf.set("c", RawBsonRef::Null)?.is_none());
+    /// assert_eq!(Document::try_from(&*docbuf)?, doc! {"a": "one", "b": 2, "c": null});
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn set<V>(&mut self, key: &str, value: V) -> RawResult<Option<RawBson>>
+    where
+        V: TryInto<RawBson>,
+        RawError: From<V::Error>,
+    {
+        let value = value.try_into()?;
+        self.set_segments(Segment::new(key), &mut None.into_iter(), &value)
+    }
+
+    /// Set the value at a dotted path, returning the value it replaces.
+    ///
+    /// Missing documents along the path are created, so setting `"a.b.c"`
+    /// in an empty document produces `{"a": {"b": {"c": value}}}`.  An array
+    /// can be extended by setting the index one past its last element;
+    /// larger indexes fail with [`ErrorKind::OutOfRange`], and keys that are
+    /// not indexes with [`ErrorKind::BadArrayIndex`].  A path through any
+    /// other type of value fails with [`ErrorKind::UnexpectedType`].
+    ///
+    /// See the [`path`](crate::path) module for the path syntax.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError, elem::RawBsonRef};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": {"b": 1, "list": [1]}});
+    /// docbuf.set_path("a.b", RawBsonRef::Int32(2))?;
+    /// docbuf.set_path("a.list.1", RawBsonRef::Int32(3))?;
+    /// docbuf.set_path("x.y", RawBsonRef::Boolean(true))?;
+    /// assert_eq!(
+    ///     Document::try_from(&*docbuf)?,
+    ///     doc! {"a": {"b": 2, "list": [1, 3]}, "x": {"y": true}},
+    /// );
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn set_path<P, V>(&mut self, path: &P, value: V) -> RawResult<Option<RawBson>>
+    where
+        P: AsKeyPath + ?Sized,
+        V: TryInto<RawBson>,
+        RawError: From<V::Error>,
+    {
+        let value = value.try_into()?;
+        let mut segments = path.segments();
+        match segments.next() {
+            Some(segment) => self.set_segments(segment, &mut segments, &value),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove the first element with key `key`, returning its value.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 2});
+    /// assert_eq!(docbuf.remove("a")?.unwrap().as_element().as_i32()?, 1);
+    /// assert!(docbuf.remove("a")?.is_none());
+    /// assert_eq!(Document::try_from(&*docbuf)?, doc! {"b": 2});
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn remove(&mut self, key: &str) -> RawResult<Option<RawBson>> {
+        self.remove_segments(Segment::new(key), &mut None.into_iter())
+    }
+
+    /// Remove the element at a dotted path, returning its value.
+    ///
+    /// Removing an array element renumbers the elements after it.  Returns
+    /// `Ok(None)` if nothing is found at the path, including when the path
+    /// runs through a value that is neither a document nor an array.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": {"b": 1, "list": [1, 2, 3]}});
+    /// docbuf.remove_path("a.b")?;
+    /// docbuf.remove_path("a.list.0")?;
+    /// assert!(docbuf.remove_path("a.b.c")?.is_none());
+    /// assert_eq!(Document::try_from(&*docbuf)?, doc! {"a": {"list": [2, 3]}});
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn remove_path<P>(&mut self, path: &P) -> RawResult<Option<RawBson>>
+    where
+        P: AsKeyPath + ?Sized,
+    {
+        let mut segments = path.segments();
+        match segments.next() {
+            Some(segment) => self.remove_segments(segment, &mut segments),
+            None => Ok(None),
+        }
+    }
+
+    /// Rename the first element with key `from` to `to`, keeping its
+    /// position.  Returns false if there is no such element.
+    ///
+    /// As with MongoDB's `$rename`, any other element already named `to`
+    /// is removed.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 2, "c": 3});
+    /// assert!(docbuf.rename_key("a", "c")?);
+    /// assert!(!docbuf.rename_key("x", "y")?);
+    /// assert_eq!(Document::try_from(&*docbuf)?, doc! {"c": 1, "b": 2});
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn rename_key(&mut self, from: &str, to: &str) -> RawResult<bool> {
+        self.rename_segments(Segment::new(from), &mut None.into_iter(), to)
+    }
+
+    /// Rename the element at a dotted path to `to`, leaving it in the same
+    /// document.  Returns false if nothing is found at the path.
+    ///
+    /// Array elements cannot be renamed, and fail with
+    /// [`ErrorKind::UnexpectedType`].
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, RawError};
+    /// # use std::convert::TryFrom;
+    /// use bson::{doc, Document};
+    /// let mut docbuf = DocBuf::from_document(&doc! {"a": {"b": 1}});
+    /// assert!(docbuf.rename_path("a.b", "c")?);
+    /// assert_eq!(Document::try_from(&*docbuf)?, doc! {"a": {"c": 1}});
+    /// # Ok::<(), RawError>(())
+    /// ```
+    pub fn rename_path<P>(&mut self, path: &P, to: &str) -> RawResult<bool>
+    where
+        P: AsKeyPath + ?Sized,
+    {
+        let mut segments = path.segments();
+        match segments.next() {
+            Some(segment) => self.rename_segments(segment, &mut segments, to),
+            None => Ok(false),
+        }
+    }
+
+    fn locate<'p>(
+        &self,
+        segment: Segment<'p>,
+        segments: &mut impl Iterator<Item = Segment<'p>>,
+        ancestors: &mut Vec<usize>,
+    ) -> RawResult<Target<'p>> {
+        locate(&self.data, self, false, segment, segments, ancestors)
+    }
+
+    /// Like `locate`, but treating a path through a scalar as missing.
+    fn locate_existing<'p>(
+        &self,
+        segment: Segment<'p>,
+        segments: &mut impl Iterator<Item = Segment<'p>>,
+        ancestors: &mut Vec<usize>,
+    ) -> RawResult<Option<Target<'p>>> {
+        match self.locate(segment, segments, ancestors) {
+            Ok(Target::Missing { .. }) => Ok(None),
+            Ok(found) => Ok(Some(found)),
+            Err(err) if err.kind() == &ErrorKind::UnexpectedType => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn set_segments<'p>(
+        &mut self,
+        segment: Segment<'p>,
+        segments: &mut impl Iterator<Item = Segment<'p>>,
+        value: &RawBson,
+    ) -> RawResult<Option<RawBson>> {
+        let mut ancestors = Vec::new();
+        match self.locate(segment, segments, &mut ancestors)? {
+            Target::Found {
+                element,
+                value_start,
+                element_type,
+                ..
+            } => {
+                let old =
+                    Element::new(element_type, &self.data[value_start..element.end]).to_owned();
+                // Keep the existing key, replacing the type byte and value.
+                let mut bytes = self.data[element.start..value_start].to_vec();
+                bytes[0] = value.element_type() as u8;
+                bytes.extend_from_slice(value.as_bytes());
+                self.splice(&ancestors, element, &bytes)?;
+                Ok(Some(old))
+            }
+            Target::Missing {
+                in_array,
+                len,
+                rest,
+            } => {
+                let container = *ancestors.last().unwrap();
+                if in_array {
+                    // Arrays can only grow by one element, at the end.
+                    if Segment::new(rest[0]).index.is_none() {
+                        return Err(RawError::new(ErrorKind::BadArrayIndex).at(container));
+                    } else if rest[0] != len.to_string() {
+                        return Err(RawError::new(ErrorKind::OutOfRange).at(container));
+                    }
+                }
+                // Build the documents for the rest of the path inside out.
+                let mut element_type = value.element_type();
+                let mut bytes = value.as_bytes().to_vec();
+                for key in rest[1..].iter().rev() {
+                    bytes = encode_document(&encode_element(element_type, key, &bytes)?)?;
+                    element_type = ElementType::EmbeddedDocument;
+                }
+                let end =
+                    container + i32_from_slice(&self.data[container..container + 4]) as usize - 1;
+                let bytes = encode_element(element_type, rest[0], &bytes)?;
+                self.splice(&ancestors, end..end, &bytes)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn remove_segments<'p>(
+        &mut self,
+        segment: Segment<'p>,
+        segments: &mut impl Iterator<Item = Segment<'p>>,
+    ) -> RawResult<Option<RawBson>> {
+        let mut ancestors = Vec::new();
+        let (in_array, element, value_start, element_type) =
+            match self.locate_existing(segment, segments, &mut ancestors)? {
+                Some(Target::Found {
+                    in_array,
+                    element,
+                    value_start,
+                    element_type,
+                }) => (in_array, element, value_start, element_type),
+                _ => return Ok(None),
+            };
+        let old = Element::new(element_type, &self.data[value_start..element.end]).to_owned();
+        if in_array {
+            // The keys of later elements change, so rebuild the whole array.
+            let start = ancestors.pop().unwrap();
+            let length = i32_from_slice(&self.data[start..start + 4]) as usize;
+            let array = Doc::new(&self.data[start..start + length])?;
+            let mut body = Vec::with_capacity(length);
+            let mut next = 0;
+            for result in array {
+                let (_, elem) = result?;
+                if offset_in(&self.data, elem.as_bytes()) == value_start {
+                    continue;
+                }
+                let key = next.to_string();
+                body.extend(encode_element(elem.element_type(), &key, elem.as_bytes())?);
+                next += 1;
+            }
+            let bytes = encode_document(&body)?;
+            self.splice(&ancestors, start..start + length, &bytes)?;
+        } else {
+            self.splice(&ancestors, element, &[])?;
+        }
+        Ok(Some(old))
+    }
+
+    fn rename_segments<'p>(
+        &mut self,
+        segment: Segment<'p>,
+        segments: &mut impl Iterator<Item = Segment<'p>>,
+        to: &str,
+    ) -> RawResult<bool> {
+        let mut ancestors = Vec::new();
+        let (in_array, element, value_start) =
+            match self.locate_existing(segment, segments, &mut ancestors)? {
+                Some(Target::Found {
+                    in_array,
+                    element,
+                    value_start,
+                    ..
+                }) => (in_array, element, value_start),
+                _ => return Ok(false),
+            };
+        if in_array {
+            return Err(RawError::new(ErrorKind::UnexpectedType)
+                .at(element.start)
+                .of_type(ElementType::Array));
+        }
+        let key_range = element.start + 1..value_start - 1;
+        if &self.data[key_range.clone()] == to.as_bytes() {
+            return Ok(true);
+        }
+        if let Some(position) = to.bytes().position(|b| b == 0) {
+            return Err(RawError::new(ErrorKind::InteriorNul).at(key_range.start + position));
+        }
+
+        // Find any other element already using the new name.
+        let parent = *ancestors.last().unwrap();
+        let length = i32_from_slice(&self.data[parent..parent + 4]) as usize;
+        let mut existing = None;
+        for result in Doc::new(&self.data[parent..parent + length])? {
+            let (key, elem) = result?;
+            if key == to {
+                let start = offset_in(&self.data, elem.as_bytes());
+                existing = Some(start - key.len() - 2..start + elem.as_bytes().len());
+                break;
+            }
+        }
+
+        // Splice the later range first, so the earlier one stays put.
+        match existing {
+            Some(existing) if existing.start > element.start => {
+                self.splice(&ancestors, existing, &[])?;
+                self.splice(&ancestors, key_range, to.as_bytes())?;
+            }
+            Some(existing) => {
+                self.splice(&ancestors, key_range, to.as_bytes())?;
+                self.splice(&ancestors, existing, &[])?;
+            }
+            None => self.splice(&ancestors, key_range, to.as_bytes())?,
+        }
+        Ok(true)
+    }
+
+    /// Insert an encoded element at `position`, inside the container at the
+    /// top of `ancestors`.
+    fn insert_element(
+        &mut self,
+        ancestors: &[usize],
+        position: usize,
+        key: &str,
+        value: &RawBson,
+    ) -> RawResult<()> {
+        let bytes = encode_element(value.element_type(), key, value.as_bytes())
+            .map_err(|err| err.shifted(position))?;
+        self.splice(ancestors, position..position, &bytes)
+    }
+
+    /// Replace `range` of the buffer with `bytes`, adjusting the length
+    /// prefix of the container starting at each offset in `ancestors`.
+    ///
+    /// Nothing is changed if any length would overflow.
+    fn splice(&mut self, ancestors: &[usize], range: Range<usize>, bytes: &[u8]) -> RawResult<()> {
+        let growth = bytes.len() as i64 - range.len() as i64;
+        let mut lengths = Vec::with_capacity(ancestors.len());
+        for &start in ancestors {
+            let length = i64::from(i32_from_slice(&self.data[start..start + 4])) + growth;
+            let length =
+                i32::try_from(length).map_err(|_| RawError::new(ErrorKind::BadLength).at(start))?;
+            lengths.push(length);
+        }
+        let mut data = std::mem::take(&mut self.data).into_vec();
+        data.splice(range, bytes.iter().copied());
+        for (&start, length) in ancestors.iter().zip(lengths) {
+            data[start..start + 4].copy_from_slice(&length.to_le_bytes());
+        }
+        self.data = data.into_boxed_slice();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use bson::{doc, Bson, Document};
+
+    use crate::{
+        elem::{RawBson, RawBsonRef},
+        DocBuf, ErrorKind, KeyPath,
+    };
+
+    /// Check that the edited buffer is still well formed, and convert it.
+    fn document(docbuf: &DocBuf) -> Document {
+        let checked = DocBuf::new(docbuf.as_bytes().to_vec())
+            .unwrap()
+            .into_validated()
+            .unwrap();
+        Document::try_from(&**checked).unwrap()
+    }
+
+    fn int(value: i32) -> RawBson {
+        RawBson::try_from(RawBsonRef::Int32(value)).unwrap()
+    }
+
+    #[test]
+    fn top_level_edits() {
+        let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 2});
+        docbuf.append("a.b", int(3)).unwrap();
+        docbuf.insert(0, "first", RawBsonRef::Null).unwrap();
+        docbuf.insert(4, "last", RawBsonRef::Null).unwrap();
+        assert_eq!(
+            docbuf.insert(6, "x", RawBsonRef::Null).unwrap_err().kind(),
+            &ErrorKind::OutOfRange
+        );
+        assert_eq!(
+            document(&docbuf),
+            doc! {"first": null, "a": 1, "b": 2, "a.b": 3, "last": null}
+        );
+
+        // Keys are never split on dots outside the path methods.
+        let old = docbuf.set("a.b", RawBsonRef::String("three")).unwrap();
+        assert_eq!(old, Some(int(3)));
+        assert_eq!(
+            docbuf.remove("first").unwrap(),
+            Some(RawBson::try_from(RawBsonRef::Null).unwrap())
+        );
+        assert_eq!(docbuf.remove("first").unwrap(), None);
+        assert_eq!(
+            document(&docbuf),
+            doc! {"a": 1, "b": 2, "a.b": "three", "last": null}
+        );
+
+        let err = docbuf.append("bad\0key", int(0)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InteriorNul);
+        assert_eq!(document(&docbuf).len(), 4);
+    }
+
+    #[test]
+    fn renames() {
+        let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 2, "c": 3, "d": 4});
+        assert!(docbuf.rename_key("b", "renamed").unwrap());
+        assert!(!docbuf.rename_key("b", "x").unwrap());
+        assert!(docbuf.rename_key("a", "a").unwrap());
+        // An existing element with the new name is removed, whether it
+        // comes before or after the renamed one.
+        assert!(docbuf.rename_key("a", "d").unwrap());
+        assert!(docbuf.rename_key("c", "d").unwrap());
+        assert_eq!(document(&docbuf), doc! {"renamed": 2, "d": 3});
+
+        let err = docbuf.rename_key("d", "\0").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InteriorNul);
+
+        let mut docbuf = DocBuf::from_document(&doc! {"a": {"b": [{"c": 1}]}});
+        assert!(docbuf.rename_path("a.b.0.c", "longer name").unwrap());
+        assert!(!docbuf.rename_path("a.x.c", "y").unwrap());
+        let err = docbuf.rename_path("a.b.0", "x").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        assert_eq!(document(&docbuf), doc! {"a": {"b": [{"longer name": 1}]}});
+    }
+
+    #[test]
+    fn nested_set() {
+        let mut docbuf = DocBuf::from_document(&doc! {
+            "a": {"b": "short", "list": [{"x": 1}, 2]},
+            "after": true,
+        });
+        let old = docbuf
+            .set_path("a.b", RawBsonRef::String("a much longer string"))
+            .unwrap();
+        assert_eq!(old.unwrap().as_element().as_str(), Ok("short"));
+        docbuf
+            .set_path("a.list.0.x", RawBsonRef::Boolean(false))
+            .unwrap();
+        docbuf
+            .set_path(&KeyPath::new("a.list.2.y"), int(3))
+            .unwrap();
+        assert!(docbuf.set_path("new.deep.key", int(4)).unwrap().is_none());
+        assert_eq!(
+            document(&docbuf),
+            doc! {
+                "a": {"b": "a much longer string", "list": [{"x": false}, 2, {"y": 3}]},
+                "after": true,
+                "new": {"deep": {"key": 4}},
+            }
+        );
+
+        let err = docbuf.set_path("a.list.4", int(0)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::OutOfRange);
+        let err = docbuf.set_path("a.list.x", int(0)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadArrayIndex);
+        let err = docbuf.set_path("after.x", int(0)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        assert_eq!(err.key_path(), "after");
+    }
+
+    #[test]
+    fn nested_remove() {
+        let list: Vec<Bson> = (0..12).map(Bson::Int32).collect();
+        let mut docbuf = DocBuf::from_document(&doc! {"a": {"list": list, "b": {"c": 1}}});
+        // Later elements are renumbered, shortening "10" and "11".
+        assert_eq!(docbuf.remove_path("a.list.3").unwrap(), Some(int(3)));
+        assert_eq!(docbuf.remove_path("a.b.c").unwrap(), Some(int(1)));
+        assert_eq!(docbuf.remove_path("a.b.c").unwrap(), None);
+        assert_eq!(docbuf.remove_path("a.list.11").unwrap(), None);
+        assert_eq!(docbuf.remove_path("a.list.0.x").unwrap(), None);
+
+        let expected: Vec<Bson> = (0..12).filter(|&n| n != 3).map(Bson::Int32).collect();
+        assert_eq!(document(&docbuf), doc! {"a": {"list": expected, "b": {}}});
+        assert_eq!(docbuf.get_path_i32("a.list.10").unwrap(), Some(11));
+    }
+}
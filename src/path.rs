@@ -0,0 +1,290 @@
+//! Dotted-path lookup across nested documents and arrays.
+//!
+//! A path such as `a.b.0.c` names the element reached by looking up key `a`,
+//! then key `b` in the document found there, then index `0` in the array
+//! found there, and so on.  Numeric segments index into arrays; in a
+//! document they are looked up as ordinary keys.  Lookups borrow from the
+//! document and never copy values.
+//!
+//! [`Doc::get_path`] and its typed variants accept either a `&str`, which is
+//! split on every call, or a [`KeyPath`], which is parsed once and can be
+//! reused across many documents.
+//!
+//! ```
+//! use bson::doc;
+//! use rawbson::{DocBuf, KeyPath};
+//!
+//! let docs = vec![
+//!     DocBuf::from_document(&doc! {"a": {"b": [{"c": "first"}]}}),
+//!     DocBuf::from_document(&doc! {"a": {"b": [{"c": "second"}]}}),
+//!     DocBuf::from_document(&doc! {"a": {"b": []}}),
+//! ];
+//!
+//! assert_eq!(docs[0].get_path_str("a.b.0.c")?, Some("first"));
+//!
+//! let path = KeyPath::new("a.b.0.c");
+//! let found: Vec<Option<&str>> = docs
+//!     .iter()
+//!     .map(|doc| doc.get_path_str(&path))
+//!     .collect::<Result<_, _>>()?;
+//! assert_eq!(found, vec![Some("first"), Some("second"), None]);
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::{fmt, iter::Peekable, slice, str::Split};
+
+use bson::spec::ElementType;
+
+use crate::{elem::Element, offset_in, Array, Doc, OptResult, RawResult};
+
+/// A dotted key path, parsed once for repeated lookups.
+///
+/// See the [module documentation](self) for the path syntax.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyPath {
+    pub(crate) segments: Vec<(Box<str>, Option<usize>)>,
+}
+
+impl KeyPath {
+    /// Parse a dotted path such as `"a.b.0.c"`.
+    pub fn new(path: &str) -> KeyPath {
+        KeyPath {
+            segments: path
+                .split('.')
+                .map(|key| (key.into(), parse_index(key)))
+                .collect(),
+        }
+    }
+
+    /// Iterate over the keys making up the path.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().map(|(key, _)| &**key)
+    }
+}
+
+impl From<&str> for KeyPath {
+    fn from(path: &str) -> KeyPath {
+        KeyPath::new(path)
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, key) in self.keys().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            f.write_str(key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Types that can be used as a path in [`Doc::get_path`].
+///
+/// This is implemented for `str`, `String` and [`KeyPath`], and cannot be
+/// implemented outside this crate.
+pub trait AsKeyPath: private::Sealed {
+    #[doc(hidden)]
+    fn segments(&self) -> Segments<'_>;
+}
+
+impl AsKeyPath for str {
+    fn segments(&self) -> Segments<'_> {
+        Segments::Unparsed(self.split('.'))
+    }
+}
+
+impl AsKeyPath for String {
+    fn segments(&self) -> Segments<'_> {
+        self.as_str().segments()
+    }
+}
+
+impl AsKeyPath for KeyPath {
+    fn segments(&self) -> Segments<'_> {
+        Segments::Parsed(self.segments.iter())
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl Sealed for super::KeyPath {}
+}
+
+#[doc(hidden)]
+pub enum Segments<'p> {
+    Unparsed(Split<'p, char>),
+    Parsed(slice::Iter<'p, (Box<str>, Option<usize>)>),
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct Segment<'p> {
+    pub(crate) key: &'p str,
+    pub(crate) index: Option<usize>,
+}
+
+impl<'p> Segment<'p> {
+    /// A single segment, for looking up a key that may contain dots.
+    pub(crate) fn new(key: &'p str) -> Segment<'p> {
+        Segment {
+            key,
+            index: parse_index(key),
+        }
+    }
+}
+
+impl<'p> Iterator for Segments<'p> {
+    type Item = Segment<'p>;
+
+    fn next(&mut self) -> Option<Segment<'p>> {
+        match self {
+            Segments::Unparsed(split) => split.next().map(Segment::new),
+            Segments::Parsed(iter) => iter
+                .next()
+                .map(|(key, index)| Segment { key, index: *index }),
+        }
+    }
+}
+
+/// Parse an array index segment.  Only canonical decimal numbers are
+/// accepted, so `"01"` and `"+1"` never match an array element.
+fn parse_index(key: &str) -> Option<usize> {
+    let canonical = !key.is_empty()
+        && key.bytes().all(|b| b.is_ascii_digit())
+        && (key.len() == 1 || !key.starts_with('0'));
+    if canonical {
+        key.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Look up `path` in `doc`, and convert the element found with `f`.
+///
+/// Returns `Ok(None)` if any segment is missing, or if the path runs through
+/// a value that is neither a document nor an array.  Errors report offsets
+/// into `doc` and the key path leading to the failure.
+pub(crate) fn get_path<'a, P, T>(
+    doc: &'a Doc,
+    path: &P,
+    f: impl FnOnce(Element<'a>) -> RawResult<T>,
+) -> OptResult<T>
+where
+    P: AsKeyPath + ?Sized,
+{
+    walk(doc, false, &mut path.segments().peekable(), f)
+}
+
+fn walk<'a, 'p, T>(
+    container: &'a Doc,
+    is_array: bool,
+    segments: &mut Peekable<Segments<'p>>,
+    f: impl FnOnce(Element<'a>) -> RawResult<T>,
+) -> OptResult<T> {
+    let segment = match segments.next() {
+        Some(segment) => segment,
+        None => return Ok(None),
+    };
+    let elem = if is_array {
+        match segment.index {
+            Some(index) => Array::from_doc(container).get(index)?,
+            None => None,
+        }
+    } else {
+        container.get(segment.key)?
+    };
+    let elem = match elem {
+        Some(elem) => elem,
+        None => return Ok(None),
+    };
+
+    let found = if segments.peek().is_none() {
+        f(elem).map(Some)
+    } else {
+        match elem.element_type() {
+            ElementType::EmbeddedDocument => elem
+                .as_document()
+                .and_then(|doc| walk(doc, false, segments, f)),
+            ElementType::Array => elem
+                .as_array()
+                .and_then(|arr| walk(&arr.doc, true, segments, f)),
+            _ => Ok(None),
+        }
+    };
+    found.map_err(|err| {
+        err.within(
+            segment.key,
+            offset_in(container.as_bytes(), elem.as_bytes()),
+            elem.element_type(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::KeyPath;
+    use crate::{Doc, DocBuf, ErrorKind};
+
+    #[test]
+    fn descends_documents_and_arrays() {
+        let docbuf = DocBuf::from_document(&doc! {
+            "a": {"b": [{"c": "x"}, {"c": "y"}], "1": "key"},
+            "n": 5,
+        });
+        assert_eq!(docbuf.get_path_str("a.b.1.c"), Ok(Some("y")));
+        assert_eq!(docbuf.get_path_i32("n"), Ok(Some(5)));
+        assert_eq!(docbuf.get_path_str(&String::from("a.b.0.c")), Ok(Some("x")));
+        // numeric segments are plain keys in documents
+        assert_eq!(docbuf.get_path_str("a.1"), Ok(Some("key")));
+        // only canonical indexes match array elements
+        assert_eq!(docbuf.get_path_str("a.b.01.c"), Ok(None));
+        assert_eq!(docbuf.get_path_str("a.b.+1.c"), Ok(None));
+        // missing keys, indexes past the end, and paths through scalars
+        assert!(docbuf.get_path("a.x.c").unwrap().is_none());
+        assert!(docbuf.get_path("a.b.2").unwrap().is_none());
+        assert!(docbuf.get_path("n.0").unwrap().is_none());
+        assert!(docbuf.get_path("").unwrap().is_none());
+
+        let array = docbuf.get_path_array("a.b").unwrap().unwrap();
+        assert_eq!(
+            array.get_document(0).unwrap().unwrap().get_str("c"),
+            Ok(Some("x"))
+        );
+    }
+
+    #[test]
+    fn key_paths_are_reusable() {
+        let path = KeyPath::new("a.b.0");
+        assert_eq!(path.keys().collect::<Vec<_>>(), vec!["a", "b", "0"]);
+        assert_eq!(path.to_string(), "a.b.0");
+        assert_eq!(KeyPath::from("a.b.0"), path);
+
+        for i in 0..3 {
+            let docbuf = DocBuf::from_document(&doc! {"a": {"b": [i]}});
+            assert_eq!(docbuf.get_path_i32(&path), Ok(Some(i)));
+        }
+    }
+
+    #[test]
+    fn errors_report_full_path() {
+        let docbuf = DocBuf::from_document(&doc! {"a": {"b": [true, "two"]}});
+        let err = docbuf.get_path_bool("a.b.1").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        assert_eq!(err.key_path(), "a.b.1");
+        assert_eq!(err.offset(), Some(25));
+
+        // {"a": {"b": <boolean 0x02>}}
+        let bytes = b"\x11\0\0\0\x03a\0\x09\0\0\0\x08b\0\x02\0\0";
+        let err = Doc::new(bytes).unwrap().get_path_bool("a.b").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadBool(2));
+        assert_eq!(err.key_path(), "a.b");
+        assert_eq!(err.offset(), Some(14));
+    }
+}
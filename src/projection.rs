@@ -0,0 +1,608 @@
+//! Projections, which copy selected fields of a document into a new one.
+//!
+//! A [`Projection`] is compiled once from a MongoDB projection document and
+//! applied to any number of documents with [`Doc::project`].  Projecting
+//! copies the bytes of each element that is kept, without decoding it, and
+//! only rebuilds the documents and arrays that are trimmed.
+//!
+//! The projection document follows MongoDB's rules:
+//!
+//! * `{"a": 1, "b.c": 1}` includes only the named fields, and `_id` unless
+//!   it is excluded with `"_id": 0`.
+//! * `{"a": 0, "b.c": 0}` includes every field except those named.
+//! * `{"list": {"$slice": n}}` keeps the first `n` elements of an array, or
+//!   the last `-n`, and `{"$slice": [skip, limit]}` skips some first.  A
+//!   `$slice` alone does not turn a projection into an inclusion.
+//! * `{"list": {"$elemMatch": filter}}` keeps only the first element that
+//!   matches, as the query operator of the same name does.
+//!
+//! Dotted paths, or the equivalent nested documents such as
+//! `{"a": {"b": 1}}`, descend into embedded documents and into every
+//! document in an array.
+//!
+//! ```
+//! # use std::convert::TryFrom;
+//! use bson::{doc, Document};
+//! use rawbson::{DocBuf, Projection};
+//!
+//! let docbuf = DocBuf::from_document(&doc! {
+//!     "_id": 7,
+//!     "name": "widget",
+//!     "tags": ["a", "b", "c"],
+//!     "parts": [{"sku": "x1", "qty": 2}, {"sku": "y2", "qty": 5}],
+//!     "history": ["a long list"],
+//! });
+//! let projection = Projection::new(&DocBuf::from_document(&doc! {
+//!     "name": 1,
+//!     "tags": {"$slice": -2},
+//!     "parts.sku": 1,
+//! }))?;
+//! let trimmed = docbuf.project(&projection)?;
+//! assert_eq!(
+//!     Document::try_from(&*trimmed)?,
+//!     doc! {"_id": 7, "name": "widget", "tags": ["b", "c"], "parts": [{"sku": "x1"}, {"sku": "y2"}]},
+//! );
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::io::Write;
+
+use bson::spec::ElementType;
+
+use crate::{
+    elem::Element,
+    offset_in,
+    query::{truthy, ElemMatch},
+    Array, Doc, DocBuf, ErrorKind, RawError, RawResult,
+};
+
+/// A compiled projection.
+///
+/// See the [module documentation](self) for the projection syntax.
+#[derive(Clone, Debug)]
+pub struct Projection {
+    // Whether fields not named in the projection are left out.
+    include: bool,
+    root: Node,
+}
+
+/// The fields named at one level of a projection.
+#[derive(Clone, Debug, Default)]
+struct Node {
+    fields: Vec<(Box<str>, Field)>,
+}
+
+#[derive(Clone, Debug)]
+enum Field {
+    /// Kept by an inclusion projection, or dropped by an exclusion.
+    Whole,
+    Nested(Node),
+    Slice(Slice),
+    ElemMatch(ElemMatch),
+}
+
+/// The operand of `$slice`.
+#[derive(Clone, Copy, Debug)]
+struct Slice {
+    // Counted from the end if negative.
+    skip: i64,
+    limit: Option<usize>,
+}
+
+/// One field of a projection document, before the projection is built.
+enum Spec {
+    Include,
+    Exclude,
+    Slice(Slice),
+    ElemMatch(ElemMatch),
+}
+
+impl Projection {
+    /// Compile a projection document.
+    ///
+    /// Fails with [`ErrorKind::BadProjection`] if the projection mixes
+    /// inclusion and exclusion, other than for `_id`, or names a field both
+    /// on its own and through a longer path.  Invalid `$slice` and
+    /// `$elemMatch` operands fail as they do in [`Matcher::new`], and any
+    /// other operator with [`ErrorKind::UnknownOperator`].
+    ///
+    /// [`Matcher::new`]: crate::Matcher::new
+    pub fn new(projection: &Doc) -> RawResult<Projection> {
+        let mut specs = Vec::new();
+        flatten(projection, &[], &mut specs)?;
+
+        // The first inclusion or exclusion other than of _id decides which
+        // kind of projection this is.
+        let mut include = None;
+        for (path, spec) in &specs {
+            let included = match spec {
+                _ if path[..] == ["_id"] => continue,
+                Spec::Include | Spec::ElemMatch(_) => true,
+                Spec::Exclude => false,
+                Spec::Slice(_) => continue,
+            };
+            match include {
+                None => include = Some(included),
+                Some(include) if include != included => {
+                    return Err(RawError::new(ErrorKind::BadProjection).in_key(&path.join(".")))
+                }
+                Some(_) => {}
+            }
+        }
+        let include = include.unwrap_or_else(|| {
+            specs
+                .iter()
+                .any(|(path, spec)| path[..] == ["_id"] && matches!(spec, Spec::Include))
+        });
+
+        let mut root = Node::default();
+        let mut include_id = include;
+        for (path, spec) in specs {
+            let field = match spec {
+                Spec::Include if include => Field::Whole,
+                Spec::Exclude if !include => Field::Whole,
+                // Only _id can be excluded from an inclusion projection, and
+                // including it in an exclusion projection changes nothing.
+                Spec::Include => continue,
+                Spec::Exclude => {
+                    include_id = false;
+                    continue;
+                }
+                Spec::Slice(slice) => Field::Slice(slice),
+                Spec::ElemMatch(elem_match) => Field::ElemMatch(elem_match),
+            };
+            root.insert(&path, field)
+                .map_err(|err| err.in_key(&path.join(".")))?;
+        }
+        if include_id && root.get("_id").is_none() {
+            root.fields.push(("_id".into(), Field::Whole));
+        }
+        Ok(Projection { include, root })
+    }
+}
+
+/// Collect the fields of a projection document, with the full path of
+/// each, flattening nested projections such as `{"a": {"b": 1}}`.
+fn flatten<'a>(
+    projection: &'a Doc,
+    prefix: &[&'a str],
+    specs: &mut Vec<(Vec<&'a str>, Spec)>,
+) -> RawResult<()> {
+    for result in projection {
+        let (key, elem) = result?;
+        let mut path = prefix.to_vec();
+        path.extend(key.split('.'));
+        let spec = match elem.element_type() {
+            ElementType::EmbeddedDocument => {
+                let doc = elem.as_document()?;
+                match doc.into_iter().next().transpose()? {
+                    Some((operator, _)) if operator.starts_with('$') => compile_operator(doc),
+                    _ => {
+                        flatten(doc, &path, specs).map_err(|err| {
+                            err.within(
+                                key,
+                                offset_in(projection.as_bytes(), elem.as_bytes()),
+                                elem.element_type(),
+                            )
+                        })?;
+                        continue;
+                    }
+                }
+            }
+            ElementType::Boolean
+            | ElementType::Int32
+            | ElementType::Int64
+            | ElementType::Double => {
+                truthy(elem).map(|truthy| if truthy { Spec::Include } else { Spec::Exclude })
+            }
+            _ => Err(RawError::new(ErrorKind::BadProjection)),
+        };
+        let spec = spec.map_err(|err| {
+            err.within(
+                key,
+                offset_in(projection.as_bytes(), elem.as_bytes()),
+                elem.element_type(),
+            )
+        })?;
+        specs.push((path, spec));
+    }
+    Ok(())
+}
+
+/// Compile a projection operator document, such as `{"$slice": 5}`.
+fn compile_operator(doc: &Doc) -> RawResult<Spec> {
+    let mut specs = Vec::new();
+    for result in doc {
+        let (operator, operand) = result?;
+        let spec = match operator {
+            "$slice" => Slice::new(operand).map(Spec::Slice),
+            "$elemMatch" => ElemMatch::new(operand).map(Spec::ElemMatch),
+            _ => Err(RawError::new(ErrorKind::UnknownOperator(operator.into()))),
+        };
+        let spec = spec.map_err(|err| {
+            err.within(
+                operator,
+                offset_in(doc.as_bytes(), operand.as_bytes()),
+                operand.element_type(),
+            )
+        })?;
+        specs.push(spec);
+    }
+    match specs.len() {
+        1 => Ok(specs.pop().unwrap()),
+        _ => Err(RawError::new(ErrorKind::BadProjection)),
+    }
+}
+
+impl Slice {
+    fn new(operand: Element<'_>) -> RawResult<Slice> {
+        let bad_operand = || RawError::new(ErrorKind::BadOperand("$slice".into()));
+        let integer = |elem: Element<'_>| match elem.element_type() {
+            ElementType::Int32 => elem.as_i32().map(i64::from),
+            ElementType::Int64 => elem.as_i64(),
+            ElementType::Double if elem.as_f64()?.fract() == 0.0 => Ok(elem.as_f64()? as i64),
+            _ => Err(bad_operand()),
+        };
+        if operand.element_type() != ElementType::Array {
+            let count = integer(operand)?;
+            return Ok(if count >= 0 {
+                Slice {
+                    skip: 0,
+                    limit: Some(usize::try_from(count).unwrap_or(usize::MAX)),
+                }
+            } else {
+                Slice {
+                    skip: count,
+                    limit: None,
+                }
+            });
+        }
+        let array = operand.as_array()?;
+        let mut values = array.into_iter();
+        match (values.next(), values.next(), values.next()) {
+            (Some(skip), Some(limit), None) => {
+                let skip = integer(skip?)?;
+                match usize::try_from(integer(limit?)?) {
+                    Ok(limit) if limit > 0 => Ok(Slice {
+                        skip,
+                        limit: Some(limit),
+                    }),
+                    _ => Err(bad_operand()),
+                }
+            }
+            _ => Err(bad_operand()),
+        }
+    }
+
+    /// The range of indexes kept from an array of `len` elements.
+    fn range(self, len: usize) -> std::ops::Range<usize> {
+        let skip = usize::try_from(self.skip.unsigned_abs()).unwrap_or(usize::MAX);
+        let start = if self.skip >= 0 {
+            skip.min(len)
+        } else {
+            len.saturating_sub(skip)
+        };
+        let end = match self.limit {
+            Some(limit) => start.saturating_add(limit).min(len),
+            None => len,
+        };
+        start..end
+    }
+}
+
+impl Node {
+    fn get(&self, key: &str) -> Option<&Field> {
+        self.fields
+            .iter()
+            .find(|(name, _)| &**name == key)
+            .map(|(_, field)| field)
+    }
+
+    fn insert(&mut self, path: &[&str], field: Field) -> RawResult<()> {
+        let (key, rest) = path.split_first().expect("paths are never empty");
+        let position = self.fields.iter().position(|(name, _)| &**name == *key);
+        if rest.is_empty() {
+            if position.is_some() {
+                return Err(RawError::new(ErrorKind::BadProjection));
+            }
+            self.fields.push(((*key).into(), field));
+            return Ok(());
+        }
+        let position = match position {
+            Some(position) => position,
+            None => {
+                self.fields
+                    .push(((*key).into(), Field::Nested(Node::default())));
+                self.fields.len() - 1
+            }
+        };
+        match &mut self.fields[position].1 {
+            Field::Nested(node) => node.insert(rest, field),
+            _ => Err(RawError::new(ErrorKind::BadProjection)),
+        }
+    }
+
+    fn write_document(&self, doc: &Doc, include: bool, out: &mut Vec<u8>) -> RawResult<()> {
+        let start = begin(out);
+        for result in doc {
+            let (key, elem) = result?;
+            self.write_field(key, elem, include, out).map_err(|err| {
+                err.within(
+                    key,
+                    offset_in(doc.as_bytes(), elem.as_bytes()),
+                    elem.element_type(),
+                )
+            })?;
+        }
+        end(out, start)
+    }
+
+    fn write_field(
+        &self,
+        key: &str,
+        elem: Element<'_>,
+        include: bool,
+        out: &mut Vec<u8>,
+    ) -> RawResult<()> {
+        let field = match self.get(key) {
+            Some(field) => field,
+            None if include => return Ok(()),
+            None => return copy_element(out, key, elem),
+        };
+        match field {
+            Field::Whole if include => copy_element(out, key, elem),
+            Field::Whole => Ok(()),
+            Field::Nested(node) => match elem.element_type() {
+                ElementType::EmbeddedDocument => {
+                    write_header(out, ElementType::EmbeddedDocument, key);
+                    node.write_document(elem.as_document()?, include, out)
+                }
+                ElementType::Array => {
+                    write_header(out, ElementType::Array, key);
+                    node.write_array(elem.as_array()?, include, out)
+                }
+                _ if include => Ok(()),
+                _ => copy_element(out, key, elem),
+            },
+            Field::Slice(slice) => match elem.element_type() {
+                ElementType::Array => {
+                    let array = elem.as_array()?;
+                    let mut len = 0;
+                    for result in array {
+                        result?;
+                        len += 1;
+                    }
+                    let range = slice.range(len);
+                    write_header(out, ElementType::Array, key);
+                    let start = begin(out);
+                    for (index, result) in array.into_iter().enumerate() {
+                        if range.contains(&index) {
+                            copy_element(out, index - range.start, result?)?;
+                        }
+                    }
+                    end(out, start)
+                }
+                _ => copy_element(out, key, elem),
+            },
+            Field::ElemMatch(elem_match) => {
+                if elem.element_type() != ElementType::Array {
+                    return Ok(());
+                }
+                for result in elem.as_array()? {
+                    let value = result?;
+                    if elem_match.matches(value)? {
+                        write_header(out, ElementType::Array, key);
+                        let start = begin(out);
+                        copy_element(out, 0, value)?;
+                        return end(out, start);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply this node to every document in an array, and to nested
+    /// arrays.  An inclusion projection drops any other elements.
+    fn write_array(&self, array: &Array, include: bool, out: &mut Vec<u8>) -> RawResult<()> {
+        let start = begin(out);
+        let mut index = 0;
+        for result in array {
+            let elem = result?;
+            match elem.element_type() {
+                ElementType::EmbeddedDocument => {
+                    write_header(out, ElementType::EmbeddedDocument, index);
+                    self.write_document(elem.as_document()?, include, out)?;
+                }
+                ElementType::Array => {
+                    write_header(out, ElementType::Array, index);
+                    self.write_array(elem.as_array()?, include, out)?;
+                }
+                _ if include => continue,
+                _ => copy_element(out, index, elem)?,
+            }
+            index += 1;
+        }
+        end(out, start)
+    }
+}
+
+/// Write the type and key of an element.  Array indexes are formatted in
+/// place.
+fn write_header(out: &mut Vec<u8>, element_type: ElementType, key: impl std::fmt::Display) {
+    out.push(element_type as u8);
+    // Writing to a Vec cannot fail.
+    write!(out, "{}\0", key).unwrap();
+}
+
+fn copy_element(
+    out: &mut Vec<u8>,
+    key: impl std::fmt::Display,
+    elem: Element<'_>,
+) -> RawResult<()> {
+    write_header(out, elem.element_type(), key);
+    out.extend_from_slice(elem.as_bytes());
+    Ok(())
+}
+
+/// Reserve space for the length of a document, returning its start.
+fn begin(out: &mut Vec<u8>) -> usize {
+    out.extend_from_slice(&[0; 4]);
+    out.len() - 4
+}
+
+/// Close the document started at `start`, filling in its length.
+fn end(out: &mut Vec<u8>, start: usize) -> RawResult<()> {
+    out.push(0);
+    let length = i32::try_from(out.len() - start)
+        .map_err(|_| RawError::new(ErrorKind::BadLength).at(start))?;
+    out[start..start + 4].copy_from_slice(&length.to_le_bytes());
+    Ok(())
+}
+
+impl Doc {
+    /// Copy the fields selected by `projection` into a new document.
+    ///
+    /// Fields keep their order from this document.  Errors report the key
+    /// path of any malformed element that had to be read.
+    ///
+    /// See the [`projection`](crate::projection) module for an example.
+    pub fn project(&self, projection: &Projection) -> RawResult<DocBuf> {
+        let mut out = Vec::new();
+        projection
+            .root
+            .write_document(self, projection.include, &mut out)?;
+        DocBuf::new(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use bson::{doc, Document};
+
+    use super::Projection;
+    use crate::{DocBuf, ErrorKind, RawError};
+
+    fn compile(projection: Document) -> Result<Projection, RawError> {
+        Projection::new(&DocBuf::from_document(&projection))
+    }
+
+    fn project(projection: Document, doc: Document) -> Document {
+        let projected = DocBuf::from_document(&doc)
+            .project(&compile(projection).unwrap())
+            .unwrap();
+        Document::try_from(&*projected).unwrap()
+    }
+
+    #[test]
+    fn inclusion_and_exclusion() {
+        let doc = doc! {"_id": 1, "a": 1, "b": {"c": 2, "d": 3}, "e": "x"};
+        assert_eq!(
+            project(doc! {"e": 1, "a": true}, doc.clone()),
+            doc! {"_id": 1, "a": 1, "e": "x"}
+        );
+        assert_eq!(
+            project(doc! {"b.c": 1, "_id": 0}, doc.clone()),
+            doc! {"b": {"c": 2}}
+        );
+        assert_eq!(
+            project(doc! {"b": {"d": 1}}, doc.clone()),
+            doc! {"_id": 1, "b": {"d": 3}}
+        );
+        assert_eq!(
+            project(doc! {"b.c": 0, "e": 0, "_id": 1}, doc.clone()),
+            doc! {"_id": 1, "a": 1, "b": {"d": 3}}
+        );
+        assert_eq!(
+            project(doc! {"_id": 0}, doc.clone()),
+            doc! {"a": 1, "b": {"c": 2, "d": 3}, "e": "x"}
+        );
+        assert_eq!(project(doc! {"_id": 1}, doc.clone()), doc! {"_id": 1});
+        assert_eq!(project(doc! {}, doc.clone()), doc);
+        // A path through a scalar selects nothing.
+        assert_eq!(project(doc! {"a.z": 1, "_id": 0}, doc.clone()), doc! {});
+        assert_eq!(project(doc! {"a.z": 0}, doc.clone()), doc);
+    }
+
+    #[test]
+    fn arrays() {
+        let doc = doc! {"a": [{"b": 1, "c": 2}, 5, [{"b": 3, "c": 4}], {"c": 6}]};
+        assert_eq!(
+            project(doc! {"a.b": 1, "_id": 0}, doc.clone()),
+            doc! {"a": [{"b": 1}, [{"b": 3}], {}]}
+        );
+        assert_eq!(
+            project(doc! {"a.b": 0}, doc.clone()),
+            doc! {"a": [{"c": 2}, 5, [{"c": 4}], {"c": 6}]}
+        );
+
+        let doc = doc! {"_id": 1, "n": [1, 2, 3, 4, 5], "s": "x"};
+        let slice = |operand| project(doc! {"n": {"$slice": operand}}, doc.clone());
+        assert_eq!(
+            slice(bson::bson!(2)),
+            doc! {"_id": 1, "n": [1, 2], "s": "x"}
+        );
+        assert_eq!(
+            slice(bson::bson!(-2)),
+            doc! {"_id": 1, "n": [4, 5], "s": "x"}
+        );
+        assert_eq!(
+            slice(bson::bson!([1, 2])),
+            doc! {"_id": 1, "n": [2, 3], "s": "x"}
+        );
+        assert_eq!(
+            slice(bson::bson!([-2, 5])),
+            doc! {"_id": 1, "n": [4, 5], "s": "x"}
+        );
+        assert_eq!(
+            slice(bson::bson!([9, 1])),
+            doc! {"_id": 1, "n": [], "s": "x"}
+        );
+        assert_eq!(
+            project(doc! {"s": {"$slice": 1}, "n": 1}, doc.clone()),
+            doc! {"_id": 1, "n": [1, 2, 3, 4, 5], "s": "x"}
+        );
+
+        let doc = doc! {"_id": 1, "a": [{"k": 1}, {"k": 2, "v": 1}, {"k": 2}], "b": 3};
+        assert_eq!(
+            project(doc! {"a": {"$elemMatch": {"k": 2}}}, doc.clone()),
+            doc! {"_id": 1, "a": [{"k": 2, "v": 1}]}
+        );
+        assert_eq!(
+            project(doc! {"a": {"$elemMatch": {"k": 3}}, "b": 1}, doc),
+            doc! {"_id": 1, "b": 3}
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let kind = |projection| compile(projection).unwrap_err().kind().clone();
+        assert_eq!(kind(doc! {"a": 1, "b": 0}), ErrorKind::BadProjection);
+        assert_eq!(
+            kind(doc! {"a": 0, "b": {"$elemMatch": {}}}),
+            ErrorKind::BadProjection
+        );
+        assert_eq!(kind(doc! {"a": "yes"}), ErrorKind::BadProjection);
+        assert_eq!(
+            kind(doc! {"a": {"$slice": 0, "$elemMatch": {}}}),
+            ErrorKind::BadProjection
+        );
+        assert_eq!(
+            kind(doc! {"a": {"$slice": [1, 0]}}),
+            ErrorKind::BadOperand("$slice".into())
+        );
+        assert_eq!(
+            kind(doc! {"a": {"$meta": "textScore"}}),
+            ErrorKind::UnknownOperator("$meta".into())
+        );
+
+        let err = compile(doc! {"a.b": 1, "a": {"b": {"c": 1}}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadProjection);
+        assert_eq!(err.key_path(), "a.b.c");
+        let err = compile(doc! {"x": {"y": {"$slice": "z"}}}).unwrap_err();
+        assert_eq!(err.key_path(), "x.y.$slice");
+    }
+}
@@ -1,7 +1,11 @@
+use std::convert::TryFrom;
+
 use bson::{spec::BinarySubtype, Binary, Bson, Document, JavaScriptCodeWithScope, Regex};
 
 use proptest::prelude::*;
 
+use crate::elem::{RawBson, RawBsonRef, RawDecimal128};
+
 fn arbitrary_binary_subtype() -> impl Strategy<Value = BinarySubtype> {
     prop_oneof![
         Just(BinarySubtype::Generic),
@@ -57,3 +61,29 @@ pub(crate) fn arbitrary_bson() -> impl Strategy<Value = Bson> {
         ]
     })
 }
+
+/// Numbers of every numeric type, most of them within one of 2^53, where
+/// doubles stop representing every integer.
+pub(crate) fn arbitrary_number() -> impl Strategy<Value = RawBson> {
+    let raw = |value: RawBsonRef<'_>| RawBson::try_from(value).unwrap();
+    let decimal = move |mantissa: i128, scale: i32| {
+        raw(RawBsonRef::Decimal128(
+            RawDecimal128::from_i128_with_scale(mantissa, scale).unwrap(),
+        ))
+    };
+    let near = (0..3, -1..=1i64).prop_map(move |(kind, offset)| {
+        let value = (1 << 53) + offset;
+        match kind {
+            0 => raw(RawBsonRef::Int64(value)),
+            1 => raw(RawBsonRef::Double(value as f64)),
+            _ => decimal(value.into(), 0),
+        }
+    });
+    prop_oneof![
+        1 => any::<i32>().prop_map(move |value| raw(RawBsonRef::Int32(value))),
+        1 => any::<i64>().prop_map(move |value| raw(RawBsonRef::Int64(value))),
+        1 => any::<f64>().prop_map(move |value| raw(RawBsonRef::Double(value))),
+        1 => (any::<i64>(), -40..40).prop_map(move |(mantissa, scale)| decimal(mantissa.into(), scale)),
+        8 => near,
+    ]
+}
@@ -0,0 +1,910 @@
+//! MongoDB query filters, evaluated directly against raw documents.
+//!
+//! A [`Matcher`] is compiled once from a filter document, such as
+//! `{"age": {"$gte": 21}, "tags": {"$in": ["a", "b"]}}`, and can then test
+//! any number of documents without converting them to [`bson::Document`]s
+//! or allocating.
+//!
+//! The supported operators are:
+//!
+//! * comparison: `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in` and `$nin`
+//! * logical: `$and`, `$or`, `$nor` and `$not`
+//! * element: `$exists` and `$type`
+//! * evaluation: `$regex`, with `$options`
+//! * array: `$elemMatch`, `$size` and `$all`
+//!
+//! Values compare as they do in MongoDB: numbers by value across all four
+//! numeric types, and other values only with values of the same type.  As
+//! in MongoDB, a dotted path reaching an array is followed into every
+//! document in it, and a condition on a path that ends at an array is met
+//! if the array itself or any of its elements meets it.  Numeric path
+//! segments index into arrays.
+//!
+//! Regular expressions use the syntax of the [`regex`] crate, which lacks
+//! some PCRE features such as backreferences and lookaround.
+//!
+//! ```
+//! use bson::doc;
+//! use rawbson::{DocBuf, Matcher};
+//!
+//! let filter = DocBuf::from_document(&doc! {
+//!     "age": {"$gte": 21},
+//!     "pets.kind": "cat",
+//! });
+//! let matcher = Matcher::new(&filter)?;
+//!
+//! let alice = DocBuf::from_document(&doc! {
+//!     "age": 30,
+//!     "pets": [{"kind": "dog"}, {"kind": "cat"}],
+//! });
+//! let bob = DocBuf::from_document(&doc! {"age": 19.5, "pets": [{"kind": "cat"}]});
+//! assert!(matcher.matches(&alice)?);
+//! assert!(!matcher.matches(&bob)?);
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use bson::spec::ElementType;
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    cmp::{rank, try_compare},
+    elem::{Element, RawBson},
+    offset_in, Doc, ErrorKind, KeyPath, RawError, RawResult,
+};
+
+/// A compiled query filter.
+///
+/// See the [module documentation](self) for the supported operators.
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    // The top level of a filter is an implicit $and.
+    clauses: Vec<Expr>,
+}
+
+impl Matcher {
+    /// Compile a filter document.
+    ///
+    /// Fails with [`ErrorKind::UnknownOperator`] for an unsupported
+    /// operator, [`ErrorKind::BadOperand`] for an operator given the wrong
+    /// type of value, and [`ErrorKind::BadRegex`] for an invalid regular
+    /// expression.  The key path of the error leads to the failing operator
+    /// within the filter.
+    ///
+    /// ```
+    /// # use rawbson::{DocBuf, ErrorKind, Matcher};
+    /// use bson::doc;
+    /// let filter = DocBuf::from_document(&doc! {"$or": [{"a": {"$size": "two"}}]});
+    /// let err = Matcher::new(&filter).unwrap_err();
+    /// assert_eq!(err.kind(), &ErrorKind::BadOperand("$size".into()));
+    /// assert_eq!(err.key_path(), "$or.0.a.$size");
+    /// ```
+    pub fn new(filter: &Doc) -> RawResult<Matcher> {
+        Ok(Matcher {
+            clauses: compile_clauses(filter)?,
+        })
+    }
+
+    /// Test whether `doc` matches the filter.
+    ///
+    /// Only the parts of `doc` needed to decide are read, so a malformed
+    /// document may match without error.
+    pub fn matches(&self, doc: &Doc) -> RawResult<bool> {
+        for clause in &self.clauses {
+            if !clause.matches(doc)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// A clause of a filter document.
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Vec<Matcher>),
+    Or(Vec<Matcher>),
+    Nor(Vec<Matcher>),
+    Field(KeyPath, Cond),
+}
+
+impl Expr {
+    fn matches(&self, doc: &Doc) -> RawResult<bool> {
+        match self {
+            Expr::And(matchers) => {
+                for matcher in matchers {
+                    if !matcher.matches(doc)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Expr::Or(matchers) => any_matches(matchers, doc),
+            Expr::Nor(matchers) => Ok(!any_matches(matchers, doc)?),
+            Expr::Field(path, cond) => cond.matches(Source::Path(doc, path)),
+        }
+    }
+}
+
+fn any_matches(matchers: &[Matcher], doc: &Doc) -> RawResult<bool> {
+    for matcher in matchers {
+        if matcher.matches(doc)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// A condition on the values found at a path.
+#[derive(Clone, Debug)]
+pub(crate) enum Cond {
+    And(Vec<Cond>),
+    Not(Box<Cond>),
+    Eq(RawBson),
+    /// Met by values that compare to the operand with the given ordering,
+    /// or also equal to it if the flag is set.
+    Compare(Ordering, bool, RawBson),
+    /// Met if any of the conditions, each `Eq` or `Regex`, is met.
+    In(Vec<Cond>),
+    Exists,
+    Type(Vec<ElementType>),
+    Regex(Regex),
+    ElemMatch(Box<ElemMatch>),
+    Size(usize),
+}
+
+/// The operand of `$elemMatch`, which is also used in projections.
+#[derive(Clone, Debug)]
+pub(crate) enum ElemMatch {
+    /// Conditions on the elements themselves, as in
+    /// `{"$elemMatch": {"$gt": 1}}`.
+    Value(Cond),
+    /// A filter on elements that are documents.
+    Document(Matcher),
+}
+
+/// Where a condition finds its values.
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    Path(&'a Doc, &'a KeyPath),
+    Value(Element<'a>),
+}
+
+type Visitor<'f> = dyn FnMut(Option<Element<'_>>, bool) -> RawResult<bool> + 'f;
+
+impl Cond {
+    fn matches(&self, source: Source<'_>) -> RawResult<bool> {
+        match self {
+            Cond::And(conds) => {
+                for cond in conds {
+                    if !cond.matches(source)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Cond::Not(cond) => Ok(!cond.matches(source)?),
+            leaf => {
+                let mut test = |value: Option<Element<'_>>, expanded| leaf.test(value, expanded);
+                match source {
+                    Source::Path(doc, path) => visit_doc(doc, &path.segments, &mut test),
+                    Source::Value(value) => visit_leaf(value, &mut test),
+                }
+            }
+        }
+    }
+
+    /// Test one value found at a path, or `None` where the path is missing.
+    /// `expanded` is set for the elements of an array found at the end of
+    /// the path.
+    fn test(&self, value: Option<Element<'_>>, expanded: bool) -> RawResult<bool> {
+        Ok(match self {
+            Cond::Eq(operand) => equals(value, operand.as_element())?,
+            Cond::Compare(direction, or_equal, operand) => {
+                let operand = operand.as_element();
+                let ordering = match value {
+                    // As in MongoDB, {"$gte": null} matches missing fields.
+                    None if operand.element_type() == ElementType::Null => Ordering::Equal,
+                    None => return Ok(false),
+                    Some(value) if comparable(value, operand) => try_compare(value, operand)?,
+                    Some(_) => return Ok(false),
+                };
+                ordering == *direction || (*or_equal && ordering == Ordering::Equal)
+            }
+            Cond::In(conds) => {
+                for cond in conds {
+                    if cond.test(value, expanded)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Cond::Exists => value.is_some(),
+            Cond::Type(types) => value.is_some_and(|value| types.contains(&value.element_type())),
+            Cond::Regex(regex) => match value {
+                Some(value) if value.element_type() == ElementType::String => {
+                    regex.is_match(value.as_str()?)
+                }
+                Some(value) if value.element_type() == ElementType::Symbol => {
+                    regex.is_match(value.as_symbol()?)
+                }
+                _ => false,
+            },
+            Cond::ElemMatch(elem_match) => match value {
+                Some(value) if !expanded && value.element_type() == ElementType::Array => {
+                    for elem in value.as_array()? {
+                        if elem_match.matches(elem?)? {
+                            return Ok(true);
+                        }
+                    }
+                    false
+                }
+                _ => false,
+            },
+            Cond::Size(size) => match value {
+                Some(value) if !expanded && value.element_type() == ElementType::Array => {
+                    let mut len = 0;
+                    for elem in value.as_array()? {
+                        elem?;
+                        len += 1;
+                    }
+                    len == *size
+                }
+                _ => false,
+            },
+            Cond::And(_) | Cond::Not(_) => unreachable!("handled by Cond::matches"),
+        })
+    }
+}
+
+impl ElemMatch {
+    pub(crate) fn new(operand: Element<'_>) -> RawResult<ElemMatch> {
+        if operand.element_type() != ElementType::EmbeddedDocument {
+            return Err(bad_operand("$elemMatch"));
+        }
+        let doc = operand.as_document()?;
+        let first = doc.into_iter().next().transpose()?.map(|(key, _)| key);
+        match first {
+            Some(key) if key.starts_with('$') && !matches!(key, "$and" | "$or" | "$nor") => {
+                Ok(ElemMatch::Value(compile_operators(doc)?))
+            }
+            _ => Ok(ElemMatch::Document(Matcher::new(doc)?)),
+        }
+    }
+
+    pub(crate) fn matches(&self, elem: Element<'_>) -> RawResult<bool> {
+        match self {
+            ElemMatch::Value(cond) => cond.matches(Source::Value(elem)),
+            ElemMatch::Document(matcher) => match elem.element_type() {
+                ElementType::EmbeddedDocument => matcher.matches(elem.as_document()?),
+                _ => Ok(false),
+            },
+        }
+    }
+}
+
+/// Values compare only with values of the same type, except that every
+/// value compares with MinKey and MaxKey.
+fn comparable(value: Element<'_>, operand: Element<'_>) -> bool {
+    rank(value.element_type()) == rank(operand.element_type())
+        || matches!(
+            operand.element_type(),
+            ElementType::MinKey | ElementType::MaxKey
+        )
+}
+
+/// Equality as MongoDB defines it, where null also matches a missing value.
+fn equals(value: Option<Element<'_>>, operand: Element<'_>) -> RawResult<bool> {
+    match value {
+        Some(value) => Ok(try_compare(value, operand)? == Ordering::Equal),
+        None => Ok(operand.element_type() == ElementType::Null),
+    }
+}
+
+/// Look up `segments` in `doc`, calling `f` on each value found until it
+/// returns true.
+fn visit_doc(
+    doc: &Doc,
+    segments: &[(Box<str>, Option<usize>)],
+    f: &mut Visitor<'_>,
+) -> RawResult<bool> {
+    let ((key, _), rest) = segments.split_first().expect("paths are never empty");
+    visit(doc.get(key)?, rest, f)
+}
+
+fn visit(
+    value: Option<Element<'_>>,
+    segments: &[(Box<str>, Option<usize>)],
+    f: &mut Visitor<'_>,
+) -> RawResult<bool> {
+    let value = match value {
+        Some(value) => value,
+        None => return f(None, false),
+    };
+    let (index, rest) = match segments.split_first() {
+        Some(((_, index), rest)) => (*index, rest),
+        None => return visit_leaf(value, f),
+    };
+    match value.element_type() {
+        ElementType::EmbeddedDocument => visit_doc(value.as_document()?, segments, f),
+        ElementType::Array => {
+            let array = value.as_array()?;
+            if let Some(index) = index {
+                return visit(array.get(index)?, rest, f);
+            }
+            // Follow the path into every document in the array.  With none
+            // to follow, as in an array of scalars, the path is missing.
+            let mut visited = false;
+            for elem in array {
+                let elem = elem?;
+                if elem.element_type() == ElementType::EmbeddedDocument {
+                    visited = true;
+                    if visit_doc(elem.as_document()?, segments, f)? {
+                        return Ok(true);
+                    }
+                }
+            }
+            if visited {
+                Ok(false)
+            } else {
+                f(None, false)
+            }
+        }
+        // A path through any other value is missing.
+        _ => f(None, false),
+    }
+}
+
+/// Call `f` on a value at the end of a path, and then on each of its
+/// elements if it is an array.
+fn visit_leaf(value: Element<'_>, f: &mut Visitor<'_>) -> RawResult<bool> {
+    if f(Some(value), false)? {
+        return Ok(true);
+    }
+    if value.element_type() == ElementType::Array {
+        for elem in value.as_array()? {
+            if f(Some(elem?), true)? {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Add the key and offset of `elem` within `doc` to an error.
+fn context<'a>(
+    doc: &'a Doc,
+    key: &'a str,
+    elem: Element<'a>,
+) -> impl FnOnce(RawError) -> RawError + 'a {
+    move |err| {
+        err.within(
+            key,
+            offset_in(doc.as_bytes(), elem.as_bytes()),
+            elem.element_type(),
+        )
+    }
+}
+
+fn bad_operand(operator: &str) -> RawError {
+    RawError::new(ErrorKind::BadOperand(operator.into()))
+}
+
+fn compile_clauses(filter: &Doc) -> RawResult<Vec<Expr>> {
+    let mut clauses = Vec::new();
+    for result in filter {
+        let (key, elem) = result?;
+        let clause = match key {
+            "$and" => compile_filters(key, elem).map(Expr::And),
+            "$or" => compile_filters(key, elem).map(Expr::Or),
+            "$nor" => compile_filters(key, elem).map(Expr::Nor),
+            "$comment" => continue,
+            _ if key.starts_with('$') => Err(RawError::new(ErrorKind::UnknownOperator(key.into()))),
+            _ => compile_condition(elem).map(|cond| Expr::Field(KeyPath::new(key), cond)),
+        };
+        clauses.push(clause.map_err(context(filter, key, elem))?);
+    }
+    Ok(clauses)
+}
+
+/// Compile the non-empty array of filters given to `$and`, `$or` or `$nor`.
+fn compile_filters(operator: &str, elem: Element<'_>) -> RawResult<Vec<Matcher>> {
+    if elem.element_type() != ElementType::Array {
+        return Err(bad_operand(operator));
+    }
+    let array = elem.as_array()?;
+    let mut matchers = Vec::new();
+    for result in &array.doc {
+        let (key, filter) = result?;
+        let matcher = match filter.element_type() {
+            ElementType::EmbeddedDocument => Matcher::new(filter.as_document()?),
+            _ => Err(bad_operand(operator)),
+        };
+        matchers.push(matcher.map_err(context(&array.doc, key, filter))?);
+    }
+    if matchers.is_empty() {
+        return Err(bad_operand(operator));
+    }
+    Ok(matchers)
+}
+
+/// Compile the value given for a field in a filter.
+fn compile_condition(elem: Element<'_>) -> RawResult<Cond> {
+    match elem.element_type() {
+        ElementType::EmbeddedDocument => {
+            let doc = elem.as_document()?;
+            if is_operator_doc(doc)? {
+                compile_operators(doc)
+            } else {
+                Ok(Cond::Eq(elem.to_owned()))
+            }
+        }
+        ElementType::RegularExpression => {
+            let regex = elem.as_regex()?;
+            compile_regex(regex.pattern(), regex.options()).map(Cond::Regex)
+        }
+        _ => Ok(Cond::Eq(elem.to_owned())),
+    }
+}
+
+/// A document whose first key starts with `$` holds operators; any other
+/// document is a value to compare with.
+fn is_operator_doc(doc: &Doc) -> RawResult<bool> {
+    match doc.into_iter().next().transpose()? {
+        Some((key, _)) => Ok(key.starts_with('$')),
+        None => Ok(false),
+    }
+}
+
+fn compile_operators(doc: &Doc) -> RawResult<Cond> {
+    let mut conds = Vec::new();
+    let mut regex = None;
+    let mut options = None;
+    for result in doc {
+        let (operator, operand) = result?;
+        let cond = match operator {
+            "$regex" => {
+                regex = Some((operand, context(doc, operator, operand)));
+                continue;
+            }
+            "$options" => {
+                options = Some(
+                    string_operand(operator, operand).map_err(context(doc, operator, operand))?,
+                );
+                continue;
+            }
+            _ => compile_operator(operator, operand),
+        };
+        conds.push(cond.map_err(context(doc, operator, operand))?);
+    }
+    match regex {
+        Some((operand, context)) => {
+            let cond = match operand.element_type() {
+                ElementType::RegularExpression => {
+                    let regex = operand.as_regex()?;
+                    let options = format!("{}{}", regex.options(), options.unwrap_or(""));
+                    compile_regex(regex.pattern(), &options)
+                }
+                _ => string_operand("$regex", operand)
+                    .and_then(|pattern| compile_regex(pattern, options.unwrap_or(""))),
+            };
+            conds.push(Cond::Regex(cond.map_err(context)?));
+        }
+        None if options.is_some() => return Err(bad_operand("$options")),
+        None => {}
+    }
+    if conds.len() == 1 {
+        Ok(conds.pop().unwrap())
+    } else {
+        Ok(Cond::And(conds))
+    }
+}
+
+fn compile_operator(operator: &str, operand: Element<'_>) -> RawResult<Cond> {
+    let compare = |direction, or_equal| Ok(Cond::Compare(direction, or_equal, operand.to_owned()));
+    match operator {
+        "$eq" => Ok(Cond::Eq(operand.to_owned())),
+        "$ne" => Ok(Cond::Not(Box::new(Cond::Eq(operand.to_owned())))),
+        "$gt" => compare(Ordering::Greater, false),
+        "$gte" => compare(Ordering::Greater, true),
+        "$lt" => compare(Ordering::Less, false),
+        "$lte" => compare(Ordering::Less, true),
+        "$in" => compile_in(operator, operand),
+        "$nin" => compile_in(operator, operand).map(|cond| Cond::Not(Box::new(cond))),
+        "$exists" => match truthy(operand)? {
+            true => Ok(Cond::Exists),
+            false => Ok(Cond::Not(Box::new(Cond::Exists))),
+        },
+        "$type" => compile_type(operand),
+        "$not" => {
+            let cond = match operand.element_type() {
+                ElementType::RegularExpression => compile_condition(operand)?,
+                ElementType::EmbeddedDocument if is_operator_doc(operand.as_document()?)? => {
+                    compile_operators(operand.as_document()?)?
+                }
+                _ => return Err(bad_operand(operator)),
+            };
+            Ok(Cond::Not(Box::new(cond)))
+        }
+        "$elemMatch" => Ok(Cond::ElemMatch(Box::new(ElemMatch::new(operand)?))),
+        "$size" => {
+            let size = match operand.element_type() {
+                ElementType::Int32 => i64::from(operand.as_i32()?),
+                ElementType::Int64 => operand.as_i64()?,
+                ElementType::Double if operand.as_f64()?.fract() == 0.0 => operand.as_f64()? as i64,
+                _ => return Err(bad_operand(operator)),
+            };
+            match usize::try_from(size) {
+                Ok(size) => Ok(Cond::Size(size)),
+                Err(_) => Err(bad_operand(operator)),
+            }
+        }
+        "$all" => {
+            if operand.element_type() != ElementType::Array {
+                return Err(bad_operand(operator));
+            }
+            let array = operand.as_array()?;
+            let mut conds = Vec::new();
+            for result in &array.doc {
+                let (key, value) = result?;
+                let cond = match value.element_type() {
+                    ElementType::EmbeddedDocument => {
+                        let doc = value.as_document()?;
+                        match doc.into_iter().next().transpose()? {
+                            Some(("$elemMatch", _)) => compile_operators(doc),
+                            _ => Ok(Cond::Eq(value.to_owned())),
+                        }
+                    }
+                    _ => compile_condition(value),
+                };
+                conds.push(cond.map_err(context(&array.doc, key, value))?);
+            }
+            if conds.is_empty() {
+                // As in MongoDB, {"$all": []} matches nothing.
+                Ok(Cond::In(conds))
+            } else {
+                Ok(Cond::And(conds))
+            }
+        }
+        _ => Err(RawError::new(ErrorKind::UnknownOperator(operator.into()))),
+    }
+}
+
+/// Compile the array of values given to `$in` or `$nin`.
+fn compile_in(operator: &str, operand: Element<'_>) -> RawResult<Cond> {
+    if operand.element_type() != ElementType::Array {
+        return Err(bad_operand(operator));
+    }
+    let array = operand.as_array()?;
+    let mut conds = Vec::new();
+    for result in &array.doc {
+        let (key, value) = result?;
+        let cond = match value.element_type() {
+            ElementType::RegularExpression => compile_condition(value),
+            _ => Ok(Cond::Eq(value.to_owned())),
+        };
+        conds.push(cond.map_err(context(&array.doc, key, value))?);
+    }
+    Ok(Cond::In(conds))
+}
+
+/// Compile the type number, alias, or array of either given to `$type`.
+fn compile_type(operand: Element<'_>) -> RawResult<Cond> {
+    let mut types = Vec::new();
+    if operand.element_type() == ElementType::Array {
+        let array = operand.as_array()?;
+        for result in &array.doc {
+            let (key, value) = result?;
+            add_types(&mut types, value).map_err(context(&array.doc, key, value))?;
+        }
+    } else {
+        add_types(&mut types, operand)?;
+    }
+    Ok(Cond::Type(types))
+}
+
+fn add_types(types: &mut Vec<ElementType>, operand: Element<'_>) -> RawResult<()> {
+    let number = match operand.element_type() {
+        ElementType::Int32 => i64::from(operand.as_i32()?),
+        ElementType::Int64 => operand.as_i64()?,
+        ElementType::Double if operand.as_f64()?.fract() == 0.0 => operand.as_f64()? as i64,
+        ElementType::String => {
+            let element_type = match operand.as_str()? {
+                "number" => {
+                    types.extend_from_slice(&[
+                        ElementType::Double,
+                        ElementType::Int32,
+                        ElementType::Int64,
+                        ElementType::Decimal128,
+                    ]);
+                    return Ok(());
+                }
+                "double" => ElementType::Double,
+                "string" => ElementType::String,
+                "object" => ElementType::EmbeddedDocument,
+                "array" => ElementType::Array,
+                "binData" => ElementType::Binary,
+                "undefined" => ElementType::Undefined,
+                "objectId" => ElementType::ObjectId,
+                "bool" => ElementType::Boolean,
+                "date" => ElementType::DateTime,
+                "null" => ElementType::Null,
+                "regex" => ElementType::RegularExpression,
+                "dbPointer" => ElementType::DbPointer,
+                "javascript" => ElementType::JavaScriptCode,
+                "symbol" => ElementType::Symbol,
+                "javascriptWithScope" => ElementType::JavaScriptCodeWithScope,
+                "int" => ElementType::Int32,
+                "timestamp" => ElementType::Timestamp,
+                "long" => ElementType::Int64,
+                "decimal" => ElementType::Decimal128,
+                "minKey" => ElementType::MinKey,
+                "maxKey" => ElementType::MaxKey,
+                _ => return Err(bad_operand("$type")),
+            };
+            types.push(element_type);
+            return Ok(());
+        }
+        _ => return Err(bad_operand("$type")),
+    };
+    // MinKey is numbered -1 in queries, and 0xff on the wire.
+    let tag = match number {
+        -1 => Some(ElementType::MinKey),
+        1..=127 => ElementType::from(number as u8),
+        _ => None,
+    };
+    match tag {
+        Some(element_type) => {
+            types.push(element_type);
+            Ok(())
+        }
+        None => Err(bad_operand("$type")),
+    }
+}
+
+/// Whether the operand of `$exists` is true.  As in MongoDB, any value
+/// other than false, zero, null and undefined is true.
+pub(crate) fn truthy(operand: Element<'_>) -> RawResult<bool> {
+    Ok(match operand.element_type() {
+        ElementType::Boolean => operand.as_bool()?,
+        ElementType::Int32 => operand.as_i32()? != 0,
+        ElementType::Int64 => operand.as_i64()? != 0,
+        ElementType::Double => operand.as_f64()? != 0.0,
+        ElementType::Null | ElementType::Undefined => false,
+        _ => true,
+    })
+}
+
+fn string_operand<'a>(operator: &str, operand: Element<'a>) -> RawResult<&'a str> {
+    match operand.element_type() {
+        ElementType::String => operand.as_str(),
+        _ => Err(bad_operand(operator)),
+    }
+}
+
+/// Compile a regular expression with MongoDB's option letters.
+fn compile_regex(pattern: &str, options: &str) -> RawResult<Regex> {
+    let mut builder = RegexBuilder::new(pattern);
+    for option in options.chars() {
+        match option {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            // Patterns are always unicode-aware.
+            'u' => &mut builder,
+            _ => {
+                return Err(RawError::new(ErrorKind::BadRegex(format!(
+                    "unknown option {:?}",
+                    option
+                ))))
+            }
+        };
+    }
+    builder
+        .build()
+        .map_err(|err| RawError::new(ErrorKind::BadRegex(err.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, spec::BinarySubtype, Binary, Bson, Document, Regex};
+
+    use super::Matcher;
+    use crate::{DocBuf, ErrorKind, RawError};
+
+    fn compile(filter: Document) -> Result<Matcher, RawError> {
+        Matcher::new(&DocBuf::from_document(&filter))
+    }
+
+    fn matches(filter: Document, doc: Document) -> bool {
+        compile(filter)
+            .unwrap()
+            .matches(&DocBuf::from_document(&doc))
+            .unwrap()
+    }
+
+    #[test]
+    fn comparison() {
+        let doc = doc! {"n": 5, "s": "abc", "none": null};
+        assert!(matches(doc! {"n": 5.0}, doc.clone()));
+        assert!(matches(doc! {"n": {"$gt": 4i64, "$lte": 5}}, doc.clone()));
+        assert!(!matches(doc! {"n": {"$lt": 5}}, doc.clone()));
+        assert!(matches(doc! {"n": {"$ne": 4}}, doc.clone()));
+        // Values of different types never compare.
+        assert!(!matches(doc! {"n": {"$lt": "z"}}, doc.clone()));
+        assert!(!matches(doc! {"s": {"$gt": 1}}, doc.clone()));
+        assert!(matches(doc! {"s": {"$gt": Bson::MinKey}}, doc.clone()));
+        assert!(matches(
+            doc! {"s": {"$gte": "abc", "$lt": "abd"}},
+            doc.clone()
+        ));
+        assert!(matches(doc! {"n": {"$in": [1, 5]}}, doc.clone()));
+        assert!(matches(doc! {"n": {"$nin": [1, 2]}}, doc.clone()));
+        // null matches both null and missing values.
+        assert!(matches(doc! {"none": null, "missing": null}, doc.clone()));
+        assert!(matches(doc! {"missing": {"$gte": null}}, doc.clone()));
+        assert!(!matches(doc! {"n": null}, doc.clone()));
+        assert!(matches(doc! {"missing": {"$ne": 5}}, doc));
+    }
+
+    #[test]
+    fn logical() {
+        let doc = doc! {"a": 1, "b": 2};
+        assert!(matches(doc! {"$or": [{"a": 2}, {"b": 2}]}, doc.clone()));
+        assert!(!matches(doc! {"$and": [{"a": 1}, {"b": 1}]}, doc.clone()));
+        assert!(matches(doc! {"$nor": [{"a": 2}, {"b": 3}]}, doc.clone()));
+        assert!(matches(doc! {"a": {"$not": {"$gt": 1}}}, doc.clone()));
+        assert!(matches(doc! {"c": {"$not": {"$gt": 1}}}, doc.clone()));
+        assert!(matches(doc! {"$comment": "ignored", "a": 1}, doc.clone()));
+        assert!(matches(doc! {}, doc));
+    }
+
+    #[test]
+    fn element_and_regex() {
+        let doc = doc! {
+            "s": "Hello\nWorld",
+            "n": 1i64,
+            "d": 2.5,
+            "b": Binary { subtype: BinarySubtype::Generic, bytes: vec![1] },
+            "list": [1, "two"],
+        };
+        assert!(matches(
+            doc! {"s": {"$exists": true}, "x": {"$exists": 0}},
+            doc.clone()
+        ));
+        assert!(!matches(doc! {"s": {"$exists": false}}, doc.clone()));
+        assert!(matches(
+            doc! {"n": {"$type": "long"}, "d": {"$type": 1}},
+            doc.clone()
+        ));
+        assert!(matches(
+            doc! {"n": {"$type": "number"}, "b": {"$type": ["int", 5]}},
+            doc.clone()
+        ));
+        assert!(matches(doc! {"list": {"$type": "array"}}, doc.clone()));
+        // The elements of an array are checked as well.
+        assert!(matches(doc! {"list": {"$type": "string"}}, doc.clone()));
+
+        let regex = |pattern: &str, options: &str| {
+            Bson::RegularExpression(Regex {
+                pattern: pattern.into(),
+                options: options.into(),
+            })
+        };
+        assert!(matches(doc! {"s": regex("^world", "im")}, doc.clone()));
+        assert!(!matches(doc! {"s": regex("^world", "i")}, doc.clone()));
+        assert!(matches(
+            doc! {"s": {"$regex": "o.W", "$options": "s"}},
+            doc.clone()
+        ));
+        assert!(matches(
+            doc! {"s": {"$in": [1, regex("^H", "")]}},
+            doc.clone()
+        ));
+        assert!(matches(doc! {"s": {"$not": regex("x", "")}}, doc.clone()));
+        assert!(matches(doc! {"list": {"$regex": "^t"}}, doc.clone()));
+        assert!(!matches(doc! {"n": {"$regex": "1"}}, doc));
+    }
+
+    #[test]
+    fn arrays() {
+        let doc = doc! {
+            "tags": ["a", "b", "c"],
+            "scores": [3, 8, [10]],
+            "items": [{"name": "x", "qty": 1}, {"name": "y", "qty": 7}],
+        };
+        assert!(matches(doc! {"tags": "b"}, doc.clone()));
+        assert!(matches(doc! {"tags": ["a", "b", "c"]}, doc.clone()));
+        assert!(!matches(doc! {"tags": ["a", "b"]}, doc.clone()));
+        assert!(matches(doc! {"tags": {"$all": ["c", "a"]}}, doc.clone()));
+        assert!(!matches(doc! {"tags": {"$all": ["a", "z"]}}, doc.clone()));
+        assert!(!matches(doc! {"tags": {"$all": []}}, doc.clone()));
+        assert!(matches(doc! {"tags": {"$size": 3}}, doc.clone()));
+        assert!(!matches(doc! {"scores": {"$size": 1}}, doc.clone()));
+        assert!(matches(doc! {"tags.1": "b"}, doc.clone()));
+
+        // Each operator may be met by a different element...
+        assert!(matches(doc! {"scores": {"$gt": 5, "$lt": 4}}, doc.clone()));
+        // ...unless they are grouped with $elemMatch.
+        assert!(!matches(
+            doc! {"scores": {"$elemMatch": {"$gt": 5, "$lt": 4}}},
+            doc.clone()
+        ));
+        assert!(matches(
+            doc! {"scores": {"$elemMatch": {"$gt": 5, "$lt": 9}}},
+            doc.clone()
+        ));
+        // Only one level of nested arrays is expanded.
+        assert!(matches(doc! {"scores": [10]}, doc.clone()));
+        assert!(!matches(doc! {"scores": 10}, doc.clone()));
+
+        assert!(matches(doc! {"items.name": "y"}, doc.clone()));
+        assert!(matches(doc! {"items.qty": {"$gt": 5}}, doc.clone()));
+        assert!(matches(
+            doc! {"items.name": "x", "items.qty": 7},
+            doc.clone()
+        ));
+        assert!(!matches(
+            doc! {"items": {"$elemMatch": {"name": "x", "qty": 7}}},
+            doc.clone()
+        ));
+        assert!(matches(
+            doc! {"items": {"$all": [{"$elemMatch": {"qty": {"$lt": 2}}}]}},
+            doc.clone()
+        ));
+        assert!(matches(
+            doc! {"items.price": {"$exists": false}},
+            doc.clone()
+        ));
+        assert!(matches(doc! {"items.0.qty": 1}, doc.clone()));
+
+        // A path through an array of scalars is missing.
+        assert!(matches(doc! {"tags.x": null}, doc.clone()));
+        assert!(matches(doc! {"tags.x": {"$exists": false}}, doc.clone()));
+        assert!(!matches(doc! {"tags.x": {"$exists": true}}, doc.clone()));
+        assert!(matches(doc! {"tags.x": {"$ne": "a"}}, doc.clone()));
+        assert!(!matches(doc! {"tags.x": {"$ne": null}}, doc.clone()));
+        assert!(matches(doc! {"empty.x": null}, doc! {"empty": []}));
+        // Documents in the array are still followed.
+        assert!(!matches(doc! {"items.name": null}, doc.clone()));
+        assert!(!matches(
+            doc! {"mixed.name": null},
+            doc! {"mixed": [1, {"name": "x"}]}
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let err = compile(doc! {"a": {"$near": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnknownOperator("$near".into()));
+        assert_eq!(err.key_path(), "a.$near");
+
+        let err = compile(doc! {"$where": "true"}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnknownOperator("$where".into()));
+
+        let err = compile(doc! {"$and": [{"a": 1}, 2]}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$and".into()));
+        assert_eq!(err.key_path(), "$and.1");
+        let err = compile(doc! {"$or": []}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$or".into()));
+
+        let err = compile(doc! {"a": {"$in": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$in".into()));
+        let err = compile(doc! {"a": {"$type": ["int", "integer"]}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$type".into()));
+        assert_eq!(err.key_path(), "a.$type.1");
+        let err = compile(doc! {"a": {"$size": -1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$size".into()));
+        let err = compile(doc! {"a": {"$options": "i"}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$options".into()));
+
+        let err = compile(doc! {"a": {"$regex": "(", "$options": "i"}}).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::BadRegex(_)));
+        assert_eq!(err.key_path(), "a.$regex");
+        let err = compile(doc! {"a": {"$regex": "x", "$options": "q"}}).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::BadRegex(_)));
+    }
+}
@@ -0,0 +1,635 @@
+//! Reading concatenated BSON documents.
+//!
+//! Dump files written by `mongodump`, and many message queues, hold BSON
+//! documents back to back with nothing between them.  [`DocReader`] reads
+//! such a stream one document at a time, reusing a single buffer, and
+//! [`DocSeq`] borrows each document from a slice already in memory.
+//!
+//! ```
+//! use bson::doc;
+//! use rawbson::{DocBuf, DocReader};
+//!
+//! let mut stream = Vec::new();
+//! for n in 0..3 {
+//!     stream.extend_from_slice(DocBuf::from_document(&doc! {"n": n}).as_bytes());
+//! }
+//!
+//! let mut reader = DocReader::new(&stream[..]);
+//! let mut total = 0;
+//! while let Some(doc) = reader.next_doc()? {
+//!     total += doc.get_i32("n")?.unwrap_or(0);
+//! }
+//! assert_eq!(total, 3);
+//! # Ok::<(), rawbson::reader::ReadError>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::{i32_from_slice, Doc, DocBuf, ErrorKind, RawError, RawResult};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncDocReader, DocCodec};
+
+/// The default maximum document size: 16MiB, the largest document MongoDB
+/// stores.
+pub const DEFAULT_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// An error from [`DocReader`]: either the underlying reader failed, or the
+/// stream does not hold well-formed documents.
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Raw(RawError),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+impl From<RawError> for ReadError {
+    fn from(err: RawError) -> ReadError {
+        ReadError::Raw(err)
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "{}", err),
+            ReadError::Raw(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            ReadError::Raw(err) => Some(err),
+        }
+    }
+}
+
+/// Reads concatenated BSON documents from an [`io::Read`].
+///
+/// [`next_doc`](DocReader::next_doc) lends each document from an internal
+/// buffer that is reused for the next one, so reading a stream allocates
+/// only as much as its largest document.  To keep documents around, use
+/// [`next_docbuf`](DocReader::next_docbuf) or iterate over the reader,
+/// which yields owned [`DocBuf`]s.
+///
+/// Only the envelope of each document (its length and trailing nul) is
+/// checked.  A `RawError` has its offset relative to the start of the
+/// failing document, which is found in the stream at
+/// [`offset`](DocReader::offset).  After any error the reader is exhausted,
+/// since the position of the next document can no longer be trusted.
+///
+/// The reader makes many small reads, so wrap unbuffered sources such as
+/// files and sockets in an [`io::BufReader`].
+pub struct DocReader<R> {
+    reader: R,
+    frame: Frame,
+}
+
+impl<R: Read> DocReader<R> {
+    /// Read documents from `reader`, accepting documents up to
+    /// [`DEFAULT_MAX_SIZE`] bytes long.
+    pub fn new(reader: R) -> DocReader<R> {
+        DocReader {
+            reader,
+            frame: Frame::new(),
+        }
+    }
+
+    /// Set the largest document, in bytes, that the reader accepts.
+    ///
+    /// A longer document produces an error of kind
+    /// [`ErrorKind::TooLarge`] before any of its body is read.
+    ///
+    /// ```
+    /// # use rawbson::{DocReader, ErrorKind, reader::ReadError};
+    /// let stream = b"\x0d\0\0\0\x10n\0\x01\0\0\0\0";
+    /// let mut reader = DocReader::new(&stream[..]).with_max_size(8);
+    /// match reader.next_doc() {
+    ///     Err(ReadError::Raw(err)) => assert_eq!(err.kind(), &ErrorKind::TooLarge),
+    ///     _ => panic!("expected an error"),
+    /// }
+    /// ```
+    pub fn with_max_size(mut self, max_size: usize) -> DocReader<R> {
+        self.frame.max_size = max_size;
+        self
+    }
+
+    /// Read the next document into the internal buffer and borrow it.
+    ///
+    /// Returns `Ok(None)` when the stream ends cleanly between documents.
+    /// A stream that ends partway through a document produces an error of
+    /// kind [`ErrorKind::TruncatedLength`].
+    pub fn next_doc(&mut self) -> Result<Option<&Doc>, ReadError> {
+        if self.frame.done {
+            return Ok(None);
+        }
+        let filled = self.fill();
+        self.frame.result(filled)
+    }
+
+    /// Read the next document into a new [`DocBuf`].
+    pub fn next_docbuf(&mut self) -> Result<Option<DocBuf>, ReadError> {
+        Ok(self.next_doc()?.map(Doc::to_docbuf))
+    }
+
+    /// Read one document into `buf`, returning false at the end of the
+    /// stream.
+    fn fill(&mut self) -> Result<bool, ReadError> {
+        let read = read_full(&mut self.reader, self.frame.header())?;
+        let body = match self.frame.body(read)? {
+            Some(body) => body,
+            None => return Ok(false),
+        };
+        let read = read_full(&mut self.reader, body)?;
+        self.frame.finish(read)?;
+        Ok(true)
+    }
+
+    /// The position in the stream of the document most recently read, or
+    /// of the one that failed.
+    pub fn offset(&self) -> u64 {
+        self.frame.offset
+    }
+
+    /// Borrow the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Mutably borrow the underlying reader.  Reading from it directly will
+    /// desynchronize the `DocReader`.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for DocReader<R> {
+    type Item = Result<DocBuf, ReadError>;
+
+    fn next(&mut self) -> Option<Result<DocBuf, ReadError>> {
+        self.next_docbuf().transpose()
+    }
+}
+
+/// The framing state shared by [`DocReader`] and the async reader: the
+/// buffer holding the current document, and its position in the stream.
+///
+/// A read goes through [`header`](Frame::header), [`body`](Frame::body) and
+/// [`finish`](Frame::finish), with the caller filling each slice returned
+/// from its reader, and then [`result`](Frame::result) lends the document.
+pub(crate) struct Frame {
+    buf: Vec<u8>,
+    pub(crate) max_size: usize,
+    pub(crate) offset: u64,
+    next_offset: u64,
+    pub(crate) done: bool,
+}
+
+impl Frame {
+    pub(crate) fn new() -> Frame {
+        Frame {
+            buf: Vec::new(),
+            max_size: DEFAULT_MAX_SIZE,
+            offset: 0,
+            next_offset: 0,
+            done: false,
+        }
+    }
+
+    /// Start reading the next document, returning the space for its length
+    /// prefix.
+    pub(crate) fn header(&mut self) -> &mut [u8] {
+        self.offset = self.next_offset;
+        self.buf.clear();
+        self.buf.resize(4, 0);
+        &mut self.buf
+    }
+
+    /// Check the length prefix, of which `read` bytes were read, and return
+    /// the space for the rest of the document, or `None` at the end of the
+    /// stream.
+    pub(crate) fn body(&mut self, read: usize) -> RawResult<Option<&mut [u8]>> {
+        match read {
+            0 => return Ok(None),
+            4 => {}
+            _ => return Err(RawError::new(ErrorKind::TruncatedLength).at(0)),
+        }
+        let length = check_length(&self.buf, self.max_size)?;
+        self.buf.resize(length, 0);
+        Ok(Some(&mut self.buf[4..]))
+    }
+
+    /// Check the document once `read` bytes of its body were read.
+    pub(crate) fn finish(&mut self, read: usize) -> RawResult<()> {
+        let read = 4 + read;
+        if read < self.buf.len() {
+            return Err(RawError::new(ErrorKind::TruncatedLength).at(read));
+        }
+        Doc::new(&self.buf)?;
+        self.next_offset += self.buf.len() as u64;
+        Ok(())
+    }
+
+    /// Lend the document once `filled` reports how the read went, marking
+    /// the stream as exhausted at its end or after an error.
+    pub(crate) fn result(
+        &mut self,
+        filled: Result<bool, ReadError>,
+    ) -> Result<Option<&Doc>, ReadError> {
+        match filled {
+            Ok(true) => {
+                // SAFETY: `finish` checked the envelope of the buffer.
+                Ok(Some(unsafe { Doc::new_unchecked(&self.buf) }))
+            }
+            Ok(false) => {
+                self.done = true;
+                Ok(None)
+            }
+            Err(err) => {
+                self.done = true;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Read the length prefix at the start of `header`, checking that it is
+/// large enough for a document and no larger than `max_size`.
+pub(crate) fn check_length(header: &[u8], max_size: usize) -> RawResult<usize> {
+    match usize::try_from(i32_from_slice(&header[..4])) {
+        Ok(length) if length > max_size => Err(RawError::new(ErrorKind::TooLarge).at(0)),
+        Ok(length) if length >= 5 => Ok(length),
+        _ => Err(RawError::new(ErrorKind::BadLength).at(0)),
+    }
+}
+
+/// Read into `buf` until it is full or the reader is exhausted, returning
+/// the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// A view of back-to-back BSON documents in a byte slice, such as a mapped
+/// dump file or the document sequence section of an `OP_MSG`.
+///
+/// Iterating yields each document as a [`&Doc`](Doc) borrowed from the
+/// slice, without copying.  Only the envelope of each document is checked,
+/// and errors have their offsets relative to the start of the slice.
+/// Iteration stops after the first error.
+///
+/// For random access, [`DocSeq::indexed`] records where every document
+/// starts.
+///
+/// ```
+/// use bson::doc;
+/// use rawbson::{DocBuf, DocSeq};
+///
+/// let mut bytes = Vec::new();
+/// for name in &["ant", "bee", "cat"] {
+///     bytes.extend_from_slice(DocBuf::from_document(&doc! {"name": name}).as_bytes());
+/// }
+///
+/// let seq = DocSeq::new(&bytes);
+/// let names = seq
+///     .iter()
+///     .map(|doc| doc?.get_str("name").map(Option::unwrap))
+///     .collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(names, ["ant", "bee", "cat"]);
+///
+/// let indexed = seq.indexed()?;
+/// assert_eq!(indexed.len(), 3);
+/// assert_eq!(indexed.get(1).unwrap().get_str("name")?, Some("bee"));
+/// # Ok::<(), rawbson::RawError>(())
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DocSeq<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DocSeq<'a> {
+    /// View `data` as a sequence of documents.  Nothing is checked until
+    /// the sequence is read.
+    pub fn new<D: AsRef<[u8]> + ?Sized>(data: &'a D) -> DocSeq<'a> {
+        DocSeq {
+            data: data.as_ref(),
+        }
+    }
+
+    /// The underlying bytes.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Iterate over the documents in the sequence.
+    pub fn iter(&self) -> DocSeqIter<'a> {
+        DocSeqIter {
+            data: self.data,
+            offset: 0,
+        }
+    }
+
+    /// Walk the sequence once, recording the offset of every document.
+    ///
+    /// Returns the first error found, if any.
+    pub fn indexed(&self) -> RawResult<IndexedDocSeq<'a>> {
+        let mut offsets = vec![0];
+        let mut iter = self.iter();
+        while let Some(result) = iter.next() {
+            result?;
+            offsets.push(iter.offset);
+        }
+        Ok(IndexedDocSeq {
+            data: self.data,
+            offsets,
+        })
+    }
+}
+
+impl<'a> IntoIterator for DocSeq<'a> {
+    type IntoIter = DocSeqIter<'a>;
+    type Item = RawResult<&'a Doc>;
+
+    fn into_iter(self) -> DocSeqIter<'a> {
+        self.iter()
+    }
+}
+
+/// An iterator over the documents in a [`DocSeq`].
+#[derive(Clone, Debug)]
+pub struct DocSeqIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DocSeqIter<'a> {
+    /// The offset in the slice of the next document.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn next_doc(&self) -> RawResult<&'a Doc> {
+        let rest = &self.data[self.offset..];
+        if rest.len() < 4 {
+            return Err(RawError::new(ErrorKind::TruncatedLength).at(0));
+        }
+        let length = check_length(rest, usize::MAX)?;
+        match rest.get(..length) {
+            Some(data) => Doc::new(data),
+            None => Err(RawError::new(ErrorKind::TruncatedLength).at(rest.len())),
+        }
+    }
+}
+
+impl<'a> Iterator for DocSeqIter<'a> {
+    type Item = RawResult<&'a Doc>;
+
+    fn next(&mut self) -> Option<RawResult<&'a Doc>> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        match self.next_doc() {
+            Ok(doc) => {
+                self.offset += doc.as_bytes().len();
+                Some(Ok(doc))
+            }
+            Err(err) => {
+                let err = err.shifted(self.offset);
+                // Don't try to resume parsing from an unknown position.
+                self.offset = self.data.len();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// A [`DocSeq`] with a table of document offsets, for random access.
+#[derive(Clone, Debug)]
+pub struct IndexedDocSeq<'a> {
+    data: &'a [u8],
+    // The start of every document, followed by the end of the last.
+    offsets: Vec<usize>,
+}
+
+impl<'a> IndexedDocSeq<'a> {
+    /// The number of documents in the sequence.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Returns true if the sequence holds no documents.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `index`th document, counting from zero.
+    pub fn get(&self, index: usize) -> Option<&'a Doc> {
+        let start = *self.offsets.get(index)?;
+        let end = *self.offsets.get(index + 1)?;
+        // SAFETY: The envelope of every document was checked while indexing.
+        Some(unsafe { Doc::new_unchecked(&self.data[start..end]) })
+    }
+
+    /// The offset in the slice of the `index`th document.
+    pub fn offset(&self, index: usize) -> Option<usize> {
+        if index < self.len() {
+            Some(self.offsets[index])
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the documents, which are known to be well-framed.
+    pub fn iter(&self) -> impl Iterator<Item = &'a Doc> + '_ {
+        (0..self.len()).map(move |index| self.get(index).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use bson::doc;
+
+    use super::{DocReader, DocSeq, ReadError};
+    use crate::{DocBuf, ErrorKind};
+
+    fn stream(count: i32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for n in 0..count {
+            let docbuf = DocBuf::from_document(&doc! {"n": n, "s": "x".repeat(n as usize)});
+            bytes.extend_from_slice(docbuf.as_bytes());
+        }
+        bytes
+    }
+
+    fn raw_error<T: std::fmt::Debug>(result: Result<T, ReadError>) -> (ErrorKind, Option<usize>) {
+        match result {
+            Err(ReadError::Raw(err)) => (err.kind().clone(), err.offset()),
+            other => panic!("expected a RawError, got {:?}", other),
+        }
+    }
+
+    /// Hands out at most one byte per read, interrupting every other call.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(self.data.len()).min(1);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reads_every_document() {
+        let bytes = stream(5);
+        let mut reader = DocReader::new(Trickle {
+            data: &bytes,
+            interrupt: false,
+        });
+        let mut offsets = Vec::new();
+        let mut n = 0;
+        while let Some(doc) = reader.next_doc().unwrap() {
+            assert_eq!(doc.get_i32("n").unwrap(), Some(n));
+            offsets.push(reader.offset());
+            n += 1;
+        }
+        assert_eq!(n, 5);
+        assert_eq!(offsets, [0, 20, 41, 63, 86]);
+        assert!(reader.next_doc().unwrap().is_none());
+
+        let docbufs: Vec<DocBuf> = DocReader::new(&bytes[..]).map(Result::unwrap).collect();
+        assert_eq!(docbufs.len(), 5);
+        assert_eq!(docbufs[4].get_str("s").unwrap(), Some("xxxx"));
+        assert!(DocReader::new(io::empty()).next().is_none());
+    }
+
+    #[test]
+    fn truncated_documents() {
+        let bytes = stream(2);
+        let mut reader = DocReader::new(&bytes[..bytes.len() - 3]);
+        assert!(reader.next_doc().unwrap().is_some());
+        assert_eq!(
+            raw_error(reader.next_doc()),
+            (ErrorKind::TruncatedLength, Some(18))
+        );
+        assert_eq!(reader.offset(), 20);
+        assert!(reader.next_doc().unwrap().is_none());
+
+        let mut reader = DocReader::new(&bytes[..23]);
+        reader.next_doc().unwrap();
+        assert_eq!(
+            raw_error(reader.next_doc()),
+            (ErrorKind::TruncatedLength, Some(0))
+        );
+    }
+
+    #[test]
+    fn bad_envelopes() {
+        let mut reader = DocReader::new(&b"\x04\0\0\0"[..]);
+        assert_eq!(
+            raw_error(reader.next_doc()),
+            (ErrorKind::BadLength, Some(0))
+        );
+
+        let mut reader = DocReader::new(&b"\x06\0\0\0\0\x01"[..]);
+        assert_eq!(
+            raw_error(reader.next_doc()),
+            (ErrorKind::MissingNul, Some(5))
+        );
+
+        let bytes = stream(3);
+        let mut reader = DocReader::new(&bytes[..]).with_max_size(22);
+        assert_eq!(reader.by_ref().count(), 3);
+        let mut reader = DocReader::new(&bytes[..]).with_max_size(20);
+        assert!(reader.next_doc().unwrap().is_some());
+        assert_eq!(raw_error(reader.next_doc()), (ErrorKind::TooLarge, Some(0)));
+        // Only the length prefix of the oversized document was consumed.
+        assert_eq!(reader.into_inner().len(), bytes.len() - 24);
+    }
+
+    #[test]
+    fn doc_seq() {
+        let bytes = stream(4);
+        let seq = DocSeq::new(&bytes);
+        let docs: Vec<_> = seq.into_iter().map(Result::unwrap).collect();
+        assert_eq!(docs.len(), 4);
+        assert_eq!(docs[3].get_str("s").unwrap(), Some("xxx"));
+        assert_eq!(docs[1].as_bytes().as_ptr(), bytes[20..].as_ptr());
+
+        let indexed = seq.indexed().unwrap();
+        assert_eq!(indexed.len(), 4);
+        assert_eq!(indexed.offset(2), Some(41));
+        assert_eq!(indexed.offset(4), None);
+        assert_eq!(indexed.get(2).unwrap().get_i32("n").unwrap(), Some(2));
+        assert!(indexed.get(4).is_none());
+        assert_eq!(indexed.iter().count(), 4);
+        assert!(DocSeq::new(&[]).indexed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn doc_seq_errors() {
+        let bytes = stream(3);
+        let seq = DocSeq::new(&bytes[..bytes.len() - 1]);
+        let mut iter = seq.iter();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.offset(), 41);
+        let err = iter.next().unwrap().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.offset()),
+            (&ErrorKind::TruncatedLength, Some(62))
+        );
+        assert!(iter.next().is_none());
+        assert_eq!(seq.indexed().unwrap_err(), err);
+
+        let err = DocSeq::new(&bytes[..22]).indexed().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.offset()),
+            (&ErrorKind::TruncatedLength, Some(20))
+        );
+
+        let mut bytes = bytes;
+        bytes[20] = 4;
+        let err = DocSeq::new(&bytes).iter().nth(1).unwrap().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.offset()),
+            (&ErrorKind::BadLength, Some(20))
+        );
+    }
+}
@@ -0,0 +1,309 @@
+// Async counterparts of DocReader, for the `async` feature.
+
+use std::io;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{check_length, Frame, ReadError, DEFAULT_MAX_SIZE};
+use crate::{Doc, DocBuf, ErrorKind, RawError};
+
+/// Reads concatenated BSON documents from a [`tokio::io::AsyncRead`].
+///
+/// This is the async version of [`DocReader`](super::DocReader), with the
+/// same framing checks, offsets and behavior after an error.  Readers
+/// implementing `futures::io::AsyncRead` can be adapted with
+/// [`tokio_util::compat::FuturesAsyncReadCompatExt::compat`].
+///
+/// The futures returned by [`next_doc`](AsyncDocReader::next_doc) and
+/// [`next_docbuf`](AsyncDocReader::next_docbuf) are not cancellation safe:
+/// dropping one partway through a document loses the position in the
+/// stream.  Use [`tokio_util::codec::FramedRead`] with a [`DocCodec`] where
+/// reads may be cancelled, for example inside `select!`.
+///
+/// ```
+/// # async fn run() -> Result<(), rawbson::reader::ReadError> {
+/// use rawbson::AsyncDocReader;
+/// let stream: &[u8] = b"\x05\0\0\0\0\x05\0\0\0\0";
+/// let mut reader = AsyncDocReader::new(stream);
+/// let mut count = 0;
+/// while let Some(docbuf) = reader.next_docbuf().await? {
+///     assert!(docbuf.iter().next().is_none());
+///     count += 1;
+/// }
+/// assert_eq!(count, 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncDocReader<R> {
+    reader: R,
+    frame: Frame,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDocReader<R> {
+    /// Read documents from `reader`, accepting documents up to
+    /// [`DEFAULT_MAX_SIZE`] bytes long.
+    pub fn new(reader: R) -> AsyncDocReader<R> {
+        AsyncDocReader {
+            reader,
+            frame: Frame::new(),
+        }
+    }
+
+    /// Set the largest document, in bytes, that the reader accepts.
+    pub fn with_max_size(mut self, max_size: usize) -> AsyncDocReader<R> {
+        self.frame.max_size = max_size;
+        self
+    }
+
+    /// Read the next document into the internal buffer and borrow it.
+    ///
+    /// Returns `Ok(None)` when the stream ends cleanly between documents.
+    pub async fn next_doc(&mut self) -> Result<Option<&Doc>, ReadError> {
+        if self.frame.done {
+            return Ok(None);
+        }
+        let filled = self.fill().await;
+        self.frame.result(filled)
+    }
+
+    /// Read the next document into a new [`DocBuf`].
+    pub async fn next_docbuf(&mut self) -> Result<Option<DocBuf>, ReadError> {
+        Ok(self.next_doc().await?.map(Doc::to_docbuf))
+    }
+
+    async fn fill(&mut self) -> Result<bool, ReadError> {
+        let read = read_full(&mut self.reader, self.frame.header()).await?;
+        let body = match self.frame.body(read)? {
+            Some(body) => body,
+            None => return Ok(false),
+        };
+        let read = read_full(&mut self.reader, body).await?;
+        self.frame.finish(read)?;
+        Ok(true)
+    }
+
+    /// The position in the stream of the document most recently read, or
+    /// of the one that failed.
+    pub fn offset(&self) -> u64 {
+        self.frame.offset
+    }
+
+    /// Borrow the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Mutably borrow the underlying reader.  Reading from it directly will
+    /// desynchronize the `AsyncDocReader`.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+async fn read_full<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]).await {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// A [`tokio_util::codec`] codec that frames BSON documents on a byte
+/// stream.
+///
+/// Decoding checks each length prefix as [`DocReader`](super::DocReader)
+/// does, and the envelope of each document as [`DocBuf::new`] does.  Bytes
+/// left over at the end of the stream produce an error of kind
+/// [`ErrorKind::TruncatedLength`].  Encoding writes documents unchanged,
+/// refusing any larger than the maximum size.
+///
+/// ```
+/// use bytes::BytesMut;
+/// use tokio_util::codec::{Decoder, Encoder};
+/// use rawbson::{DocBuf, DocCodec};
+///
+/// let mut codec = DocCodec::new();
+/// let mut buf = BytesMut::new();
+/// codec.encode(DocBuf::new(b"\x05\0\0\0\0".to_vec())?, &mut buf)?;
+/// buf.extend_from_slice(b"\x05\0");
+///
+/// assert!(codec.decode(&mut buf)?.is_some());
+/// assert!(codec.decode(&mut buf)?.is_none());
+/// assert!(codec.decode_eof(&mut buf).is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct DocCodec {
+    max_size: usize,
+}
+
+impl DocCodec {
+    /// A codec accepting documents up to [`DEFAULT_MAX_SIZE`] bytes long.
+    pub fn new() -> DocCodec {
+        DocCodec {
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Set the largest document, in bytes, that the codec accepts.
+    pub fn with_max_size(mut self, max_size: usize) -> DocCodec {
+        self.max_size = max_size;
+        self
+    }
+}
+
+impl Default for DocCodec {
+    fn default() -> DocCodec {
+        DocCodec::new()
+    }
+}
+
+impl Decoder for DocCodec {
+    type Item = DocBuf;
+    type Error = ReadError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<DocBuf>, ReadError> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let length = check_length(src, self.max_size)?;
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+        let data = src.split_to(length);
+        Ok(Some(DocBuf::new(data.to_vec())?))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<DocBuf>, ReadError> {
+        match self.decode(src)? {
+            Some(docbuf) => Ok(Some(docbuf)),
+            None if src.is_empty() => Ok(None),
+            None => Err(RawError::new(ErrorKind::TruncatedLength)
+                .at(src.len())
+                .into()),
+        }
+    }
+}
+
+impl<'a> Encoder<&'a Doc> for DocCodec {
+    type Error = ReadError;
+
+    fn encode(&mut self, doc: &'a Doc, dst: &mut BytesMut) -> Result<(), ReadError> {
+        let data = doc.as_bytes();
+        if data.len() > self.max_size {
+            return Err(RawError::new(ErrorKind::TooLarge).at(0).into());
+        }
+        dst.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+impl Encoder<DocBuf> for DocCodec {
+    type Error = ReadError;
+
+    fn encode(&mut self, docbuf: DocBuf, dst: &mut BytesMut) -> Result<(), ReadError> {
+        self.encode(&*docbuf, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use bytes::BytesMut;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::{AsyncDocReader, DocCodec};
+    use crate::{reader::ReadError, DocBuf, ErrorKind};
+
+    fn stream(count: i32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for n in 0..count {
+            bytes.extend_from_slice(DocBuf::from_document(&doc! {"n": n}).as_bytes());
+        }
+        bytes
+    }
+
+    fn raw_kind<T: std::fmt::Debug>(result: Result<T, ReadError>) -> ErrorKind {
+        match result {
+            Err(ReadError::Raw(err)) => err.kind().clone(),
+            other => panic!("expected a RawError, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn async_reader() {
+        let bytes = stream(3);
+        // A duplex pipe with a tiny buffer delivers the stream in pieces.
+        let (mut writer, reader) = tokio::io::duplex(5);
+        let write = {
+            let bytes = bytes.clone();
+            tokio::spawn(async move {
+                writer.write_all(&bytes).await.unwrap();
+                writer.write_all(b"\x0c\0\0").await.unwrap();
+            })
+        };
+        let mut reader = AsyncDocReader::new(reader);
+        for n in 0..3 {
+            let doc = reader.next_doc().await.unwrap().unwrap();
+            assert_eq!(doc.get_i32("n").unwrap(), Some(n));
+        }
+        assert_eq!(
+            raw_kind(reader.next_docbuf().await),
+            ErrorKind::TruncatedLength
+        );
+        assert_eq!(reader.offset(), bytes.len() as u64);
+        assert!(reader.next_doc().await.unwrap().is_none());
+        write.await.unwrap();
+
+        let mut reader = AsyncDocReader::new(&bytes[..]).with_max_size(8);
+        assert_eq!(raw_kind(reader.next_doc().await), ErrorKind::TooLarge);
+    }
+
+    #[test]
+    fn codec() {
+        let mut codec = DocCodec::new();
+        let bytes = stream(2);
+        let mut buf = BytesMut::new();
+        for &byte in &bytes[..11] {
+            buf.extend_from_slice(&[byte]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+        }
+        buf.extend_from_slice(&bytes[11..]);
+        let first = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(first.get_i32("n").unwrap(), Some(0));
+        let second = codec.decode_eof(&mut buf).unwrap().unwrap();
+        assert_eq!(second.get_i32("n").unwrap(), Some(1));
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+
+        let mut out = BytesMut::new();
+        codec.encode(&*first, &mut out).unwrap();
+        codec.encode(second, &mut out).unwrap();
+        assert_eq!(&out[..], &bytes[..]);
+
+        let mut small = DocCodec::new().with_max_size(11);
+        assert_eq!(
+            raw_kind(small.decode(&mut out.clone())),
+            ErrorKind::TooLarge
+        );
+        assert_eq!(raw_kind(small.encode(first, &mut out)), ErrorKind::TooLarge);
+
+        let mut buf = BytesMut::from(&b"\x04\0\0\0"[..]);
+        assert_eq!(raw_kind(codec.decode(&mut buf)), ErrorKind::BadLength);
+        let mut buf = BytesMut::from(&b"\x06\0\0\0\0\x01"[..]);
+        assert_eq!(raw_kind(codec.decode(&mut buf)), ErrorKind::MissingNul);
+    }
+}
@@ -0,0 +1,1356 @@
+//! Serde serialization directly into raw BSON bytes.
+//!
+//! The [`Serializer`] in this module writes BSON straight into a `Vec<u8>`,
+//! without building an intermediate [`bson::Document`].  Structs using the
+//! special names from the [`de`](crate::de) module (for example
+//! [`object_id::NAME`](crate::de::object_id::NAME)) are written as the
+//! corresponding BSON types, so values round-trip through
+//! [`de::from_doc`](crate::de::from_doc).
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use rawbson::{de::from_doc, ser::to_docbuf};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Book<'a> {
+//!     title: &'a str,
+//!     pages: i32,
+//! }
+//!
+//! let book = Book { title: "Moby-Dick", pages: 635 };
+//! let docbuf = to_docbuf(&book)?;
+//! assert_eq!(docbuf.get_str("title")?, Some("Moby-Dick"));
+//! assert_eq!(from_doc::<Book>(&docbuf)?, book);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::io::Write;
+use std::num::TryFromIntError;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use bson::{oid, spec::ElementType};
+
+use crate::de::{
+    binary, datetime, db_pointer, decimal128, js, marker, object_id, regex, symbol, timestamp,
+};
+use crate::{elem::RawDecimal128, Doc, DocBuf};
+
+mod raw;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The top level value was not a map or a struct.
+    NotADocument,
+    /// A map key was not a string.
+    InvalidKey,
+    /// A key or regular expression contained a NUL byte, which cannot be
+    /// represented in a BSON cstring.
+    InteriorNul,
+    /// One of the special bson structs was missing a field, or contained
+    /// a field of the wrong type.
+    MalformedSpecial(&'static str),
+    IntConversion(TryFromIntError),
+    Io(std::io::Error),
+    Custom(String),
+}
+
+impl From<TryFromIntError> for Error {
+    fn from(err: TryFromIntError) -> Error {
+        Error::IntConversion(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: std::fmt::Display>(err: T) -> Error {
+        Error::Custom(format!("{}", err))
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Serialize `value` to a `Vec<u8>` of BSON data.
+///
+/// The value must serialize as a map or a struct, since the top level of
+/// a BSON payload is always a document.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut serializer = Serializer::new();
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_vec())
+}
+
+/// Serialize `value` to a [`DocBuf`].
+///
+/// ```
+/// # use rawbson::ser::to_docbuf;
+/// use std::collections::BTreeMap;
+/// let mut map = BTreeMap::new();
+/// map.insert("hello", "world");
+/// let docbuf = to_docbuf(&map)?;
+/// assert_eq!(docbuf.get_str("hello")?, Some("world"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn to_docbuf<T: Serialize + ?Sized>(value: &T) -> Result<DocBuf> {
+    let bytes = to_vec(value)?;
+    // SAFETY: The serializer always writes a correct length prefix and
+    // trailing NUL for the top level document.
+    Ok(unsafe { DocBuf::new_unchecked(bytes) })
+}
+
+/// Serialize `value` as BSON into the provided writer.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(mut writer: W, value: &T) -> Result<()> {
+    let bytes = to_vec(value)?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// A serde Serializer that writes BSON bytes into an internal buffer.
+///
+/// Each element's type byte is written as a placeholder along with its key,
+/// and filled in once the value has been serialized.
+pub struct Serializer {
+    bytes: Vec<u8>,
+    type_index: usize,
+}
+
+impl Serializer {
+    pub fn new() -> Serializer {
+        Serializer {
+            bytes: Vec::new(),
+            type_index: 0,
+        }
+    }
+
+    /// Return the serialized bytes.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn update_element_type(&mut self, element_type: ElementType) -> Result<()> {
+        if self.type_index == 0 {
+            // Nothing has been written yet, so we are at the top level.
+            if let ElementType::EmbeddedDocument = element_type {
+                Ok(())
+            } else {
+                Err(Error::NotADocument)
+            }
+        } else {
+            self.bytes[self.type_index] = element_type as u8;
+            Ok(())
+        }
+    }
+
+    fn write_cstring(&mut self, s: &str) -> Result<()> {
+        write_cstring(&mut self.bytes, s)
+    }
+
+    fn write_string(&mut self, s: &str) -> Result<()> {
+        write_string(&mut self.bytes, s)
+    }
+
+    /// Write the placeholder type byte and key of a new element.
+    fn write_key(&mut self, key: &str) -> Result<()> {
+        self.type_index = self.bytes.len();
+        self.bytes.push(0);
+        self.write_cstring(key)
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Serializer {
+        Serializer::new()
+    }
+}
+
+fn write_cstring(bytes: &mut Vec<u8>, s: &str) -> Result<()> {
+    if s.as_bytes().contains(&0) {
+        return Err(Error::InteriorNul);
+    }
+    bytes.extend_from_slice(s.as_bytes());
+    bytes.push(0);
+    Ok(())
+}
+
+fn write_string(bytes: &mut Vec<u8>, s: &str) -> Result<()> {
+    let length = i32::try_from(s.len() + 1)?;
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+    bytes.push(0);
+    Ok(())
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = DocumentSerializer<'a>;
+    type SerializeTuple = DocumentSerializer<'a>;
+    type SerializeTupleStruct = DocumentSerializer<'a>;
+    type SerializeTupleVariant = VariantSerializer<'a>;
+    type SerializeMap = DocumentSerializer<'a>;
+    type SerializeStruct = StructSerializer<'a>;
+    type SerializeStructVariant = VariantSerializer<'a>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.update_element_type(ElementType::Boolean)?;
+        self.bytes.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.update_element_type(ElementType::Int32)?;
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.update_element_type(ElementType::Int64)?;
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    /// 128-bit integers are written as decimal128 values, which hold up to
+    /// 34 significant digits.
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        let value = RawDecimal128::try_from(v).map_err(|err| Error::Custom(err.to_string()))?;
+        self.update_element_type(ElementType::Decimal128)?;
+        self.bytes.extend_from_slice(&value.bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.serialize_i64(i64::try_from(v)?)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.serialize_i128(i128::try_from(v)?)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.update_element_type(ElementType::Double)?;
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.update_element_type(ElementType::String)?;
+        self.write_string(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        let length = i32::try_from(v.len())?;
+        self.update_element_type(ElementType::Binary)?;
+        self.bytes.extend_from_slice(&length.to_le_bytes());
+        self.bytes.push(0); // BinarySubtype::Generic
+        self.bytes.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.update_element_type(ElementType::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        let mut doc = DocumentSerializer::start(self, ElementType::EmbeddedDocument)?;
+        ser::SerializeStruct::serialize_field(&mut doc, variant, value)?;
+        ser::SerializeStruct::end(doc)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<DocumentSerializer<'a>> {
+        DocumentSerializer::start(self, ElementType::Array)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<DocumentSerializer<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<DocumentSerializer<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<VariantSerializer<'a>> {
+        VariantSerializer::start(self, variant, ElementType::Array)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<DocumentSerializer<'a>> {
+        DocumentSerializer::start(self, ElementType::EmbeddedDocument)
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<StructSerializer<'a>> {
+        let special = if name == object_id::NAME {
+            Some(SpecialKind::ObjectId)
+        } else if name == datetime::NAME {
+            Some(SpecialKind::DateTime)
+        } else if name == binary::NAME {
+            Some(SpecialKind::Binary)
+        } else if name == regex::NAME {
+            Some(SpecialKind::Regex)
+        } else if name == js::NAME {
+            Some(SpecialKind::JavaScript)
+        } else if name == js::WITH_SCOPE_NAME {
+            Some(SpecialKind::JavaScriptWithScope)
+        } else if name == decimal128::NAME {
+            Some(SpecialKind::Decimal128)
+        } else if name == db_pointer::NAME {
+            Some(SpecialKind::DbPointer)
+        } else if name == timestamp::NAME {
+            Some(SpecialKind::Timestamp)
+        } else if name == symbol::NAME {
+            Some(SpecialKind::Symbol)
+        } else if name == marker::UNDEFINED_NAME {
+            Some(SpecialKind::Undefined)
+        } else if name == marker::MIN_KEY_NAME {
+            Some(SpecialKind::MinKey)
+        } else if name == marker::MAX_KEY_NAME {
+            Some(SpecialKind::MaxKey)
+        } else {
+            None
+        };
+        match special {
+            Some(kind) => Ok(StructSerializer::Special(SpecialSerializer::new(self, kind))),
+            None => DocumentSerializer::start(self, ElementType::EmbeddedDocument)
+                .map(StructSerializer::Document),
+        }
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<VariantSerializer<'a>> {
+        VariantSerializer::start(self, variant, ElementType::EmbeddedDocument)
+    }
+}
+
+/// Serializes the elements of an embedded document or array.
+pub struct DocumentSerializer<'a> {
+    root: &'a mut Serializer,
+    start: usize,
+    index: usize,
+}
+
+impl<'a> DocumentSerializer<'a> {
+    fn start(root: &'a mut Serializer, element_type: ElementType) -> Result<Self> {
+        root.update_element_type(element_type)?;
+        let start = root.bytes.len();
+        root.bytes.extend_from_slice(&[0; 4]);
+        Ok(DocumentSerializer {
+            root,
+            start,
+            index: 0,
+        })
+    }
+
+    fn serialize_array_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.index.to_string();
+        self.root.write_key(&key)?;
+        self.index += 1;
+        value.serialize(&mut *self.root)
+    }
+
+    fn end_document(self) -> Result<()> {
+        self.root.bytes.push(0);
+        let length = i32::try_from(self.root.bytes.len() - self.start)?;
+        self.root.bytes[self.start..self.start + 4].copy_from_slice(&length.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeSeq for DocumentSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.serialize_array_element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_document()
+    }
+}
+
+impl<'a> ser::SerializeTuple for DocumentSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.serialize_array_element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_document()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for DocumentSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.serialize_array_element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_document()
+    }
+}
+
+impl<'a> ser::SerializeMap for DocumentSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        key.serialize(KeySerializer { root: self.root })
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut *self.root)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_document()
+    }
+}
+
+impl<'a> ser::SerializeStruct for DocumentSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.root.write_key(key)?;
+        value.serialize(&mut *self.root)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_document()
+    }
+}
+
+/// Serializes an enum variant as a single-key document, `{variant: value}`.
+pub struct VariantSerializer<'a> {
+    outer_start: usize,
+    inner: DocumentSerializer<'a>,
+}
+
+impl<'a> VariantSerializer<'a> {
+    fn start(root: &'a mut Serializer, variant: &str, inner_type: ElementType) -> Result<Self> {
+        root.update_element_type(ElementType::EmbeddedDocument)?;
+        let outer_start = root.bytes.len();
+        root.bytes.extend_from_slice(&[0; 4]);
+        root.write_key(variant)?;
+        let inner = DocumentSerializer::start(root, inner_type)?;
+        Ok(VariantSerializer { outer_start, inner })
+    }
+
+    fn end_variant(self) -> Result<()> {
+        let outer_start = self.outer_start;
+        let root = &mut *self.inner.root;
+        root.bytes.push(0);
+        let length = i32::try_from(root.bytes.len() - self.inner.start)?;
+        root.bytes[self.inner.start..self.inner.start + 4].copy_from_slice(&length.to_le_bytes());
+        root.bytes.push(0);
+        let length = i32::try_from(root.bytes.len() - outer_start)?;
+        root.bytes[outer_start..outer_start + 4].copy_from_slice(&length.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for VariantSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.inner.serialize_array_element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_variant()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for VariantSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_variant()
+    }
+}
+
+/// Serializes a struct, either as an embedded document, or as one of the
+/// special bson types named in the [`de`](crate::de) module.
+pub enum StructSerializer<'a> {
+    Document(DocumentSerializer<'a>),
+    Special(SpecialSerializer<'a>),
+}
+
+impl<'a> ser::SerializeStruct for StructSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        match self {
+            StructSerializer::Document(doc) => {
+                ser::SerializeStruct::serialize_field(doc, key, value)
+            }
+            StructSerializer::Special(special) => special.serialize_field(key, value),
+        }
+    }
+
+    fn end(self) -> Result<()> {
+        match self {
+            StructSerializer::Document(doc) => doc.end_document(),
+            StructSerializer::Special(special) => special.end(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SpecialKind {
+    ObjectId,
+    DateTime,
+    Binary,
+    Regex,
+    JavaScript,
+    JavaScriptWithScope,
+    Decimal128,
+    DbPointer,
+    Timestamp,
+    Symbol,
+    Undefined,
+    MinKey,
+    MaxKey,
+}
+
+/// Collects the fields of a special bson struct, and writes the
+/// corresponding element when the struct is complete.
+pub struct SpecialSerializer<'a> {
+    root: &'a mut Serializer,
+    kind: SpecialKind,
+    first: Option<Captured>,
+    second: Option<Captured>,
+}
+
+impl<'a> SpecialSerializer<'a> {
+    fn new(root: &'a mut Serializer, kind: SpecialKind) -> SpecialSerializer<'a> {
+        SpecialSerializer {
+            root,
+            kind,
+            first: None,
+            second: None,
+        }
+    }
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        let (slot, captured) = match self.kind {
+            SpecialKind::ObjectId if key == object_id::FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::DateTime if key == datetime::FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::Binary if key == binary::SUBTYPE_FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::Binary if key == binary::DATA_FIELD => {
+                (&mut self.second, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::Regex if key == regex::REGEXP_FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::Regex if key == regex::OPTIONS_FIELD => {
+                (&mut self.second, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::JavaScript | SpecialKind::JavaScriptWithScope
+                if key == js::DATA_FIELD =>
+            {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::JavaScriptWithScope if key == js::SCOPE_FIELD => {
+                (&mut self.second, Captured::Document(to_vec(value)?))
+            }
+            SpecialKind::Decimal128 if key == decimal128::FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::DbPointer if key == db_pointer::FIELD => {
+                (&mut self.first, Captured::Document(to_vec(value)?))
+            }
+            SpecialKind::Timestamp if key == timestamp::FIELD => {
+                (&mut self.first, Captured::Document(to_vec(value)?))
+            }
+            SpecialKind::Symbol if key == symbol::FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::Undefined if key == marker::UNDEFINED_FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::MinKey if key == marker::MIN_KEY_FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            SpecialKind::MaxKey if key == marker::MAX_KEY_FIELD => {
+                (&mut self.first, value.serialize(CaptureSerializer)?)
+            }
+            _ => return Err(Error::MalformedSpecial("unexpected field")),
+        };
+        *slot = Some(captured);
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        let root = self.root;
+        match (self.kind, self.first, self.second) {
+            (SpecialKind::ObjectId, Some(Captured::Str(hex)), None) => {
+                let oid = oid::ObjectId::with_string(&hex)
+                    .map_err(|_| Error::MalformedSpecial("invalid object id"))?;
+                root.update_element_type(ElementType::ObjectId)?;
+                root.bytes.extend_from_slice(&oid.bytes());
+            }
+            (SpecialKind::ObjectId, Some(Captured::Bytes(bytes)), None) if bytes.len() == 12 => {
+                root.update_element_type(ElementType::ObjectId)?;
+                root.bytes.extend_from_slice(&bytes);
+            }
+            (SpecialKind::DateTime, Some(Captured::Int(millis)), None) => {
+                root.update_element_type(ElementType::DateTime)?;
+                root.bytes.extend_from_slice(&millis.to_le_bytes());
+            }
+            (SpecialKind::Binary, Some(Captured::Int(subtype)), Some(Captured::Bytes(data))) => {
+                let subtype = u8::try_from(subtype)?;
+                root.update_element_type(ElementType::Binary)?;
+                root.bytes
+                    .extend_from_slice(&i32::try_from(data.len())?.to_le_bytes());
+                root.bytes.push(subtype);
+                root.bytes.extend_from_slice(&data);
+            }
+            (SpecialKind::Regex, Some(Captured::Str(pattern)), Some(Captured::Str(options))) => {
+                root.update_element_type(ElementType::RegularExpression)?;
+                root.write_cstring(&pattern)?;
+                root.write_cstring(&options)?;
+            }
+            (SpecialKind::JavaScript, Some(Captured::Str(code)), None) => {
+                root.update_element_type(ElementType::JavaScriptCode)?;
+                root.write_string(&code)?;
+            }
+            (
+                SpecialKind::JavaScriptWithScope,
+                Some(Captured::Str(code)),
+                Some(Captured::Document(scope)),
+            ) => {
+                root.update_element_type(ElementType::JavaScriptCodeWithScope)?;
+                let length = i32::try_from(4 + 4 + code.len() + 1 + scope.len())?;
+                root.bytes.extend_from_slice(&length.to_le_bytes());
+                root.write_string(&code)?;
+                root.bytes.extend_from_slice(&scope);
+            }
+            (SpecialKind::Decimal128, Some(Captured::Str(value)), None) => {
+                let value: RawDecimal128 = value
+                    .parse()
+                    .map_err(|_| Error::MalformedSpecial("invalid decimal128"))?;
+                root.update_element_type(ElementType::Decimal128)?;
+                root.bytes.extend_from_slice(&value.bytes());
+            }
+            (SpecialKind::Decimal128, Some(Captured::Bytes(bytes)), None) if bytes.len() == 16 => {
+                root.update_element_type(ElementType::Decimal128)?;
+                root.bytes.extend_from_slice(&bytes);
+            }
+            (SpecialKind::DbPointer, Some(Captured::Document(body)), None) => {
+                let body =
+                    Doc::new(&body).map_err(|_| Error::MalformedSpecial("invalid dbpointer"))?;
+                let namespace = body.get_str(db_pointer::REF_FIELD).ok().flatten();
+                let id = body.get_object_id(db_pointer::ID_FIELD).ok().flatten();
+                match (namespace, id) {
+                    (Some(namespace), Some(id)) => {
+                        root.update_element_type(ElementType::DbPointer)?;
+                        root.write_string(namespace)?;
+                        root.bytes.extend_from_slice(&id.bytes());
+                    }
+                    _ => return Err(Error::MalformedSpecial("dbpointer")),
+                }
+            }
+            (SpecialKind::Timestamp, Some(Captured::Document(body)), None) => {
+                let body =
+                    Doc::new(&body).map_err(|_| Error::MalformedSpecial("invalid timestamp"))?;
+                let field = |key| -> Result<u32> {
+                    let value = match body.get(key) {
+                        Ok(Some(value)) => value,
+                        _ => return Err(Error::MalformedSpecial("timestamp")),
+                    };
+                    match value.element_type() {
+                        ElementType::Int32 => value.as_i32().ok().map(i64::from),
+                        ElementType::Int64 => value.as_i64().ok(),
+                        _ => None,
+                    }
+                    .ok_or(Error::MalformedSpecial("timestamp"))
+                    .and_then(|value| Ok(u32::try_from(value)?))
+                };
+                let time = field(timestamp::TIME_FIELD)?;
+                let increment = field(timestamp::INCREMENT_FIELD)?;
+                root.update_element_type(ElementType::Timestamp)?;
+                root.bytes.extend_from_slice(&increment.to_le_bytes());
+                root.bytes.extend_from_slice(&time.to_le_bytes());
+            }
+            (SpecialKind::Symbol, Some(Captured::Str(symbol)), None) => {
+                root.update_element_type(ElementType::Symbol)?;
+                root.write_string(&symbol)?;
+            }
+            (SpecialKind::Undefined, Some(Captured::Bool(true)), None) => {
+                root.update_element_type(ElementType::Undefined)?;
+            }
+            (SpecialKind::MinKey, Some(Captured::Int(1)), None) => {
+                root.update_element_type(ElementType::MinKey)?;
+            }
+            (SpecialKind::MaxKey, Some(Captured::Int(1)), None) => {
+                root.update_element_type(ElementType::MaxKey)?;
+            }
+            (kind, _, _) => {
+                return Err(Error::MalformedSpecial(match kind {
+                    SpecialKind::ObjectId => "object id",
+                    SpecialKind::DateTime => "datetime",
+                    SpecialKind::Binary => "binary",
+                    SpecialKind::Regex => "regex",
+                    SpecialKind::JavaScript => "javascript",
+                    SpecialKind::JavaScriptWithScope => "javascript with scope",
+                    SpecialKind::Decimal128 => "decimal128",
+                    SpecialKind::DbPointer => "dbpointer",
+                    SpecialKind::Timestamp => "timestamp",
+                    SpecialKind::Symbol => "symbol",
+                    SpecialKind::Undefined => "undefined",
+                    SpecialKind::MinKey => "min key",
+                    SpecialKind::MaxKey => "max key",
+                }))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes map keys directly into the output buffer.
+struct KeySerializer<'a> {
+    root: &'a mut Serializer,
+}
+
+macro_rules! invalid_key {
+    ($($method:ident($($arg:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<()> {
+                Err(Error::InvalidKey)
+            }
+        )*
+    };
+}
+
+impl<'a> ser::Serializer for KeySerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.root.write_key(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    invalid_key! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<()> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::InvalidKey)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::InvalidKey)
+    }
+}
+
+/// A field value of a special bson struct.
+enum Captured {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Document(Vec<u8>),
+}
+
+/// Captures the scalar fields of special bson structs.
+struct CaptureSerializer;
+
+macro_rules! capture_int {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<Captured> {
+                Ok(Captured::Int(v.into()))
+            }
+        )*
+    };
+}
+
+macro_rules! not_captured {
+    ($($method:ident($($arg:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Captured> {
+                Err(Error::MalformedSpecial("unsupported field type"))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for CaptureSerializer {
+    type Ok = Captured;
+    type Error = Error;
+
+    type SerializeSeq = ByteSeqSerializer;
+    type SerializeTuple = Impossible<Captured, Error>;
+    type SerializeTupleStruct = Impossible<Captured, Error>;
+    type SerializeTupleVariant = Impossible<Captured, Error>;
+    type SerializeMap = Impossible<Captured, Error>;
+    type SerializeStruct = Impossible<Captured, Error>;
+    type SerializeStructVariant = Impossible<Captured, Error>;
+
+    capture_int! {
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Captured> {
+        Ok(Captured::Int(i64::try_from(v)?))
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Captured> {
+        Ok(Captured::Bool(v))
+    }
+
+    not_captured! {
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Captured> {
+        Ok(Captured::Str(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Captured> {
+        Ok(Captured::Bytes(v.to_vec()))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Captured> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Captured> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Captured> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ByteSeqSerializer> {
+        Ok(ByteSeqSerializer {
+            bytes: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::MalformedSpecial("unsupported field type"))
+    }
+}
+
+/// Collects a sequence of integers, such as a `Vec<u8>`, as binary data.
+struct ByteSeqSerializer {
+    bytes: Vec<u8>,
+}
+
+impl ser::SerializeSeq for ByteSeqSerializer {
+    type Ok = Captured;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        match value.serialize(CaptureSerializer)? {
+            Captured::Int(byte) => {
+                self.bytes.push(u8::try_from(byte)?);
+                Ok(())
+            }
+            _ => Err(Error::MalformedSpecial("expected a sequence of bytes")),
+        }
+    }
+
+    fn end(self) -> Result<Captured> {
+        Ok(Captured::Bytes(self.bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope};
+    use serde::{Deserialize, Serialize};
+
+    use super::{to_docbuf, to_vec, Error};
+    use crate::de::{binary, datetime, from_doc, js, object_id, regex};
+    use crate::DocBuf;
+
+    fn to_bytes(doc: &bson::Document) -> Vec<u8> {
+        let mut docbytes = Vec::new();
+        doc.to_writer(&mut docbytes).unwrap();
+        docbytes
+    }
+
+    #[test]
+    fn serialize_struct_matches_bson() {
+        #[derive(Serialize)]
+        struct Person<'a> {
+            first_name: &'a str,
+            middle_name: Option<String>,
+            age: i32,
+            height: f64,
+            aliases: Vec<&'a str>,
+            has_cookies: bool,
+            lucky_number: i64,
+            nested: BTreeMap<&'a str, u32>,
+        }
+
+        let mut nested = BTreeMap::new();
+        nested.insert("count", 7);
+        let person = Person {
+            first_name: "Edward",
+            middle_name: None,
+            age: 38,
+            height: 1.85,
+            aliases: vec!["Blackbeard", "Thatch"],
+            has_cookies: true,
+            lucky_number: 1 << 40,
+            nested,
+        };
+        let expected = to_bytes(&doc! {
+            "first_name": "Edward",
+            "middle_name": Bson::Null,
+            "age": 38,
+            "height": 1.85,
+            "aliases": ["Blackbeard", "Thatch"],
+            "has_cookies": true,
+            "lucky_number": 1i64 << 40,
+            "nested": { "count": 7i64 },
+        });
+        assert_eq!(to_vec(&person).expect("serialize person"), expected);
+    }
+
+    #[test]
+    fn roundtrip_special_structs() {
+        #[derive(Serialize)]
+        struct Special<'a> {
+            oid: OidStruct,
+            date: DateStruct,
+            bin: BinaryStruct<'a>,
+            regex: RegexStruct<'a>,
+            code: JsStruct<'a>,
+            scoped: ScopedStruct<'a>,
+        }
+
+        struct OidStruct(String);
+        impl Serialize for OidStruct {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = s.serialize_struct(object_id::NAME, 1)?;
+                state.serialize_field(object_id::FIELD, &self.0)?;
+                state.end()
+            }
+        }
+
+        struct DateStruct(i64);
+        impl Serialize for DateStruct {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = s.serialize_struct(datetime::NAME, 1)?;
+                state.serialize_field(datetime::FIELD, &self.0)?;
+                state.end()
+            }
+        }
+
+        struct BinaryStruct<'a>(u8, &'a [u8]);
+        impl<'a> Serialize for BinaryStruct<'a> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = s.serialize_struct(binary::NAME, 2)?;
+                state.serialize_field(binary::SUBTYPE_FIELD, &self.0)?;
+                state.serialize_field(binary::DATA_FIELD, self.1)?;
+                state.end()
+            }
+        }
+
+        struct RegexStruct<'a>(&'a str, &'a str);
+        impl<'a> Serialize for RegexStruct<'a> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = s.serialize_struct(regex::NAME, 2)?;
+                state.serialize_field(regex::REGEXP_FIELD, self.0)?;
+                state.serialize_field(regex::OPTIONS_FIELD, self.1)?;
+                state.end()
+            }
+        }
+
+        struct JsStruct<'a>(&'a str);
+        impl<'a> Serialize for JsStruct<'a> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = s.serialize_struct(js::NAME, 1)?;
+                state.serialize_field(js::DATA_FIELD, self.0)?;
+                state.end()
+            }
+        }
+
+        struct ScopedStruct<'a>(&'a str, BTreeMap<&'a str, &'a str>);
+        impl<'a> Serialize for ScopedStruct<'a> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = s.serialize_struct(js::WITH_SCOPE_NAME, 2)?;
+                state.serialize_field(js::DATA_FIELD, self.0)?;
+                state.serialize_field(js::SCOPE_FIELD, &self.1)?;
+                state.end()
+            }
+        }
+
+        let mut scope = BTreeMap::new();
+        scope.insert("value", "Hello world");
+        let value = Special {
+            oid: OidStruct(String::from("abcdefabcdefabcdefabcdef")),
+            date: DateStruct(1_600_000_000_000),
+            bin: BinaryStruct(4, b"0123456789abcdef"),
+            regex: RegexStruct("^_id$", "i"),
+            code: JsStruct("console.log(1);"),
+            scoped: ScopedStruct("console.log(value);", scope),
+        };
+        let docbuf = to_docbuf(&value).expect("serialize special structs");
+
+        let expected = DocBuf::from_document(&doc! {
+            "oid": ObjectId::with_string("abcdefabcdefabcdefabcdef").unwrap(),
+            "date": Bson::DateTime(chrono::TimeZone::timestamp_millis_opt(&chrono::Utc, 1_600_000_000_000).unwrap()),
+            "bin": Binary { subtype: BinarySubtype::Uuid, bytes: b"0123456789abcdef".to_vec() },
+            "regex": bson::Regex { pattern: String::from("^_id$"), options: String::from("i") },
+            "code": Bson::JavaScriptCode(String::from("console.log(1);")),
+            "scoped": JavaScriptCodeWithScope {
+                code: String::from("console.log(value);"),
+                scope: doc! { "value": "Hello world" },
+            },
+        });
+        assert_eq!(docbuf.as_bytes(), expected.as_bytes());
+
+        #[derive(Deserialize)]
+        struct Roundtrip<'a> {
+            oid: ObjectId,
+            #[serde(borrow)]
+            regex: (&'a str, &'a str),
+            scoped: (&'a str, BTreeMap<&'a str, &'a str>),
+        }
+        let roundtrip: Roundtrip = from_doc(&docbuf).expect("deserialize special structs");
+        assert_eq!(roundtrip.oid.to_hex(), "abcdefabcdefabcdefabcdef");
+        assert_eq!(roundtrip.regex, ("^_id$", "i"));
+        assert_eq!(roundtrip.scoped.0, "console.log(value);");
+        assert_eq!(roundtrip.scoped.1.get("value"), Some(&"Hello world"));
+    }
+
+    #[test]
+    fn serialize_enums() {
+        #[derive(Serialize, Debug)]
+        enum Shape {
+            Point,
+            Circle(f64),
+            Line(i32, i32),
+            Rect { w: i32, h: i32 },
+        }
+
+        let value = vec![
+            Shape::Point,
+            Shape::Circle(1.5),
+            Shape::Line(1, 2),
+            Shape::Rect { w: 3, h: 4 },
+        ];
+        let mut map = BTreeMap::new();
+        map.insert("shapes", value);
+        let expected = to_bytes(&doc! {
+            "shapes": [
+                "Point",
+                { "Circle": 1.5 },
+                { "Line": [1, 2] },
+                { "Rect": { "w": 3, "h": 4 } },
+            ],
+        });
+        assert_eq!(to_vec(&map).expect("serialize enums"), expected);
+    }
+
+    #[test]
+    fn top_level_must_be_document() {
+        assert!(matches!(to_vec(&5i32), Err(Error::NotADocument)));
+        assert!(matches!(to_vec(&vec![1, 2, 3]), Err(Error::NotADocument)));
+        assert!(matches!(to_vec(&"hello"), Err(Error::NotADocument)));
+    }
+
+    #[test]
+    fn invalid_keys() {
+        let mut map = BTreeMap::new();
+        map.insert(1, "one");
+        assert!(matches!(to_vec(&map), Err(Error::InvalidKey)));
+
+        let mut map = BTreeMap::new();
+        map.insert("nul\0key", "value");
+        assert!(matches!(to_vec(&map), Err(Error::InteriorNul)));
+    }
+}
@@ -0,0 +1,404 @@
+//! `Serialize` implementations for the raw document types.
+//!
+//! Documents serialize as maps and arrays as sequences, read straight from
+//! the underlying bytes.  For human-readable formats such as JSON, values
+//! without a native serde equivalent are written the way the `bson` crate
+//! writes them: as extended JSON documents like `{"$oid": "..."}`.  Other
+//! formats, including this crate's own [`Serializer`](super::Serializer),
+//! receive the special structs from the [`de`](crate::de) module, so the
+//! values round-trip back into the same BSON types.
+
+use serde::ser::{Error, Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer};
+
+use bson::spec::ElementType;
+
+use crate::de::{
+    binary, datetime, db_pointer, decimal128, js, marker, object_id, regex, symbol, timestamp,
+};
+use crate::{
+    elem::{Element, RawBsonTimestamp, RawDbPointer, RawDecimal128},
+    Array, Doc, DocBuf,
+};
+
+impl Serialize for Doc {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for result in self {
+            let (key, value) = result.map_err(S::Error::custom)?;
+            map.serialize_entry(key, &value)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for DocBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        AsRef::<Doc>::as_ref(self).serialize(serializer)
+    }
+}
+
+impl Serialize for Array {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        for result in self {
+            seq.serialize_element(&result.map_err(S::Error::custom)?)?;
+        }
+        seq.end()
+    }
+}
+
+/// Serializes a byte slice with `serialize_bytes`, rather than as a sequence.
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl Serialize for RawDecimal128 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(decimal128::FIELD, &self.to_string())?;
+            map.end()
+        } else {
+            let mut state = serializer.serialize_struct(decimal128::NAME, 1)?;
+            state.serialize_field(decimal128::FIELD, &Bytes(&self.bytes()))?;
+            state.end()
+        }
+    }
+}
+
+/// Serializes an object id as `{"$oid": "..."}`, or as the object id special
+/// struct for formats that are not human-readable.
+struct ObjectIdValue<'a>(&'a bson::oid::ObjectId);
+
+impl Serialize for ObjectIdValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(object_id::FIELD, &self.0.to_hex())?;
+            map.end()
+        } else {
+            let mut state = serializer.serialize_struct(object_id::NAME, 1)?;
+            state.serialize_field(object_id::FIELD, &Bytes(&self.0.bytes()))?;
+            state.end()
+        }
+    }
+}
+
+/// The `{"$ref": ..., "$id": ...}` map inside a DBPointer.
+struct DbPointerBody<'a, 'b>(&'b RawDbPointer<'a>);
+
+impl Serialize for DbPointerBody<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry(db_pointer::REF_FIELD, self.0.namespace())?;
+        map.serialize_entry(db_pointer::ID_FIELD, &ObjectIdValue(self.0.id()))?;
+        map.end()
+    }
+}
+
+impl Serialize for RawDbPointer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct(db_pointer::NAME, 1)?;
+        state.serialize_field(db_pointer::FIELD, &DbPointerBody(self))?;
+        state.end()
+    }
+}
+
+/// The `{"t": ..., "i": ...}` map inside a Timestamp.
+struct TimestampBody<'a>(RawBsonTimestamp<'a>);
+
+impl Serialize for TimestampBody<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry(timestamp::TIME_FIELD, &self.0.time())?;
+        map.serialize_entry(timestamp::INCREMENT_FIELD, &self.0.increment())?;
+        map.end()
+    }
+}
+
+impl Serialize for Element<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.element_type() {
+            ElementType::Double => {
+                return serializer.serialize_f64(self.as_f64().map_err(S::Error::custom)?)
+            }
+            ElementType::String => {
+                return serializer.serialize_str(self.as_str().map_err(S::Error::custom)?)
+            }
+            ElementType::EmbeddedDocument => {
+                return self
+                    .as_document()
+                    .map_err(S::Error::custom)?
+                    .serialize(serializer)
+            }
+            ElementType::Array => {
+                return self
+                    .as_array()
+                    .map_err(S::Error::custom)?
+                    .serialize(serializer)
+            }
+            ElementType::Boolean => {
+                return serializer.serialize_bool(self.as_bool().map_err(S::Error::custom)?)
+            }
+            ElementType::Null => return serializer.serialize_unit(),
+            ElementType::Decimal128 => {
+                return self
+                    .as_decimal128()
+                    .map_err(S::Error::custom)?
+                    .serialize(serializer)
+            }
+            ElementType::Int32 => {
+                return serializer.serialize_i32(self.as_i32().map_err(S::Error::custom)?)
+            }
+            ElementType::Int64 => {
+                return serializer.serialize_i64(self.as_i64().map_err(S::Error::custom)?)
+            }
+            ElementType::DbPointer => {
+                return self
+                    .as_db_pointer()
+                    .map_err(S::Error::custom)?
+                    .serialize(serializer)
+            }
+            ElementType::Undefined => {
+                let mut state = serializer.serialize_struct(marker::UNDEFINED_NAME, 1)?;
+                state.serialize_field(marker::UNDEFINED_FIELD, &true)?;
+                return state.end();
+            }
+            ElementType::MinKey => {
+                let mut state = serializer.serialize_struct(marker::MIN_KEY_NAME, 1)?;
+                state.serialize_field(marker::MIN_KEY_FIELD, &1)?;
+                return state.end();
+            }
+            ElementType::MaxKey => {
+                let mut state = serializer.serialize_struct(marker::MAX_KEY_NAME, 1)?;
+                state.serialize_field(marker::MAX_KEY_FIELD, &1)?;
+                return state.end();
+            }
+            ElementType::JavaScriptCodeWithScope => {
+                let (code, scope) = self.as_javascript_with_scope().map_err(S::Error::custom)?;
+                return if serializer.is_human_readable() {
+                    let mut map = serializer.serialize_map(Some(2))?;
+                    map.serialize_entry("$code", code)?;
+                    map.serialize_entry("$scope", scope)?;
+                    map.end()
+                } else {
+                    let mut state = serializer.serialize_struct(js::WITH_SCOPE_NAME, 2)?;
+                    state.serialize_field(js::DATA_FIELD, code)?;
+                    state.serialize_field(js::SCOPE_FIELD, scope)?;
+                    state.end()
+                };
+            }
+            _ => {}
+        }
+
+        if !serializer.is_human_readable() {
+            match self.element_type() {
+                ElementType::ObjectId => {
+                    let mut state = serializer.serialize_struct(object_id::NAME, 1)?;
+                    state.serialize_field(object_id::FIELD, &Bytes(self.as_bytes()))?;
+                    return state.end();
+                }
+                ElementType::DateTime => {
+                    let millis = self.datetime_millis().map_err(S::Error::custom)?;
+                    let mut state = serializer.serialize_struct(datetime::NAME, 1)?;
+                    state.serialize_field(datetime::FIELD, &millis)?;
+                    return state.end();
+                }
+                ElementType::Binary => {
+                    let binary = self.as_binary().map_err(S::Error::custom)?;
+                    let mut state = serializer.serialize_struct(binary::NAME, 2)?;
+                    state.serialize_field(binary::SUBTYPE_FIELD, &u8::from(binary.subtype()))?;
+                    state.serialize_field(binary::DATA_FIELD, &Bytes(binary.as_bytes()))?;
+                    return state.end();
+                }
+                ElementType::RegularExpression => {
+                    let regex = self.as_regex().map_err(S::Error::custom)?;
+                    let mut state = serializer.serialize_struct(regex::NAME, 2)?;
+                    state.serialize_field(regex::REGEXP_FIELD, regex.pattern())?;
+                    state.serialize_field(regex::OPTIONS_FIELD, regex.options())?;
+                    return state.end();
+                }
+                ElementType::JavaScriptCode => {
+                    let code = self.as_javascript().map_err(S::Error::custom)?;
+                    let mut state = serializer.serialize_struct(js::NAME, 1)?;
+                    state.serialize_field(js::DATA_FIELD, code)?;
+                    return state.end();
+                }
+                ElementType::Symbol => {
+                    let value = self.as_symbol().map_err(S::Error::custom)?;
+                    let mut state = serializer.serialize_struct(symbol::NAME, 1)?;
+                    state.serialize_field(symbol::FIELD, value)?;
+                    return state.end();
+                }
+                ElementType::Timestamp => {
+                    let ts = self.as_timestamp().map_err(S::Error::custom)?;
+                    let mut state = serializer.serialize_struct(timestamp::NAME, 1)?;
+                    state.serialize_field(timestamp::FIELD, &TimestampBody(ts))?;
+                    return state.end();
+                }
+                _ => {}
+            }
+        }
+
+        // Everything else is written the way the bson crate writes it.
+        let value = match self.element_type() {
+            ElementType::ObjectId => {
+                bson::Bson::ObjectId(self.as_object_id().map_err(S::Error::custom)?)
+            }
+            ElementType::DateTime => {
+                let millis = self.datetime_millis().map_err(S::Error::custom)?;
+                match self.as_datetime() {
+                    Ok(datetime) => bson::Bson::DateTime(datetime),
+                    // Beyond chrono's range, write the form the bson crate
+                    // uses for dates outside years 0 to 99999.
+                    Err(_) => bson::Bson::Document(
+                        bson::doc! {"$date": {"$numberLong": millis.to_string()}},
+                    ),
+                }
+            }
+            ElementType::Binary => {
+                let binary = self.as_binary().map_err(S::Error::custom)?;
+                bson::Bson::Binary(bson::Binary {
+                    subtype: binary.subtype(),
+                    bytes: binary.as_bytes().to_vec(),
+                })
+            }
+            ElementType::RegularExpression => {
+                let regex = self.as_regex().map_err(S::Error::custom)?;
+                bson::Bson::RegularExpression(bson::Regex {
+                    pattern: regex.pattern().into(),
+                    options: regex.options().into(),
+                })
+            }
+            ElementType::JavaScriptCode => {
+                bson::Bson::JavaScriptCode(self.as_javascript().map_err(S::Error::custom)?.into())
+            }
+            ElementType::Symbol => {
+                bson::Bson::Symbol(self.as_symbol().map_err(S::Error::custom)?.into())
+            }
+            ElementType::Timestamp => {
+                let ts = self.as_timestamp().map_err(S::Error::custom)?;
+                bson::Bson::Timestamp(bson::Timestamp {
+                    time: ts.time(),
+                    increment: ts.increment(),
+                })
+            }
+            ElementType::Double
+            | ElementType::String
+            | ElementType::EmbeddedDocument
+            | ElementType::Array
+            | ElementType::Boolean
+            | ElementType::Null
+            | ElementType::Decimal128
+            | ElementType::Int32
+            | ElementType::Int64
+            | ElementType::DbPointer
+            | ElementType::Undefined
+            | ElementType::MinKey
+            | ElementType::MaxKey
+            | ElementType::JavaScriptCodeWithScope => unreachable!("handled above"),
+        };
+        value.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope};
+
+    use crate::{elem::RawDbPointer, ser::to_docbuf, DocBuf};
+
+    fn sample() -> bson::Document {
+        doc! {
+            "f64": 2.5,
+            "str": "hello",
+            "doc": {"a": 1, "b": [true, null]},
+            "oid": ObjectId::with_string("abcdefabcdefabcdefabcdef").unwrap(),
+            "date": Bson::DateTime(chrono::TimeZone::timestamp_millis_opt(&chrono::Utc, 1_600_000_000_000).unwrap()),
+            "bin": Binary { subtype: BinarySubtype::Uuid, bytes: b"0123456789abcdef".to_vec() },
+            "regex": bson::Regex { pattern: String::from("^_id$"), options: String::from("i") },
+            "code": Bson::JavaScriptCode(String::from("f()")),
+            "scoped": JavaScriptCodeWithScope {
+                code: String::from("g(x)"),
+                scope: doc! { "x": 1 },
+            },
+            "i64": 1i64 << 40,
+            "ts": bson::Timestamp { time: 7, increment: 3 },
+            "max": Bson::MaxKey,
+            "min": Bson::MinKey,
+            "undefined": Bson::Undefined,
+            "pointer": Bson::from(RawDbPointer::new(
+                "db.coll",
+                ObjectId::with_string("abcdefabcdefabcdefabcdef").unwrap(),
+            )),
+        }
+    }
+
+    #[test]
+    fn matches_bson_json() {
+        let document = sample();
+        let docbuf = DocBuf::from_document(&document);
+        assert_eq!(
+            serde_json::to_value(&docbuf).unwrap(),
+            serde_json::to_value(&document).unwrap(),
+        );
+    }
+
+    #[test]
+    fn transcodes_to_bson() {
+        let document = sample();
+        let docbuf = DocBuf::from_document(&document);
+        assert_eq!(bson::to_document(&docbuf).unwrap(), document);
+    }
+
+    #[test]
+    fn roundtrips_through_raw_serializer() {
+        let document = doc! {
+            "nested": {"list": [1, "two", {"three": 3.0}]},
+            "oid": ObjectId::with_string("abcdefabcdefabcdefabcdef").unwrap(),
+            "date": Bson::DateTime(chrono::TimeZone::timestamp_millis_opt(&chrono::Utc, 1_600_000_000_000).unwrap()),
+            "bin": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
+            "regex": bson::Regex { pattern: String::from("a+"), options: String::from("m") },
+            "code": Bson::JavaScriptCode(String::from("f()")),
+            "scoped": JavaScriptCodeWithScope {
+                code: String::from("g(x)"),
+                scope: doc! { "x": 1 },
+            },
+            "legacy": [Bson::Undefined, Bson::MinKey, Bson::MaxKey],
+            "ts": bson::Timestamp { time: 7, increment: 3 },
+            "symbol": Bson::Symbol(String::from("sym")),
+            "pointer": Bson::from(RawDbPointer::new(
+                "db.coll",
+                ObjectId::with_string("abcdefabcdefabcdefabcdef").unwrap(),
+            )),
+        };
+        let docbuf = DocBuf::from_document(&document);
+        let copy = to_docbuf(&docbuf).unwrap();
+        assert_eq!(copy.as_bytes(), docbuf.as_bytes());
+
+        let array = docbuf.get_path_array("nested.list").unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_string(array).unwrap(),
+            r#"[1,"two",{"three":3.0}]"#
+        );
+    }
+
+    #[test]
+    fn datetimes_outside_chrono_range() {
+        let mut bytes = b"\x10\0\0\0\x09d\0".to_vec();
+        bytes.extend_from_slice(&i64::MAX.to_le_bytes());
+        bytes.push(0);
+        let docbuf = DocBuf::new(bytes).unwrap();
+
+        let copy = to_docbuf(&docbuf).unwrap();
+        assert_eq!(copy.as_bytes(), docbuf.as_bytes());
+        assert_eq!(
+            serde_json::to_string(&docbuf).unwrap(),
+            r#"{"d":{"$date":{"$numberLong":"9223372036854775807"}}}"#
+        );
+    }
+}
@@ -0,0 +1,778 @@
+//! Applying MongoDB update operators to a document in place.
+//!
+//! [`apply`] takes an update document such as
+//! `{"$set": {"a.b": 1}, "$inc": {"count": 1}}` and edits a [`DocBuf`] the
+//! way the server would edit the stored document.  The supported operators
+//! are:
+//!
+//! * `$set`, `$unset` and `$rename`;
+//! * `$inc` and `$mul`, following MongoDB's rules for numeric types: the
+//!   result is a decimal if either value is one, otherwise a double if
+//!   either is one, otherwise a 64-bit integer if either is one or if a
+//!   32-bit result would overflow.  Decimal results are rounded to 34
+//!   significant digits, as IEEE 754 decimal arithmetic does;
+//! * `$min` and `$max`, which compare values of any type in MongoDB's
+//!   order;
+//! * `$push`, with the `$each`, `$slice` and `$position` modifiers,
+//!   `$addToSet`, with `$each`, `$pull` and `$pop`;
+//! * `$currentDate`, with either `true` or `{"$type": "date"}` or
+//!   `{"$type": "timestamp"}`.
+//!
+//! Paths are dotted, as described in the [`path`](crate::path) module, and
+//! missing documents along them are created.  The positional operators
+//! `$` and `$[]` are not supported.
+//!
+//! ```
+//! # use std::convert::TryFrom;
+//! use bson::{doc, Document};
+//! use rawbson::{update, DocBuf};
+//!
+//! let mut docbuf = DocBuf::from_document(&doc! {"n": 1, "tags": ["a"], "old": true});
+//! update::apply(&mut docbuf, &DocBuf::from_document(&doc! {
+//!     "$inc": {"n": 2.5},
+//!     "$push": {"tags": {"$each": ["b", "c"], "$slice": -2}},
+//!     "$rename": {"old": "stats.flag"},
+//! }))?;
+//! assert_eq!(
+//!     Document::try_from(&*docbuf)?,
+//!     doc! {"n": 3.5, "tags": ["b", "c"], "stats": {"flag": true}},
+//! );
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use bson::spec::ElementType;
+use chrono::Utc;
+
+use crate::{
+    cmp::{try_compare, Number},
+    elem::{Element, RawBson, RawBsonRef, RawDecimal128},
+    offset_in,
+    query::ElemMatch,
+    Doc, DocBuf, ErrorKind, RawError, RawResult,
+};
+
+type Operator = fn(&mut DocBuf, &str, Element<'_>) -> RawResult<()>;
+
+/// Apply the operators in `update` to `doc`.
+///
+/// The operators are applied in the order they appear, and either all of
+/// them succeed or `doc` is left unchanged.
+///
+/// Errors name the path being updated in their
+/// [`key_path`](RawError::key_path).  An operator value with the wrong type
+/// fails with [`ErrorKind::BadOperand`], and a numeric operator applied to
+/// a value that is not a number, or an array operator applied to a value
+/// that is not an array, fails with [`ErrorKind::UnexpectedType`].  Integer
+/// arithmetic that overflows a 64-bit integer fails with
+/// [`ErrorKind::OutOfRange`].  Updating a field twice, or a field and a
+/// path through it, fails with [`ErrorKind::ConflictingUpdate`].
+pub fn apply(doc: &mut DocBuf, update: &Doc) -> RawResult<()> {
+    let mut updated = doc.clone();
+    let mut paths = Vec::new();
+    for result in update {
+        let (name, fields) = result?;
+        let context = |err: RawError| {
+            err.within(
+                name,
+                offset_in(update.as_bytes(), fields.as_bytes()),
+                fields.element_type(),
+            )
+        };
+        let operator: Operator = match name {
+            "$set" => set,
+            "$unset" => unset,
+            "$rename" => rename,
+            "$inc" => inc,
+            "$mul" => mul,
+            "$min" => |doc, path, operand| min_max(doc, path, operand, Ordering::Less),
+            "$max" => |doc, path, operand| min_max(doc, path, operand, Ordering::Greater),
+            "$push" => push,
+            "$addToSet" => add_to_set,
+            "$pull" => pull,
+            "$pop" => pop,
+            "$currentDate" => current_date,
+            _ => {
+                return Err(context(RawError::new(ErrorKind::UnknownOperator(
+                    name.into(),
+                ))))
+            }
+        };
+        if fields.element_type() != ElementType::EmbeddedDocument {
+            return Err(context(bad_operand(name)));
+        }
+        for result in fields.as_document()? {
+            let (path, operand) = result?;
+            let at_path = |mut err: RawError| {
+                err.context.key_path = path.split('.').map(String::from).collect();
+                err
+            };
+            claim(&mut paths, path).map_err(at_path)?;
+            if name == "$rename" && operand.element_type() == ElementType::String {
+                claim(&mut paths, operand.as_str()?).map_err(at_path)?;
+            }
+            operator(&mut updated, path, operand).map_err(at_path)?;
+        }
+    }
+    *doc = updated;
+    Ok(())
+}
+
+/// Record that an update changes `path`, checking that no other part of
+/// the update changes it or a path through it.
+fn claim<'a>(paths: &mut Vec<&'a str>, path: &'a str) -> RawResult<()> {
+    if let Some(key) = path.split('.').find(|key| key.starts_with('$')) {
+        return Err(RawError::new(ErrorKind::UnknownOperator(key.into())));
+    }
+    let overlaps = |a: &str, b: &str| a.starts_with(b) && a[b.len()..].starts_with('.');
+    for claimed in paths.iter() {
+        if *claimed == path || overlaps(claimed, path) || overlaps(path, claimed) {
+            return Err(RawError::new(ErrorKind::ConflictingUpdate));
+        }
+    }
+    paths.push(path);
+    Ok(())
+}
+
+fn bad_operand(operator: &str) -> RawError {
+    RawError::new(ErrorKind::BadOperand(operator.into()))
+}
+
+fn set(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    doc.set_path(path, operand)?;
+    Ok(())
+}
+
+/// Remove a field.  As in MongoDB, an array element is replaced with null
+/// rather than removed, so that the elements after it keep their indexes.
+fn unset(doc: &mut DocBuf, path: &str, _: Element<'_>) -> RawResult<()> {
+    if doc.get_path(path)?.is_none() {
+        return Ok(());
+    }
+    let in_array = match path.rfind('.') {
+        Some(split) => doc
+            .get_path(&path[..split])?
+            .is_some_and(|parent| parent.element_type() == ElementType::Array),
+        None => false,
+    };
+    if in_array {
+        doc.set_path(path, RawBsonRef::Null)?;
+    } else {
+        doc.remove_path(path)?;
+    }
+    Ok(())
+}
+
+/// Move a field to a new path.  As in MongoDB, this removes both paths and
+/// then sets the new one, so the field may change position.
+fn rename(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let to = match operand.element_type() {
+        ElementType::String => operand.as_str()?,
+        _ => return Err(bad_operand("$rename")),
+    };
+    // Neither path may pass through an array.
+    for path in [path, to].iter() {
+        let mut prefix = 0;
+        while let Some(split) = path[prefix..].find('.') {
+            prefix += split;
+            if let Some(elem) = doc.get_path(&path[..prefix])? {
+                if elem.element_type() == ElementType::Array {
+                    return Err(
+                        RawError::new(ErrorKind::UnexpectedType).of_type(ElementType::Array)
+                    );
+                }
+            }
+            prefix += 1;
+        }
+    }
+    let value = match doc.get_path(path)? {
+        Some(value) => value.to_owned(),
+        None => return Ok(()),
+    };
+    doc.remove_path(path)?;
+    doc.remove_path(to)?;
+    doc.set_path(to, value)?;
+    Ok(())
+}
+
+fn inc(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let value = match doc.get_path(path)? {
+        Some(current) => arithmetic(Arithmetic::Add, current, operand)?,
+        None => {
+            Number::new(operand).map_err(|_| bad_operand("$inc"))?;
+            operand.to_owned()
+        }
+    };
+    doc.set_path(path, value)?;
+    Ok(())
+}
+
+/// Multiply a field.  A missing field is set to zero, with the type of the
+/// multiplier.
+fn mul(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let value = match doc.get_path(path)? {
+        Some(current) => arithmetic(Arithmetic::Mul, current, operand)?,
+        None => RawBson::try_from(match operand.element_type() {
+            ElementType::Int32 => RawBsonRef::Int32(0),
+            ElementType::Int64 => RawBsonRef::Int64(0),
+            ElementType::Double => RawBsonRef::Double(0.0),
+            ElementType::Decimal128 => RawBsonRef::Decimal128(RawDecimal128::from(0u8)),
+            _ => return Err(bad_operand("$mul")),
+        })?,
+    };
+    doc.set_path(path, value)?;
+    Ok(())
+}
+
+/// Set a field to `operand` if it is missing, or if `operand` compares to
+/// it with `ordering`.
+fn min_max(
+    doc: &mut DocBuf,
+    path: &str,
+    operand: Element<'_>,
+    ordering: Ordering,
+) -> RawResult<()> {
+    if let Some(current) = doc.get_path(path)? {
+        if try_compare(operand, current)? != ordering {
+            return Ok(());
+        }
+    }
+    doc.set_path(path, operand)?;
+    Ok(())
+}
+
+fn push(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let mut values = array_at(doc, path)?.unwrap_or_default();
+    let mut each = Vec::new();
+    let mut slice = None;
+    let mut position = None;
+    match each_modifier(operand, "$push")? {
+        Some(modifiers) => {
+            for result in modifiers {
+                let (modifier, value) = result?;
+                match modifier {
+                    "$each" => {
+                        for value in value.as_array()? {
+                            each.push(value?.to_owned());
+                        }
+                    }
+                    "$slice" => slice = Some(integer(value, "$slice")?),
+                    "$position" => position = Some(integer(value, "$position")?),
+                    _ => return Err(RawError::new(ErrorKind::UnknownOperator(modifier.into()))),
+                }
+            }
+        }
+        None => each.push(operand.to_owned()),
+    }
+    let position = match position {
+        Some(position) => from_end(position, values.len()),
+        None => values.len(),
+    };
+    values.splice(position..position, each);
+    match slice {
+        Some(slice) if slice >= 0 => values.truncate(usize::try_from(slice).unwrap_or(usize::MAX)),
+        Some(slice) => {
+            let start = from_end(slice, values.len());
+            values.drain(..start);
+        }
+        None => {}
+    }
+    doc.set_path(path, encode_array(&values)?)?;
+    Ok(())
+}
+
+fn add_to_set(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let mut values = array_at(doc, path)?.unwrap_or_default();
+    let mut each = Vec::new();
+    match each_modifier(operand, "$addToSet")? {
+        Some(modifiers) => {
+            for result in modifiers {
+                let (modifier, value) = result?;
+                match modifier {
+                    "$each" => {
+                        for value in value.as_array()? {
+                            each.push(value?);
+                        }
+                    }
+                    _ => return Err(RawError::new(ErrorKind::UnknownOperator(modifier.into()))),
+                }
+            }
+        }
+        None => each.push(operand),
+    }
+    let len = values.len();
+    for value in each {
+        if !contains(&values, value)? {
+            values.push(value.to_owned());
+        }
+    }
+    if values.len() > len || doc.get_path(path)?.is_none() {
+        doc.set_path(path, encode_array(&values)?)?;
+    }
+    Ok(())
+}
+
+/// Remove every element that equals `operand`, or that matches it if it is
+/// a document, as with `$elemMatch`.
+fn pull(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let values = match array_at(doc, path)? {
+        Some(values) => values,
+        None => return Ok(()),
+    };
+    let filter = match operand.element_type() {
+        ElementType::EmbeddedDocument => Some(ElemMatch::new(operand)?),
+        _ => None,
+    };
+    let mut kept = Vec::with_capacity(values.len());
+    for value in &values {
+        let matches = match &filter {
+            Some(filter) => filter.matches(value.as_element())?,
+            None => try_compare(value.as_element(), operand)? == Ordering::Equal,
+        };
+        if !matches {
+            kept.push(value.clone());
+        }
+    }
+    if kept.len() < values.len() {
+        doc.set_path(path, encode_array(&kept)?)?;
+    }
+    Ok(())
+}
+
+/// Remove the last element of an array, or the first if `operand` is -1.
+fn pop(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let first = match integer(operand, "$pop") {
+        Ok(1) => false,
+        Ok(-1) => true,
+        _ => return Err(bad_operand("$pop")),
+    };
+    let mut values = match array_at(doc, path)? {
+        Some(values) if !values.is_empty() => values,
+        _ => return Ok(()),
+    };
+    if first {
+        values.remove(0);
+    } else {
+        values.pop();
+    }
+    doc.set_path(path, encode_array(&values)?)?;
+    Ok(())
+}
+
+fn current_date(doc: &mut DocBuf, path: &str, operand: Element<'_>) -> RawResult<()> {
+    let now = Utc::now();
+    let timestamp = match operand.element_type() {
+        ElementType::Boolean => false,
+        ElementType::EmbeddedDocument => {
+            let spec = operand.as_document()?;
+            let mut keys = spec.into_iter();
+            match (keys.next().transpose()?, keys.next()) {
+                (Some(("$type", kind)), None) if kind.element_type() == ElementType::String => {
+                    match kind.as_str()? {
+                        "date" => false,
+                        "timestamp" => true,
+                        _ => return Err(bad_operand("$currentDate")),
+                    }
+                }
+                _ => return Err(bad_operand("$currentDate")),
+            }
+        }
+        _ => return Err(bad_operand("$currentDate")),
+    };
+    let value = if timestamp {
+        let time = u32::try_from(now.timestamp()).unwrap_or(u32::MAX);
+        let mut bytes = Vec::with_capacity(8);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&time.to_le_bytes());
+        Element::new(ElementType::Timestamp, &bytes).to_owned()
+    } else {
+        RawBson::try_from(RawBsonRef::DateTime(now))?
+    };
+    doc.set_path(path, value)?;
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Arithmetic {
+    Add,
+    Mul,
+}
+
+impl Arithmetic {
+    fn name(self) -> &'static str {
+        match self {
+            Arithmetic::Add => "$inc",
+            Arithmetic::Mul => "$mul",
+        }
+    }
+
+    fn ints(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Add => a.checked_add(b),
+            Arithmetic::Mul => a.checked_mul(b),
+        }
+    }
+
+    fn doubles(self, a: f64, b: f64) -> f64 {
+        match self {
+            Arithmetic::Add => a + b,
+            Arithmetic::Mul => a * b,
+        }
+    }
+
+    /// Combine two decimals, rounding to 34 significant digits as MongoDB
+    /// does.
+    fn decimals(self, a: RawDecimal128, b: RawDecimal128) -> RawDecimal128 {
+        match self {
+            Arithmetic::Add => a.add_rounded(b),
+            Arithmetic::Mul => a.mul_rounded(b),
+        }
+    }
+}
+
+/// Add or multiply two numbers, promoting the result as MongoDB does.
+fn arithmetic(op: Arithmetic, current: Element<'_>, operand: Element<'_>) -> RawResult<RawBson> {
+    let b = Number::new(operand).map_err(|_| bad_operand(op.name()))?;
+    let a = Number::new(current)?;
+    let types = [current.element_type(), operand.element_type()];
+    let value = if types.contains(&ElementType::Decimal128) {
+        RawBsonRef::Decimal128(decimal(op, a, b))
+    } else if types.contains(&ElementType::Double) {
+        RawBsonRef::Double(op.doubles(a.to_f64(), b.to_f64()))
+    } else {
+        let int = |number| match number {
+            Number::Int(value) => value,
+            _ => unreachable!("only integers remain"),
+        };
+        let result = op
+            .ints(int(a), int(b))
+            .ok_or_else(|| RawError::new(ErrorKind::OutOfRange))?;
+        match i32::try_from(result) {
+            Ok(result) if types == [ElementType::Int32; 2] => RawBsonRef::Int32(result),
+            _ => RawBsonRef::Int64(result),
+        }
+    };
+    RawBson::try_from(value)
+}
+
+/// Add or multiply two numbers as decimals.  Doubles are converted to the
+/// shortest decimal that rounds to them.
+fn decimal(op: Arithmetic, a: Number, b: Number) -> RawDecimal128 {
+    let decimal = |number| match number {
+        Number::Int(value) => RawDecimal128::from(value),
+        Number::Double(value) => RawDecimal128::from(value),
+        Number::Decimal(value) => value,
+    };
+    op.decimals(decimal(a), decimal(b))
+}
+
+/// The elements of the array at `path`, or `None` if the path is missing.
+fn array_at(doc: &DocBuf, path: &str) -> RawResult<Option<Vec<RawBson>>> {
+    let elem = match doc.get_path(path)? {
+        Some(elem) => elem,
+        None => return Ok(None),
+    };
+    if elem.element_type() != ElementType::Array {
+        return Err(RawError::new(ErrorKind::UnexpectedType).of_type(elem.element_type()));
+    }
+    let mut values = Vec::new();
+    for value in elem.as_array()? {
+        values.push(value?.to_owned());
+    }
+    Ok(Some(values))
+}
+
+fn encode_array(values: &[RawBson]) -> RawResult<RawBson> {
+    let mut bytes = vec![0; 4];
+    for (index, value) in values.iter().enumerate() {
+        bytes.push(value.element_type() as u8);
+        bytes.extend_from_slice(index.to_string().as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(value.as_bytes());
+    }
+    bytes.push(0);
+    let length = i32::try_from(bytes.len()).map_err(|_| RawError::new(ErrorKind::BadLength))?;
+    bytes[..4].copy_from_slice(&length.to_le_bytes());
+    Ok(Element::new(ElementType::Array, &bytes).to_owned())
+}
+
+/// The modifiers of a `$push` or `$addToSet` operand, if it is a document
+/// with an `$each` array.
+fn each_modifier<'a>(operand: Element<'a>, operator: &str) -> RawResult<Option<&'a Doc>> {
+    if operand.element_type() != ElementType::EmbeddedDocument {
+        return Ok(None);
+    }
+    let modifiers = operand.as_document()?;
+    match modifiers.get("$each")? {
+        Some(each) if each.element_type() == ElementType::Array => Ok(Some(modifiers)),
+        Some(_) => Err(bad_operand(operator)),
+        None => Ok(None),
+    }
+}
+
+fn contains(values: &[RawBson], value: Element<'_>) -> RawResult<bool> {
+    for existing in values {
+        if try_compare(existing.as_element(), value)? == Ordering::Equal {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Read an integer operand, which may be given as any numeric type.
+fn integer(operand: Element<'_>, operator: &str) -> RawResult<i64> {
+    match Number::new(operand) {
+        Ok(Number::Int(value)) => Ok(value),
+        Ok(Number::Double(value)) if value.fract() == 0.0 => Ok(value as i64),
+        Ok(Number::Decimal(value)) => i64::try_from(value).map_err(|_| bad_operand(operator)),
+        _ => Err(bad_operand(operator)),
+    }
+}
+
+/// Resolve an index that counts from the end of `len` elements if it is
+/// negative, clamped to `0..=len`.
+fn from_end(index: i64, len: usize) -> usize {
+    let magnitude = usize::try_from(index.unsigned_abs()).unwrap_or(usize::MAX);
+    if index >= 0 {
+        magnitude.min(len)
+    } else {
+        len.saturating_sub(magnitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use bson::{doc, spec::ElementType, Bson, Document};
+
+    use super::apply;
+    use crate::{
+        elem::{RawBson, RawBsonRef},
+        DocBuf, ErrorKind, RawError,
+    };
+
+    fn update(doc: Document, update: Document) -> Result<Document, RawError> {
+        let mut docbuf = DocBuf::from_document(&doc);
+        apply(&mut docbuf, &DocBuf::from_document(&update))?;
+        Ok(Document::try_from(&*docbuf).unwrap())
+    }
+
+    #[test]
+    fn fields() {
+        let doc = doc! {"a": 1, "b": {"c": 2}, "list": [1, 2, 3]};
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$set": {"a": "x", "b.d": 3, "e.f": true}}
+            )
+            .unwrap(),
+            doc! {"a": "x", "b": {"c": 2, "d": 3}, "list": [1, 2, 3], "e": {"f": true}}
+        );
+        // Unsetting an array element leaves null in its place.
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$unset": {"a": "", "list.1": "", "x.y": ""}}
+            )
+            .unwrap(),
+            doc! {"b": {"c": 2}, "list": [1, Bson::Null, 3]}
+        );
+        assert_eq!(
+            update(doc.clone(), doc! {"$rename": {"a": "b.a", "missing": "z"}}).unwrap(),
+            doc! {"b": {"c": 2, "a": 1}, "list": [1, 2, 3]}
+        );
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$min": {"a": 0, "b.c": 5, "n": 1}, "$max": {"list": "s"}}
+            )
+            .unwrap(),
+            doc! {"a": 0, "b": {"c": 2}, "list": [1, 2, 3], "n": 1}
+        );
+        // Arrays sort after strings.
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$max": {"a": "s"}, "$min": {"list": "s"}}
+            )
+            .unwrap(),
+            doc! {"a": "s", "b": {"c": 2}, "list": "s"}
+        );
+
+        let updated = update(
+            doc,
+            doc! {"$currentDate": {"a": true, "b.c": {"$type": "timestamp"}}},
+        )
+        .unwrap();
+        assert_eq!(
+            updated.get("a").unwrap().element_type(),
+            ElementType::DateTime
+        );
+        assert_eq!(
+            updated
+                .get_document("b")
+                .unwrap()
+                .get("c")
+                .unwrap()
+                .element_type(),
+            ElementType::Timestamp
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        let doc = doc! {"i": 1, "l": 1i64, "d": 1.5, "max": i32::MAX};
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$inc": {"i": 2, "l": 2, "d": 1, "max": 1, "new": 3i64}}
+            )
+            .unwrap(),
+            doc! {"i": 3, "l": 3i64, "d": 2.5, "max": i32::MAX as i64 + 1, "new": 3i64}
+        );
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$mul": {"i": 2i64, "l": 0.5, "new": 7, "d": 2}}
+            )
+            .unwrap(),
+            doc! {"i": 2i64, "l": 0.5, "d": 3.0, "max": i32::MAX, "new": 0}
+        );
+
+        // Decimal arithmetic is exact within 34 digits, and decimal results
+        // stay decimal.
+        let decimal = |value: &str| RawBson::try_from(RawBsonRef::Decimal128(value.parse().unwrap())).unwrap();
+        let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 0.1});
+        let mut inc = DocBuf::from_document(&doc! {});
+        inc.set_path("$inc.a", decimal("0.10")).unwrap();
+        inc.set_path("$inc.b", decimal("0.2")).unwrap();
+        apply(&mut docbuf, &inc).unwrap();
+        assert_eq!(
+            docbuf.get_path("a").unwrap().unwrap().to_owned(),
+            decimal("1.10")
+        );
+        assert_eq!(
+            docbuf.get_path("b").unwrap().unwrap().to_owned(),
+            decimal("0.3")
+        );
+        // Beyond 34 digits, results are rounded rather than rejected.
+        let mut docbuf = DocBuf::from_document(&doc! {"a": 1, "b": 7});
+        let mut ops = DocBuf::from_document(&doc! {});
+        ops.set_path("$inc.a", decimal("1E+40")).unwrap();
+        ops.set_path("$mul.b", decimal("3333333333333333333333333333333333E-40"))
+            .unwrap();
+        apply(&mut docbuf, &ops).unwrap();
+        assert_eq!(
+            docbuf.get_path("a").unwrap().unwrap().to_owned(),
+            decimal("1.000000000000000000000000000000000E+40")
+        );
+        assert_eq!(
+            docbuf.get_path("b").unwrap().unwrap().to_owned(),
+            decimal("2.333333333333333333333333333333333E-6")
+        );
+
+        let err = update(doc! {"l": i64::MAX}, doc! {"$inc": {"l": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::OutOfRange);
+        assert_eq!(err.key_path(), "l");
+        let err = update(doc! {"a": {"b": "x"}}, doc! {"$inc": {"a.b": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        assert_eq!(err.key_path(), "a.b");
+        assert_eq!(err.element_type(), Some(ElementType::String));
+        let err = update(doc! {}, doc! {"$mul": {"a": "2"}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$mul".into()));
+    }
+
+    #[test]
+    fn arrays() {
+        let doc = doc! {"list": [1, 2, 3]};
+        assert_eq!(
+            update(doc.clone(), doc! {"$push": {"list": 4, "new": {"a": 1}}}).unwrap(),
+            doc! {"list": [1, 2, 3, 4], "new": [{"a": 1}]}
+        );
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$push": {"list": {"$each": [8, 9], "$position": -1, "$slice": 4}}}
+            )
+            .unwrap(),
+            doc! {"list": [1, 2, 8, 9]}
+        );
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$push": {"list": {"$each": [], "$slice": -1}}}
+            )
+            .unwrap(),
+            doc! {"list": [3]}
+        );
+        assert_eq!(
+            update(
+                doc.clone(),
+                doc! {"$addToSet": {"list": {"$each": [3.0, 4, 4]}, "s": 1}}
+            )
+            .unwrap(),
+            doc! {"list": [1, 2, 3, 4], "s": [1]}
+        );
+        assert_eq!(
+            update(doc.clone(), doc! {"$pop": {"list": -1, "missing": 1}}).unwrap(),
+            doc! {"list": [2, 3]}
+        );
+        assert_eq!(
+            update(doc.clone(), doc! {"$pull": {"list": {"$gte": 2}}}).unwrap(),
+            doc! {"list": [1]}
+        );
+        assert_eq!(
+            update(doc! {"list": [[1], 1, [1]]}, doc! {"$pull": {"list": [1]}}).unwrap(),
+            doc! {"list": [1]}
+        );
+        assert_eq!(
+            update(
+                doc! {"list": [{"a": 1, "b": 2}, {"a": 2}, 5]},
+                doc! {"$pull": {"list": {"a": 1}}}
+            )
+            .unwrap(),
+            doc! {"list": [{"a": 2}, 5]}
+        );
+
+        let err = update(doc! {"a": 1}, doc! {"$push": {"a": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        assert_eq!(err.key_path(), "a");
+        let err = update(doc.clone(), doc! {"$pop": {"list": 2}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadOperand("$pop".into()));
+        let err = update(doc, doc! {"$push": {"list": {"$each": [1], "$sort": 1}}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnknownOperator("$sort".into()));
+    }
+
+    #[test]
+    fn errors() {
+        let doc = doc! {"a": {"b": 1}, "list": [{"c": 1}]};
+        let err = update(doc.clone(), doc! {"$set": {"a": 1}, "$inc": {"a.b": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ConflictingUpdate);
+        assert_eq!(err.key_path(), "a.b");
+        let err = update(doc.clone(), doc! {"$rename": {"x": "a"}, "$set": {"a": 1}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ConflictingUpdate);
+        let err = update(doc.clone(), doc! {"$rename": {"list.0.c": "d"}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedType);
+        let err = update(doc.clone(), doc! {"$set": {"list.$.c": 2}}).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnknownOperator("$".into()));
+        let err = update(doc.clone(), doc! {"$setOnInsert": {"x": 1}}).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::UnknownOperator("$setOnInsert".into())
+        );
+        assert_eq!(err.key_path(), "$setOnInsert");
+
+        // A failed update leaves the document as it was.
+        let mut docbuf = DocBuf::from_document(&doc);
+        let err = apply(
+            &mut docbuf,
+            &DocBuf::from_document(&doc! {"$set": {"x": 1}, "$inc": {"a": 1}}),
+        )
+        .unwrap_err();
+        assert_eq!(err.key_path(), "a");
+        assert_eq!(Document::try_from(&*docbuf).unwrap(), doc);
+    }
+}
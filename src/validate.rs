@@ -0,0 +1,613 @@
+//! Eager validation of complete BSON documents.
+//!
+//! [`Doc::validate`] walks the entire document tree once, checking every
+//! length prefix, terminator, UTF-8 string, boolean byte, binary subtype
+//! and nested document.  On success it returns a [`ValidDoc`], a marker type
+//! whose iterator and key lookups ([`ValidDoc::iter`], [`ValidDoc::get`] and
+//! [`ValidDoc::get_document`]) skip those checks and cannot fail.
+//!
+//! ```
+//! use bson::doc;
+//! use rawbson::{DocBuf, ValidDocBuf};
+//!
+//! let bytes = DocBuf::from_document(&doc! {"hi": "y'all", "nested": {"list": [1, 2]}})
+//!     .into_inner();
+//! let valid: ValidDocBuf = DocBuf::new_validated(bytes)?;
+//! for (key, value) in valid.iter() {
+//!     println!("{}: {:?}", key, value.element_type());
+//! }
+//! assert_eq!(valid.get("hi").unwrap().as_str()?, "y'all");
+//!
+//! let truncated = b"\x0d\x00\x00\x00\x02hi\x00\x06\x00\x00\x00\x00";
+//! assert!(rawbson::Doc::new(truncated)?.validate().is_err());
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::{borrow::Borrow, convert::TryFrom, ops::Deref};
+
+use bson::spec::ElementType;
+
+use crate::{
+    elem::Element, i32_from_slice, try_to_str, Doc, DocBuf, ErrorKind, RawError, RawResult,
+};
+
+/// Validate the complete document contained in `data`.
+///
+/// This does not recurse on the call stack, so arbitrarily deep nesting
+/// cannot overflow it.  Errors report offsets into `data`, and the key path
+/// of the element involved.
+pub(crate) fn validate_document(data: &[u8]) -> RawResult<()> {
+    struct Frame<'a> {
+        key: &'a str,
+        end: usize,
+        array_index: Option<usize>,
+    }
+
+    // Attach the key path of the current frame to an error.
+    fn in_frames(mut err: RawError, stack: &[Frame]) -> RawError {
+        for frame in stack.iter().skip(1).rev() {
+            err = err.in_key(frame.key);
+        }
+        err
+    }
+
+    let end = document_end(data, 0)?;
+    if end != data.len() {
+        return Err(RawError::new(ErrorKind::BadLength).at(0));
+    }
+    let mut stack = vec![Frame {
+        key: "",
+        end,
+        array_index: None,
+    }];
+    let mut offset = 4;
+
+    while let Some(frame) = stack.last_mut() {
+        if offset == frame.end - 1 {
+            // document_end() has already checked the trailing NUL.
+            offset = frame.end;
+            stack.pop();
+            continue;
+        }
+
+        let limit = frame.end - 1;
+        let key = match read_cstring(data, offset + 1, limit) {
+            Ok(key) => key,
+            Err(err) => return Err(in_frames(err, &stack)),
+        };
+        let tag = data[offset];
+        let element_type = match ElementType::from(tag) {
+            Some(element_type) => element_type,
+            None => {
+                let err = RawError::new(ErrorKind::InvalidTag(tag)).at(offset);
+                return Err(in_frames(err.in_key(key), &stack));
+            }
+        };
+        if let Some(index) = frame.array_index.as_mut() {
+            if key.parse() != Ok(*index) {
+                let err = RawError::new(ErrorKind::BadArrayIndex).at(offset + 1);
+                return Err(in_frames(err.in_key(key).of_type(element_type), &stack));
+            }
+            *index += 1;
+        }
+
+        let start = offset + 1 + key.len() + 1;
+        let next = match element_type {
+            ElementType::EmbeddedDocument | ElementType::Array => document_end(data, start)
+                .and_then(|end| {
+                    if end > limit {
+                        Err(RawError::new(ErrorKind::TruncatedLength).at(start))
+                    } else {
+                        Ok(end)
+                    }
+                }),
+            ElementType::JavaScriptCodeWithScope => {
+                validate_javascript_with_scope(data, start, limit)
+            }
+            _ => validate_value(data, element_type, start, limit),
+        };
+        let next = match next {
+            Ok(next) => next,
+            Err(err) => return Err(in_frames(err.in_key(key).of_type(element_type), &stack)),
+        };
+
+        offset = match element_type {
+            ElementType::EmbeddedDocument | ElementType::Array => {
+                stack.push(Frame {
+                    key,
+                    end: next,
+                    array_index: match element_type {
+                        ElementType::Array => Some(0),
+                        _ => None,
+                    },
+                });
+                start + 4
+            }
+            ElementType::JavaScriptCodeWithScope => {
+                // The scope is validated as a nested document; when it
+                // finishes, iteration continues right after the element.
+                let code_length = 4 + i32_from_slice(&data[start + 4..start + 8]) as usize;
+                stack.push(Frame {
+                    key,
+                    end: next,
+                    array_index: None,
+                });
+                start + 4 + code_length + 4
+            }
+            _ => next,
+        };
+    }
+    Ok(())
+}
+
+/// Check the envelope of a javascript with scope value starting at `start`,
+/// returning the offset just past it.  The scope document itself is not
+/// validated.
+fn validate_javascript_with_scope(data: &[u8], start: usize, limit: usize) -> RawResult<usize> {
+    let length = read_length(data, start, limit)?;
+    let end = start + length;
+    if end > limit {
+        return Err(RawError::new(ErrorKind::TruncatedLength).at(start));
+    }
+    let code_end = read_string(data, start + 4, end)?;
+    let scope_end = document_end(data, code_end)?;
+    if scope_end != end {
+        return Err(RawError::new(ErrorKind::BadLength).at(start));
+    }
+    Ok(end)
+}
+
+/// Validate a single non-container value starting at `start`, which must end
+/// at or before `limit`.  Returns the offset just past the value.
+fn validate_value(
+    data: &[u8],
+    element_type: ElementType,
+    start: usize,
+    limit: usize,
+) -> RawResult<usize> {
+    let fixed = |size: usize| {
+        if start + size > limit {
+            Err(RawError::new(ErrorKind::TruncatedLength).at(start))
+        } else {
+            Ok(start + size)
+        }
+    };
+    match element_type {
+        ElementType::Double
+        | ElementType::DateTime
+        | ElementType::Timestamp
+        | ElementType::Int64 => fixed(8),
+        ElementType::Int32 => fixed(4),
+        ElementType::Decimal128 => fixed(16),
+        ElementType::ObjectId => fixed(12),
+        ElementType::Undefined | ElementType::Null | ElementType::MaxKey | ElementType::MinKey => {
+            Ok(start)
+        }
+        ElementType::Boolean => {
+            let end = fixed(1)?;
+            match data[start] {
+                0 | 1 => Ok(end),
+                value => Err(RawError::new(ErrorKind::BadBool(value)).at(start)),
+            }
+        }
+        ElementType::String | ElementType::JavaScriptCode | ElementType::Symbol => {
+            read_string(data, start, limit)
+        }
+        ElementType::DbPointer => {
+            let end = read_string(data, start, limit)?;
+            if end + 12 > limit {
+                return Err(RawError::new(ErrorKind::TruncatedLength).at(end));
+            }
+            Ok(end + 12)
+        }
+        ElementType::RegularExpression => {
+            let pattern = read_cstring(data, start, limit)?;
+            let options = read_cstring(data, start + pattern.len() + 1, limit)?;
+            Ok(start + pattern.len() + options.len() + 2)
+        }
+        ElementType::Binary => {
+            let length = read_length(data, start, limit)?;
+            let end = start + 5 + length;
+            if end > limit {
+                return Err(RawError::new(ErrorKind::TruncatedLength).at(start));
+            }
+            match data[start + 4] {
+                0x02 => {
+                    if length < 4 || read_length(data, start + 5, end)? + 4 != length {
+                        return Err(RawError::new(ErrorKind::BadLength).at(start + 5));
+                    }
+                }
+                0x00..=0x09 | 0x80..=0xff => {}
+                subtype => {
+                    return Err(RawError::new(ErrorKind::ReservedSubtype(subtype)).at(start + 4))
+                }
+            }
+            Ok(end)
+        }
+        ElementType::EmbeddedDocument
+        | ElementType::Array
+        | ElementType::JavaScriptCodeWithScope => {
+            unreachable!("containers are handled by validate_document")
+        }
+    }
+}
+
+/// Check the length prefix and trailing NUL of a document starting at
+/// `start`, returning the offset just past its end.
+fn document_end(data: &[u8], start: usize) -> RawResult<usize> {
+    let length = read_length(data, start, data.len())?;
+    if length < 5 {
+        return Err(RawError::new(ErrorKind::BadLength).at(start));
+    }
+    let end = start + length;
+    if end > data.len() {
+        return Err(RawError::new(ErrorKind::TruncatedLength).at(start));
+    }
+    if data[end - 1] != 0 {
+        return Err(RawError::new(ErrorKind::MissingNul).at(end - 1));
+    }
+    Ok(end)
+}
+
+/// Read a non-negative i32 length at `start`, which must fit before `limit`.
+fn read_length(data: &[u8], start: usize, limit: usize) -> RawResult<usize> {
+    if start + 4 > limit {
+        return Err(RawError::new(ErrorKind::TruncatedLength).at(start));
+    }
+    usize::try_from(i32_from_slice(&data[start..start + 4]))
+        .map_err(|_| RawError::new(ErrorKind::BadLength).at(start))
+}
+
+/// Read a length-prefixed string at `start`, returning the offset just past it.
+fn read_string(data: &[u8], start: usize, limit: usize) -> RawResult<usize> {
+    let length = read_length(data, start, limit)?;
+    let end = start + 4 + length;
+    if length < 1 {
+        return Err(RawError::new(ErrorKind::BadLength).at(start));
+    }
+    if end > limit {
+        return Err(RawError::new(ErrorKind::TruncatedLength).at(start));
+    }
+    if data[end - 1] != 0 {
+        return Err(RawError::new(ErrorKind::MissingNul).at(end - 1));
+    }
+    try_to_str(&data[start + 4..end - 1]).map_err(|err| err.shifted(start + 4))?;
+    Ok(end)
+}
+
+/// Read a NUL-terminated string at `start`, which must end before `limit`.
+fn read_cstring(data: &[u8], start: usize, limit: usize) -> RawResult<&str> {
+    let bytes = data.get(start..limit).unwrap_or_default();
+    let length = bytes
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| RawError::new(ErrorKind::MissingNul).at(limit))?;
+    try_to_str(&bytes[..length]).map_err(|err| err.shifted(start))
+}
+
+/// Read every value in `doc` recursively through the checked accessors,
+/// re-encoding each one, and return the first error.
+///
+/// The tests and fuzz targets use this to check that any document which
+/// passes [`Doc::validate`] can also be read lazily.
+#[cfg(any(test, fuzzing))]
+#[doc(hidden)]
+pub fn walk(doc: &Doc) -> RawResult<()> {
+    use crate::elem::{RawBson, RawBsonRef};
+
+    for item in doc {
+        let (_, elem) = item?;
+        let value = elem.as_raw_bson_ref()?;
+        RawBson::try_from(value.clone())?;
+        match value {
+            RawBsonRef::Document(doc) => walk(doc)?,
+            RawBsonRef::Array(arr) => {
+                arr.to_vec()?;
+                walk(Doc::new(arr.as_bytes())?)?
+            }
+            RawBsonRef::JavaScriptCodeWithScope { scope, .. } => walk(scope)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A [`Doc`] that has been fully validated by [`Doc::validate`].
+///
+/// `ValidDoc` dereferences to `Doc`, so all the usual accessors are
+/// available.  Only [`iter`](ValidDoc::iter), [`get`](ValidDoc::get) and
+/// [`get_document`](ValidDoc::get_document) skip the format checks and
+/// cannot fail; the typed `get_*` accessors reached through `Doc` check
+/// their values as they would on any other document.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct ValidDoc {
+    doc: Doc,
+}
+
+impl ValidDoc {
+    /// Wrap a `Doc` without validating it.
+    ///
+    /// # Safety
+    ///
+    /// The document must have passed [`validate_document`].
+    pub(crate) unsafe fn new_unchecked(doc: &Doc) -> &ValidDoc {
+        // SAFETY: ValidDoc is a transparent wrapper around Doc.
+        unsafe { &*(doc as *const Doc as *const ValidDoc) }
+    }
+
+    /// Return the validated document as a plain `&Doc`.
+    pub fn as_doc(&self) -> &Doc {
+        &self.doc
+    }
+
+    /// Iterate over the elements of the document.  Unlike
+    /// [`DocIter`](crate::DocIter), this yields elements directly rather
+    /// than `Result`s.
+    pub fn iter(&self) -> ValidDocIter<'_> {
+        ValidDocIter {
+            data: self.doc.as_bytes(),
+            offset: 4,
+        }
+    }
+
+    /// Get an element from the document.  Like [`Doc::get`], this is an
+    /// O(N) operation, but it skips all format checks.
+    pub fn get(&self, key: &str) -> Option<Element<'_>> {
+        self.iter()
+            .find(|(thiskey, _)| *thiskey == key)
+            .map(|(_, elem)| elem)
+    }
+
+    /// Get an embedded document, preserving the validated marker.
+    pub fn get_document(&self, key: &str) -> Option<&ValidDoc> {
+        self.get(key)
+            .filter(|elem| elem.element_type() == ElementType::EmbeddedDocument)
+            // SAFETY: Embedded documents were validated along with self.
+            .map(|elem| unsafe { ValidDoc::new_unchecked(Doc::new_unchecked(elem.as_bytes())) })
+    }
+}
+
+impl Deref for ValidDoc {
+    type Target = Doc;
+
+    fn deref(&self) -> &Doc {
+        &self.doc
+    }
+}
+
+impl AsRef<Doc> for ValidDoc {
+    fn as_ref(&self) -> &Doc {
+        &self.doc
+    }
+}
+
+impl ToOwned for ValidDoc {
+    type Owned = ValidDocBuf;
+
+    fn to_owned(&self) -> ValidDocBuf {
+        ValidDocBuf {
+            docbuf: self.doc.to_docbuf(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ValidDoc {
+    type IntoIter = ValidDocIter<'a>;
+    type Item = (&'a str, Element<'a>);
+
+    fn into_iter(self) -> ValidDocIter<'a> {
+        self.iter()
+    }
+}
+
+/// An owned, fully validated document, created by
+/// [`DocBuf::new_validated`] or [`DocBuf::into_validated`].
+#[derive(Clone, Debug)]
+pub struct ValidDocBuf {
+    docbuf: DocBuf,
+}
+
+impl ValidDocBuf {
+    /// Return the underlying [`DocBuf`], dropping the validated marker.
+    pub fn into_docbuf(self) -> DocBuf {
+        self.docbuf
+    }
+
+    /// Return the contained data as a `Vec<u8>`
+    pub fn into_inner(self) -> Vec<u8> {
+        self.docbuf.into_inner()
+    }
+
+    pub(crate) fn new_unchecked(docbuf: DocBuf) -> ValidDocBuf {
+        ValidDocBuf { docbuf }
+    }
+}
+
+impl Deref for ValidDocBuf {
+    type Target = ValidDoc;
+
+    fn deref(&self) -> &ValidDoc {
+        // SAFETY: ValidDocBuf can only be constructed from a validated DocBuf.
+        unsafe { ValidDoc::new_unchecked(&self.docbuf) }
+    }
+}
+
+impl AsRef<Doc> for ValidDocBuf {
+    fn as_ref(&self) -> &Doc {
+        &self.docbuf
+    }
+}
+
+impl Borrow<ValidDoc> for ValidDocBuf {
+    fn borrow(&self) -> &ValidDoc {
+        self
+    }
+}
+
+impl<'a> IntoIterator for &'a ValidDocBuf {
+    type IntoIter = ValidDocIter<'a>;
+    type Item = (&'a str, Element<'a>);
+
+    fn into_iter(self) -> ValidDocIter<'a> {
+        self.iter()
+    }
+}
+
+/// An infallible iterator over the elements of a [`ValidDoc`].
+pub struct ValidDocIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for ValidDocIter<'a> {
+    type Item = (&'a str, Element<'a>);
+
+    fn next(&mut self) -> Option<(&'a str, Element<'a>)> {
+        if self.offset >= self.data.len() - 1 {
+            return None;
+        }
+        let data = self.data;
+        let element_type = ElementType::from(data[self.offset]).expect("validated element type");
+        let key_start = self.offset + 1;
+        let key_len = data[key_start..]
+            .iter()
+            .position(|b| *b == 0)
+            .expect("validated key");
+        // SAFETY: Keys were checked for valid UTF-8 during validation.
+        let key = unsafe { std::str::from_utf8_unchecked(&data[key_start..key_start + key_len]) };
+        let start = key_start + key_len + 1;
+        let length = |at: usize| i32_from_slice(&data[at..at + 4]) as usize;
+        let size = match element_type {
+            ElementType::Double
+            | ElementType::DateTime
+            | ElementType::Timestamp
+            | ElementType::Int64 => 8,
+            ElementType::Int32 => 4,
+            ElementType::Decimal128 => 16,
+            ElementType::ObjectId => 12,
+            ElementType::Boolean => 1,
+            ElementType::Undefined
+            | ElementType::Null
+            | ElementType::MaxKey
+            | ElementType::MinKey => 0,
+            ElementType::String | ElementType::JavaScriptCode | ElementType::Symbol => {
+                4 + length(start)
+            }
+            ElementType::DbPointer => 4 + length(start) + 12,
+            ElementType::EmbeddedDocument
+            | ElementType::Array
+            | ElementType::JavaScriptCodeWithScope => length(start),
+            ElementType::Binary => 5 + length(start),
+            ElementType::RegularExpression => {
+                let mut nuls = data[start..]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b == 0)
+                    .map(|(i, _)| i);
+                nuls.nth(1).expect("validated regex") + 1
+            }
+        };
+        self.offset = start + size;
+        Some((key, Element::new(element_type, &data[start..start + size])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, spec::BinarySubtype, Binary, Bson, JavaScriptCodeWithScope, Regex, Timestamp};
+
+    use super::walk;
+    use crate::{Doc, DocBuf, ErrorKind};
+
+    fn valid_bytes() -> Vec<u8> {
+        DocBuf::from_document(&doc! {
+            "f64": 2.5,
+            "string": "hello",
+            "document": { "inner": [1, { "deep": true }] },
+            "binary": Binary { subtype: BinarySubtype::BinaryOld, bytes: vec![1, 2, 3] },
+            "object_id": bson::oid::ObjectId::with_bytes([1; 12]),
+            "null": Bson::Null,
+            "regex": Regex { pattern: String::from("^a"), options: String::from("i") },
+            "js_with_scope": JavaScriptCodeWithScope {
+                code: String::from("x"),
+                scope: doc! { "x": [true] },
+            },
+            "timestamp": Timestamp { time: 3542578, increment: 7 },
+            "int64": 46i64,
+        })
+        .into_inner()
+    }
+
+    #[test]
+    fn validates_and_iterates() {
+        let bytes = valid_bytes();
+        let valid = DocBuf::new_validated(bytes.clone()).expect("valid document");
+        let checked: Vec<_> = Doc::new(&bytes)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.map(|(key, elem)| (key, elem.as_bytes())).unwrap())
+            .collect();
+        let unchecked: Vec<_> = valid
+            .iter()
+            .map(|(key, elem)| (key, elem.as_bytes()))
+            .collect();
+        assert_eq!(checked, unchecked);
+
+        let deep = valid.get_document("document").expect("nested document");
+        assert_eq!(
+            deep.get("inner").unwrap().as_array().unwrap().get_i32(0),
+            Ok(Some(1))
+        );
+    }
+
+    #[test]
+    fn validated_documents_are_readable() {
+        let bytes = valid_bytes();
+        for end in 5..bytes.len() {
+            let mut truncated = bytes[..end].to_vec();
+            truncated[..4].copy_from_slice(&(end as i32).to_le_bytes());
+            truncated[end - 1] = 0;
+            if let Ok(valid) = DocBuf::new_validated(truncated) {
+                walk(&valid).expect("validated document is readable");
+            }
+        }
+        for i in 4..bytes.len() - 1 {
+            for value in &[0x00, 0x01, 0x02, 0x7f, 0x80, 0xff] {
+                let mut mutated = bytes.clone();
+                mutated[i] = *value;
+                if let Ok(valid) = DocBuf::new_validated(mutated) {
+                    walk(&valid).expect("validated document is readable");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let bad_bool = b"\x09\0\0\0\x08b\0\x02\0";
+        let err = Doc::new(bad_bool).unwrap().validate().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadBool(2));
+        assert_eq!(err.offset(), Some(7));
+
+        let bad_utf8 = b"\x0f\0\0\0\x02s\0\x03\0\0\0\xff\xfe\0\0";
+        let err = Doc::new(bad_utf8).unwrap().validate().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadUtf8);
+        assert_eq!(err.offset(), Some(11));
+        assert_eq!(err.key_path(), "s");
+
+        let bad_subtype = b"\x0e\0\0\0\x05b\0\x01\0\0\0\x42\x00\0";
+        let err = Doc::new(bad_subtype).unwrap().validate().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ReservedSubtype(0x42));
+
+        let bad_index = b"\x13\0\0\0\x04a\0\x0b\0\0\0\x0a1\0\x0a0\0\0\0";
+        let err = Doc::new(bad_index).unwrap().validate().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadArrayIndex);
+        assert_eq!(err.key_path(), "a.1");
+
+        let bad_tag = b"\x08\0\0\0\x42a\0\0";
+        let err = Doc::new(bad_tag).unwrap().validate().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidTag(0x42));
+        assert_eq!(err.offset(), Some(4));
+    }
+}
@@ -0,0 +1,1060 @@
+//! Zero-copy parsing of MongoDB wire protocol messages.
+//!
+//! BSON is the payload of the [MongoDB wire
+//! protocol](https://www.mongodb.com/docs/manual/reference/mongodb-wire-protocol/).
+//! This module reads the message framing around it, borrowing every
+//! document from the message bytes:
+//!
+//! * [`OpMsg`] (`OP_MSG`), with its flag bits, its body section, any
+//!   document sequence sections, and the optional checksum.
+//! * The legacy [`OpQuery`] (`OP_QUERY`) and [`OpReply`] (`OP_REPLY`).
+//!
+//! [`OpMsgBuilder`] goes the other way, assembling an `OP_MSG` from
+//! existing documents.
+//!
+//! [`Message::parse`] dispatches on the opcode in the header.  Messages are
+//! checked completely when they are parsed, including the envelope of every
+//! document, so their accessors cannot fail.  Error offsets are relative to
+//! the start of the message.
+//!
+//! ```
+//! use rawbson::wire::{Message, OpCode};
+//!
+//! // An OP_MSG carrying {"hello": 1, "$db": "admin"}.
+//! let bytes = b"\x34\0\0\0\x01\0\0\0\0\0\0\0\xdd\x07\0\0\0\0\0\0\
+//!     \0\x1f\0\0\0\x10hello\0\x01\0\0\0\x02$db\0\x06\0\0\0admin\0\0";
+//! match Message::parse(bytes)? {
+//!     Message::Msg(msg) => {
+//!         assert_eq!(msg.header().op_code, OpCode::Msg);
+//!         assert_eq!(msg.body().get_str("$db")?, Some("admin"));
+//!     }
+//!     _ => unreachable!(),
+//! }
+//! # Ok::<(), rawbson::RawError>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::io;
+use std::ops::BitOr;
+
+use crate::{
+    i32_from_slice, i64_from_slice, read_nullterminated, reader::check_length, u32_from_slice, Doc,
+    DocSeq, ErrorKind, RawError, RawResult,
+};
+
+/// The opcode of a wire protocol message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OpCode {
+    Reply,
+    Update,
+    Insert,
+    Query,
+    GetMore,
+    Delete,
+    KillCursors,
+    Compressed,
+    Msg,
+    /// An opcode not listed above.
+    Other(i32),
+}
+
+impl From<i32> for OpCode {
+    fn from(code: i32) -> OpCode {
+        match code {
+            1 => OpCode::Reply,
+            2001 => OpCode::Update,
+            2002 => OpCode::Insert,
+            2004 => OpCode::Query,
+            2005 => OpCode::GetMore,
+            2006 => OpCode::Delete,
+            2007 => OpCode::KillCursors,
+            2012 => OpCode::Compressed,
+            2013 => OpCode::Msg,
+            other => OpCode::Other(other),
+        }
+    }
+}
+
+impl From<OpCode> for i32 {
+    fn from(code: OpCode) -> i32 {
+        match code {
+            OpCode::Reply => 1,
+            OpCode::Update => 2001,
+            OpCode::Insert => 2002,
+            OpCode::Query => 2004,
+            OpCode::GetMore => 2005,
+            OpCode::Delete => 2006,
+            OpCode::KillCursors => 2007,
+            OpCode::Compressed => 2012,
+            OpCode::Msg => 2013,
+            OpCode::Other(other) => other,
+        }
+    }
+}
+
+/// The standard header at the start of every message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsgHeader {
+    /// The length of the whole message in bytes, including the header.
+    pub message_length: i32,
+    pub request_id: i32,
+    /// The request id of the message this one responds to.
+    pub response_to: i32,
+    pub op_code: OpCode,
+}
+
+impl MsgHeader {
+    /// The length of an encoded header.
+    pub const LENGTH: usize = 16;
+
+    /// Read the header at the start of `data`.
+    ///
+    /// Only the header itself needs to be present, so this can be used to
+    /// find out how long a message is before the rest of it has arrived.
+    /// Returns an error if the declared length is too short to hold the
+    /// header.
+    pub fn parse(data: &[u8]) -> RawResult<MsgHeader> {
+        if data.len() < MsgHeader::LENGTH {
+            return Err(RawError::new(ErrorKind::TruncatedLength).at(data.len()));
+        }
+        let message_length = i32_from_slice(&data[..4]);
+        if message_length < MsgHeader::LENGTH as i32 {
+            return Err(RawError::new(ErrorKind::BadLength).at(0));
+        }
+        Ok(MsgHeader {
+            message_length,
+            request_id: i32_from_slice(&data[4..8]),
+            response_to: i32_from_slice(&data[8..12]),
+            op_code: OpCode::from(i32_from_slice(&data[12..16])),
+        })
+    }
+}
+
+/// Reads the fields of a message in order, reporting errors at offsets
+/// from the start of the message.
+struct Fields<'a> {
+    message: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    /// Check the header of the message at the start of `data` and prepare
+    /// to read the fields after it.
+    fn new(data: &'a [u8], op_code: OpCode) -> RawResult<(MsgHeader, Fields<'a>)> {
+        let header = MsgHeader::parse(data)?;
+        let length = header.message_length as usize;
+        if data.len() < length {
+            return Err(RawError::new(ErrorKind::TruncatedLength).at(data.len()));
+        }
+        if header.op_code != op_code {
+            return Err(RawError::new(ErrorKind::UnexpectedType).at(12));
+        }
+        let fields = Fields {
+            message: &data[..length],
+            pos: MsgHeader::LENGTH,
+        };
+        Ok((header, fields))
+    }
+
+    fn remaining(&self) -> usize {
+        self.message.len() - self.pos
+    }
+
+    fn take(&mut self, count: usize) -> RawResult<&'a [u8]> {
+        if self.remaining() < count {
+            return Err(RawError::new(ErrorKind::TruncatedLength).at(self.message.len()));
+        }
+        let taken = &self.message[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> RawResult<i32> {
+        self.take(4).map(i32_from_slice)
+    }
+
+    fn u32(&mut self) -> RawResult<u32> {
+        self.take(4).map(u32_from_slice)
+    }
+
+    fn i64(&mut self) -> RawResult<i64> {
+        self.take(8).map(i64_from_slice)
+    }
+
+    fn cstring(&mut self) -> RawResult<&'a str> {
+        let value =
+            read_nullterminated(&self.message[self.pos..]).map_err(|err| err.shifted(self.pos))?;
+        self.pos += value.len() + 1;
+        Ok(value)
+    }
+
+    fn doc(&mut self) -> RawResult<&'a Doc> {
+        let start = self.pos;
+        let header = self.take(4)?;
+        let length = check_length(header, usize::MAX).map_err(|err| err.shifted(start))?;
+        self.pos = start;
+        let data = self.take(length)?;
+        Doc::new(data).map_err(|err| err.shifted(start))
+    }
+
+    /// Check every document in the rest of the message, returning them as a
+    /// sequence.
+    fn doc_seq(&mut self) -> RawResult<DocSeq<'a>> {
+        let start = self.pos;
+        let seq = DocSeq::new(&self.message[start..]);
+        for result in seq {
+            result.map_err(|err| err.shifted(start))?;
+        }
+        self.pos = self.message.len();
+        Ok(seq)
+    }
+
+    fn finish(&self) -> RawResult<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            _ => Err(RawError::new(ErrorKind::BadLength).at(self.pos)),
+        }
+    }
+}
+
+/// Any wire protocol message, as identified by its opcode.
+#[derive(Clone, Debug)]
+pub enum Message<'a> {
+    Msg(OpMsg<'a>),
+    Query(OpQuery<'a>),
+    Reply(OpReply<'a>),
+    /// A message of another type, with its unparsed body.
+    Other(MsgHeader, &'a [u8]),
+}
+
+impl<'a> Message<'a> {
+    /// Parse the message at the start of `data`.
+    ///
+    /// Bytes after the end of the message are ignored;
+    /// `header().message_length` says where it ends.
+    pub fn parse(data: &'a [u8]) -> RawResult<Message<'a>> {
+        let header = MsgHeader::parse(data)?;
+        match header.op_code {
+            OpCode::Msg => OpMsg::parse(data).map(Message::Msg),
+            OpCode::Query => OpQuery::parse(data).map(Message::Query),
+            OpCode::Reply => OpReply::parse(data).map(Message::Reply),
+            _ => {
+                let (_, fields) = Fields::new(data, header.op_code)?;
+                Ok(Message::Other(header, &fields.message[MsgHeader::LENGTH..]))
+            }
+        }
+    }
+
+    pub fn header(&self) -> &MsgHeader {
+        match self {
+            Message::Msg(msg) => &msg.header,
+            Message::Query(query) => &query.header,
+            Message::Reply(reply) => &reply.header,
+            Message::Other(header, _) => header,
+        }
+    }
+}
+
+/// The flag bits of an `OP_MSG`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct MsgFlags {
+    bits: u32,
+}
+
+impl MsgFlags {
+    /// The message ends with a CRC-32C checksum.
+    pub const CHECKSUM_PRESENT: MsgFlags = MsgFlags { bits: 1 };
+    /// The sender will send another message without waiting for a reply.
+    pub const MORE_TO_COME: MsgFlags = MsgFlags { bits: 1 << 1 };
+    /// The client is prepared for multiple replies to this request.
+    pub const EXHAUST_ALLOWED: MsgFlags = MsgFlags { bits: 1 << 16 };
+
+    /// The low 16 bits are required to be understood by the receiver.
+    const REQUIRED: u32 = 0xffff;
+
+    pub fn from_bits(bits: u32) -> MsgFlags {
+        MsgFlags { bits }
+    }
+
+    pub fn bits(self) -> u32 {
+        self.bits
+    }
+
+    /// Returns true if every flag set in `other` is set in `self`.
+    pub fn contains(self, other: MsgFlags) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl BitOr for MsgFlags {
+    type Output = MsgFlags;
+
+    fn bitor(self, other: MsgFlags) -> MsgFlags {
+        MsgFlags {
+            bits: self.bits | other.bits,
+        }
+    }
+}
+
+/// An `OP_MSG` message.
+#[derive(Clone, Debug)]
+pub struct OpMsg<'a> {
+    header: MsgHeader,
+    flags: MsgFlags,
+    body: &'a Doc,
+    sections: &'a [u8],
+    checksum: Option<u32>,
+    message: &'a [u8],
+}
+
+impl<'a> OpMsg<'a> {
+    /// Parse the `OP_MSG` at the start of `data`.
+    ///
+    /// Returns an error if the message has another opcode, sets unknown
+    /// required flag bits, or does not have exactly one body section.  The
+    /// checksum is read but not verified.
+    pub fn parse(data: &'a [u8]) -> RawResult<OpMsg<'a>> {
+        let (header, mut fields) = Fields::new(data, OpCode::Msg)?;
+        let flags = MsgFlags::from_bits(fields.u32()?);
+        let known = MsgFlags::CHECKSUM_PRESENT | MsgFlags::MORE_TO_COME;
+        let unknown = flags.bits() & MsgFlags::REQUIRED & !known.bits();
+        if unknown != 0 {
+            return Err(RawError::new(ErrorKind::BadFlags(unknown)).at(16));
+        }
+        let checksum = if flags.contains(MsgFlags::CHECKSUM_PRESENT) {
+            if fields.remaining() < 4 {
+                return Err(RawError::new(ErrorKind::TruncatedLength).at(fields.message.len()));
+            }
+            let end = fields.message.len() - 4;
+            let checksum = u32_from_slice(&fields.message[end..]);
+            fields.message = &fields.message[..end];
+            Some(checksum)
+        } else {
+            None
+        };
+        let sections_start = fields.pos;
+        let mut body = None;
+        while fields.remaining() > 0 {
+            let kind_pos = fields.pos;
+            match fields.take(1)?[0] {
+                0 => {
+                    let doc = fields.doc()?;
+                    if body.replace(doc).is_some() {
+                        return Err(RawError::new(ErrorKind::BadSections).at(kind_pos));
+                    }
+                }
+                1 => {
+                    let size_pos = fields.pos;
+                    let size = fields.i32()?;
+                    let end = match usize::try_from(size) {
+                        Ok(size) if size >= 5 && size <= fields.remaining() + 4 => size_pos + size,
+                        _ => return Err(RawError::new(ErrorKind::BadLength).at(size_pos)),
+                    };
+                    let mut section = Fields {
+                        message: &fields.message[..end],
+                        pos: fields.pos,
+                    };
+                    section.cstring()?;
+                    section.doc_seq()?;
+                    fields.pos = end;
+                }
+                kind => return Err(RawError::new(ErrorKind::InvalidTag(kind)).at(kind_pos)),
+            }
+        }
+        let body = body.ok_or_else(|| RawError::new(ErrorKind::BadSections).at(sections_start))?;
+        Ok(OpMsg {
+            header,
+            flags,
+            body,
+            sections: &fields.message[sections_start..],
+            checksum,
+            message: &data[..header.message_length as usize],
+        })
+    }
+
+    pub fn header(&self) -> &MsgHeader {
+        &self.header
+    }
+
+    pub fn flags(&self) -> MsgFlags {
+        self.flags
+    }
+
+    /// The document in the body (kind 0) section.
+    pub fn body(&self) -> &'a Doc {
+        self.body
+    }
+
+    /// Iterate over the sections of the message in order.
+    pub fn sections(&self) -> Sections<'a> {
+        Sections {
+            data: self.sections,
+        }
+    }
+
+    /// The first document sequence (kind 1) section with the given
+    /// identifier, such as `"documents"` for an insert command.
+    pub fn sequence(&self, identifier: &str) -> Option<DocSequence<'a>> {
+        self.sections().find_map(|section| match section {
+            Section::Sequence(seq) if seq.identifier == identifier => Some(seq),
+            _ => None,
+        })
+    }
+
+    /// The checksum at the end of the message, if the `CHECKSUM_PRESENT`
+    /// flag is set.
+    pub fn checksum(&self) -> Option<u32> {
+        self.checksum
+    }
+
+    /// The bytes of the whole message.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.message
+    }
+}
+
+/// A section of an [`OpMsg`].
+#[derive(Clone, Copy, Debug)]
+pub enum Section<'a> {
+    /// A kind 0 section, holding the command document.
+    Body(&'a Doc),
+    /// A kind 1 section, holding a sequence of documents.
+    Sequence(DocSequence<'a>),
+}
+
+/// A document sequence section of an [`OpMsg`]: an identifier naming the
+/// command argument the documents belong to, and the documents themselves.
+#[derive(Clone, Copy, Debug)]
+pub struct DocSequence<'a> {
+    identifier: &'a str,
+    documents: DocSeq<'a>,
+}
+
+impl<'a> DocSequence<'a> {
+    pub fn identifier(&self) -> &'a str {
+        self.identifier
+    }
+
+    /// The documents in the section.  They were checked when the message
+    /// was parsed, so iterating over them does not fail.
+    pub fn documents(&self) -> DocSeq<'a> {
+        self.documents
+    }
+}
+
+/// An iterator over the sections of an [`OpMsg`].
+#[derive(Clone, Debug)]
+pub struct Sections<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Sections<'a> {
+    type Item = Section<'a>;
+
+    // The sections were checked by `OpMsg::parse`.
+    fn next(&mut self) -> Option<Section<'a>> {
+        let (&kind, rest) = self.data.split_first()?;
+        if kind == 0 {
+            let length = i32_from_slice(&rest[..4]) as usize;
+            self.data = &rest[length..];
+            // SAFETY: The envelope was checked when parsing.
+            Some(Section::Body(unsafe {
+                Doc::new_unchecked(&rest[..length])
+            }))
+        } else {
+            let size = i32_from_slice(&rest[..4]) as usize;
+            self.data = &rest[size..];
+            let identifier = read_nullterminated(&rest[4..size]).ok()?;
+            Some(Section::Sequence(DocSequence {
+                identifier,
+                documents: DocSeq::new(&rest[4 + identifier.len() + 1..size]),
+            }))
+        }
+    }
+}
+
+/// A legacy `OP_QUERY` message.
+#[derive(Clone, Debug)]
+pub struct OpQuery<'a> {
+    header: MsgHeader,
+    flags: i32,
+    full_collection_name: &'a str,
+    number_to_skip: i32,
+    number_to_return: i32,
+    query: &'a Doc,
+    return_fields_selector: Option<&'a Doc>,
+}
+
+impl<'a> OpQuery<'a> {
+    /// Parse the `OP_QUERY` at the start of `data`.
+    pub fn parse(data: &'a [u8]) -> RawResult<OpQuery<'a>> {
+        let (header, mut fields) = Fields::new(data, OpCode::Query)?;
+        let query = OpQuery {
+            header,
+            flags: fields.i32()?,
+            full_collection_name: fields.cstring()?,
+            number_to_skip: fields.i32()?,
+            number_to_return: fields.i32()?,
+            query: fields.doc()?,
+            return_fields_selector: match fields.remaining() {
+                0 => None,
+                _ => Some(fields.doc()?),
+            },
+        };
+        fields.finish()?;
+        Ok(query)
+    }
+
+    pub fn header(&self) -> &MsgHeader {
+        &self.header
+    }
+
+    /// The query flag bits, such as `4` for `SlaveOk`.
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    /// The namespace queried, as `"database.collection"`.
+    pub fn full_collection_name(&self) -> &'a str {
+        self.full_collection_name
+    }
+
+    pub fn number_to_skip(&self) -> i32 {
+        self.number_to_skip
+    }
+
+    pub fn number_to_return(&self) -> i32 {
+        self.number_to_return
+    }
+
+    pub fn query(&self) -> &'a Doc {
+        self.query
+    }
+
+    pub fn return_fields_selector(&self) -> Option<&'a Doc> {
+        self.return_fields_selector
+    }
+}
+
+/// A legacy `OP_REPLY` message.
+#[derive(Clone, Debug)]
+pub struct OpReply<'a> {
+    header: MsgHeader,
+    response_flags: i32,
+    cursor_id: i64,
+    starting_from: i32,
+    number_returned: i32,
+    documents: DocSeq<'a>,
+}
+
+impl<'a> OpReply<'a> {
+    /// Parse the `OP_REPLY` at the start of `data`.
+    pub fn parse(data: &'a [u8]) -> RawResult<OpReply<'a>> {
+        let (header, mut fields) = Fields::new(data, OpCode::Reply)?;
+        Ok(OpReply {
+            header,
+            response_flags: fields.i32()?,
+            cursor_id: fields.i64()?,
+            starting_from: fields.i32()?,
+            number_returned: fields.i32()?,
+            documents: fields.doc_seq()?,
+        })
+    }
+
+    pub fn header(&self) -> &MsgHeader {
+        &self.header
+    }
+
+    /// The response flag bits, such as `2` for `QueryFailure`.
+    pub fn response_flags(&self) -> i32 {
+        self.response_flags
+    }
+
+    pub fn cursor_id(&self) -> i64 {
+        self.cursor_id
+    }
+
+    pub fn starting_from(&self) -> i32 {
+        self.starting_from
+    }
+
+    /// The number of documents, as declared by the message.
+    pub fn number_returned(&self) -> i32 {
+        self.number_returned
+    }
+
+    /// The documents in the reply.  They were checked when the message was
+    /// parsed, so iterating over them does not fail.
+    pub fn documents(&self) -> DocSeq<'a> {
+        self.documents
+    }
+}
+
+/// Builds an `OP_MSG` from a body document and any number of document
+/// sequences.
+///
+/// The documents are borrowed and copied into the message as they are,
+/// without being re-encoded.  If the `CHECKSUM_PRESENT` flag is set, with
+/// [`checksum`](OpMsgBuilder::checksum) or [`flags`](OpMsgBuilder::flags),
+/// a CRC-32C checksum of the message is appended.
+///
+/// ```
+/// use bson::doc;
+/// use rawbson::{
+///     wire::{OpMsg, OpMsgBuilder},
+///     DocBuf,
+/// };
+///
+/// let body = DocBuf::from_document(&doc! {"insert": "coll", "$db": "test"});
+/// let docs = [
+///     DocBuf::from_document(&doc! {"_id": 1}),
+///     DocBuf::from_document(&doc! {"_id": 2}),
+/// ];
+/// let bytes = OpMsgBuilder::new(&body)
+///     .request_id(7)
+///     .sequence("documents", docs.iter().map(|doc| doc.as_ref()))
+///     .checksum(true)
+///     .to_vec()?;
+///
+/// let msg = OpMsg::parse(&bytes)?;
+/// assert_eq!(msg.header().request_id, 7);
+/// assert_eq!(msg.sequence("documents").unwrap().documents().into_iter().count(), 2);
+/// assert!(msg.checksum().is_some());
+/// # Ok::<(), rawbson::RawError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct OpMsgBuilder<'a> {
+    request_id: i32,
+    response_to: i32,
+    flags: MsgFlags,
+    body: &'a Doc,
+    sequences: Vec<(&'a str, Vec<&'a Doc>)>,
+}
+
+impl<'a> OpMsgBuilder<'a> {
+    /// Start a message with the given body, no flags, and request and
+    /// response ids of zero.
+    pub fn new(body: &'a Doc) -> OpMsgBuilder<'a> {
+        OpMsgBuilder {
+            request_id: 0,
+            response_to: 0,
+            flags: MsgFlags::default(),
+            body,
+            sequences: Vec::new(),
+        }
+    }
+
+    pub fn request_id(&mut self, request_id: i32) -> &mut Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Set the id of the request this message responds to.
+    pub fn response_to(&mut self, response_to: i32) -> &mut Self {
+        self.response_to = response_to;
+        self
+    }
+
+    /// Set the flag bits.  Bits are written as given, even those that a
+    /// receiver will not understand.
+    pub fn flags(&mut self, flags: MsgFlags) -> &mut Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set or clear the `CHECKSUM_PRESENT` flag.
+    pub fn checksum(&mut self, checksum: bool) -> &mut Self {
+        let bits = match checksum {
+            true => self.flags.bits() | MsgFlags::CHECKSUM_PRESENT.bits(),
+            false => self.flags.bits() & !MsgFlags::CHECKSUM_PRESENT.bits(),
+        };
+        self.flags = MsgFlags::from_bits(bits);
+        self
+    }
+
+    /// Add a document sequence section, after any added before.
+    pub fn sequence(
+        &mut self,
+        identifier: &'a str,
+        documents: impl IntoIterator<Item = &'a Doc>,
+    ) -> &mut Self {
+        self.sequences
+            .push((identifier, documents.into_iter().collect()));
+        self
+    }
+
+    /// Check the identifiers and compute the length of the message.
+    fn message_length(&self) -> RawResult<i32> {
+        let too_long = || RawError::new(ErrorKind::BadLength);
+        let mut length = MsgHeader::LENGTH + 4 + 1 + self.body.as_bytes().len();
+        for (identifier, documents) in &self.sequences {
+            if identifier.contains('\0') {
+                return Err(RawError::new(ErrorKind::InteriorNul));
+            }
+            let size = 4 + identifier.len() + 1 + documents_length(documents);
+            i32::try_from(size).map_err(|_| too_long())?;
+            length += 1 + size;
+        }
+        if self.flags.contains(MsgFlags::CHECKSUM_PRESENT) {
+            length += 4;
+        }
+        i32::try_from(length).map_err(|_| too_long())
+    }
+
+    /// Write the message to `writer`, returning the first error from the
+    /// writer.
+    fn write<W: io::Write + ?Sized>(&self, message_length: i32, writer: &mut W) -> io::Result<()> {
+        let mut writer = ChecksumWriter { writer, crc: 0 };
+        writer.write(&message_length.to_le_bytes())?;
+        writer.write(&self.request_id.to_le_bytes())?;
+        writer.write(&self.response_to.to_le_bytes())?;
+        writer.write(&i32::from(OpCode::Msg).to_le_bytes())?;
+        writer.write(&self.flags.bits().to_le_bytes())?;
+        writer.write(&[0])?;
+        writer.write(self.body.as_bytes())?;
+        for (identifier, documents) in &self.sequences {
+            let size = 4 + identifier.len() + 1 + documents_length(documents);
+            writer.write(&[1])?;
+            writer.write(&(size as i32).to_le_bytes())?;
+            writer.write(identifier.as_bytes())?;
+            writer.write(&[0])?;
+            for doc in documents {
+                writer.write(doc.as_bytes())?;
+            }
+        }
+        if self.flags.contains(MsgFlags::CHECKSUM_PRESENT) {
+            let crc = writer.crc;
+            writer.writer.write_all(&crc.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Build the message into a new buffer.
+    ///
+    /// Returns an error if an identifier contains a nul byte, or the
+    /// message is too long for its length prefix.
+    pub fn to_vec(&self) -> RawResult<Vec<u8>> {
+        let message_length = self.message_length()?;
+        let mut out = Vec::with_capacity(message_length as usize);
+        self.write(message_length, &mut out)
+            .expect("writing to a Vec cannot fail");
+        Ok(out)
+    }
+
+    /// Write the message to `writer`.
+    ///
+    /// If the message cannot be built, the [`RawError`] is returned wrapped
+    /// in an [`io::Error`] of kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// before anything is written.
+    pub fn write_to<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let message_length = self
+            .message_length()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.write(message_length, writer)
+    }
+}
+
+fn documents_length(documents: &[&Doc]) -> usize {
+    documents.iter().map(|doc| doc.as_bytes().len()).sum()
+}
+
+/// Passes writes through, keeping a running CRC-32C of the bytes written.
+struct ChecksumWriter<'w, W: ?Sized> {
+    writer: &'w mut W,
+    crc: u32,
+}
+
+impl<W: io::Write + ?Sized> ChecksumWriter<'_, W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc = crc32c::crc32c_append(self.crc, bytes);
+        self.writer.write_all(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{
+        Message, MsgFlags, MsgHeader, OpCode, OpMsg, OpMsgBuilder, OpQuery, OpReply, Section,
+    };
+    use crate::{Doc, DocBufBuilder, ErrorKind};
+
+    // Captured client and server messages.
+
+    /// `hello` command: {"hello": 1, "$db": "admin"}
+    const HELLO: &[u8] = b"\x34\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\xdd\x07\x00\x00\
+        \x00\x00\x00\x00\x00\x1f\x00\x00\x00\x10\x68\x65\x6c\x6c\x6f\x00\
+        \x01\x00\x00\x00\x02\x24\x64\x62\x00\x06\x00\x00\x00\x61\x64\x6d\
+        \x69\x6e\x00\x00";
+
+    /// `insert` into test.coll with the documents {"_id": 1} and {"_id": 2}
+    /// in a "documents" sequence, and a checksum.
+    const INSERT: &[u8] = b"\x68\x00\x00\x00\x07\x00\x00\x00\x00\x00\x00\x00\xdd\x07\x00\x00\
+        \x01\x00\x00\x00\x00\x24\x00\x00\x00\x02\x69\x6e\x73\x65\x72\x74\
+        \x00\x05\x00\x00\x00\x63\x6f\x6c\x6c\x00\x02\x24\x64\x62\x00\x05\
+        \x00\x00\x00\x74\x65\x73\x74\x00\x00\x01\x2a\x00\x00\x00\x64\x6f\
+        \x63\x75\x6d\x65\x6e\x74\x73\x00\x0e\x00\x00\x00\x10\x5f\x69\x64\
+        \x00\x01\x00\x00\x00\x00\x0e\x00\x00\x00\x10\x5f\x69\x64\x00\x02\
+        \x00\x00\x00\x00\x7c\x46\xef\xa4";
+
+    /// Reply to INSERT: {"n": 2, "ok": 1.0}
+    const INSERT_REPLY: &[u8] = b"\x2d\x00\x00\x00\x08\x00\x00\x00\x07\x00\x00\x00\xdd\x07\x00\x00\
+        \x00\x00\x00\x00\x00\x18\x00\x00\x00\x10\x6e\x00\x02\x00\x00\x00\
+        \x01\x6f\x6b\x00\x00\x00\x00\x00\x00\x00\xf0\x3f\x00";
+
+    /// Legacy `isMaster` handshake on admin.$cmd, with SlaveOk set.
+    const IS_MASTER: &[u8] = b"\x3a\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00\xd4\x07\x00\x00\
+        \x04\x00\x00\x00\x61\x64\x6d\x69\x6e\x2e\x24\x63\x6d\x64\x00\x00\
+        \x00\x00\x00\xff\xff\xff\xff\x13\x00\x00\x00\x10\x69\x73\x4d\x61\
+        \x73\x74\x65\x72\x00\x01\x00\x00\x00\x00";
+
+    /// find on test.coll: {"x": 1}, skipping 5 and returning 10, with the
+    /// projection {"_id": 0}.
+    const FIND: &[u8] = b"\x40\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00\xd4\x07\x00\x00\
+        \x00\x00\x00\x00\x74\x65\x73\x74\x2e\x63\x6f\x6c\x6c\x00\x05\x00\
+        \x00\x00\x0a\x00\x00\x00\x0c\x00\x00\x00\x10\x78\x00\x01\x00\x00\
+        \x00\x00\x0e\x00\x00\x00\x10\x5f\x69\x64\x00\x00\x00\x00\x00\x00";
+
+    /// Reply to IS_MASTER with AwaitCapable set: {"ismaster": true, "ok": 1.0}
+    const IS_MASTER_REPLY: &[u8] =
+        b"\x40\x00\x00\x00\x05\x00\x00\x00\x03\x00\x00\x00\x01\x00\x00\x00\
+        \x08\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\
+        \x01\x00\x00\x00\x1c\x00\x00\x00\x08\x69\x73\x6d\x61\x73\x74\x65\
+        \x72\x00\x01\x01\x6f\x6b\x00\x00\x00\x00\x00\x00\x00\xf0\x3f\x00";
+
+    fn keys(doc: &Doc) -> Vec<&str> {
+        doc.into_iter().map(|result| result.unwrap().0).collect()
+    }
+
+    #[test]
+    fn op_msg() {
+        let msg = OpMsg::parse(HELLO).unwrap();
+        assert_eq!(
+            *msg.header(),
+            MsgHeader {
+                message_length: 52,
+                request_id: 1,
+                response_to: 0,
+                op_code: OpCode::Msg,
+            }
+        );
+        assert_eq!(msg.flags(), MsgFlags::default());
+        assert_eq!(keys(msg.body()), ["hello", "$db"]);
+        assert_eq!(msg.sections().count(), 1);
+        assert_eq!(msg.checksum(), None);
+
+        let reply = OpMsg::parse(INSERT_REPLY).unwrap();
+        assert_eq!(reply.header().response_to, 7);
+        assert_eq!(reply.body().get_f64("ok").unwrap(), Some(1.0));
+    }
+
+    #[test]
+    fn document_sequences() {
+        let msg = OpMsg::parse(INSERT).unwrap();
+        assert!(msg.flags().contains(MsgFlags::CHECKSUM_PRESENT));
+        assert!(!msg.flags().contains(MsgFlags::MORE_TO_COME));
+        assert_eq!(msg.checksum(), Some(0xa4ef_467c));
+        assert_eq!(msg.body().get_str("insert").unwrap(), Some("coll"));
+
+        let sections: Vec<Section> = msg.sections().collect();
+        assert_eq!(sections.len(), 2);
+        assert!(
+            matches!(sections[0], Section::Body(doc) if doc.as_bytes() == msg.body().as_bytes())
+        );
+        let seq = match sections[1] {
+            Section::Sequence(seq) => seq,
+            _ => panic!("expected a document sequence"),
+        };
+        assert_eq!(seq.identifier(), "documents");
+        let ids: Vec<i32> = seq
+            .documents()
+            .into_iter()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap().unwrap())
+            .collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(msg.sequence("documents").unwrap().identifier(), "documents");
+        assert!(msg.sequence("updates").is_none());
+    }
+
+    #[test]
+    fn legacy_messages() {
+        let query = OpQuery::parse(IS_MASTER).unwrap();
+        assert_eq!(query.header().request_id, 3);
+        assert_eq!(query.flags(), 4);
+        assert_eq!(query.full_collection_name(), "admin.$cmd");
+        assert_eq!((query.number_to_skip(), query.number_to_return()), (0, -1));
+        assert_eq!(keys(query.query()), ["isMaster"]);
+        assert!(query.return_fields_selector().is_none());
+
+        let find = OpQuery::parse(FIND).unwrap();
+        assert_eq!(find.full_collection_name(), "test.coll");
+        assert_eq!((find.number_to_skip(), find.number_to_return()), (5, 10));
+        assert_eq!(keys(find.return_fields_selector().unwrap()), ["_id"]);
+
+        let reply = OpReply::parse(IS_MASTER_REPLY).unwrap();
+        assert_eq!(reply.header().response_to, 3);
+        assert_eq!(reply.response_flags(), 8);
+        assert_eq!((reply.cursor_id(), reply.starting_from()), (0, 0));
+        assert_eq!(reply.number_returned(), 1);
+        let docs: Vec<&Doc> = reply.documents().into_iter().map(Result::unwrap).collect();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].get_bool("ismaster").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut stream = Vec::new();
+        for bytes in &[HELLO, INSERT, IS_MASTER, IS_MASTER_REPLY] {
+            stream.extend_from_slice(bytes);
+        }
+        // A made-up OP_KILL_CURSORS with an empty body.
+        stream.extend_from_slice(b"\x10\0\0\0\x09\0\0\0\0\0\0\0\xd7\x07\0\0");
+
+        let mut rest = &stream[..];
+        let mut ops = Vec::new();
+        while !rest.is_empty() {
+            let message = Message::parse(rest).unwrap();
+            ops.push(match message {
+                Message::Msg(_) => "msg",
+                Message::Query(_) => "query",
+                Message::Reply(_) => "reply",
+                Message::Other(header, body) => {
+                    assert_eq!(header.op_code, OpCode::KillCursors);
+                    assert!(body.is_empty());
+                    "other"
+                }
+            });
+            rest = &rest[message.header().message_length as usize..];
+        }
+        assert_eq!(ops, ["msg", "msg", "query", "reply", "other"]);
+        assert_eq!(i32::from(OpCode::from(2013)), 2013);
+        assert_eq!(OpCode::from(99), OpCode::Other(99));
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let error = |data: &[u8]| {
+            let err = Message::parse(data).unwrap_err();
+            (err.kind().clone(), err.offset())
+        };
+        assert_eq!(error(&HELLO[..10]), (ErrorKind::TruncatedLength, Some(10)));
+        assert_eq!(error(&HELLO[..40]), (ErrorKind::TruncatedLength, Some(40)));
+        assert_eq!(
+            OpQuery::parse(HELLO).unwrap_err().kind(),
+            &ErrorKind::UnexpectedType
+        );
+
+        let mut bytes = HELLO.to_vec();
+        bytes[0] = 8;
+        assert_eq!(error(&bytes), (ErrorKind::BadLength, Some(0)));
+
+        // Unknown required flag bits are rejected, but optional ones are not.
+        let mut bytes = HELLO.to_vec();
+        bytes[16] = 0x04;
+        assert_eq!(error(&bytes), (ErrorKind::BadFlags(4), Some(16)));
+        bytes[16] = 0;
+        bytes[18] = 0x01;
+        let msg = OpMsg::parse(&bytes).unwrap();
+        assert!(msg.flags().contains(MsgFlags::EXHAUST_ALLOWED));
+
+        // No body section.
+        assert_eq!(
+            error(b"\x14\0\0\0\x01\0\0\0\0\0\0\0\xdd\x07\0\0\0\0\0\0"),
+            (ErrorKind::BadSections, Some(20))
+        );
+        let mut bytes = HELLO.to_vec();
+        bytes[20] = 2;
+        assert_eq!(error(&bytes), (ErrorKind::InvalidTag(2), Some(20)));
+
+        // The document sequence claims to extend into the checksum.
+        let mut bytes = INSERT.to_vec();
+        bytes[58] += 1;
+        assert_eq!(error(&bytes), (ErrorKind::BadLength, Some(58)));
+        // The second document in the sequence is missing its nul.
+        let mut bytes = INSERT.to_vec();
+        bytes[99] = 1;
+        assert_eq!(error(&bytes), (ErrorKind::MissingNul, Some(99)));
+
+        // Trailing bytes after the return fields selector.
+        let mut bytes = FIND.to_vec();
+        bytes[0] += 1;
+        bytes.push(0);
+        assert_eq!(error(&bytes), (ErrorKind::BadLength, Some(64)));
+    }
+
+    #[test]
+    fn builder_matches_captures() {
+        let hello = OpMsg::parse(HELLO).unwrap();
+        let bytes = OpMsgBuilder::new(hello.body())
+            .request_id(1)
+            .to_vec()
+            .unwrap();
+        assert_eq!(bytes, HELLO);
+
+        let reply = OpMsg::parse(INSERT_REPLY).unwrap();
+        let bytes = OpMsgBuilder::new(reply.body())
+            .request_id(8)
+            .response_to(7)
+            .to_vec()
+            .unwrap();
+        assert_eq!(bytes, INSERT_REPLY);
+
+        // The checksum is computed over everything before it.
+        let insert = OpMsg::parse(INSERT).unwrap();
+        let documents = insert.sequence("documents").unwrap().documents();
+        let mut builder = OpMsgBuilder::new(insert.body());
+        builder
+            .request_id(7)
+            .sequence("documents", documents.into_iter().map(Result::unwrap))
+            .checksum(true);
+        assert_eq!(builder.to_vec().unwrap(), INSERT);
+        let mut out = Vec::new();
+        builder.write_to(&mut out).unwrap();
+        assert_eq!(out, INSERT);
+    }
+
+    #[test]
+    fn builder_flags_and_errors() {
+        let mut body = DocBufBuilder::new();
+        body.append_i32("ping", 1);
+        let body = body.finish().unwrap();
+        let empty = Doc::new(b"\x05\0\0\0\0").unwrap();
+
+        let mut builder = OpMsgBuilder::new(&body);
+        builder
+            .flags(MsgFlags::MORE_TO_COME | MsgFlags::EXHAUST_ALLOWED)
+            .checksum(true)
+            .checksum(false)
+            .sequence("updates", Vec::new())
+            .sequence("deletes", vec![empty, empty]);
+        let bytes = builder.to_vec().unwrap();
+        let msg = OpMsg::parse(&bytes).unwrap();
+        assert_eq!(
+            msg.flags(),
+            MsgFlags::MORE_TO_COME | MsgFlags::EXHAUST_ALLOWED
+        );
+        assert_eq!(msg.checksum(), None);
+        let sections: Vec<(&str, usize)> = msg
+            .sections()
+            .filter_map(|section| match section {
+                Section::Sequence(seq) => {
+                    Some((seq.identifier(), seq.documents().into_iter().count()))
+                }
+                Section::Body(_) => None,
+            })
+            .collect();
+        assert_eq!(sections, [("updates", 0), ("deletes", 2)]);
+
+        builder.sequence("bad\0id", Vec::new());
+        assert_eq!(
+            builder.to_vec().unwrap_err().kind(),
+            &ErrorKind::InteriorNul
+        );
+        let mut out = Vec::new();
+        let err = builder.write_to(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(out.is_empty());
+    }
+}